@@ -0,0 +1,120 @@
+defmodule RustReq.CookieJar do
+  @moduledoc """
+  Cookie jars kept in Rust and shared between requests.
+
+  A jar given as the `cookie_jar` option sends its matching cookies with the synchronous
+  request functions such as `RustReq.get/3` and `RustReq.request/5`, with
+  `RustReq.get_async/3` and `RustReq.post_async/4`, and stores the cookies their
+  responses set, including those of redirects they follow: each hop of a login
+  answered by a redirect carries the cookies stored before it. Given to
+  `RustReq.client_new/1`, it holds the session of every request on the client. Use
+  `cookies/1` to see what a jar holds.
+
+  A cookie is only shared with subdomains when its `Domain` is a registrable domain:
+  cookies for a public suffix such as `com` or `co.uk` (per the Public Suffix List),
+  a single label such as `localhost` or an IP address other than the host that set it
+  are refused, while a host that is itself one of these keeps the cookie to itself.
+
+  Jars can be exchanged with curl, wget, yt-dlp and browser exports in the Netscape
+  `cookies.txt` format, or persisted encrypted with a key of your own.
+
+  ## Examples
+
+      jar = RustReq.CookieJar.new()
+      {:ok, _count} = RustReq.CookieJar.import_netscape(jar, "~/cookies.txt")
+
+      RustReq.get("https://example.com/account", [], %RustReq.Options{cookie_jar: jar})
+
+      :ok = RustReq.CookieJar.export_netscape(jar, "~/cookies.txt")
+  """
+
+  alias RustReq.Native
+
+  @typedoc "A cookie jar"
+  @type t :: reference()
+
+  @typedoc "A stored cookie; `expires` is a Unix timestamp, nil for a session cookie"
+  @type cookie :: %{
+          name: String.t(),
+          value: String.t(),
+          domain: String.t(),
+          path: String.t(),
+          secure: boolean(),
+          http_only: boolean(),
+          expires: non_neg_integer() | nil
+        }
+
+  @doc """
+  Creates an empty jar.
+
+  ## Options
+  The jar's acceptance policy, enforced whenever a cookie is stored, including on import:
+  - `:block_third_party`: Refuse cookies from a response on another site than the one
+    requested, e.g. after a cross-site redirect. Sites are compared by the last two
+    labels of the host name. (default: false)
+  - `:allow_domains`: Only accept cookies for these domains and their subdomains
+    (default: any domain)
+  - `:drop_names`: Refuse cookies whose name matches one of these patterns, where `*`
+    matches any run of characters, e.g. `["_ga*", "*_tracking"]` (default: [])
+
+  ## Examples
+
+      jar = RustReq.CookieJar.new(allow_domains: ["example.com"], drop_names: ["_ga*"])
+  """
+  @spec new(keyword()) :: t()
+  def new(opts \\ []) do
+    Native.cookie_jar_new(%{
+      block_third_party: Keyword.get(opts, :block_third_party, false),
+      allow_domains: Keyword.get(opts, :allow_domains),
+      drop_names: Keyword.get(opts, :drop_names, [])
+    })
+  end
+
+  @doc """
+  Adds the cookies of a Netscape `cookies.txt` file to the jar, replacing stored cookies
+  with the same name, domain and path. Malformed lines are skipped, as are cookies the
+  jar's policy refuses and cookies shared with the subdomains of a domain that may not
+  share them, as described in the module documentation.
+
+  ## Options
+  - `:key`: The 32-byte key the file was encrypted with by `export_netscape/3`
+
+  ## Returns
+  - `{:ok, count}` with the number of cookies imported
+  - `{:error, :invalid_key}` if the key isn't 32 bytes
+  - `{:error, :decryption_failed}` if the key is wrong or the file was altered
+  - `{:error, reason}` if the file can't be read
+  """
+  @spec import_netscape(t(), Path.t(), keyword()) :: {:ok, non_neg_integer()} | {:error, term()}
+  def import_netscape(jar, path, opts \\ []) do
+    Native.cookie_jar_import_netscape(jar, Path.expand(path), Keyword.get(opts, :key))
+  catch
+    :error, reason -> {:error, reason}
+  end
+
+  @doc """
+  Writes the unexpired cookies of the jar to a Netscape `cookies.txt` file. Session
+  cookies are written with an expiry of 0, as curl does.
+
+  ## Options
+  - `:key`: A 32-byte key to encrypt the file with, using AES-256-GCM. The file can then
+    only be read back by `import_netscape/3` with the same key.
+
+  ## Examples
+
+      key = :crypto.strong_rand_bytes(32)
+      :ok = RustReq.CookieJar.export_netscape(jar, "cookies.jar", key: key)
+  """
+  @spec export_netscape(t(), Path.t(), keyword()) :: :ok | {:error, term()}
+  def export_netscape(jar, path, opts \\ []) do
+    Native.cookie_jar_export_netscape(jar, Path.expand(path), Keyword.get(opts, :key))
+  catch
+    :error, reason -> {:error, reason}
+  end
+
+  @doc """
+  Lists the unexpired cookies of the jar.
+  """
+  @spec cookies(t()) :: [cookie()]
+  def cookies(jar), do: Native.cookie_jar_cookies(jar)
+end
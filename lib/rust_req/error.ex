@@ -0,0 +1,74 @@
+defmodule RustReq.Error do
+  @moduledoc """
+  A failed request, returned as `{:error, %RustReq.Error{}}` by the request functions
+  of `RustReq`.
+
+  `reason` is what failed: one of the atoms listed under "Errors" in `RustReq`, a tuple
+  such as `{:body_interrupted, details}` or `{:unknown_profile, name}`, or a message
+  string. The other fields say which request failed, so errors surfacing from batches
+  and background jobs can be traced without correlating logs:
+
+  - `method`: the HTTP method, e.g. `"GET"`
+  - `url`: the request URL with credentials and secret-looking query values redacted
+  - `attempt`: which attempt of the request failed, starting at 1
+  - `elapsed_ms`: time from the call until the failure
+
+  Being an exception, it can also be raised as is.
+
+  ## Examples
+
+      case RustReq.get(url) do
+        {:ok, response} -> response
+        {:error, %RustReq.Error{reason: :timeout}} -> :retry_later
+        {:error, error} -> raise error
+      end
+  """
+
+  defexception [:reason, :method, :url, :elapsed_ms, attempt: 1]
+
+  @type t :: %__MODULE__{
+          reason: term(),
+          method: String.t(),
+          url: String.t(),
+          attempt: pos_integer(),
+          elapsed_ms: non_neg_integer()
+        }
+
+  # Query parameters whose values are replaced when reporting a URL
+  @secret_params ~r/token|key|secret|pass|auth|sig|credential|session/i
+
+  @impl true
+  def message(%__MODULE__{} = error) do
+    reason = if is_binary(error.reason), do: error.reason, else: inspect(error.reason)
+
+    "#{error.method} #{error.url} failed after #{error.elapsed_ms}ms " <>
+      "(attempt #{error.attempt}): #{reason}"
+  end
+
+  @doc false
+  def redact(url) when is_binary(url) do
+    case URI.new(url) do
+      {:ok, uri} ->
+        userinfo = if uri.userinfo, do: "REDACTED"
+        URI.to_string(%{uri | userinfo: userinfo, query: redact_query(uri.query)})
+
+      {:error, _part} ->
+        url
+    end
+  end
+
+  def redact(url), do: inspect(url)
+
+  defp redact_query(nil), do: nil
+
+  defp redact_query(query) do
+    query
+    |> String.split("&")
+    |> Enum.map_join("&", fn pair ->
+      case String.split(pair, "=", parts: 2) do
+        [name, _value] -> if name =~ @secret_params, do: name <> "=REDACTED", else: pair
+        [_name] -> pair
+      end
+    end)
+  end
+end
@@ -0,0 +1,120 @@
+defmodule RustReq.Middleware do
+  @moduledoc """
+  Request middleware implemented in Elixir, consulted by the NIF around each request.
+
+  A middleware module implements this behaviour and runs under `start_link/2`. Setting
+  the server's pid as the `middleware` option (usually through
+  `RustReq.configure_defaults/1`) makes `RustReq.get/3`, `RustReq.post/4`,
+  `RustReq.get_async/3` and `RustReq.post_async/4` call it:
+
+  - `c:on_request/1` before the request is sent; it may replace the headers or veto
+    the request. Without an answer within `middleware_timeout_ms` the request goes
+    ahead unchanged.
+  - `c:on_response/1` once the response is received, for observation only.
+
+  Callbacks run in the server process, one at a time, so keep them fast.
+
+  A vetoed request fails with the reason `{:middleware_halt, reason}` in its `RustReq.Error`.
+
+  ## Examples
+
+      defmodule MyApp.AuthMiddleware do
+        @behaviour RustReq.Middleware
+
+        @impl true
+        def on_request(%{url: "https://internal." <> _, headers: headers}),
+          do: {:ok, [{"authorization", "Bearer " <> MyApp.token()} | headers]}
+
+        def on_request(_request), do: :pass
+
+        @impl true
+        def on_response(%{status: status, url: url}),
+          do: MyApp.Audit.log(url, status)
+      end
+
+      {:ok, pid} = RustReq.Middleware.start_link(MyApp.AuthMiddleware, name: MyApp.AuthMiddleware)
+      :ok = RustReq.configure_defaults(middleware: pid)
+
+  ## Message protocol
+  Any process can act as middleware. It receives
+  `{:rust_req_middleware, :on_request, id, request}` and must answer with
+  `RustReq.Native.middleware_reply(id, decision)`, and receives
+  `{:rust_req_middleware, :on_response, response}` without answering.
+  """
+
+  use GenServer
+
+  alias RustReq.Native
+
+  @typedoc "The request about to be sent"
+  @type request :: %{method: String.t(), url: String.t(), headers: [{String.t(), String.t()}]}
+
+  @typedoc "The received response, without its body"
+  @type response :: %{
+          method: String.t(),
+          url: String.t(),
+          status: non_neg_integer(),
+          headers: [{String.t(), String.t()}]
+        }
+
+  @doc """
+  Decides what happens to a request: `:pass` sends it as is, `{:ok, headers}` sends it
+  with `headers` instead of its own, and `{:halt, reason}` fails it without sending.
+  """
+  @callback on_request(request()) :: :pass | {:ok, [{String.t(), String.t()}]} | {:halt, term()}
+
+  @doc """
+  Observes a response. The return value is ignored.
+  """
+  @callback on_response(response()) :: any()
+
+  @doc """
+  Starts a server running the callbacks of `module`.
+
+  `opts` are passed to `GenServer.start_link/3`, e.g. `:name`.
+  """
+  @spec start_link(module(), GenServer.options()) :: GenServer.on_start()
+  def start_link(module, opts \\ []) when is_atom(module) do
+    GenServer.start_link(__MODULE__, module, opts)
+  end
+
+  @doc false
+  def child_spec({module, opts}) do
+    %{id: {__MODULE__, module}, start: {__MODULE__, :start_link, [module, opts]}}
+  end
+
+  def child_spec(module) when is_atom(module), do: child_spec({module, []})
+
+  @impl true
+  def init(module), do: {:ok, module}
+
+  @impl true
+  def handle_info({:rust_req_middleware, :on_request, id, request}, module) do
+    decision =
+      try do
+        module.on_request(request)
+      catch
+        # A failing callback must not break requests
+        _kind, _reason -> :pass
+      end
+
+    try do
+      Native.middleware_reply(id, decision)
+    rescue
+      # Not a valid decision: let the request through unchanged
+      ArgumentError -> Native.middleware_reply(id, :pass)
+    end
+
+    {:noreply, module}
+  end
+
+  def handle_info({:rust_req_middleware, :on_response, response}, module) do
+    try do
+      module.on_response(response)
+    catch
+      _kind, _reason -> :ok
+    end
+
+    {:noreply, module}
+  end
+end
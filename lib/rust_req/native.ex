@@ -21,11 +21,175 @@ defmodule RustReq.Native do
   # Synchronous operations
   def http_get(_url, _headers, _options), do: :erlang.nif_error(:nif_not_loaded)
   def http_post(_url, _headers, _body, _options), do: :erlang.nif_error(:nif_not_loaded)
+  def http_request(_method, _url, _headers, _body, _options), do: :erlang.nif_error(:nif_not_loaded)
+  def http_upload(_method, _url, _path, _headers, _options), do: :erlang.nif_error(:nif_not_loaded)
+  def http_post_multipart(_url, _parts, _headers, _options), do: :erlang.nif_error(:nif_not_loaded)
+  def http_post_json(_url, _data, _headers, _options), do: :erlang.nif_error(:nif_not_loaded)
+
+  def http_get_if_modified(_url, _etag, _since, _headers, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
 
   # Async operations
   def http_get_async(_url, _headers, _options), do: :erlang.nif_error(:nif_not_loaded)
   def http_post_async(_url, _headers, _body, _options), do: :erlang.nif_error(:nif_not_loaded)
 
+  def request_async(_method, _url, _redacted_url, _tag, _headers, _body, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  def request_send(_pid, _method, _url, _redacted_url, _headers, _body, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  # Persistent clients
+  def client_new(_options), do: :erlang.nif_error(:nif_not_loaded)
+
+  def client_request(_client, _method, _url, _headers, _body, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  def client_breakers(_client), do: :erlang.nif_error(:nif_not_loaded)
+  def client_breaker_reset(_client, _host), do: :erlang.nif_error(:nif_not_loaded)
+
   # Batch operations
   def http_get_batch(_urls, _headers, _options), do: :erlang.nif_error(:nif_not_loaded)
+
+  def http_get_batch_async(_urls, _redacted_urls, _headers, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  def batch_cancel(_handle), do: :erlang.nif_error(:nif_not_loaded)
+  def batch_results_next(_handle), do: :erlang.nif_error(:nif_not_loaded)
+
+  # Newline-delimited JSON streams
+  def ndjson_stream(_method, _url, _redacted_url, _tag, _headers, _body, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  def ndjson_cancel(_handle), do: :erlang.nif_error(:nif_not_loaded)
+
+  # Downloads
+  def http_download(_url, _headers, _path, _options), do: :erlang.nif_error(:nif_not_loaded)
+
+  def http_download_segmented(_url, _headers, _path, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  def http_fetch_resolved(_url, _headers, _path, _options), do: :erlang.nif_error(:nif_not_loaded)
+
+  # tus resumable uploads
+  def tus_create(_url, _upload_length, _metadata, _headers, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  def tus_offset(_upload_url, _headers, _options), do: :erlang.nif_error(:nif_not_loaded)
+
+  def tus_patch(_upload_url, _offset, _chunk, _headers, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  def tus_upload_file(_upload_url, _path, _chunk_size, _headers, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  # S3 multipart uploads
+  def s3_multipart_upload(_url, _path, _credentials, _part_size, _concurrency, _upload_id, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  def s3_multipart_abort(_url, _upload_id, _credentials, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  # WebDAV
+  def webdav_propfind(_url, _depth, _props, _headers, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  def webdav_mkcol(_url, _headers, _options), do: :erlang.nif_error(:nif_not_loaded)
+
+  def webdav_copy(_url, _destination, _overwrite, _depth, _headers, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  def webdav_move(_url, _destination, _overwrite, _headers, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  # SOAP
+  def soap_call(_url, _action, _body, _header, _version, _headers, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  # Streaming
+  def stream_request(_method, _url, _headers, _body, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  def stream_read(_stream, _max_bytes), do: :erlang.nif_error(:nif_not_loaded)
+  def stream_close(_stream), do: :erlang.nif_error(:nif_not_loaded)
+
+  # CONNECT tunnels
+  def tunnel_open(_proxy, _target_host, _port, _headers, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  def tunnel_read(_tunnel, _max_bytes, _timeout_ms), do: :erlang.nif_error(:nif_not_loaded)
+  def tunnel_write(_tunnel, _data), do: :erlang.nif_error(:nif_not_loaded)
+  def tunnel_close(_tunnel), do: :erlang.nif_error(:nif_not_loaded)
+
+  # JSON-RPC
+  def jsonrpc_call(_url, _method, _params, _headers, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  def jsonrpc_batch(_url, _calls, _headers, _options), do: :erlang.nif_error(:nif_not_loaded)
+
+  # gRPC
+  def grpc_unary(_url, _service_method, _message, _metadata, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  # robots.txt
+  def robots_fetch(_url, _user_agent, _options), do: :erlang.nif_error(:nif_not_loaded)
+  def robots_allowed(_rules, _url), do: :erlang.nif_error(:nif_not_loaded)
+
+  # Body resources
+  def body_size(_body), do: :erlang.nif_error(:nif_not_loaded)
+  def body_slice(_body, _offset, _length), do: :erlang.nif_error(:nif_not_loaded)
+  def body_to_file(_body, _path), do: :erlang.nif_error(:nif_not_loaded)
+
+  # Link headers
+  def parse_link_header(_value), do: :erlang.nif_error(:nif_not_loaded)
+
+  # Multipart bodies
+  def parse_multipart(_content_type, _body), do: :erlang.nif_error(:nif_not_loaded)
+
+  # Configuration
+  def configure_defaults(_options), do: :erlang.nif_error(:nif_not_loaded)
+  def reconfigure(_target, _delta), do: :erlang.nif_error(:nif_not_loaded)
+  def validate_options(_options), do: :erlang.nif_error(:nif_not_loaded)
+  def profile_define(_name, _options), do: :erlang.nif_error(:nif_not_loaded)
+  def get_defaults(), do: :erlang.nif_error(:nif_not_loaded)
+
+  # Cookie jars
+  def cookie_jar_new(_policy), do: :erlang.nif_error(:nif_not_loaded)
+  def cookie_jar_import_netscape(_jar, _path, _key), do: :erlang.nif_error(:nif_not_loaded)
+  def cookie_jar_export_netscape(_jar, _path, _key), do: :erlang.nif_error(:nif_not_loaded)
+  def cookie_jar_cookies(_jar), do: :erlang.nif_error(:nif_not_loaded)
+
+  # Middleware
+  def middleware_reply(_id, _decision), do: :erlang.nif_error(:nif_not_loaded)
+
+  # Introspection
+  def info(), do: :erlang.nif_error(:nif_not_loaded)
+
+  # Metrics
+  def host_stats(), do: :erlang.nif_error(:nif_not_loaded)
+  def host_stats_reset(), do: :erlang.nif_error(:nif_not_loaded)
+  def latency_histogram(_host), do: :erlang.nif_error(:nif_not_loaded)
+
+  # DNS
+  def dns_prefetch(_hosts, _ttl_ms), do: :erlang.nif_error(:nif_not_loaded)
+
+  # Blocking pool
+  def blocking_pool_configure(_max_workers, _max_queue), do: :erlang.nif_error(:nif_not_loaded)
+
+  # Global in-flight limit
+  def inflight_configure(_global_max_inflight, _when_full), do: :erlang.nif_error(:nif_not_loaded)
+  def drain(_timeout_ms), do: :erlang.nif_error(:nif_not_loaded)
+  def resume, do: :erlang.nif_error(:nif_not_loaded)
+
+  # Proxy pools
+  def proxy_pool_status(_proxies), do: :erlang.nif_error(:nif_not_loaded)
+
+  # Events
+  def event_sink(_pid), do: :erlang.nif_error(:nif_not_loaded)
+
+  # Audit log
+  def audit_enable(_capacity, _path), do: :erlang.nif_error(:nif_not_loaded)
+  def audit_disable(), do: :erlang.nif_error(:nif_not_loaded)
+  def audit_dump(), do: :erlang.nif_error(:nif_not_loaded)
+  def audit_dropped(), do: :erlang.nif_error(:nif_not_loaded)
 end
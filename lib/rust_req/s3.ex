@@ -0,0 +1,89 @@
+defmodule RustReq.S3 do
+  @moduledoc """
+  S3 multipart uploads driven from Rust.
+
+  Requests are signed with AWS Signature Version 4, so any S3-compatible service
+  (AWS, MinIO, R2, ...) can be used by passing the object URL, e.g.
+  `"https://my-bucket.s3.eu-west-1.amazonaws.com/backups/db.tar.gz"`.
+
+  ## Credentials
+  A map with:
+  - `access_key_id`
+  - `secret_access_key`
+  - `session_token`: for temporary credentials (default: nil)
+  - `region`: e.g. `"eu-west-1"`
+
+  ## Examples
+
+      credentials = %{access_key_id: "AKIA...", secret_access_key: "...", region: "eu-west-1"}
+
+      case RustReq.S3.multipart_upload(url, "/var/backups/db.tar.gz", credentials, concurrency: 8) do
+        {:ok, %{etag: etag}} ->
+          {:ok, etag}
+
+        {:error, {:upload_failed, upload_id, _part, _reason}} ->
+          # Resume later with `upload_id: upload_id`, or give up:
+          RustReq.S3.multipart_abort(url, upload_id, credentials)
+      end
+  """
+
+  alias RustReq.{Native, Options}
+
+  @default_part_size 8 * 1024 * 1024
+
+  @doc """
+  Uploads a file to `url` with a multipart upload.
+
+  The file is split into parts that are read from disk and uploaded in parallel,
+  each attempted up to three times when it fails on a network error, a 5xx or a 429;
+  other rejections fail the upload at once. Part sizes are raised as needed to respect S3's
+  5 MiB minimum and 10,000 parts maximum.
+
+  ## Options
+  - `:part_size`: Bytes per part (default: 8 MiB)
+  - `:concurrency`: Parts uploaded in parallel (default: 4)
+  - `:upload_id`: Resume this upload instead of initiating a new one; parts already
+    stored with the expected size are skipped
+  - `:options`: RustReq.Options struct (default: %Options{})
+
+  ## Returns
+  - `{:ok, %{upload_id, etag, parts, resumed_parts}}` on success
+  - `{:error, {:upload_failed, upload_id, part_number, reason}}` if a part or the
+    completion failed; the upload is kept so it can be resumed or aborted
+    (`part_number` is 0 when the completion failed)
+  - `{:error, reason}` on other failures
+  """
+  @spec multipart_upload(String.t(), Path.t(), map(), keyword()) :: {:ok, map()} | {:error, term()}
+  def multipart_upload(url, path, credentials, opts \\ []) do
+    Native.s3_multipart_upload(
+      url,
+      Path.expand(path),
+      normalize_credentials(credentials),
+      Keyword.get(opts, :part_size, @default_part_size),
+      Keyword.get(opts, :concurrency, 4),
+      Keyword.get(opts, :upload_id),
+      Keyword.get(opts, :options, %Options{})
+    )
+  catch
+    :error, reason -> {:error, reason}
+  end
+
+  @doc """
+  Aborts a multipart upload, discarding its stored parts.
+  """
+  @spec multipart_abort(String.t(), String.t(), map(), Options.t()) :: :ok | {:error, term()}
+  def multipart_abort(url, upload_id, credentials, options \\ %Options{}) do
+    Native.s3_multipart_abort(url, upload_id, normalize_credentials(credentials), options)
+  catch
+    :error, reason -> {:error, reason}
+  end
+
+  defp normalize_credentials(credentials) do
+    %{
+      access_key_id: Map.fetch!(credentials, :access_key_id),
+      secret_access_key: Map.fetch!(credentials, :secret_access_key),
+      session_token: Map.get(credentials, :session_token),
+      region: Map.fetch!(credentials, :region)
+    }
+  end
+end
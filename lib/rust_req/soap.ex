@@ -0,0 +1,75 @@
+defmodule RustReq.SOAP do
+  @moduledoc """
+  Calls SOAP 1.1 and 1.2 endpoints.
+
+  The payload is wrapped in a SOAP envelope, the action is sent the way the SOAP
+  version expects (the `SOAPAction` header for 1.1, the `action` parameter of the
+  `Content-Type` for 1.2), and the response envelope is parsed in Rust.
+
+  ## Response decoding
+  The children of the response `Body` are returned as maps keyed by element local
+  name (namespace prefixes are dropped):
+  - an element without attributes or children decodes to its text
+  - otherwise it decodes to a map of its children, its attributes as `"@name"`
+    keys and, when it also has text, a `"#text"` key
+  - repeated child elements decode to a list
+
+  ## Faults
+  A `Fault` in the response body returns
+  `{:error, {:soap_fault, %{code: code, reason: reason, detail: detail}}}`, with
+  `detail` decoded as above (or `nil`) for both SOAP versions, whatever the HTTP status.
+
+  ## Examples
+
+      {:ok, %{"GetPriceResponse" => %{"Price" => price}}} =
+        RustReq.SOAP.call(
+          "https://legacy.example.com/StockService",
+          "urn:example#GetPrice",
+          ~s(<m:GetPrice xmlns:m="urn:example"><m:Item>Apples</m:Item></m:GetPrice>)
+        )
+  """
+
+  alias RustReq.{Native, Options}
+
+  @doc """
+  Sends `body` (an XML fragment) in a SOAP envelope and returns the decoded
+  response body.
+
+  ## Parameters
+  - `url`: The service endpoint
+  - `action`: The SOAP action URI
+  - `body`: XML placed inside the envelope's `Body`
+  - `opts`:
+    - `:version`: `"1.1"` or `"1.2"` (default: "1.1")
+    - `:header`: XML placed inside the envelope's `Header`, such as WS-Security
+      elements (default: no header)
+    - `:headers`: Additional HTTP request headers (default: [])
+    - `:options`: RustReq.Options struct (default: %Options{})
+
+  ## Returns
+  - `{:ok, body}` with the decoded children of the response `Body`
+  - `{:error, {:soap_fault, fault}}` when the server returns a fault
+  - `{:error, {:unexpected_status, status}}` for a non-2xx response without a SOAP envelope
+  - `{:error, reason}` on other failures
+  """
+  @spec call(String.t(), String.t(), String.t(), keyword()) :: {:ok, map()} | {:error, term()}
+  def call(url, action, body, opts \\ []) do
+    Native.soap_call(
+      url,
+      action,
+      body,
+      Keyword.get(opts, :header),
+      Keyword.get(opts, :version, "1.1"),
+      headers(opts),
+      Keyword.get(opts, :options, %Options{})
+    )
+  catch
+    :error, reason -> {:error, reason}
+  end
+
+  defp headers(opts) do
+    opts
+    |> Keyword.get(:headers, [])
+    |> Enum.map(fn {k, v} -> {to_string(k), to_string(v)} end)
+  end
+end
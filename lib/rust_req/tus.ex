@@ -0,0 +1,119 @@
+defmodule RustReq.Tus do
+  @moduledoc """
+  Client for the [tus](https://tus.io) resumable upload protocol (version 1.0.0).
+
+  Supports the core protocol (HEAD offset discovery, PATCH with `Upload-Offset`),
+  the creation extension and the checksum extension: every PATCH carries a SHA-1
+  `Upload-Checksum` of its chunk.
+
+  ## Errors
+  Besides the usual request errors, functions may return:
+  - `{:error, :offset_mismatch}` when the server's offset differs from the one sent (409)
+  - `{:error, :checksum_mismatch}` when a chunk was corrupted in transit (460)
+  - `{:error, :upload_not_found}` when the upload expired or never existed (404/410)
+  - `{:error, {:unexpected_status, status}}` for any other unexpected status
+
+  ## Examples
+
+      {:ok, upload_url} =
+        RustReq.Tus.create("https://media.example.com/files/", File.stat!(path).size,
+          metadata: [{"filename", "video.mp4"}]
+        )
+
+      # Safe to call again after an interruption: resumes from the server's offset
+      {:ok, _size} = RustReq.Tus.upload_file(upload_url, path)
+  """
+
+  alias RustReq.{Native, Options}
+
+  @default_chunk_size 5 * 1024 * 1024
+
+  @doc """
+  Creates an upload of `upload_length` bytes and returns its URL.
+
+  ## Parameters
+  - `url`: The creation endpoint
+  - `upload_length`: Total size of the upload in bytes
+  - `opts`:
+    - `:metadata`: List of `{key, value}` pairs sent as `Upload-Metadata` (default: [])
+    - `:headers`: Additional request headers (default: [])
+    - `:options`: RustReq.Options struct (default: %Options{})
+  """
+  @spec create(String.t(), non_neg_integer(), keyword()) :: {:ok, String.t()} | {:error, term()}
+  def create(url, upload_length, opts \\ []) do
+    Native.tus_create(
+      url,
+      upload_length,
+      Keyword.get(opts, :metadata, []),
+      headers(opts),
+      Keyword.get(opts, :options, %Options{})
+    )
+  catch
+    :error, reason -> {:error, reason}
+  end
+
+  @doc """
+  Returns how many bytes of the upload the server has received, and the upload
+  length if the server reports it.
+
+  Accepts the `:headers` and `:options` options of `create/3`.
+  """
+  @spec offset(String.t(), keyword()) ::
+          {:ok, {non_neg_integer(), non_neg_integer() | nil}} | {:error, term()}
+  def offset(upload_url, opts \\ []) do
+    Native.tus_offset(upload_url, headers(opts), Keyword.get(opts, :options, %Options{}))
+  catch
+    :error, reason -> {:error, reason}
+  end
+
+  @doc """
+  Sends `chunk` at `offset` and returns the new offset.
+
+  Accepts the `:headers` and `:options` options of `create/3`.
+  """
+  @spec patch(String.t(), non_neg_integer(), binary(), keyword()) ::
+          {:ok, non_neg_integer()} | {:error, term()}
+  def patch(upload_url, offset, chunk, opts \\ []) do
+    Native.tus_patch(upload_url, offset, chunk, headers(opts), Keyword.get(opts, :options, %Options{}))
+  catch
+    :error, reason -> {:error, reason}
+  end
+
+  @doc """
+  Uploads a file to an existing upload, resuming from the offset the server reports.
+
+  The file is read and sent in chunks from Rust. After a failed chunk, or one the
+  server accepts without moving its offset forward, the offset is rediscovered and
+  the upload continues from there; it gives up after three consecutive failures.
+
+  Accepts the `:headers` and `:options` options of `create/3`, plus:
+  - `:chunk_size`: Bytes sent per PATCH request, a positive integer (default: 5 MiB)
+
+  Returns the final offset, which equals the file size on success, or
+  `{:error, {:invalid_chunk_size, chunk_size}}` when `:chunk_size` is not positive.
+  """
+  @spec upload_file(String.t(), Path.t(), keyword()) :: {:ok, non_neg_integer()} | {:error, term()}
+  def upload_file(upload_url, path, opts \\ []) do
+    case Keyword.get(opts, :chunk_size, @default_chunk_size) do
+      chunk_size when is_integer(chunk_size) and chunk_size > 0 ->
+        Native.tus_upload_file(
+          upload_url,
+          Path.expand(path),
+          chunk_size,
+          headers(opts),
+          Keyword.get(opts, :options, %Options{})
+        )
+
+      chunk_size ->
+        {:error, {:invalid_chunk_size, chunk_size}}
+    end
+  catch
+    :error, reason -> {:error, reason}
+  end
+
+  defp headers(opts) do
+    opts
+    |> Keyword.get(:headers, [])
+    |> Enum.map(fn {k, v} -> {to_string(k), to_string(v)} end)
+  end
+end
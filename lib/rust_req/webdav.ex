@@ -0,0 +1,124 @@
+defmodule RustReq.WebDAV do
+  @moduledoc """
+  Helpers for [WebDAV](https://www.rfc-editor.org/rfc/rfc4918) servers: PROPFIND,
+  MKCOL, COPY and MOVE.
+
+  PROPFIND responses (207 Multi-Status) are parsed in Rust into a list of maps:
+
+      %{
+        href: "/files/report.pdf",
+        collection: false,
+        props: %{"getcontentlength" => "5120", "resourcetype" => ""}
+      }
+
+  Property keys are the local name for properties in the `DAV:` namespace and
+  Clark notation (`"{urn:example}color"`) for everything else. A property value is
+  its text, or the list of its child element names when it has any (for example
+  `"resourcetype" => ["collection"]`). Only properties the server returned with a
+  200 status are included.
+
+  ## Errors
+  Besides the usual request errors, functions return
+  `{:error, {:unexpected_status, status}}` when the server answers with an
+  unexpected status.
+
+  ## Examples
+
+      {:ok, entries} = RustReq.WebDAV.propfind("https://dav.example.com/files/", depth: 1)
+      {:ok, 201} = RustReq.WebDAV.mkcol("https://dav.example.com/files/archive/")
+      {:ok, _status} = RustReq.WebDAV.move("https://dav.example.com/files/a.txt", "/files/archive/a.txt")
+  """
+
+  alias RustReq.{Native, Options}
+
+  @doc """
+  Lists the properties of a resource and, depending on `:depth`, its members.
+
+  ## Parameters
+  - `url`: The resource URL
+  - `opts`:
+    - `:depth`: `0`, `1` or `:infinity` (default: 1)
+    - `:props`: List of `{namespace, name}` properties to request, for example
+      `[{"DAV:", "getetag"}]`; when empty all properties are requested. Names are
+      XML names without a prefix; any other name returns
+      `{:error, "XML error: invalid property name ..."}` (default: [])
+    - `:headers`: Additional request headers (default: [])
+    - `:options`: RustReq.Options struct (default: %Options{})
+  """
+  @spec propfind(String.t(), keyword()) :: {:ok, [map()]} | {:error, term()}
+  def propfind(url, opts \\ []) do
+    Native.webdav_propfind(
+      url,
+      depth(Keyword.get(opts, :depth, 1)),
+      Keyword.get(opts, :props, []),
+      headers(opts),
+      Keyword.get(opts, :options, %Options{})
+    )
+  catch
+    :error, reason -> {:error, reason}
+  end
+
+  @doc """
+  Creates a collection and returns the response status.
+
+  Accepts the `:headers` and `:options` options of `propfind/2`.
+  """
+  @spec mkcol(String.t(), keyword()) :: {:ok, integer()} | {:error, term()}
+  def mkcol(url, opts \\ []) do
+    Native.webdav_mkcol(url, headers(opts), Keyword.get(opts, :options, %Options{}))
+  catch
+    :error, reason -> {:error, reason}
+  end
+
+  @doc """
+  Copies a resource to `destination` and returns the response status: 201 when
+  the destination was created, 204 when it was overwritten.
+
+  `destination` may be relative to `url`.
+
+  Accepts the `:headers` and `:options` options of `propfind/2`, plus:
+  - `:overwrite`: Whether an existing destination may be replaced (default: true)
+  - `:depth`: `0` to copy a collection without its members, or `:infinity` (default: server default)
+  """
+  @spec copy(String.t(), String.t(), keyword()) :: {:ok, integer()} | {:error, term()}
+  def copy(url, destination, opts \\ []) do
+    Native.webdav_copy(
+      url,
+      destination,
+      Keyword.get(opts, :overwrite, true),
+      opts |> Keyword.get(:depth) |> depth(),
+      headers(opts),
+      Keyword.get(opts, :options, %Options{})
+    )
+  catch
+    :error, reason -> {:error, reason}
+  end
+
+  @doc """
+  Moves a resource to `destination` and returns the response status.
+
+  Accepts the same options as `copy/3` except `:depth`.
+  """
+  @spec move(String.t(), String.t(), keyword()) :: {:ok, integer()} | {:error, term()}
+  def move(url, destination, opts \\ []) do
+    Native.webdav_move(
+      url,
+      destination,
+      Keyword.get(opts, :overwrite, true),
+      headers(opts),
+      Keyword.get(opts, :options, %Options{})
+    )
+  catch
+    :error, reason -> {:error, reason}
+  end
+
+  defp depth(nil), do: nil
+  defp depth(:infinity), do: "infinity"
+  defp depth(depth) when depth in [0, 1], do: Integer.to_string(depth)
+
+  defp headers(opts) do
+    opts
+    |> Keyword.get(:headers, [])
+    |> Enum.map(fn {k, v} -> {to_string(k), to_string(v)} end)
+  end
+end
@@ -36,6 +36,7 @@ defmodule RustReq.MixProject do
         "native/rust_req_nif/.cargo",
         "native/rust_req_nif/src",
         "native/rust_req_nif/Cargo*",
+        "native/rust_req_nif/build.rs",
         "checksum-*.exs",
         ".formatter.exs",
         "mix.exs",
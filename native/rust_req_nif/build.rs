@@ -0,0 +1,21 @@
+use std::fs;
+
+// Expose the resolved reqwest version to the crate as `REQWEST_VERSION`
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+
+    let version = fs::read_to_string("Cargo.lock")
+        .ok()
+        .and_then(|lock| {
+            let mut lines = lock.lines();
+            lines.find(|line| *line == "name = \"reqwest\"")?;
+            lines
+                .next()?
+                .strip_prefix("version = \"")?
+                .strip_suffix('"')
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=REQWEST_VERSION={}", version);
+}
@@ -0,0 +1,176 @@
+use rustler::{Decoder, Encoder, Env, NifMap, NifResult, Term};
+
+use crate::{HttpOptions, HttpResponse, ResponseMeta};
+
+// The weight of an `accept` preference, from 0 (not acceptable) to 1. Integers are
+// accepted too, so `{"text/html", 1}` reads as expected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QValue(f64);
+
+impl<'a> Decoder<'a> for QValue {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        term.decode::<f64>()
+            .or_else(|_| term.decode::<i64>().map(|q| q as f64))
+            .map(QValue)
+    }
+}
+
+impl Encoder for QValue {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        self.0.encode(env)
+    }
+}
+
+// Which `accept` preference the response's `Content-Type` satisfies
+#[derive(Debug, NifMap)]
+pub struct Variant {
+    // The served media type, lowercased and without parameters; None without a
+    // `Content-Type`
+    media_type: Option<String>,
+    // The most specific media range it matches and that range's weight; None when it
+    // matches none
+    range: Option<String>,
+    q: Option<f64>,
+}
+
+// `text/html;q=0.5`, with qvalues given to at most three decimals as RFC 9110 requires
+fn format_q(q: f64) -> String {
+    let q = format!("{:.3}", q.clamp(0.0, 1.0));
+    q.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+// The `Accept` header for a preference list, in the order given; a weight of 1 is implied
+pub fn header(preferences: &[(String, QValue)]) -> String {
+    preferences
+        .iter()
+        .map(|(range, QValue(q))| {
+            if *q >= 1.0 {
+                range.trim().to_string()
+            } else {
+                format!("{};q={}", range.trim(), format_q(*q))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// A media type or range split into its lowercased `type/subtype` and parameters
+pub fn media_type(value: &str) -> (String, Vec<(String, String)>) {
+    let mut parts = value.split(';');
+    let essence = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+    let params = parts
+        .filter_map(|param| param.split_once('='))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().trim_matches('"').to_string()))
+        .collect();
+    (essence, params)
+}
+
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$&^_.+-*".contains(c))
+}
+
+// Problems with an `accept` option, for `validate_options`
+pub fn errors(preferences: &[(String, QValue)]) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    if preferences.is_empty() {
+        errors.push("must not be empty".to_string());
+    }
+
+    for (range, QValue(q)) in preferences {
+        let (essence, _) = media_type(range);
+        let valid = match essence.split_once('/') {
+            Some(("*", subtype)) => subtype == "*",
+            Some((kind, subtype)) => is_token(kind) && is_token(subtype),
+            None => false,
+        };
+        if !valid {
+            errors.push(format!("not a media range: {}", range));
+        }
+        if !(0.0..=1.0).contains(q) {
+            errors.push(format!("weight of {} must be between 0 and 1", range));
+        }
+    }
+
+    errors
+}
+
+// How specifically `range` matches the served type: `*/*`, then `type/*`, then the full
+// type, ties going to the range with more parameters. None if it doesn't match.
+fn specificity(range: &str, served: &(String, Vec<(String, String)>)) -> Option<(u8, usize)> {
+    let (essence, params) = media_type(range);
+    let (served_essence, served_params) = served;
+
+    let level = if essence == "*/*" {
+        0
+    } else if let Some(kind) = essence.strip_suffix("/*") {
+        if served_essence.split('/').next() != Some(kind) {
+            return None;
+        }
+        1
+    } else if essence == *served_essence {
+        2
+    } else {
+        return None;
+    };
+
+    let params_match = params.iter().filter(|(name, _)| name != "q").all(|(name, value)| {
+        served_params
+            .iter()
+            .any(|(served_name, served_value)| served_name == name && served_value.eq_ignore_ascii_case(value))
+    });
+
+    params_match.then_some((level, params.len()))
+}
+
+// Match a response's `Content-Type` against the preferences. The most specific match
+// decides, the first in the list winning a tie, so a weight of 0 reports a type the
+// caller ruled out.
+pub fn negotiate(preferences: &[(String, QValue)], content_type: Option<&str>) -> Variant {
+    let Some(content_type) = content_type else {
+        return Variant {
+            media_type: None,
+            range: None,
+            q: None,
+        };
+    };
+
+    let served = media_type(content_type);
+    let best = preferences
+        .iter()
+        .rev()
+        .filter_map(|(range, q)| specificity(range, &served).map(|level| (level, range, q)))
+        .max_by_key(|(level, _, _)| *level);
+
+    Variant {
+        media_type: Some(served.0),
+        range: best.map(|(_, range, _)| range.trim().to_string()),
+        q: best.map(|(_, _, QValue(q))| *q),
+    }
+}
+
+fn preferences(options: &HttpOptions) -> Option<&[(String, QValue)]> {
+    options.accept.as_deref().filter(|preferences| !preferences.is_empty())
+}
+
+// Add an `Accept` header built from `accept`, unless the caller set one themselves
+pub fn apply(options: &HttpOptions, mut headers: Vec<(String, String)>) -> Vec<(String, String)> {
+    if let Some(preferences) = preferences(options) {
+        if !headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("accept")) {
+            headers.push(("accept".to_string(), header(preferences)));
+        }
+    }
+    headers
+}
+
+// With `accept`, report the variant served as `variant` in the response metadata
+pub fn annotate(options: &HttpOptions, mut response: HttpResponse) -> HttpResponse {
+    if let Some(preferences) = preferences(options) {
+        let variant = negotiate(preferences, response.headers.get("content-type").map(String::as_str));
+        response.meta.get_or_insert_with(ResponseMeta::default).variant = Some(variant);
+    }
+    response
+}
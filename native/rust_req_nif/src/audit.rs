@@ -0,0 +1,332 @@
+use rustler::{Atom, Encoder, Env, Error, NifMap, NifResult};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::atoms;
+
+// Query parameters whose values are redacted, matching `RustReq.Error.redact/1`
+const SECRET_PARAMS: [&str; 8] = ["token", "key", "secret", "pass", "auth", "sig", "credential", "session"];
+
+// Requests kept between start and finish; past it the oldest pending one is dropped and
+// counted, as cancelled batch entries never finish
+const MAX_PENDING: usize = 65536;
+
+#[derive(NifMap, Clone)]
+pub struct AuditEntry {
+    // When the request started
+    timestamp_us: u64,
+    method: String,
+    url: String,
+    // None when the request failed before a complete response was read
+    status: Option<u16>,
+    bytes_out: u64,
+    bytes_in: u64,
+    // The process that made the request, as printed (`<0.123.0>`); None for requests
+    // the library makes on its own
+    pid: Option<String>,
+}
+
+struct Log {
+    capacity: usize,
+    entries: VecDeque<AuditEntry>,
+    file: Option<File>,
+    // By request id, which grows with each request, so the first is the oldest
+    pending: BTreeMap<u64, AuditEntry>,
+    max_pending: usize,
+    // Pending requests dropped to stay within `max_pending`
+    dropped: u64,
+}
+
+impl Log {
+    fn new(capacity: usize, file: Option<File>, max_pending: usize) -> Log {
+        Log {
+            capacity,
+            entries: VecDeque::with_capacity(capacity.min(4096)),
+            file,
+            pending: BTreeMap::new(),
+            max_pending,
+            dropped: 0,
+        }
+    }
+
+    fn start(&mut self, id: u64, entry: AuditEntry) {
+        while self.pending.len() >= self.max_pending.max(1) {
+            self.pending.pop_first();
+            self.dropped += 1;
+        }
+        self.pending.insert(id, entry);
+    }
+
+    fn finish(&mut self, id: u64, status: Option<u16>, bytes_out: usize, bytes_in: usize) {
+        if let Some(mut entry) = self.pending.remove(&id) {
+            entry.status = status;
+            entry.bytes_out = bytes_out as u64;
+            entry.bytes_in = bytes_in as u64;
+            self.append(entry);
+        }
+    }
+
+    fn append(&mut self, entry: AuditEntry) {
+        if let Some(file) = &mut self.file {
+            let line = serde_json::json!({
+                "timestamp_us": entry.timestamp_us,
+                "method": entry.method,
+                "url": entry.url,
+                "status": entry.status,
+                "bytes_out": entry.bytes_out,
+                "bytes_in": entry.bytes_in,
+                "pid": entry.pid,
+            });
+            // Written line by line, so a crash loses at most the entry being written
+            let _ = writeln!(file, "{}", line).and_then(|_| file.flush());
+        }
+
+        if self.capacity > 0 {
+            if self.entries.len() == self.capacity {
+                self.entries.pop_front();
+            }
+            self.entries.push_back(entry);
+        }
+    }
+}
+
+// None while auditing is off
+static LOG: Mutex<Option<Log>> = Mutex::new(None);
+
+fn now_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+fn redact(url: &str) -> String {
+    let Ok(mut parsed) = reqwest::Url::parse(url) else {
+        return url.to_string();
+    };
+
+    if !parsed.username().is_empty() || parsed.password().is_some() {
+        let _ = parsed.set_password(None);
+        let _ = parsed.set_username("REDACTED");
+    }
+
+    if let Some(query) = parsed.query() {
+        let query = query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((name, _)) if SECRET_PARAMS.iter().any(|secret| name.to_lowercase().contains(secret)) => {
+                    format!("{}=REDACTED", name)
+                }
+                _ => pair.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+        parsed.set_query(Some(&query));
+    }
+
+    parsed.to_string()
+}
+
+// The calling process as audit entries record it, None while auditing is off
+pub fn caller(env: Env) -> Option<String> {
+    LOG.lock()
+        .unwrap()
+        .as_ref()
+        .map(|_| format!("{:?}", env.pid().encode(env)))
+}
+
+// A request announced by `events::enqueue`, recorded once `finish` reports its outcome
+pub fn start(id: u64, method: &str, url: &str, caller: Option<String>) {
+    let mut log = LOG.lock().unwrap();
+    let Some(log) = log.as_mut() else {
+        return;
+    };
+
+    log.start(id, AuditEntry {
+        timestamp_us: now_us(),
+        method: method.to_string(),
+        url: redact(url),
+        status: None,
+        bytes_out: 0,
+        bytes_in: 0,
+        pid: caller,
+    });
+}
+
+pub fn finish(id: u64, status: Option<u16>, bytes_out: usize, bytes_in: usize) {
+    let mut log = LOG.lock().unwrap();
+    let Some(log) = log.as_mut() else {
+        return;
+    };
+
+    log.finish(id, status, bytes_out, bytes_in);
+}
+
+// Record a request made outside the `events` lifecycle, once its outcome is known
+pub fn record(
+    caller: Option<&str>,
+    method: &str,
+    url: &str,
+    started: SystemTime,
+    status: Option<u16>,
+    bytes_out: usize,
+    bytes_in: usize,
+) {
+    let mut log = LOG.lock().unwrap();
+    let Some(log) = log.as_mut() else {
+        return;
+    };
+
+    log.append(AuditEntry {
+        timestamp_us: started.duration_since(UNIX_EPOCH).map(|at| at.as_micros() as u64).unwrap_or(0),
+        method: method.to_string(),
+        url: redact(url),
+        status,
+        bytes_out: bytes_out as u64,
+        bytes_in: bytes_in as u64,
+        pid: caller.map(str::to_string),
+    });
+}
+
+fn body_len(body: Option<&[u8]>) -> usize {
+    body.map_or(0, <[u8]>::len)
+}
+
+// Execute a blocking request outside the `events` lifecycle, recording it once the
+// response head arrives; the bytes received are those its `Content-Length` announces
+pub fn execute(
+    caller: Option<&str>,
+    client: &reqwest::blocking::Client,
+    request: reqwest::blocking::Request,
+) -> reqwest::Result<reqwest::blocking::Response> {
+    let started = SystemTime::now();
+    let method = request.method().to_string();
+    let url = request.url().to_string();
+    let bytes_out = body_len(request.body().and_then(|body| body.as_bytes()));
+
+    let result = client.execute(request);
+    let response = result.as_ref().ok();
+    let bytes_in = response.and_then(|response| response.content_length()).unwrap_or(0) as usize;
+    record(caller, &method, &url, started, response.map(|r| r.status().as_u16()), bytes_out, bytes_in);
+    result
+}
+
+pub fn send(
+    caller: Option<&str>,
+    request: reqwest::blocking::RequestBuilder,
+) -> reqwest::Result<reqwest::blocking::Response> {
+    let (client, request) = request.build_split();
+    execute(caller, &client, request?)
+}
+
+// `send` for the async client
+#[cfg(feature = "http2")]
+pub async fn send_async(caller: Option<&str>, request: reqwest::RequestBuilder) -> reqwest::Result<reqwest::Response> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    let started = SystemTime::now();
+    let method = request.method().to_string();
+    let url = request.url().to_string();
+    let bytes_out = body_len(request.body().and_then(|body| body.as_bytes()));
+
+    let result = client.execute(request).await;
+    let response = result.as_ref().ok();
+    let bytes_in = response.and_then(|response| response.content_length()).unwrap_or(0) as usize;
+    record(caller, &method, &url, started, response.map(|r| r.status().as_u16()), bytes_out, bytes_in);
+    result
+}
+
+// Start auditing every outbound request, keeping the latest `capacity` entries in memory
+// and, with a `path`, appending each as a JSON line to that file. Replaces any previous log.
+#[rustler::nif(schedule = "DirtyIo")]
+fn audit_enable(capacity: usize, path: Option<String>) -> NifResult<Atom> {
+    let file = match &path {
+        Some(path) => Some(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| Error::Term(Box::new(format!("cannot open {}: {}", path, e))))?,
+        ),
+        None => None,
+    };
+
+    *LOG.lock().unwrap() = Some(Log::new(capacity, file, MAX_PENDING));
+
+    Ok(atoms::ok())
+}
+
+#[rustler::nif]
+fn audit_disable() -> Atom {
+    *LOG.lock().unwrap() = None;
+    atoms::ok()
+}
+
+// Entries in memory, oldest first
+#[rustler::nif]
+fn audit_dump() -> Vec<AuditEntry> {
+    LOG.lock()
+        .unwrap()
+        .as_ref()
+        .map(|log| log.entries.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+// Requests dropped before they finished since auditing was enabled
+#[rustler::nif]
+fn audit_dropped() -> u64 {
+    LOG.lock().unwrap().as_ref().map_or(0, |log| log.dropped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(url: &str) -> AuditEntry {
+        AuditEntry {
+            timestamp_us: 0,
+            method: "GET".to_string(),
+            url: url.to_string(),
+            status: None,
+            bytes_out: 0,
+            bytes_in: 0,
+            pid: None,
+        }
+    }
+
+    #[test]
+    fn overflowing_pending_requests_drops_the_oldest() {
+        let mut log = Log::new(10, None, 2);
+
+        log.start(1, entry("http://one/"));
+        log.start(2, entry("http://two/"));
+        log.start(3, entry("http://three/"));
+        assert_eq!(log.dropped, 1);
+
+        log.finish(1, Some(200), 0, 0);
+        log.finish(3, Some(200), 0, 5);
+        log.finish(2, None, 0, 0);
+
+        let urls: Vec<&str> = log.entries.iter().map(|entry| entry.url.as_str()).collect();
+        assert_eq!(urls, ["http://three/", "http://two/"]);
+        assert_eq!(log.entries[0].bytes_in, 5);
+        assert!(log.pending.is_empty());
+    }
+
+    #[test]
+    fn the_ring_keeps_the_latest_entries() {
+        let mut log = Log::new(2, None, 10);
+
+        for (id, url) in [(1, "http://one/"), (2, "http://two/"), (3, "http://three/")] {
+            log.start(id, entry(url));
+            log.finish(id, Some(204), 0, 0);
+        }
+
+        let urls: Vec<&str> = log.entries.iter().map(|entry| entry.url.as_str()).collect();
+        assert_eq!(urls, ["http://two/", "http://three/"]);
+        assert_eq!(log.dropped, 0);
+    }
+}
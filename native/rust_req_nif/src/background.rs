@@ -0,0 +1,349 @@
+use rustler::{Atom, Encoder, Env, Error, LocalPid, NifResult, ResourceArc, Term};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::AbortHandle;
+
+use crate::{
+    accept, atoms, audit, idempotency, inflight, batch_entry, build_async_client, check_status, parse_method, resolve_options, send_async, with_attempts,
+    pac, politeness, proxy_pool, signatures, spnego, throttle, trailers, urls, user_agents, EncodedTerm, HttpOptions,
+};
+
+// Runtime shared by every async request: those answered by message, so the calling
+// NIF returns at once, and those the calling NIF blocks on
+pub static RUNTIME: LazyLock<tokio::runtime::Runtime> = LazyLock::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .thread_name("rust_req_async")
+        .build()
+        .expect("failed to start async runtime")
+});
+
+// A `%RustReq.Error{}` built on the Rust side, for results delivered by message
+pub struct RequestError {
+    pub reason: Box<dyn Encoder + Send>,
+    pub method: String,
+    pub url: String,
+    pub attempt: u32,
+    pub elapsed_ms: u64,
+}
+
+impl Encoder for RequestError {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        Term::map_from_arrays(
+            env,
+            &[
+                atoms::__struct__(),
+                atoms::__exception__(),
+                atoms::reason(),
+                atoms::method(),
+                atoms::url(),
+                atoms::attempt(),
+                atoms::elapsed_ms(),
+            ],
+            &[
+                atoms::error_module().encode(env),
+                true.encode(env),
+                self.reason.encode(env),
+                self.method.encode(env),
+                self.url.encode(env),
+                self.attempt.encode(env),
+                self.elapsed_ms.encode(env),
+            ],
+        )
+        .expect("keys are unique")
+    }
+}
+
+// Send a request in the background and answer the caller with
+// `{:rust_req_response, tag, result}`. `redacted_url` is the URL as errors report it.
+#[allow(clippy::too_many_arguments)]
+#[rustler::nif]
+fn request_async(
+    env: Env,
+    method: String,
+    url: String,
+    redacted_url: String,
+    tag: EncodedTerm,
+    headers: Vec<(String, String)>,
+    body: String,
+    options: HttpOptions,
+) -> NifResult<Atom> {
+    spawn_request(env, env.pid(), method, url, redacted_url, tag, headers, body, options)?;
+    Ok(atoms::ok())
+}
+
+// `request_async` answering `pid` instead, tagged with a reference made for the request,
+// which is returned as `{:ok, ref}`
+#[allow(clippy::too_many_arguments)]
+#[rustler::nif]
+fn request_send<'a>(
+    env: Env<'a>,
+    pid: LocalPid,
+    method: String,
+    url: String,
+    redacted_url: String,
+    headers: Vec<(String, String)>,
+    body: String,
+    options: HttpOptions,
+) -> NifResult<Term<'a>> {
+    let reference = env.make_ref().encode(env);
+    spawn_request(env, pid, method, url, redacted_url, reference.decode()?, headers, body, options)?;
+    Ok((atoms::ok(), reference).encode(env))
+}
+
+// Start a request whose result goes to `pid` as `{:rust_req_response, tag, result}`
+#[allow(clippy::too_many_arguments)]
+fn spawn_request(
+    env: Env,
+    pid: LocalPid,
+    method: String,
+    url: String,
+    redacted_url: String,
+    tag: EncodedTerm,
+    headers: Vec<(String, String)>,
+    body: String,
+    options: HttpOptions,
+) -> NifResult<()> {
+    let method = parse_method(&method)?;
+    let mut options = resolve_options(options)?;
+    let (url, headers) = urls::apply(&options, url, headers).map_err(urls::error)?;
+    let proxy = proxy_pool::select(&mut options, &url).map_err(|reason| Error::Term(Box::new(reason)))?;
+    let headers = user_agents::apply(&options, &url, headers);
+    let headers = accept::apply(&options, headers);
+    let (headers, idempotency_key) = idempotency::apply(&options, method.as_str(), headers);
+    let headers = trailers::apply(&options, headers);
+    let caller = audit::caller(env);
+
+    RUNTIME.spawn(async move {
+        let started = Instant::now();
+
+        // Choosing a proxy with `proxy_pac` may fetch the script, Negotiate tokens come
+        // from the KDC and building the client may read certificates, so none of it
+        // holds up the caller's scheduler
+        let method_name = method.to_string();
+        let prepared = tokio::task::spawn_blocking(move || {
+            pac::select(&mut options, &url).map_err(|e| format!("PAC error: {}", e))?;
+            let headers = spnego::apply(&options, &url, headers)?;
+            let headers = signatures::apply(&options, &method_name, &url, headers, body.as_bytes())?;
+            let client = build_async_client(&options).map_err(|e| format!("Client error: {}", e))?;
+            Ok((options, url, headers, body, client))
+        })
+        .await
+        .unwrap_or_else(|e| Err(format!("Request error: {}", e)));
+
+        let result = match prepared {
+            Err(reason) => Err(Box::new(reason) as Box<dyn Encoder + Send>),
+            Ok((options, url, headers, body, client)) => {
+                politeness::wait_async(&options, &url).await;
+
+                let mut request = client.request(method.clone(), &url);
+
+                for (key, value) in headers {
+                    request = request.header(key, value);
+                }
+
+                let bytes_out = body.len();
+                if !body.is_empty() || trailers::trailers(&options).is_some() {
+                    request = request.body(throttle::upload_body(&options, trailers::body(&options, body.into_bytes())));
+                }
+
+                let sent = inflight::abortable(send_async(request, method.as_str(), &url, bytes_out, &options, None, caller)).await;
+                match sent.unwrap_or_else(|| Err((Box::new(atoms::cancelled()), false))) {
+                    Ok(response) => {
+                        proxy_pool::report_response(&options, proxy.as_deref(), response.status, &response.headers);
+                        check_status(&options, idempotency::annotate(idempotency_key, response))
+                            .map_err(|e| Box::new(e) as Box<dyn Encoder + Send>)
+                    }
+                    Err((reason, _)) => {
+                        proxy_pool::report_failure(&options, proxy.as_deref());
+                        Err(reason)
+                    }
+                }
+            }
+        };
+
+        let result = result.map_err(|reason| RequestError {
+            reason,
+            method: method.to_string(),
+            url: redacted_url,
+            attempt: 1,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        });
+
+        crate::messages::send(pid, move |env| {
+            let result = match result {
+                Ok(response) => response.encode(env),
+                Err(error) => (atoms::error(), error).encode(env),
+            };
+            (atoms::rust_req_response(), tag, result).encode(env)
+        });
+    });
+
+    Ok(())
+}
+
+// A batch running in the background, kept to cancel its unfinished entries
+pub struct BatchHandle {
+    tasks: Mutex<Vec<AbortHandle>>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for BatchHandle {}
+
+// Completion counts of a background batch, reported as
+// `{:batch_progress, handle, completed, total, errors}`
+struct Progress {
+    pid: LocalPid,
+    handle: ResourceArc<BatchHandle>,
+    total: usize,
+    completed: AtomicUsize,
+    errors: AtomicUsize,
+}
+
+impl Progress {
+    // Count a finished entry and report every `every` completions
+    fn finish(&self, failed: bool, every: Option<usize>) {
+        if failed {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        let completed = self.completed.fetch_add(1, Ordering::Relaxed) + 1;
+
+        if every.is_some_and(|every| completed.is_multiple_of(every)) {
+            self.report();
+        }
+    }
+
+    fn report(&self) {
+        let handle = self.handle.clone();
+        let completed = self.completed.load(Ordering::Relaxed);
+        let errors = self.errors.load(Ordering::Relaxed);
+        let total = self.total;
+
+        crate::messages::send(self.pid, move |env| {
+            (atoms::batch_progress(), handle, completed, total, errors).encode(env)
+        });
+    }
+}
+
+// Start a `get_batch` in the background and return its handle. Once every entry is
+// done or cancelled the caller receives `{:rust_req_batch, handle, results}`, results in
+// the order of `urls`; `redacted_urls` are the URLs as errors report them. With
+// `batch_progress_interval_ms` or `batch_progress_every` set, progress messages are
+// sent on the way, and once more before the results.
+#[rustler::nif]
+fn http_get_batch_async(
+    env: Env,
+    urls: Vec<String>,
+    redacted_urls: Vec<String>,
+    headers: Vec<(String, String)>,
+    options: HttpOptions,
+) -> NifResult<(Atom, ResourceArc<BatchHandle>)> {
+    let options = resolve_options(options)?;
+    let client = build_async_client(&options)
+        .map_err(|e| Error::Term(Box::new(format!("Client error: {}", e))))?;
+    let pid = env.pid();
+    let caller = audit::caller(env);
+
+    let started = Instant::now();
+    let deadline = options
+        .batch_deadline_ms
+        .map(|ms| tokio::time::Instant::now() + Duration::from_millis(ms));
+
+    let handle = ResourceArc::new(BatchHandle {
+        tasks: Mutex::new(Vec::new()),
+    });
+    let reply_handle = handle.clone();
+
+    let progress = Arc::new(Progress {
+        pid,
+        handle: handle.clone(),
+        total: urls.len(),
+        completed: AtomicUsize::new(0),
+        errors: AtomicUsize::new(0),
+    });
+    let every = options.batch_progress_every;
+    let reports = every.is_some() || options.batch_progress_interval_ms.is_some();
+
+    let tasks: Vec<_> = urls
+        .into_iter()
+        .map(|url| {
+            let entry = batch_entry(client.clone(), url, headers.clone(), options.clone(), deadline, caller.clone());
+            let progress = progress.clone();
+            RUNTIME.spawn(async move {
+                let outcome = entry.await;
+                progress.finish(outcome.0.is_err(), every);
+                (outcome, started.elapsed())
+            })
+        })
+        .collect();
+
+    handle.tasks.lock().unwrap().extend(tasks.iter().map(|task| task.abort_handle()));
+
+    let ticker = options.batch_progress_interval_ms.map(|ms| {
+        let progress = progress.clone();
+        RUNTIME.spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_millis(ms));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                progress.report();
+            }
+        })
+    });
+
+    RUNTIME.spawn(async move {
+        let mut results = Vec::with_capacity(tasks.len());
+
+        for (task, url) in tasks.into_iter().zip(redacted_urls) {
+            let error = |reason: Box<dyn Encoder + Send>, attempt, elapsed: Duration| RequestError {
+                reason,
+                method: "GET".to_string(),
+                url,
+                attempt,
+                elapsed_ms: elapsed.as_millis() as u64,
+            };
+
+            results.push(match task.await {
+                Ok(((Ok(response), attempts), _)) => Ok(with_attempts(&options, response, attempts)),
+                Ok(((Err(reason), attempts), elapsed)) => Err(error(reason, attempts, elapsed)),
+                Err(e) if e.is_cancelled() => Err(error(Box::new(atoms::cancelled()), 1, started.elapsed())),
+                Err(e) => Err(error(Box::new(format!("Task error: {}", e)), 1, started.elapsed())),
+            });
+        }
+
+        if let Some(ticker) = ticker {
+            ticker.abort();
+        }
+
+        if reports {
+            // Cancelled entries never finished: count them now, as errors
+            let failed = results.iter().filter(|result| result.is_err()).count();
+            progress.completed.store(results.len(), Ordering::Relaxed);
+            progress.errors.store(failed, Ordering::Relaxed);
+            progress.report();
+        }
+
+        crate::messages::send(pid, move |env| {
+            let results: Vec<Term> = results
+                .into_iter()
+                .map(|result| match result {
+                    Ok(response) => response.encode(env),
+                    Err(error) => (atoms::error(), error).encode(env),
+                })
+                .collect();
+            (atoms::rust_req_batch(), reply_handle, results).encode(env)
+        });
+    });
+
+    Ok((atoms::ok(), handle))
+}
+
+// Abort the unfinished entries of a background batch; they are reported as `:cancelled`
+#[rustler::nif]
+fn batch_cancel(handle: ResourceArc<BatchHandle>) -> Atom {
+    for task in handle.tasks.lock().unwrap().iter() {
+        task.abort();
+    }
+    atoms::ok()
+}
@@ -0,0 +1,90 @@
+use rustler::{Atom, Error, NifMap, NifResult};
+use std::sync::{Condvar, Mutex};
+
+use crate::atoms;
+
+// Admission control for the synchronous API, whose requests each hold a thread until
+// they complete
+struct Pool {
+    // Requests allowed to run at once; None for no limit
+    max_workers: Option<usize>,
+    // Requests allowed to wait for a worker; past it new ones are `:overloaded`
+    max_queue: usize,
+    active: usize,
+    waiting: usize,
+}
+
+static POOL: Mutex<Pool> = Mutex::new(Pool {
+    max_workers: None,
+    max_queue: 0,
+    active: 0,
+    waiting: 0,
+});
+
+// Signalled whenever a worker frees up or the limits change
+static FREED: Condvar = Condvar::new();
+
+#[derive(NifMap)]
+pub struct Stats {
+    max_workers: Option<usize>,
+    max_queue: usize,
+    active: usize,
+    waiting: usize,
+}
+
+// A worker slot, given back when dropped
+pub struct Permit(());
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        POOL.lock().unwrap().active -= 1;
+        FREED.notify_one();
+    }
+}
+
+// Wait for a worker slot, or fail with `:overloaded` when the queue is full
+pub fn acquire() -> NifResult<Permit> {
+    let mut pool = POOL.lock().unwrap();
+
+    if pool.max_workers.is_some_and(|max| pool.active >= max) {
+        if pool.waiting >= pool.max_queue {
+            return Err(Error::Term(Box::new(atoms::overloaded())));
+        }
+
+        pool.waiting += 1;
+        pool = FREED
+            .wait_while(pool, |pool| pool.max_workers.is_some_and(|max| pool.active >= max))
+            .unwrap();
+        pool.waiting -= 1;
+    }
+
+    pool.active += 1;
+    Ok(Permit(()))
+}
+
+pub fn stats() -> Stats {
+    let pool = POOL.lock().unwrap();
+    Stats {
+        max_workers: pool.max_workers,
+        max_queue: pool.max_queue,
+        active: pool.active,
+        waiting: pool.waiting,
+    }
+}
+
+// Limit how many synchronous requests run at once and how many may wait for a worker.
+// Requests in flight are unaffected; waiting ones are admitted if the limit grows.
+#[rustler::nif]
+fn blocking_pool_configure(max_workers: Option<usize>, max_queue: usize) -> NifResult<Atom> {
+    if max_workers == Some(0) {
+        return Err(Error::Term(Box::new("max_workers must be greater than 0")));
+    }
+
+    let mut pool = POOL.lock().unwrap();
+    pool.max_workers = max_workers;
+    pool.max_queue = max_queue;
+    drop(pool);
+
+    FREED.notify_all();
+    Ok(atoms::ok())
+}
@@ -0,0 +1,177 @@
+use rustler::types::binary::NewBinary;
+use rustler::{Atom, Binary, Encoder, Env, Error, NifResult, NifUnitEnum, ResourceArc, Term};
+use std::fmt;
+use std::io::Write;
+
+use crate::multipart::{self, Part};
+use crate::{accept, atoms, buffers, charset, json, HttpOptions};
+
+// A response body kept in Rust memory, handed to Elixir as a reference
+pub struct StoredBody {
+    data: Vec<u8>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for StoredBody {}
+
+// Bodies from this size are handed to Elixir as binaries pointing into Rust memory,
+// so they cost no copy while the result is encoded on a scheduler thread; smaller
+// binaries live on the process heap anyway
+const SHARED_BYTES: usize = 64 * 1024;
+
+// How `body_format` returns a body read in full
+#[derive(Clone, Copy, Debug, PartialEq, NifUnitEnum)]
+pub enum BodyFormat {
+    // The bytes as received
+    Binary,
+    // Text decoded as `content_type` says
+    Text,
+}
+
+// The body of a response read in full: a binary, shared when large, with
+// `body_resource` the bytes left in Rust memory, with `multipart` the parts of a
+// multipart body, or with `decode_json` a JSON body decoded next to its bytes
+pub enum ResponseBody {
+    Bytes(Vec<u8>),
+    Shared(ResourceArc<StoredBody>),
+    Resource(ResourceArc<StoredBody>),
+    Parts(Vec<Part>),
+    Json(Box<serde_json::Value>, Vec<u8>),
+}
+
+// Whether `content_type` is `application/json` or a `+json` type
+fn is_json(content_type: &str) -> bool {
+    let (essence, _) = accept::media_type(content_type);
+    essence == "application/json" || essence.ends_with("+json")
+}
+
+impl ResponseBody {
+    // Keep `bytes` as they are, decoded to text with `body_format: :text`, as a resource
+    // with `body_resource`, or split into parts with `multipart`. Bytes not kept go back
+    // to the buffer pool.
+    pub fn new(options: &HttpOptions, content_type: Option<&str>, bytes: Vec<u8>) -> ResponseBody {
+        if options.body_resource == Some(true) {
+            return ResponseBody::Resource(ResourceArc::new(StoredBody { data: bytes }));
+        }
+
+        let parts = match (options.multipart, content_type) {
+            (Some(true), Some(content_type)) => multipart::parse(content_type, &bytes),
+            _ => None,
+        };
+        if let Some(parts) = parts {
+            buffers::give(bytes);
+            return ResponseBody::Parts(parts);
+        }
+
+        if options.decode_json == Some(true) && content_type.is_some_and(is_json) {
+            if let Ok(value) = serde_json::from_slice(&bytes) {
+                return ResponseBody::Json(Box::new(value), bytes);
+            }
+        }
+
+        if options.body_format == Some(BodyFormat::Text) {
+            let text = charset::decode(content_type, &bytes);
+            buffers::give(bytes);
+            return ResponseBody::binary(text.into_bytes());
+        }
+
+        if bytes.len() >= SHARED_BYTES {
+            ResponseBody::binary(bytes)
+        } else {
+            let body = ResponseBody::Bytes(bytes.to_vec());
+            buffers::give(bytes);
+            body
+        }
+    }
+
+    // A binary, shared once it is large
+    fn binary(data: Vec<u8>) -> ResponseBody {
+        if data.len() >= SHARED_BYTES {
+            ResponseBody::Shared(ResourceArc::new(StoredBody { data }))
+        } else {
+            ResponseBody::Bytes(data)
+        }
+    }
+
+    // The body as text; empty when it is not UTF-8, kept as a resource or split into
+    // parts
+    pub fn text(&self) -> &str {
+        match self {
+            ResponseBody::Bytes(data) => std::str::from_utf8(data).unwrap_or(""),
+            ResponseBody::Shared(body) => std::str::from_utf8(&body.data).unwrap_or(""),
+            ResponseBody::Json(_, data) => std::str::from_utf8(data).unwrap_or(""),
+            ResponseBody::Resource(_) | ResponseBody::Parts(_) => "",
+        }
+    }
+
+    // The bytes of the body as received; None when it is split into parts
+    pub fn bytes(&self) -> Option<&[u8]> {
+        match self {
+            ResponseBody::Bytes(data) | ResponseBody::Json(_, data) => Some(data),
+            ResponseBody::Shared(body) | ResponseBody::Resource(body) => Some(&body.data),
+            ResponseBody::Parts(_) => None,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            ResponseBody::Bytes(data) | ResponseBody::Json(_, data) => data.len(),
+            ResponseBody::Shared(body) | ResponseBody::Resource(body) => body.data.len(),
+            ResponseBody::Parts(parts) => parts.iter().map(|part| part.body.len()).sum(),
+        }
+    }
+}
+
+impl fmt::Debug for ResponseBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResponseBody::Bytes(data) => f.debug_tuple("Bytes").field(&String::from_utf8_lossy(data)).finish(),
+            ResponseBody::Shared(body) => write!(f, "Shared({} bytes)", body.data.len()),
+            ResponseBody::Resource(body) => write!(f, "Resource({} bytes)", body.data.len()),
+            ResponseBody::Parts(parts) => f.debug_tuple("Parts").field(parts).finish(),
+            ResponseBody::Json(value, _) => f.debug_tuple("Json").field(value).finish(),
+        }
+    }
+}
+
+impl Encoder for ResponseBody {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            ResponseBody::Bytes(data) => {
+                let mut binary = NewBinary::new(env, data.len());
+                binary.as_mut_slice().copy_from_slice(data);
+                Term::from(binary)
+            }
+            ResponseBody::Shared(body) => body.make_binary(env, |body| &body.data).encode(env),
+            ResponseBody::Resource(body) => body.encode(env),
+            ResponseBody::Parts(parts) => parts.encode(env),
+            ResponseBody::Json(value, _) => json::to_term(env, value),
+        }
+    }
+}
+
+#[rustler::nif]
+fn body_size(body: ResourceArc<StoredBody>) -> usize {
+    body.data.len()
+}
+
+// Up to `length` bytes from `offset`, shorter at the end of the body and empty past it.
+// The binary refers to the stored bytes rather than copying them.
+#[rustler::nif]
+fn body_slice(env: Env, body: ResourceArc<StoredBody>, offset: usize, length: usize) -> Binary {
+    body.make_binary(env, |body| {
+        let start = offset.min(body.data.len());
+        let end = start.saturating_add(length).min(body.data.len());
+        &body.data[start..end]
+    })
+}
+
+// Write the whole body to `path`, replacing any file there
+#[rustler::nif(schedule = "DirtyIo")]
+fn body_to_file(body: ResourceArc<StoredBody>, path: String) -> NifResult<Atom> {
+    std::fs::File::create(&path)
+        .and_then(|mut file| file.write_all(&body.data).and_then(|_| file.flush()))
+        .map_err(|e| Error::Term(Box::new(format!("cannot write {}: {}", path, e))))?;
+
+    Ok(atoms::ok())
+}
@@ -0,0 +1,118 @@
+use rustler::{Atom, NifMap};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::{atoms, HttpOptions};
+
+#[derive(Default)]
+struct Breaker {
+    consecutive_failures: u32,
+    // When the breaker last opened; None while it is closed
+    opened_at: Option<Instant>,
+    // Set while the one request a half-open breaker lets through is out
+    probing: bool,
+}
+
+// Circuit breakers of a client, by host. After `circuit_breaker_threshold` failures in
+// a row, a host's breaker opens and requests to it fail with `:circuit_open` for
+// `circuit_breaker_cooldown_ms`. It is then half-open: one request goes through,
+// closing it again if it succeeds and reopening it if it fails.
+#[derive(Default)]
+pub struct Breakers {
+    hosts: Mutex<HashMap<String, Breaker>>,
+}
+
+fn cooldown(options: &HttpOptions) -> Duration {
+    Duration::from_millis(options.circuit_breaker_cooldown_ms.unwrap_or(30000))
+}
+
+// The host a request to `url` counts against, when breakers are enabled
+pub fn host(options: &HttpOptions, url: &str) -> Option<String> {
+    options.circuit_breaker_threshold?;
+    reqwest::Url::parse(url).ok()?.host_str().map(str::to_ascii_lowercase)
+}
+
+impl Breakers {
+    // Let a request to `host` through, or fail with `:circuit_open`
+    pub fn admit(&self, options: &HttpOptions, host: &str) -> Result<(), Atom> {
+        let mut hosts = self.hosts.lock().unwrap();
+        let Some(breaker) = hosts.get_mut(host) else {
+            return Ok(());
+        };
+
+        match breaker.opened_at {
+            None => Ok(()),
+            Some(opened_at) if opened_at.elapsed() < cooldown(options) || breaker.probing => {
+                Err(atoms::circuit_open())
+            }
+            Some(_) => {
+                breaker.probing = true;
+                Ok(())
+            }
+        }
+    }
+
+    // Record how a request `admit` let through went
+    pub fn record(&self, options: &HttpOptions, host: &str, success: bool) {
+        let mut hosts = self.hosts.lock().unwrap();
+
+        if success {
+            hosts.remove(host);
+            return;
+        }
+
+        let breaker = hosts.entry(host.to_string()).or_default();
+        breaker.consecutive_failures += 1;
+        if breaker.probing || breaker.consecutive_failures >= options.circuit_breaker_threshold.unwrap_or(5) {
+            breaker.opened_at = Some(Instant::now());
+            breaker.probing = false;
+        }
+    }
+
+    // Close the breaker of `host`, or of every host
+    pub fn reset(&self, host: Option<&str>) {
+        let mut hosts = self.hosts.lock().unwrap();
+        match host {
+            Some(host) => {
+                hosts.remove(&host.to_ascii_lowercase());
+            }
+            None => hosts.clear(),
+        }
+    }
+
+    pub fn status(&self, options: &HttpOptions) -> Vec<BreakerStatus> {
+        let hosts = self.hosts.lock().unwrap();
+        let cooldown = cooldown(options);
+
+        let mut status: Vec<BreakerStatus> = hosts
+            .iter()
+            .map(|(host, breaker)| {
+                let left = breaker.opened_at.map_or(Duration::ZERO, |opened_at| cooldown.saturating_sub(opened_at.elapsed()));
+                let state = match breaker.opened_at {
+                    None => atoms::closed(),
+                    Some(_) if !left.is_zero() => atoms::open(),
+                    Some(_) => atoms::half_open(),
+                };
+                BreakerStatus {
+                    host: host.clone(),
+                    state,
+                    open_ms: left.as_millis() as u64,
+                    consecutive_failures: breaker.consecutive_failures,
+                }
+            })
+            .collect();
+        status.sort_by(|a, b| a.host.cmp(&b.host));
+        status
+    }
+}
+
+#[derive(NifMap)]
+pub struct BreakerStatus {
+    host: String,
+    // `:closed`, `:open` or `:half_open`
+    state: Atom,
+    // Time left until the breaker turns half-open, 0 unless open
+    open_ms: u64,
+    consecutive_failures: u32,
+}
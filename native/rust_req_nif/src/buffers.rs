@@ -0,0 +1,60 @@
+use rustler::NifMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// Idle buffers kept; with `MAX_CAPACITY` this bounds the pool at 16 MiB
+const MAX_BUFFERS: usize = 64;
+
+// Buffers grown past this by a large body are freed rather than pinned in the pool
+const MAX_CAPACITY: usize = 256 * 1024;
+
+// Body buffers returned after use, so reading a response fills an already grown buffer
+// instead of reallocating a fresh one as the body arrives
+static POOL: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+static REUSED: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED: AtomicU64 = AtomicU64::new(0);
+
+#[derive(NifMap)]
+pub struct Stats {
+    // Idle buffers in the pool
+    pooled: usize,
+    // Buffers handed out from the pool, and freshly allocated because it was empty
+    reused: u64,
+    allocated: u64,
+}
+
+// An empty buffer, from the pool when it has one
+pub fn take() -> Vec<u8> {
+    match POOL.lock().unwrap().pop() {
+        Some(buffer) => {
+            REUSED.fetch_add(1, Ordering::Relaxed);
+            buffer
+        }
+        None => {
+            ALLOCATED.fetch_add(1, Ordering::Relaxed);
+            Vec::new()
+        }
+    }
+}
+
+// Return a buffer from `take` once its contents are no longer needed
+pub fn give(mut buffer: Vec<u8>) {
+    if buffer.capacity() == 0 || buffer.capacity() > MAX_CAPACITY {
+        return;
+    }
+    buffer.clear();
+
+    let mut pool = POOL.lock().unwrap();
+    if pool.len() < MAX_BUFFERS {
+        pool.push(buffer);
+    }
+}
+
+pub fn stats() -> Stats {
+    Stats {
+        pooled: POOL.lock().unwrap().len(),
+        reused: REUSED.load(Ordering::Relaxed),
+        allocated: ALLOCATED.load(Ordering::Relaxed),
+    }
+}
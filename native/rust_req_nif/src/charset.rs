@@ -0,0 +1,289 @@
+// Character encoding of `text/html` bodies, determined as the WHATWG HTML standard does
+// (BOM, then Content-Type charset, then a `<meta>` prescan of the first 1024 bytes) so
+// legacy pages are converted to UTF-8 instead of coming back as mojibake. Bodies of any
+// other type, and encodings other than those below, are read as UTF-8.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Windows1252,
+}
+
+// Bytes of the document the `<meta>` prescan looks at
+const PRESCAN_LIMIT: usize = 1024;
+
+// Code points of windows-1252 bytes 0x80-0x9F; the rest match ISO-8859-1
+const WINDOWS_1252_HIGH: [u16; 32] = [
+    0x20AC, 0x0081, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021, 0x02C6, 0x2030, 0x0160, 0x2039, 0x0152, 0x008D,
+    0x017D, 0x008F, 0x0090, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014, 0x02DC, 0x2122, 0x0161, 0x203A,
+    0x0153, 0x009D, 0x017E, 0x0178,
+];
+
+// The encoding for a WHATWG label, for the encodings supported here
+fn from_label(label: &str) -> Option<Encoding> {
+    match label.trim().to_ascii_lowercase().as_str() {
+        "unicode-1-1-utf-8" | "unicode11utf8" | "unicode20utf8" | "utf-8" | "utf8" | "x-unicode20utf8" => {
+            Some(Encoding::Utf8)
+        }
+        "csunicode" | "iso-10646-ucs-2" | "ucs-2" | "unicode" | "unicodefeff" | "utf-16" | "utf-16le" => {
+            Some(Encoding::Utf16Le)
+        }
+        "unicodefffe" | "utf-16be" => Some(Encoding::Utf16Be),
+        "ansi_x3.4-1968" | "ascii" | "cp1252" | "cp819" | "csisolatin1" | "ibm819" | "iso-8859-1" | "iso-ir-100"
+        | "iso8859-1" | "iso88591" | "iso_8859-1" | "iso_8859-1:1987" | "l1" | "latin1" | "us-ascii"
+        | "windows-1252" | "x-cp1252" => Some(Encoding::Windows1252),
+        _ => None,
+    }
+}
+
+fn from_bom(body: &[u8]) -> Option<(Encoding, usize)> {
+    match body {
+        [0xEF, 0xBB, 0xBF, ..] => Some((Encoding::Utf8, 3)),
+        [0xFF, 0xFE, ..] => Some((Encoding::Utf16Le, 2)),
+        [0xFE, 0xFF, ..] => Some((Encoding::Utf16Be, 2)),
+        _ => None,
+    }
+}
+
+// The value of the `charset` parameter in a Content-Type or `<meta content>` value
+fn charset_param(value: &str) -> Option<&str> {
+    let lower = value.to_ascii_lowercase();
+    let start = lower.find("charset")? + "charset".len();
+    let rest = value[start..].trim_start().strip_prefix('=')?.trim_start();
+
+    let value = match rest.chars().next()? {
+        quote @ ('"' | '\'') => rest[1..].split(quote).next()?,
+        _ => rest.split([';', ' ', '\t']).next()?,
+    };
+    (!value.is_empty()).then_some(value)
+}
+
+// Read one attribute of a tag at `pos`, returning it lowercased with the position after it
+fn attribute(bytes: &[u8], mut pos: usize) -> Option<(String, String, usize)> {
+    let is_space = |b: u8| matches!(b, b'\t' | b'\n' | b'\x0C' | b'\r' | b' ' | b'/');
+
+    while pos < bytes.len() && is_space(bytes[pos]) {
+        pos += 1;
+    }
+    if pos >= bytes.len() || bytes[pos] == b'>' {
+        return None;
+    }
+
+    let name_start = pos;
+    while pos < bytes.len() && !matches!(bytes[pos], b'=' | b'>') && !is_space(bytes[pos]) {
+        pos += 1;
+    }
+    let name = String::from_utf8_lossy(&bytes[name_start..pos]).to_ascii_lowercase();
+
+    while pos < bytes.len() && is_space(bytes[pos]) && bytes[pos] != b'/' {
+        pos += 1;
+    }
+    if bytes.get(pos) != Some(&b'=') {
+        return Some((name, String::new(), pos));
+    }
+    pos += 1;
+    while pos < bytes.len() && is_space(bytes[pos]) && bytes[pos] != b'/' {
+        pos += 1;
+    }
+
+    let value_start;
+    let value_end;
+    match bytes.get(pos) {
+        Some(&quote @ (b'"' | b'\'')) => {
+            value_start = pos + 1;
+            pos = value_start;
+            while pos < bytes.len() && bytes[pos] != quote {
+                pos += 1;
+            }
+            value_end = pos;
+            pos += 1;
+        }
+        _ => {
+            value_start = pos;
+            while pos < bytes.len() && bytes[pos] != b'>' && !is_space(bytes[pos]) {
+                pos += 1;
+            }
+            value_end = pos;
+        }
+    }
+    let value = String::from_utf8_lossy(&bytes[value_start..value_end.min(bytes.len())]).to_ascii_lowercase();
+
+    Some((name, value, pos))
+}
+
+// The encoding declared by a `<meta charset>` or `<meta http-equiv="content-type">` tag
+fn prescan(body: &[u8]) -> Option<Encoding> {
+    let bytes = &body[..body.len().min(PRESCAN_LIMIT)];
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let rest = &bytes[pos..];
+
+        if rest.starts_with(b"<!--") {
+            pos += rest[4..].windows(3).position(|w| w == b"-->").map_or(rest.len(), |end| end + 7);
+        } else if rest.len() > 6
+            && rest[..5].eq_ignore_ascii_case(b"<meta")
+            && matches!(rest[5], b'\t' | b'\n' | b'\x0C' | b'\r' | b' ' | b'/')
+        {
+            pos += 5;
+            let mut charset = None;
+            let mut content = None;
+            let mut http_equiv = false;
+
+            while let Some((name, value, next)) = attribute(bytes, pos) {
+                pos = next;
+                match name.as_str() {
+                    "charset" if charset.is_none() => charset = Some(value),
+                    "content" if content.is_none() => content = Some(value),
+                    "http-equiv" => http_equiv = value == "content-type",
+                    _ => {}
+                }
+            }
+
+            let label = charset.or_else(|| {
+                content
+                    .filter(|_| http_equiv)
+                    .and_then(|content| charset_param(&content).map(str::to_string))
+            });
+
+            // A document that could be read as ASCII can't be UTF-16
+            match label.as_deref().and_then(from_label) {
+                Some(Encoding::Utf16Le | Encoding::Utf16Be) => return Some(Encoding::Utf8),
+                Some(encoding) => return Some(encoding),
+                None => {}
+            }
+        } else if rest.len() > 1 && rest[0] == b'<' && (rest[1] == b'/' || rest[1].is_ascii_alphabetic()) {
+            // Skip other tags with their attributes, which may contain `<meta`
+            pos += if rest[1] == b'/' { 2 } else { 1 };
+            while pos < bytes.len() && !matches!(bytes[pos], b'\t' | b'\n' | b'\x0C' | b'\r' | b' ' | b'>') {
+                pos += 1;
+            }
+            while let Some((_, _, next)) = attribute(bytes, pos) {
+                pos = next;
+            }
+            pos += 1;
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            pos += rest.iter().position(|&b| b == b'>').map_or(rest.len(), |end| end + 1);
+        } else {
+            pos += 1;
+        }
+    }
+
+    None
+}
+
+fn decode_utf16(body: &[u8], big_endian: bool) -> String {
+    let units = body.chunks(2).map(|pair| match (pair, big_endian) {
+        ([high, low], true) | ([low, high], false) => u16::from_be_bytes([*high, *low]),
+        _ => 0xFFFD,
+    });
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+fn decode_windows_1252(body: &[u8]) -> String {
+    body.iter()
+        .map(|&b| match b {
+            0x80..=0x9F => char::from_u32(WINDOWS_1252_HIGH[(b - 0x80) as usize] as u32).unwrap_or('\u{FFFD}'),
+            _ => b as char,
+        })
+        .collect()
+}
+
+// Convert a response body to UTF-8, given its Content-Type header
+pub fn decode(content_type: Option<&str>, body: &[u8]) -> String {
+    let is_html = content_type
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|media_type| media_type.trim().eq_ignore_ascii_case("text/html"));
+
+    if !is_html {
+        return String::from_utf8_lossy(body).into_owned();
+    }
+
+    let (encoding, skip) = from_bom(body)
+        .or_else(|| content_type.and_then(charset_param).and_then(from_label).map(|e| (e, 0)))
+        .or_else(|| prescan(body).map(|e| (e, 0)))
+        .unwrap_or((Encoding::Utf8, 0));
+    let body = &body[skip..];
+
+    match encoding {
+        Encoding::Utf8 => String::from_utf8_lossy(body).into_owned(),
+        Encoding::Utf16Le => decode_utf16(body, false),
+        Encoding::Utf16Be => decode_utf16(body, true),
+        Encoding::Windows1252 => decode_windows_1252(body),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding_comes_from_the_bom_then_the_header_then_meta() {
+        let cases: [(Option<&str>, &[u8], &str); 16] = [
+            // BOMs win over everything and are stripped
+            (Some("text/html; charset=windows-1252"), b"\xEF\xBB\xBFcaf\xC3\xA9", "café"),
+            (Some("text/html"), b"\xFF\xFEh\x00\xE9\x00", "hé"),
+            (Some("text/html; charset=utf-8"), b"\xFE\xFF\x00h\x00\xE9", "hé"),
+            // The Content-Type charset wins over meta
+            (Some("text/html; charset=ISO-8859-1"), b"<meta charset=utf-8>caf\xE9", "<meta charset=utf-8>café"),
+            (Some("text/html;charset=\"utf-8\""), b"<meta charset=latin1>caf\xC3\xA9", "<meta charset=latin1>café"),
+            // Unknown header labels fall through to the prescan
+            (Some("text/html; charset=x-unknown"), b"<meta charset=cp1252>\x80", "<meta charset=cp1252>€"),
+            // meta charset, and http-equiv content only with http-equiv
+            (Some("text/html"), b"<html><head><META CHARSET='windows-1252'>\x93hi\x94", "<html><head><META CHARSET='windows-1252'>\u{201C}hi\u{201D}"),
+            (
+                Some("text/html"),
+                b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=iso-8859-1\">\xE9",
+                "<meta http-equiv=\"Content-Type\" content=\"text/html; charset=iso-8859-1\">é",
+            ),
+            (Some("text/html"), b"<meta content=\"text/html; charset=iso-8859-1\">\xC3\xA9", "<meta content=\"text/html; charset=iso-8859-1\">é"),
+            // A meta label of UTF-16 means UTF-8
+            (Some("text/html"), b"<meta charset=utf-16le>\xC3\xA9", "<meta charset=utf-16le>é"),
+            // Declarations in comments and other tags' attributes don't count
+            (Some("text/html"), b"<!-- <meta charset=latin1> -->\xC3\xA9", "<!-- <meta charset=latin1> -->é"),
+            (Some("text/html"), b"<div title='<meta charset=latin1>'>\xC3\xA9", "<div title='<meta charset=latin1>'>é"),
+            // No declaration, UTF-8
+            (Some("text/html"), b"caf\xC3\xA9", "café"),
+            (None, b"caf\xC3\xA9", "café"),
+            // Other types are read as UTF-8, whatever they declare
+            (Some("text/plain; charset=latin1"), b"caf\xC3\xA9", "café"),
+            (Some("application/xhtml+xml"), b"<meta charset=latin1>\xC3\xA9", "<meta charset=latin1>é"),
+        ];
+
+        for (content_type, body, expected) in cases {
+            assert_eq!(decode(content_type, body), expected, "{:?} {:?}", content_type, body);
+        }
+    }
+
+    #[test]
+    fn meta_past_the_prescan_limit_is_ignored() {
+        let mut body = vec![b' '; PRESCAN_LIMIT];
+        body.extend_from_slice(b"<meta charset=latin1>\xC3\xA9");
+        assert!(decode(Some("text/html"), &body).ends_with('é'));
+
+        let mut body = b"<meta charset=latin1>".to_vec();
+        body.extend(vec![b' '; PRESCAN_LIMIT]);
+        body.push(0xE9);
+        assert!(decode(Some("text/html"), &body).ends_with('é'));
+    }
+
+    #[test]
+    fn charset_parameters_and_labels() {
+        assert_eq!(charset_param("text/html; charset=UTF-8"), Some("UTF-8"));
+        assert_eq!(charset_param("text/html; Charset = 'latin1' ; q=1"), Some("latin1"));
+        assert_eq!(charset_param("text/html; charset="), None);
+        assert_eq!(charset_param("text/html"), None);
+
+        assert_eq!(from_label(" Latin1 "), Some(Encoding::Windows1252));
+        assert_eq!(from_label("us-ascii"), Some(Encoding::Windows1252));
+        assert_eq!(from_label("UTF-16"), Some(Encoding::Utf16Le));
+        assert_eq!(from_label("shift_jis"), None);
+
+        // An odd trailing byte of UTF-16 becomes a replacement character
+        assert_eq!(decode(Some("text/html"), b"\xFF\xFEh\x00!"), "h\u{FFFD}");
+    }
+}
@@ -0,0 +1,113 @@
+use rustler::{Atom, Encoder, Env, Error, NifResult, ResourceArc, Term};
+use std::sync::{Arc, RwLock};
+
+use crate::breaker::{self, BreakerStatus, Breakers};
+use crate::{atoms, audit, build_client, parse_method, request, request_body, resolve_options, HttpOptions, HttpResponse, Payload};
+
+// A client built once from Elixir and kept for its connection pool, so keep-alive
+// connections and TLS sessions carry over between requests
+pub struct Client {
+    // Replaced whole by `reconfigure`; requests in flight keep the client they started on
+    current: RwLock<Configured>,
+    breakers: Breakers,
+}
+
+struct Configured {
+    options: HttpOptions,
+    client: Arc<reqwest::blocking::Client>,
+}
+
+fn client_error(e: Box<dyn std::error::Error>) -> Error {
+    Error::Term(Box::new(format!("Client error: {}", e)))
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for Client {}
+
+// Build a client from `options`, resolved against the defaults as they are now
+#[rustler::nif(schedule = "DirtyIo")]
+fn client_new(env: Env, options: HttpOptions) -> NifResult<Term> {
+    let options = resolve_options(options)?;
+    let client = build_client(&options).map_err(client_error)?;
+
+    let handle = ResourceArc::new(Client {
+        current: RwLock::new(Configured {
+            options,
+            client: Arc::new(client),
+        }),
+        breakers: Breakers::default(),
+    });
+    Ok((atoms::ok(), handle).encode(env))
+}
+
+// Apply a delta to the options of `client` and rebuild its connection pool from them.
+// Held for writing while building, so concurrent reconfigurations apply in turn.
+pub fn reconfigure(client: &Client, delta: HttpOptions) -> NifResult<()> {
+    let mut current = client.current.write().unwrap();
+    let options = delta.merge(&current.options);
+    let built = build_client(&options).map_err(client_error)?;
+
+    *current = Configured {
+        options,
+        client: Arc::new(built),
+    };
+    Ok(())
+}
+
+// Send a request on `client`. Per-call options take precedence over the client's, but
+// those the connection is built from (proxy, TLS, timeout, redirects, user agent) stay
+// as the client has them. With `circuit_breaker_threshold`, requests to a host whose
+// breaker is open fail with `:circuit_open` (see `Breakers`); failed requests and 5xx
+// responses count against it.
+#[rustler::nif(schedule = "DirtyIo")]
+fn client_request(
+    env: Env,
+    client: ResourceArc<Client>,
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Payload,
+    options: HttpOptions,
+) -> NifResult<HttpResponse> {
+    let method = parse_method(&method)?;
+    let (options, inner, built_from) = {
+        let current = client.current.read().unwrap();
+        let negotiate = current.options.proxy_negotiate_auth == Some(true);
+        (options.merge(&current.options), current.client.clone(), negotiate.then(|| current.options.clone()))
+    };
+    // The proxy's Negotiate token is minted as a client is built, so each request
+    // gets a client of its own
+    let inner = match built_from {
+        Some(built_from) => Arc::new(build_client(&built_from).map_err(client_error)?),
+        None => inner,
+    };
+    let (headers, body) = body.prepare(headers);
+    let body = request_body(&method, body, &options);
+
+    let host = breaker::host(&options, &url);
+    if let Some(host) = &host {
+        client.breakers.admit(&options, host).map_err(|reason| Error::Term(Box::new(reason)))?;
+    }
+
+    let breaker_options = host.as_ref().map(|_| options.clone());
+    let result = request(method, url, headers, body, options, audit::caller(env), Some(inner));
+
+    if let (Some(host), Some(options)) = (&host, &breaker_options) {
+        let success = result.as_ref().is_ok_and(|response| response.status < 500);
+        client.breakers.record(options, host, success);
+    }
+    result
+}
+
+// State of the client's circuit breakers, one per host that failed since its last success
+#[rustler::nif]
+fn client_breakers(client: ResourceArc<Client>) -> Vec<BreakerStatus> {
+    client.breakers.status(&client.current.read().unwrap().options)
+}
+
+// Close the client's breaker for `host`, or all of them when None
+#[rustler::nif]
+fn client_breaker_reset(client: ResourceArc<Client>, host: Option<String>) -> Atom {
+    client.breakers.reset(host.as_deref());
+    atoms::ok()
+}
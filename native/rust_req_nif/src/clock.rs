@@ -0,0 +1,41 @@
+use rustler::NifMap;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{cookies, HttpOptions, HttpResponse, ResponseMeta};
+
+// How far the server clock appears to be from ours, going by the response `Date`
+#[derive(Debug, NifMap)]
+pub struct ClockSkew {
+    // Server time minus local time; positive when the server is ahead
+    offset_ms: i64,
+    // The `Date` header as a Unix timestamp
+    server_date: u64,
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+// `Date` is truncated to the second, so the server's time is taken as the middle of
+// that second
+fn measure(headers: &HashMap<String, String>) -> Option<ClockSkew> {
+    let server_date = cookies::parse_http_date(headers.get("date")?)?;
+    Some(ClockSkew {
+        offset_ms: (server_date as i64) * 1000 + 500 - now_ms(),
+        server_date,
+    })
+}
+
+// Report the skew as `:clock_skew` in the response metadata when `clock_skew` is set
+pub fn annotate(options: &HttpOptions, mut response: HttpResponse) -> HttpResponse {
+    if options.clock_skew == Some(true) {
+        if let Some(skew) = measure(&response.headers) {
+            response.meta.get_or_insert_with(ResponseMeta::default).clock_skew = Some(skew);
+        }
+    }
+    response
+}
@@ -0,0 +1,766 @@
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, LOCATION, PROXY_AUTHORIZATION, SET_COOKIE};
+use reqwest::{Method, StatusCode, Url};
+use ring::aead;
+use ring::rand::{SecureRandom, SystemRandom};
+use rustler::{Atom, Binary, Decoder, Encoder, Env, Error, NifMap, NifResult, ResourceArc, Term};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{atoms, HttpOptions};
+
+#[derive(Clone, Debug)]
+struct Cookie {
+    name: String,
+    value: String,
+    // Lowercase, without a leading dot
+    domain: String,
+    // Sent to `domain` only, not to its subdomains
+    host_only: bool,
+    path: String,
+    secure: bool,
+    http_only: bool,
+    // Unix timestamp; None for a session cookie
+    expires: Option<u64>,
+}
+
+impl Cookie {
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    fn matches(&self, url: &Url, now: u64) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        let host = host.to_ascii_lowercase();
+
+        let domain_matches = host == self.domain
+            || (!self.host_only && host.ends_with(&format!(".{}", self.domain)));
+
+        domain_matches
+            && path_matches(url.path(), &self.path)
+            && (!self.secure || url.scheme() == "https")
+            && !self.is_expired(now)
+    }
+}
+
+// Which cookies a jar accepts
+#[derive(NifMap)]
+struct CookiePolicy {
+    // Refuse cookies from a response on another site than the one requested, e.g.
+    // after a cross-site redirect
+    block_third_party: bool,
+    // Only accept cookies for these domains and their subdomains
+    allow_domains: Option<Vec<String>>,
+    // Refuse cookies whose name matches one of these patterns, where `*` matches any run
+    // of characters
+    drop_names: Vec<String>,
+}
+
+// Cookies shared by the requests given the jar as their `cookie_jar` option
+pub struct CookieJar {
+    cookies: Mutex<Vec<Cookie>>,
+    policy: CookiePolicy,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for CookieJar {}
+
+impl CookieJar {
+    // Whether the allowlist and name patterns of the policy let `cookie` in
+    fn accepts(&self, cookie: &Cookie) -> bool {
+        let allowed = self.policy.allow_domains.as_ref().is_none_or(|domains| {
+            domains.iter().any(|domain| {
+                let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+                cookie.domain == domain || cookie.domain.ends_with(&format!(".{}", domain))
+            })
+        });
+
+        allowed && !self.policy.drop_names.iter().any(|pattern| wildcard_match(pattern, &cookie.name))
+    }
+
+    // Add or replace a cookie the policy accepts, returning whether it did. An expired
+    // cookie removes its stored namesake.
+    fn store(&self, cookie: Cookie) -> bool {
+        if !self.accepts(&cookie) {
+            return false;
+        }
+
+        let mut cookies = self.cookies.lock().unwrap();
+        cookies.retain(|c| !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path));
+
+        if !cookie.is_expired(now()) {
+            cookies.push(cookie);
+        }
+        true
+    }
+}
+
+// The `cookie_jar` option; jars compare by identity
+#[derive(Clone)]
+pub struct JarRef(ResourceArc<CookieJar>);
+
+impl PartialEq for JarRef {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(&*self.0, &*other.0)
+    }
+}
+
+impl<'a> Decoder<'a> for JarRef {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        Ok(JarRef(term.decode()?))
+    }
+}
+
+impl Encoder for JarRef {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        self.0.encode(env)
+    }
+}
+
+// Whether `text` matches `pattern`, where `*` matches any run of characters
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+// Site of a host for third-party checks: the IP address, or the last two labels of the
+// name. Without a public suffix list, hosts under suffixes like `co.uk` share a site.
+fn site(host: &str) -> String {
+    if host.parse::<std::net::IpAddr>().is_ok() || host.starts_with('[') {
+        return host.to_string();
+    }
+
+    let labels: Vec<&str> = host.split('.').collect();
+    labels[labels.len().saturating_sub(2)..].join(".").to_ascii_lowercase()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+// RFC 6265 path-match
+fn path_matches(request_path: &str, cookie_path: &str) -> bool {
+    request_path == cookie_path
+        || (request_path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/')))
+}
+
+// Path a cookie without a `Path` attribute applies to: the request path up to its last `/`
+fn default_path(url: &Url) -> String {
+    match url.path().rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(end) => url.path()[..end].to_string(),
+    }
+}
+
+// Days since the Unix epoch of a proleptic Gregorian date (Howard Hinnant's algorithm)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// Unix timestamp of a cookie `Expires` date such as `Wed, 21 Oct 2015 07:28:00 GMT`,
+// also accepting the `21-Oct-2015` form
+pub fn parse_http_date(value: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+    let (mut day, mut month, mut year, mut time) = (None, None, None, None);
+
+    for token in value.split([' ', ',', '-']).filter(|t| !t.is_empty()) {
+        if time.is_none() && token.contains(':') {
+            let parts: Vec<u64> = token.split(':').filter_map(|part| part.parse().ok()).collect();
+            if let [hours, minutes, seconds] = parts[..] {
+                time = Some(hours * 3600 + minutes * 60 + seconds);
+            }
+        } else if let Ok(number) = token.parse::<i64>() {
+            if day.is_none() && token.len() <= 2 {
+                day = Some(number);
+            } else if year.is_none() {
+                year = Some(if number < 70 { number + 2000 } else if number < 100 { number + 1900 } else { number });
+            }
+        } else if month.is_none() {
+            let prefix = token.get(..3).unwrap_or(token).to_ascii_lowercase();
+            month = MONTHS.iter().position(|m| *m == prefix).map(|index| index as i64 + 1);
+        }
+    }
+
+    let days = days_from_civil(year?, month?, day?);
+    u64::try_from(days * 86_400).ok().map(|seconds| seconds + time.unwrap_or(0))
+}
+
+// A Unix timestamp as an HTTP date, e.g. `Wed, 21 Oct 2015 07:28:00 GMT`
+pub fn format_http_date(seconds: u64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let days = (seconds / 86_400) as i64;
+    let time = seconds % 86_400;
+
+    // Inverse of `days_from_civil`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[(days % 7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+fn is_ip_address(host: &str) -> bool {
+    host.trim_start_matches('[').trim_end_matches(']').parse::<std::net::IpAddr>().is_ok()
+}
+
+// Whether a cookie may be shared by `domain` and its subdomains: not when it is an IP
+// address, a single label such as `localhost` or a public suffix such as `com` or
+// `co.uk`, which would make it a supercookie sent to every site below it
+fn shares_with_subdomains(domain: &str) -> bool {
+    !is_ip_address(domain) && domain.contains('.') && psl::suffix_str(domain) != Some(domain)
+}
+
+// A `Set-Cookie` header received from `url`, or None if it is malformed or names a
+// domain the response may not set cookies for
+fn parse_set_cookie(header: &str, url: &Url) -> Option<Cookie> {
+    let host = url.host_str()?.to_ascii_lowercase();
+    let mut attributes = header.split(';');
+
+    let (name, value) = attributes.next()?.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut cookie = Cookie {
+        name: name.to_string(),
+        value: value.trim().to_string(),
+        domain: host.clone(),
+        host_only: true,
+        path: default_path(url),
+        secure: false,
+        http_only: false,
+        expires: None,
+    };
+    let mut max_age = None;
+
+    for attribute in attributes {
+        let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+        let value = value.trim();
+
+        match key.trim().to_ascii_lowercase().as_str() {
+            "domain" if !value.is_empty() => {
+                let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                // A host that is an IP address or a public suffix may name only itself,
+                // and the cookie stays its own
+                if domain == host && !shares_with_subdomains(&domain) {
+                    continue;
+                }
+                if is_ip_address(&host) || !shares_with_subdomains(&domain) {
+                    return None;
+                }
+                if host != domain && !host.ends_with(&format!(".{}", domain)) {
+                    return None;
+                }
+                cookie.domain = domain;
+                cookie.host_only = false;
+            }
+            "path" if value.starts_with('/') => cookie.path = value.to_string(),
+            "expires" => cookie.expires = parse_http_date(value).or(cookie.expires),
+            "max-age" => max_age = value.parse::<i64>().ok().or(max_age),
+            "secure" => cookie.secure = true,
+            "httponly" => cookie.http_only = true,
+            _ => {}
+        }
+    }
+
+    // Max-Age wins over Expires; zero or less expires the cookie at once
+    if let Some(seconds) = max_age {
+        cookie.expires = Some(if seconds <= 0 { 0 } else { now() + seconds as u64 });
+    }
+
+    Some(cookie)
+}
+
+// `Cookie` header value for a request to `url` from the `cookie_jar` option, if any applies
+pub fn request_header(options: &HttpOptions, url: &str) -> Option<String> {
+    let jar = options.cookie_jar.as_ref()?;
+    let url = Url::parse(url).ok()?;
+    let now = now();
+
+    let cookies = jar.0.cookies.lock().unwrap();
+    let mut matching: Vec<&Cookie> = cookies.iter().filter(|cookie| cookie.matches(&url, now)).collect();
+    if matching.is_empty() {
+        return None;
+    }
+
+    // Longer paths first, as RFC 6265 asks
+    matching.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+    Some(
+        matching
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect::<Vec<_>>()
+            .join("; "),
+    )
+}
+
+// Store the `Set-Cookie` headers of a response from `url`, to a request made to
+// `requested`, in the `cookie_jar` option
+pub fn store_response(options: &HttpOptions, requested: &str, url: &Url, headers: &HeaderMap) {
+    let Some(jar) = &options.cookie_jar else {
+        return;
+    };
+
+    if jar.0.policy.block_third_party {
+        let requested_site = Url::parse(requested).ok().and_then(|requested| requested.host_str().map(site));
+        if requested_site != url.host_str().map(site) {
+            return;
+        }
+    }
+
+    for header in headers.get_all(SET_COOKIE) {
+        if let Some(cookie) = header.to_str().ok().and_then(|header| parse_set_cookie(header, url)) {
+            jar.0.store(cookie);
+        }
+    }
+}
+
+// With a jar, clients leave redirects to `send` and `send_async`, so the `Set-Cookie`
+// headers of every hop reach the jar and each hop carries the cookies stored before it
+pub fn follows_redirects(options: &HttpOptions) -> bool {
+    options.cookie_jar.is_some()
+}
+
+// How a request sent by `send`, `send_async` or `pipeline::send` failed
+pub enum SendError {
+    Request(reqwest::Error),
+    TooManyRedirects,
+    // The `middleware` process halted the request
+    Halted(Error),
+}
+
+impl SendError {
+    pub fn into_error(self) -> Error {
+        match self {
+            SendError::Request(e) => crate::request_error(e),
+            SendError::TooManyRedirects => Error::Term(Box::new(atoms::too_many_redirects())),
+            SendError::Halted(e) => e,
+        }
+    }
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SendError::Request(e) => e.fmt(f),
+            SendError::TooManyRedirects => f.write_str("too many redirects"),
+            SendError::Halted(_) => f.write_str("halted by the middleware"),
+        }
+    }
+}
+
+// Where a response with `status` and `headers` to `method` at `url` redirects when the
+// request follows redirects, and whether it continues there as a GET without its body,
+// as browsers do on a 303 and on a POST answered with 301 or 302
+fn redirect_target(options: &HttpOptions, method: &Method, url: &Url, status: StatusCode, headers: &HeaderMap) -> Option<(Url, bool)> {
+    if options.follow_redirects == Some(false) || !status.is_redirection() {
+        return None;
+    }
+    let next = url.join(headers.get(LOCATION)?.to_str().ok()?).ok()?;
+    let as_get = status == StatusCode::SEE_OTHER || (method == Method::POST && matches!(status.as_u16(), 301 | 302));
+    Some((next, as_get))
+}
+
+// Adjust the headers of a request redirected from `from` to `to`: credentials are only
+// sent to the origin they were given for, the body's headers go with the body, and the
+// cookies are those of the jar for `to`
+fn redirect_headers(options: &HttpOptions, headers: &mut HeaderMap, from: &Url, to: &Url, as_get: bool) {
+    headers.remove(COOKIE);
+    if from.origin() != to.origin() {
+        headers.remove(AUTHORIZATION);
+        headers.remove(PROXY_AUTHORIZATION);
+    }
+    if as_get {
+        headers.remove(CONTENT_TYPE);
+        headers.remove(CONTENT_LENGTH);
+    }
+    if let Some(cookie) = request_header(options, to.as_str()).and_then(|cookie| HeaderValue::from_str(&cookie).ok()) {
+        headers.insert(COOKIE, cookie);
+    }
+}
+
+// Send a request to `requested` through `send_retrying`, storing the cookies of each
+// response and following redirects itself when `follows_redirects`. A 307 or 308 to a
+// request whose streamed body cannot be sent again is returned as is.
+pub fn send(
+    options: &HttpOptions,
+    requested: &str,
+    request: reqwest::blocking::RequestBuilder,
+) -> Result<reqwest::blocking::Response, SendError> {
+    let (client, request) = request.build_split();
+    let mut request = request.map_err(SendError::Request)?;
+    let mut hops = 0;
+
+    loop {
+        let again = follows_redirects(options).then(|| {
+            request.try_clone().map_or_else(
+                || {
+                    let mut again = reqwest::blocking::Request::new(request.method().clone(), request.url().clone());
+                    *again.headers_mut() = request.headers().clone();
+                    *again.version_mut() = request.version();
+                    *again.timeout_mut() = request.timeout().copied();
+                    (again, request.body().is_some())
+                },
+                |again| (again, false),
+            )
+        });
+
+        let response = crate::send_retrying(reqwest::blocking::RequestBuilder::from_parts(client.clone(), request), options)
+            .map_err(SendError::Request)?;
+        store_response(options, requested, response.url(), response.headers());
+
+        let Some((mut again, body_lost)) = again else {
+            return Ok(response);
+        };
+        let target = redirect_target(options, again.method(), response.url(), response.status(), response.headers());
+        let Some((next, as_get)) = target.filter(|(_, as_get)| *as_get || !body_lost) else {
+            return Ok(response);
+        };
+        if hops == options.max_redirects.unwrap_or(10) {
+            return Err(SendError::TooManyRedirects);
+        }
+        hops += 1;
+
+        crate::events::redirect(response.url().as_str(), next.as_str(), response.status().as_u16());
+        if as_get {
+            *again.method_mut() = Method::GET;
+            *again.body_mut() = None;
+        }
+        redirect_headers(options, again.headers_mut(), response.url(), &next, as_get);
+        *again.url_mut() = next;
+        request = again;
+    }
+}
+
+// `send` for the async client
+pub async fn send_async(options: &HttpOptions, requested: &str, request: reqwest::RequestBuilder) -> Result<reqwest::Response, SendError> {
+    let (client, request) = request.build_split();
+    let mut request = request.map_err(SendError::Request)?;
+    let mut hops = 0;
+
+    loop {
+        let again = follows_redirects(options).then(|| {
+            request.try_clone().map_or_else(
+                || {
+                    let mut again = reqwest::Request::new(request.method().clone(), request.url().clone());
+                    *again.headers_mut() = request.headers().clone();
+                    *again.version_mut() = request.version();
+                    *again.timeout_mut() = request.timeout().copied();
+                    (again, request.body().is_some())
+                },
+                |again| (again, false),
+            )
+        });
+
+        let response = crate::send_retrying_async(reqwest::RequestBuilder::from_parts(client.clone(), request), options)
+            .await
+            .map_err(SendError::Request)?;
+        store_response(options, requested, response.url(), response.headers());
+
+        let Some((mut again, body_lost)) = again else {
+            return Ok(response);
+        };
+        let target = redirect_target(options, again.method(), response.url(), response.status(), response.headers());
+        let Some((next, as_get)) = target.filter(|(_, as_get)| *as_get || !body_lost) else {
+            return Ok(response);
+        };
+        if hops == options.max_redirects.unwrap_or(10) {
+            return Err(SendError::TooManyRedirects);
+        }
+        hops += 1;
+
+        crate::events::redirect(response.url().as_str(), next.as_str(), response.status().as_u16());
+        if as_get {
+            *again.method_mut() = Method::GET;
+            *again.body_mut() = None;
+        }
+        redirect_headers(options, again.headers_mut(), response.url(), &next, as_get);
+        *again.url_mut() = next;
+        request = again;
+    }
+}
+
+fn file_error(e: std::io::Error) -> Error {
+    Error::Term(Box::new(format!("File error: {}", e)))
+}
+
+// Header of an encrypted jar file, followed by the nonce and the AES-256-GCM ciphertext
+const ENCRYPTED_MAGIC: &[u8] = b"RRJAR1";
+
+fn aead_key(key: &[u8]) -> NifResult<aead::LessSafeKey> {
+    aead::UnboundKey::new(&aead::AES_256_GCM, key)
+        .map(aead::LessSafeKey::new)
+        .map_err(|_| Error::Term(Box::new(atoms::invalid_key())))
+}
+
+fn encrypt(key: &[u8], plaintext: &[u8]) -> NifResult<Vec<u8>> {
+    let key = aead_key(key)?;
+
+    let mut nonce = [0u8; aead::NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| Error::Term(Box::new("Random source error".to_string())))?;
+
+    let mut sealed = plaintext.to_vec();
+    key.seal_in_place_append_tag(aead::Nonce::assume_unique_for_key(nonce), aead::Aad::from(ENCRYPTED_MAGIC), &mut sealed)
+        .map_err(|_| Error::Term(Box::new("Encryption error".to_string())))?;
+
+    Ok([ENCRYPTED_MAGIC, &nonce, &sealed].concat())
+}
+
+// Fails with `:decryption_failed` on a wrong key or a file altered since it was written
+fn decrypt(key: &[u8], file: &[u8]) -> NifResult<Vec<u8>> {
+    let key = aead_key(key)?;
+    let failed = || Error::Term(Box::new(atoms::decryption_failed()));
+
+    let rest = file.strip_prefix(ENCRYPTED_MAGIC).ok_or_else(failed)?;
+    if rest.len() < aead::NONCE_LEN {
+        return Err(failed());
+    }
+    let (nonce, sealed) = rest.split_at(aead::NONCE_LEN);
+    let nonce = aead::Nonce::try_assume_unique_for_key(nonce).map_err(|_| failed())?;
+
+    let mut sealed = sealed.to_vec();
+    let plaintext = key
+        .open_in_place(nonce, aead::Aad::from(ENCRYPTED_MAGIC), &mut sealed)
+        .map_err(|_| failed())?;
+
+    Ok(plaintext.to_vec())
+}
+
+#[rustler::nif]
+fn cookie_jar_new(policy: CookiePolicy) -> ResourceArc<CookieJar> {
+    ResourceArc::new(CookieJar {
+        cookies: Mutex::new(Vec::new()),
+        policy,
+    })
+}
+
+// Add the cookies of a Netscape `cookies.txt` file, as written by curl, wget, yt-dlp and
+// browser extensions, and return how many were imported. Malformed lines are skipped, as
+// are cookies the jar's policy refuses. With a `key`, the file is one written encrypted
+// by `cookie_jar_export_netscape`.
+#[rustler::nif(schedule = "DirtyIo")]
+fn cookie_jar_import_netscape(jar: ResourceArc<CookieJar>, path: String, key: Option<Binary>) -> NifResult<(Atom, usize)> {
+    let file = std::fs::read(&path).map_err(file_error)?;
+    let file = match key {
+        Some(key) => decrypt(key.as_slice(), &file)?,
+        None => file,
+    };
+    let contents = String::from_utf8_lossy(&file);
+    let mut imported = 0;
+
+    for line in contents.lines() {
+        let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+            Some(rest) => (rest, true),
+            None => (line, false),
+        };
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some(cookie) = parse_netscape_line(line, http_only) else {
+            continue;
+        };
+        if jar.store(cookie) {
+            imported += 1;
+        }
+    }
+
+    Ok((atoms::ok(), imported))
+}
+
+// A cookie of a Netscape `cookies.txt` line, or None if the line is malformed or shares
+// the cookie with the subdomains of a domain that may not have them share it
+fn parse_netscape_line(line: &str, http_only: bool) -> Option<Cookie> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    let [domain, include_subdomains, path, secure, expires, name, value] = fields[..] else {
+        return None;
+    };
+    let expires = expires.trim().parse::<u64>().ok()?;
+    let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+    let host_only = !include_subdomains.eq_ignore_ascii_case("TRUE");
+    if !host_only && !shares_with_subdomains(&domain) {
+        return None;
+    }
+
+    Some(Cookie {
+        name: name.to_string(),
+        value: value.to_string(),
+        domain,
+        host_only,
+        path: path.to_string(),
+        secure: secure.eq_ignore_ascii_case("TRUE"),
+        http_only,
+        expires: (expires != 0).then_some(expires),
+    })
+}
+
+// Write the unexpired cookies of the jar as a Netscape `cookies.txt` file. Session
+// cookies get an expiry of 0, as curl writes them. With a 32-byte `key`, the file is
+// encrypted with AES-256-GCM.
+#[rustler::nif(schedule = "DirtyIo")]
+fn cookie_jar_export_netscape(jar: ResourceArc<CookieJar>, path: String, key: Option<Binary>) -> NifResult<Atom> {
+    let now = now();
+    let mut contents = String::from("# Netscape HTTP Cookie File\n\n");
+
+    for cookie in jar.cookies.lock().unwrap().iter().filter(|cookie| !cookie.is_expired(now)) {
+        let bool_field = |value: bool| if value { "TRUE" } else { "FALSE" };
+        let domain = if cookie.host_only { cookie.domain.clone() } else { format!(".{}", cookie.domain) };
+
+        contents.push_str(&format!(
+            "{}{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            if cookie.http_only { "#HttpOnly_" } else { "" },
+            domain,
+            bool_field(!cookie.host_only),
+            cookie.path,
+            bool_field(cookie.secure),
+            cookie.expires.unwrap_or(0),
+            cookie.name,
+            cookie.value,
+        ));
+    }
+
+    let file = match key {
+        Some(key) => encrypt(key.as_slice(), contents.as_bytes())?,
+        None => contents.into_bytes(),
+    };
+
+    std::fs::write(&path, file).map_err(file_error)?;
+    Ok(atoms::ok())
+}
+
+#[derive(NifMap)]
+struct CookieInfo {
+    name: String,
+    value: String,
+    domain: String,
+    path: String,
+    secure: bool,
+    http_only: bool,
+    expires: Option<u64>,
+}
+
+// The unexpired cookies of the jar
+#[rustler::nif]
+fn cookie_jar_cookies(jar: ResourceArc<CookieJar>) -> Vec<CookieInfo> {
+    let now = now();
+
+    jar.cookies
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|cookie| !cookie.is_expired(now))
+        .map(|cookie| CookieInfo {
+            name: cookie.name.clone(),
+            value: cookie.value.clone(),
+            domain: cookie.domain.clone(),
+            path: cookie.path.clone(),
+            secure: cookie.secure,
+            http_only: cookie.http_only,
+            expires: cookie.expires,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_cookie(header: &str, url: &str) -> Option<Cookie> {
+        parse_set_cookie(header, &Url::parse(url).unwrap())
+    }
+
+    fn scope(cookie: Option<Cookie>) -> Option<(String, bool)> {
+        cookie.map(|cookie| (cookie.domain, cookie.host_only))
+    }
+
+    fn netscape(line: &str) -> Option<(String, bool)> {
+        scope(parse_netscape_line(line, false))
+    }
+
+    #[test]
+    fn domain_attributes_cannot_name_public_suffixes() {
+        let shared = |domain: &str| Some((domain.to_string(), false));
+        let own = |host: &str| Some((host.to_string(), true));
+
+        assert_eq!(scope(set_cookie("a=1; Domain=example.com", "https://www.example.com/")), shared("example.com"));
+        assert_eq!(scope(set_cookie("a=1; Domain=.example.co.uk", "https://www.example.co.uk/")), shared("example.co.uk"));
+
+        assert_eq!(scope(set_cookie("a=1; Domain=com", "https://example.com/")), None);
+        assert_eq!(scope(set_cookie("a=1; Domain=co.uk", "https://www.example.co.uk/")), None);
+        assert_eq!(scope(set_cookie("a=1; Domain=github.io", "https://user.github.io/")), None);
+        assert_eq!(scope(set_cookie("a=1; Domain=lan", "http://printer.lan/")), None);
+
+        // A host that is itself a public suffix or a single label keeps its cookies
+        assert_eq!(scope(set_cookie("a=1; Domain=github.io", "https://github.io/")), own("github.io"));
+        assert_eq!(scope(set_cookie("a=1; Domain=localhost", "http://localhost/")), own("localhost"));
+    }
+
+    #[test]
+    fn ip_hosts_can_only_name_themselves() {
+        assert_eq!(scope(set_cookie("a=1; Domain=0.0.1", "http://10.0.0.1/")), None);
+        assert_eq!(scope(set_cookie("a=1; Domain=1", "http://10.0.0.1/")), None);
+        assert_eq!(scope(set_cookie("a=1; Domain=10.0.0.1", "http://10.0.0.1/")), Some(("10.0.0.1".to_string(), true)));
+        assert_eq!(scope(set_cookie("a=1", "http://10.0.0.1/")), Some(("10.0.0.1".to_string(), true)));
+    }
+
+    #[test]
+    fn netscape_lines_cannot_share_cookies_with_public_suffixes() {
+        assert_eq!(netscape(".example.com\tTRUE\t/\tFALSE\t0\ta\t1"), Some(("example.com".to_string(), false)));
+        assert_eq!(netscape("localhost\tFALSE\t/\tFALSE\t0\ta\t1"), Some(("localhost".to_string(), true)));
+        assert_eq!(netscape("10.0.0.1\tFALSE\t/\tFALSE\t0\ta\t1"), Some(("10.0.0.1".to_string(), true)));
+
+        assert_eq!(netscape(".com\tTRUE\t/\tFALSE\t0\ta\t1"), None);
+        assert_eq!(netscape(".co.uk\tTRUE\t/\tFALSE\t0\ta\t1"), None);
+        assert_eq!(netscape("localhost\tTRUE\t/\tFALSE\t0\ta\t1"), None);
+        assert_eq!(netscape("10.0.0.1\tTRUE\t/\tFALSE\t0\ta\t1"), None);
+        assert_eq!(netscape("example.com\tTRUE\t/\tFALSE\tsoon\ta\t1"), None);
+    }
+}
@@ -0,0 +1,139 @@
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use rustler::NifMap;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::background::RUNTIME;
+
+// Hosts kept at once; past it expired entries are dropped, then every entry
+const MAX_ENTRIES: usize = 4096;
+
+struct Entry {
+    addresses: Vec<IpAddr>,
+    expires: Instant,
+}
+
+// Addresses seeded by `dns_prefetch`, by lowercased host name
+static CACHE: LazyLock<Mutex<HashMap<String, Entry>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn key(host: &str) -> String {
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+// The prefetched addresses of `host`, while they are fresh
+pub fn cached(host: &str) -> Option<Vec<IpAddr>> {
+    let cache = CACHE.lock().unwrap();
+    cache
+        .get(&key(host))
+        .filter(|entry| entry.expires > Instant::now())
+        .map(|entry| entry.addresses.clone())
+}
+
+fn store(host: &str, addresses: Vec<IpAddr>, ttl: Duration) {
+    let mut cache = CACHE.lock().unwrap();
+
+    if cache.len() >= MAX_ENTRIES {
+        let now = Instant::now();
+        cache.retain(|_, entry| entry.expires > now);
+        if cache.len() >= MAX_ENTRIES {
+            cache.clear();
+        }
+    }
+
+    cache.insert(key(host), Entry {
+        addresses,
+        expires: Instant::now() + ttl,
+    });
+}
+
+// The resolver of every client: prefetched hosts are answered from the cache, others
+// looked up by the system resolver, as reqwest would. The connector fills in the port.
+pub struct Resolver;
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+
+        Box::pin(async move {
+            let addresses: Vec<SocketAddr> = match cached(&host) {
+                Some(addresses) => addresses.into_iter().map(|ip| SocketAddr::new(ip, 0)).collect(),
+                None => tokio::net::lookup_host((host.as_str(), 0)).await?.collect(),
+            };
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[derive(NifMap)]
+struct Prefetch {
+    host: String,
+    // Every address found, in resolver order; empty when the lookup failed
+    addresses: Vec<String>,
+    lookup_ms: f64,
+    // Why the lookup failed, None when it succeeded
+    error: Option<String>,
+}
+
+async fn prefetch(host: String, ttl: Duration) -> Prefetch {
+    let name = host.trim_start_matches('[').trim_end_matches(']');
+    let started = Instant::now();
+
+    // Addresses need no lookup and are never cached
+    let result = match name.parse::<IpAddr>() {
+        Ok(ip) => Ok(vec![ip]),
+        Err(_) => tokio::net::lookup_host((name, 0))
+            .await
+            .map(|found| found.map(|addr| addr.ip()).collect::<Vec<_>>())
+            .and_then(|addresses| {
+                if addresses.is_empty() {
+                    Err(std::io::Error::other("no addresses found"))
+                } else {
+                    store(name, addresses.clone(), ttl);
+                    Ok(addresses)
+                }
+            }),
+    };
+    let lookup_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    match result {
+        Ok(addresses) => Prefetch {
+            host,
+            addresses: addresses.iter().map(IpAddr::to_string).collect(),
+            lookup_ms,
+            error: None,
+        },
+        Err(e) => Prefetch {
+            host,
+            addresses: Vec::new(),
+            lookup_ms,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+// Resolve `hosts` concurrently on the shared runtime and cache the addresses found for
+// `ttl_ms`, so requests to them skip the lookup. Results are in the order of `hosts`.
+#[rustler::nif(schedule = "DirtyIo")]
+fn dns_prefetch(hosts: Vec<String>, ttl_ms: u64) -> Vec<Prefetch> {
+    let ttl = Duration::from_millis(ttl_ms);
+
+    RUNTIME.block_on(async {
+        let lookups: Vec<_> = hosts
+            .into_iter()
+            .map(|host| (host.clone(), RUNTIME.spawn(prefetch(host, ttl))))
+            .collect();
+
+        let mut results = Vec::with_capacity(lookups.len());
+        for (host, lookup) in lookups {
+            results.push(lookup.await.unwrap_or_else(|e| Prefetch {
+                host,
+                addresses: Vec::new(),
+                lookup_ms: 0.0,
+                error: Some(format!("Task error: {}", e)),
+            }));
+        }
+        results
+    })
+}
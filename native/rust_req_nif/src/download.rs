@@ -0,0 +1,494 @@
+use reqwest::header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE, LOCATION, RANGE};
+use rustler::{Binary, Encoder, Env, Error, NifMap, NifResult, OwnedBinary, Term};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::throttle::{self, Limiter};
+use crate::{atoms, audit, blocking, client_for, header_map, inflight, pipeline, resolve_options, HttpOptions};
+
+#[derive(NifMap)]
+struct Download<'a> {
+    path: String,
+    bytes_written: u64,
+    prefix: Binary<'a>,
+}
+
+#[derive(NifMap)]
+struct SegmentedDownload<'a> {
+    path: String,
+    bytes_written: u64,
+    prefix: Binary<'a>,
+    segments: usize,
+    refetched_segments: usize,
+}
+
+// Stream the body to `file`, keeping up to `tee_bytes` leading bytes in `prefix`
+fn tee_body(mut body: impl Read, file: &mut impl Write, tee_bytes: usize, prefix: &mut Vec<u8>) -> std::io::Result<u64> {
+    let mut buffer = [0u8; 64 * 1024];
+    let mut written = 0u64;
+
+    loop {
+        let read = body.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+
+        let wanted = tee_bytes.saturating_sub(prefix.len()).min(read);
+        prefix.extend_from_slice(&buffer[..wanted]);
+
+        file.write_all(&buffer[..read])?;
+        written += read as u64;
+    }
+
+    file.flush()?;
+    Ok(written)
+}
+
+// GET `url` into the file at `path`, also returning the first `tee_bytes` bytes of the body
+#[rustler::nif(schedule = "DirtyIo")]
+fn http_download<'a>(
+    env: Env<'a>,
+    url: String,
+    headers: Vec<(String, String)>,
+    path: String,
+    options: HttpOptions,
+) -> NifResult<Term<'a>> {
+    let _permit = blocking::acquire()?;
+    let _inflight = inflight::acquire()?;
+    download_streamed(env, url, headers, path, resolve_options(options)?)
+}
+
+fn download_streamed<'a>(
+    env: Env<'a>,
+    url: String,
+    headers: Vec<(String, String)>,
+    path: String,
+    options: HttpOptions,
+) -> NifResult<Term<'a>> {
+    let tee_bytes = options.tee_bytes.unwrap_or(0);
+    let client = client_for(&options)
+        .map_err(|e| Error::Term(Box::new(format!("Client error: {}", e))))?;
+
+    let mut request = client.get(&url);
+
+    for (key, value) in headers {
+        request = request.header(key, value);
+    }
+
+    let response = pipeline::send(audit::caller(env).as_deref(), &options, request).map_err(|e| e.into_error())?;
+
+    let status = response.status().as_u16();
+    let headers_list: Vec<(String, String)> = header_map(response.headers()).into_iter().collect();
+
+    let file = File::create(&path)
+        .map_err(|e| Error::Term(Box::new(format!("File error: {}", e))))?;
+    let mut prefix = Vec::with_capacity(tee_bytes.min(64 * 1024));
+
+    let body = throttle::Reader::new(response, throttle::download(&options));
+    let bytes_written = match tee_body(body, &mut BufWriter::new(file), tee_bytes, &mut prefix) {
+        Ok(written) => written,
+        Err(e) => {
+            let _ = std::fs::remove_file(&path);
+            return Err(Error::Term(Box::new(format!("Body error: {}", e))));
+        }
+    };
+
+    let download = Download {
+        path,
+        bytes_written,
+        prefix: binary_from(env, &prefix)?,
+    };
+
+    Ok((atoms::ok(), (status, headers_list, download)).encode(env))
+}
+
+pub fn binary_from<'a>(env: Env<'a>, bytes: &[u8]) -> NifResult<Binary<'a>> {
+    let mut owned = OwnedBinary::new(bytes.len()).ok_or(Error::RaiseAtom("enomem"))?;
+    owned.as_mut_slice().copy_from_slice(bytes);
+    Ok(Binary::from_owned(owned, env))
+}
+
+// Inclusive byte range of the resource fetched by one request
+struct Segment {
+    index: usize,
+    start: u64,
+    end: u64,
+}
+
+// Why a segment failed: a check worth fetching it again for, or a sign the resource
+// changed since the probe, which no number of retries fixes
+enum SegmentError {
+    Retry(String),
+    Changed(String),
+}
+
+// Fetch one range and stream it to its offset, checking it against the validators
+// observed when the download started so mixed versions of the resource are detected
+#[allow(clippy::too_many_arguments)]
+fn fetch_segment(
+    caller: Option<&str>,
+    options: &HttpOptions,
+    client: &reqwest::blocking::Client,
+    url: &str,
+    headers: &[(String, String)],
+    segment: &Segment,
+    size: u64,
+    etag: Option<&str>,
+    path: &str,
+    limiter: Option<&Arc<Limiter>>,
+) -> Result<(), SegmentError> {
+    use SegmentError::{Changed, Retry};
+
+    let mut request = client
+        .get(url)
+        .header(RANGE, format!("bytes={}-{}", segment.start, segment.end));
+
+    for (key, value) in headers {
+        request = request.header(key, value);
+    }
+
+    // If-Range only accepts strong validators
+    let if_range = etag.filter(|etag| !etag.starts_with("W/"));
+    if let Some(etag) = if_range {
+        request = request.header(IF_RANGE, etag);
+    }
+
+    let response = pipeline::send(caller, options, request).map_err(|e| Retry(format!("Request error: {}", e)))?;
+
+    // A server answers a range whose If-Range no longer matches with the whole resource
+    if if_range.is_some() && response.status() == reqwest::StatusCode::OK {
+        return Err(Changed("If-Range did not match, got status 200".to_string()));
+    }
+
+    if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+        return Err(Retry(format!("expected status 206, got {}", response.status().as_u16())));
+    }
+
+    let segment_etag = response.headers().get(ETAG).and_then(|v| v.to_str().ok());
+    if etag.is_some() && segment_etag != etag {
+        return Err(Changed(format!("ETag changed to {:?}", segment_etag)));
+    }
+
+    let expected_range = format!("bytes {}-{}/{}", segment.start, segment.end, size);
+    let content_range = response.headers().get(CONTENT_RANGE).and_then(|v| v.to_str().ok());
+    if content_range != Some(expected_range.as_str()) {
+        return Err(Retry(format!("unexpected Content-Range: {:?}", content_range)));
+    }
+
+    let file_error = |e: std::io::Error| Retry(format!("File error: {}", e));
+    let mut file = OpenOptions::new().write(true).open(path).map_err(file_error)?;
+    file.seek(SeekFrom::Start(segment.start)).map_err(file_error)?;
+
+    let length = segment.end - segment.start + 1;
+    let mut body = throttle::Reader::new(response, limiter.cloned());
+    let mut buffer = [0u8; 64 * 1024];
+    let mut received = 0u64;
+
+    loop {
+        let read = body.read(&mut buffer).map_err(|e| Retry(format!("Body error: {}", e)))?;
+        if read == 0 {
+            break;
+        }
+
+        received += read as u64;
+        if received > length {
+            return Err(Retry(format!("expected {} bytes, got more", length)));
+        }
+
+        file.write_all(&buffer[..read]).map_err(file_error)?;
+    }
+
+    if received != length {
+        return Err(Retry(format!("expected {} bytes, got {}", length, received)));
+    }
+
+    Ok(())
+}
+
+// GET `url` into `path` using parallel range requests, re-fetching individual
+// segments that fail validation instead of restarting the whole file
+#[rustler::nif(schedule = "DirtyIo")]
+fn http_download_segmented<'a>(
+    env: Env<'a>,
+    url: String,
+    headers: Vec<(String, String)>,
+    path: String,
+    options: HttpOptions,
+) -> NifResult<Term<'a>> {
+    let _permit = blocking::acquire()?;
+    let _inflight = inflight::acquire()?;
+    let options = resolve_options(options)?;
+    let tee_bytes = options.tee_bytes.unwrap_or(0);
+    let segment_count = options.segments.unwrap_or(4).max(1);
+    let segment_retries = options.segment_retries.unwrap_or(3);
+    let client = client_for(&options)
+        .map_err(|e| Error::Term(Box::new(format!("Client error: {}", e))))?;
+    let caller = audit::caller(env);
+
+    let mut probe = client.head(&url);
+    for (key, value) in &headers {
+        probe = probe.header(key, value);
+    }
+    let probe = pipeline::send(caller.as_deref(), &options, probe).map_err(|e| e.into_error())?;
+
+    let status = probe.status().as_u16();
+    let headers_list: Vec<(String, String)> = header_map(probe.headers()).into_iter().collect();
+    let size = probe
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    let accepts_ranges = probe
+        .headers()
+        .get(ACCEPT_RANGES)
+        .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"bytes"));
+    let etag = probe
+        .headers()
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    let size = match size {
+        Some(size) if probe.status().is_success() && accepts_ranges && size > 0 => size,
+        // The server can't serve ranges; fall back to a single streamed request
+        _ => return download_streamed(env, url, headers, path, options),
+    };
+
+    let file = File::create(&path)
+        .map_err(|e| Error::Term(Box::new(format!("File error: {}", e))))?;
+    file.set_len(size)
+        .map_err(|e| Error::Term(Box::new(format!("File error: {}", e))))?;
+    drop(file);
+
+    let segment_count = segment_count.min(size as usize);
+    let segment_size = size.div_ceil(segment_count as u64);
+    let segments: Vec<Segment> = (0..segment_count)
+        .map(|index| {
+            let start = index as u64 * segment_size;
+            Segment {
+                index,
+                start,
+                end: (start + segment_size).min(size) - 1,
+            }
+        })
+        .filter(|segment| segment.start < size)
+        .collect();
+
+    // The segments share one rate between them
+    let limiter = throttle::download(&options);
+    let refetched = AtomicUsize::new(0);
+    let failure = std::thread::scope(|scope| {
+        let workers: Vec<_> = segments
+            .iter()
+            .map(|segment| {
+                let (caller, options, client, url, headers, etag, path, refetched, limiter) =
+                    (caller.as_deref(), &options, &client, &url, &headers, etag.as_deref(), &path, &refetched, limiter.as_ref());
+
+                scope.spawn(move || {
+                    let mut attempt = 0;
+                    loop {
+                        match fetch_segment(caller, options, client, url, headers, segment, size, etag, path, limiter) {
+                            Ok(()) => return Ok(()),
+                            Err(SegmentError::Changed(reason)) => return Err((segment.index, SegmentError::Changed(reason))),
+                            Err(SegmentError::Retry(reason)) if attempt == segment_retries => {
+                                return Err((segment.index, SegmentError::Retry(reason)))
+                            }
+                            Err(SegmentError::Retry(reason)) => {
+                                attempt += 1;
+                                refetched.fetch_add(1, Ordering::Relaxed);
+                                crate::events::retry(url, segment.index, attempt, &reason);
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .zip(&segments)
+            .map(|(worker, segment)| {
+                worker.join().unwrap_or_else(|_| {
+                    Err((segment.index, SegmentError::Retry("segment worker panicked".to_string())))
+                })
+            })
+            .filter_map(Result::err)
+            // A changed resource explains the other failures, so it is the one reported
+            .min_by_key(|(_, error)| matches!(error, SegmentError::Retry(_)))
+    });
+
+    match failure {
+        Some((index, SegmentError::Retry(reason))) => {
+            let _ = std::fs::remove_file(&path);
+            return Err(Error::Term(Box::new((atoms::segment_failed(), index, reason))));
+        }
+        Some((index, SegmentError::Changed(reason))) => {
+            let _ = std::fs::remove_file(&path);
+            return Err(Error::Term(Box::new((atoms::resource_changed(), index, reason))));
+        }
+        None => {}
+    }
+
+    let mut prefix = Vec::new();
+    File::open(&path)
+        .and_then(|file| file.take(tee_bytes as u64).read_to_end(&mut prefix))
+        .map_err(|e| Error::Term(Box::new(format!("File error: {}", e))))?;
+
+    let download = SegmentedDownload {
+        path,
+        bytes_written: size,
+        prefix: binary_from(env, &prefix)?,
+        segments: segments.len(),
+        refetched_segments: refetched.into_inner(),
+    };
+
+    Ok((atoms::ok(), (status, headers_list, download)).encode(env))
+}
+
+#[derive(NifMap)]
+struct Hop {
+    url: String,
+    status: u16,
+    location: String,
+}
+
+#[derive(NifMap)]
+struct ResolvedFetch {
+    hops: Vec<Hop>,
+    final_url: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+    content_type: Option<String>,
+    sha256: String,
+    bytes_written: u64,
+    path: String,
+}
+
+// Writer that hashes everything written through it
+struct Sha256Writer<W> {
+    inner: W,
+    context: ring::digest::Context,
+}
+
+impl<W: Write> Write for Sha256Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.context.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Follow the redirect chain of `url` hop by hop, save the final asset to `path`
+// and report every hop along with the final URL, content type and SHA-256
+#[rustler::nif(schedule = "DirtyIo")]
+fn http_fetch_resolved<'a>(
+    env: Env<'a>,
+    url: String,
+    headers: Vec<(String, String)>,
+    path: String,
+    options: HttpOptions,
+) -> NifResult<Term<'a>> {
+    let _permit = blocking::acquire()?;
+    let _inflight = inflight::acquire()?;
+    let options = HttpOptions {
+        follow_redirects: Some(false),
+        ..resolve_options(options)?
+    };
+    let max_redirects = options.max_redirects.unwrap_or(10);
+    let limiter = throttle::download(&options);
+    let client = client_for(&options).map_err(|e| Error::Term(Box::new(format!("Client error: {}", e))))?;
+
+    let mut current = reqwest::Url::parse(&url).map_err(|_| Error::Term(Box::new(atoms::invalid_url())))?;
+    let mut headers = headers;
+    let mut hops = Vec::new();
+    let caller = audit::caller(env);
+
+    let response = loop {
+        let mut request = client.get(current.clone());
+        for (key, value) in &headers {
+            request = request.header(key, value);
+        }
+        let response = pipeline::send(caller.as_deref(), &options, request).map_err(|e| e.into_error())?;
+
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .filter(|_| response.status().is_redirection());
+
+        let Some(location) = location else {
+            break response;
+        };
+
+        if hops.len() == max_redirects {
+            return Err(Error::Term(Box::new(atoms::too_many_redirects())));
+        }
+
+        let next = current
+            .join(location)
+            .map_err(|e| Error::Term(Box::new(format!("Invalid Location header: {}", e))))?;
+
+        crate::events::redirect(current.as_str(), next.as_str(), response.status().as_u16());
+        hops.push(Hop {
+            url: current.to_string(),
+            status: response.status().as_u16(),
+            location: next.to_string(),
+        });
+        // Credentials are only sent to the origin they were given for
+        if next.origin() != current.origin() {
+            crate::drop_credentials(&mut headers);
+        }
+        current = next;
+    };
+
+    let status = response.status().as_u16();
+    let headers_list: Vec<(String, String)> = header_map(response.headers()).into_iter().collect();
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    let file = File::create(&path)
+        .map_err(|e| Error::Term(Box::new(format!("File error: {}", e))))?;
+    let mut writer = Sha256Writer {
+        inner: BufWriter::new(file),
+        context: ring::digest::Context::new(&ring::digest::SHA256),
+    };
+
+    let body = throttle::Reader::new(response, limiter);
+    let bytes_written = match tee_body(body, &mut writer, 0, &mut Vec::new()) {
+        Ok(written) => written,
+        Err(e) => {
+            let _ = std::fs::remove_file(&path);
+            return Err(Error::Term(Box::new(format!("Body error: {}", e))));
+        }
+    };
+
+    let sha256 = writer
+        .context
+        .finish()
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+
+    let fetch = ResolvedFetch {
+        hops,
+        final_url: current.to_string(),
+        status,
+        headers: headers_list,
+        content_type,
+        sha256,
+        bytes_written,
+        path,
+    };
+
+    Ok((atoms::ok(), fetch).encode(env))
+}
@@ -0,0 +1,134 @@
+use rustler::{Atom, Encoder, LocalPid, NifMap};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::atoms;
+
+// Collector receiving every lifecycle event as `{:rust_req_event, event, timestamp_us, fields}`
+static SINK: RwLock<Option<LocalPid>> = RwLock::new(None);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(NifMap)]
+struct Enqueue {
+    id: u64,
+    method: String,
+    url: String,
+}
+
+#[derive(NifMap)]
+struct Connect {
+    id: u64,
+    local_address: String,
+    remote_address: String,
+}
+
+#[derive(NifMap)]
+struct Redirect {
+    from: String,
+    to: String,
+    status: u16,
+}
+
+#[derive(NifMap)]
+struct Retry {
+    url: String,
+    segment: usize,
+    attempt: u32,
+    reason: String,
+}
+
+#[derive(NifMap)]
+struct Warning {
+    url: String,
+    message: String,
+}
+
+#[derive(NifMap)]
+struct Complete {
+    id: u64,
+    status: Option<u16>,
+    duration_ms: f64,
+}
+
+fn emit<T: Encoder + Send + 'static>(event: fn() -> Atom, fields: T) {
+    let Some(pid) = *SINK.read().unwrap() else {
+        return;
+    };
+
+    let timestamp_us = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_micros() as u64)
+        .unwrap_or(0);
+
+    crate::messages::send(pid, move |env| {
+        (atoms::rust_req_event(), event(), timestamp_us, fields).encode(env)
+    });
+}
+
+// Announce a request and return the id its later events carry. `caller` is the
+// requesting process as `audit::caller` reports it.
+pub fn enqueue(method: &str, url: &str, caller: Option<String>) -> u64 {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    crate::audit::start(id, method, url, caller);
+
+    emit(atoms::enqueue, Enqueue {
+        id,
+        method: method.to_string(),
+        url: url.to_string(),
+    });
+
+    id
+}
+
+// A request was served on a newly opened connection
+pub fn connect(id: u64, local: SocketAddr, remote: SocketAddr) {
+    emit(atoms::connect, Connect {
+        id,
+        local_address: local.to_string(),
+        remote_address: remote.to_string(),
+    });
+}
+
+pub fn redirect(from: &str, to: &str, status: u16) {
+    emit(atoms::redirect, Redirect {
+        from: from.to_string(),
+        to: to.to_string(),
+        status,
+    });
+}
+
+pub fn retry(url: &str, segment: usize, attempt: u32, reason: &str) {
+    emit(atoms::retry, Retry {
+        url: url.to_string(),
+        segment,
+        attempt,
+        reason: reason.to_string(),
+    });
+}
+
+// Something about a request the caller may want to know, though it goes ahead
+pub fn warning(url: &str, message: &str) {
+    emit(atoms::warning, Warning {
+        url: url.to_string(),
+        message: message.to_string(),
+    });
+}
+
+// `status` is None when the request failed before a complete response was read
+pub fn complete(id: u64, status: Option<u16>, started: Instant) {
+    emit(atoms::complete, Complete {
+        id,
+        status,
+        duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+    });
+}
+
+// Set the collector process, or stop emitting events with `nil`
+#[rustler::nif]
+fn event_sink(pid: Option<LocalPid>) -> Atom {
+    *SINK.write().unwrap() = pid;
+    atoms::ok()
+}
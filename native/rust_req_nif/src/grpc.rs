@@ -0,0 +1,259 @@
+use rustler::{Atom, Binary, Env, Error, NifMap, NifResult};
+
+use crate::{atoms, inflight, HttpOptions};
+
+#[derive(NifMap)]
+struct GrpcResponse<'a> {
+    status: u32,
+    message: Option<String>,
+    body: Option<Binary<'a>>,
+    headers: Vec<(String, String)>,
+    trailers: Vec<(String, String)>,
+}
+
+fn grpc_error(message: &str) -> Error {
+    Error::Term(Box::new(format!("gRPC error: {}", message)))
+}
+
+// Frame `message` with the 5-byte prefix: an uncompressed flag and a big-endian length
+#[cfg(any(test, feature = "http2"))]
+fn frame(message: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(message.len() + 5);
+    framed.push(0);
+    framed.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    framed.extend_from_slice(message);
+    framed
+}
+
+// The single message of a unary response, or None when the server sent none
+#[cfg(any(test, feature = "http2"))]
+fn unframe(body: &[u8]) -> NifResult<Option<&[u8]>> {
+    if body.is_empty() {
+        return Ok(None);
+    }
+    if body.len() < 5 {
+        return Err(grpc_error("truncated message prefix"));
+    }
+    if body[0] != 0 {
+        return Err(grpc_error("compressed responses are not supported"));
+    }
+
+    let length = u32::from_be_bytes([body[1], body[2], body[3], body[4]]) as usize;
+    let message = body.get(5..5 + length).ok_or_else(|| grpc_error("truncated message"))?;
+
+    if body.len() != 5 + length {
+        return Err(grpc_error("unary response carries more than one message"));
+    }
+
+    Ok(Some(message))
+}
+
+// The grpc-status code and decoded grpc-message of a response, from its trailers or,
+// for a trailers-only response, its headers
+#[cfg(any(test, feature = "http2"))]
+fn grpc_status(headers: &reqwest::header::HeaderMap, trailers: &reqwest::header::HeaderMap) -> NifResult<(u32, Option<String>)> {
+    let grpc_header = |name: &str| trailers.get(name).or_else(|| headers.get(name)).and_then(|v| v.to_str().ok());
+
+    let status = grpc_header("grpc-status")
+        .ok_or_else(|| grpc_error("response has no grpc-status"))?
+        .parse()
+        .map_err(|_| grpc_error("invalid grpc-status"))?;
+
+    let message = grpc_header("grpc-message")
+        .map(|m| percent_encoding::percent_decode_str(m).decode_utf8_lossy().into_owned());
+
+    Ok((status, message))
+}
+
+// Prior-knowledge HTTP/2 clients of recent calls by their resolved options, most
+// recently used last, so calls with the same options share connections
+#[cfg(feature = "http2")]
+static CLIENTS: std::sync::Mutex<Vec<(HttpOptions, reqwest::Client)>> = std::sync::Mutex::new(Vec::new());
+
+// Most clients kept in `CLIENTS`
+#[cfg(feature = "http2")]
+const MAX_CLIENTS: usize = 16;
+
+#[cfg(feature = "http2")]
+fn client_for(options: &HttpOptions) -> Result<reqwest::Client, Box<dyn std::error::Error>> {
+    // The proxy's Negotiate token is minted as the client is built
+    let cached = options.proxy_negotiate_auth != Some(true);
+
+    if cached {
+        let mut clients = CLIENTS.lock().unwrap();
+        if let Some(index) = clients.iter().position(|(built_from, _)| built_from == options) {
+            let entry = clients.remove(index);
+            let client = entry.1.clone();
+            clients.push(entry);
+            return Ok(client);
+        }
+    }
+
+    let client = crate::async_client_builder(options)?.http2_prior_knowledge().build()?;
+
+    if cached {
+        let mut clients = CLIENTS.lock().unwrap();
+        if clients.len() >= MAX_CLIENTS {
+            clients.remove(0);
+        }
+        clients.push((options.clone(), client.clone()));
+    }
+    Ok(client)
+}
+
+#[cfg(feature = "http2")]
+fn call<'a>(
+    env: Env<'a>,
+    url: String,
+    service_method: String,
+    message: Binary,
+    metadata: Vec<(String, Binary)>,
+    options: HttpOptions,
+) -> NifResult<GrpcResponse<'a>> {
+    use base64::engine::general_purpose::STANDARD_NO_PAD as BASE64;
+    use base64::Engine;
+    use http_body_util::BodyExt;
+    use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, TE};
+
+    use crate::background::RUNTIME;
+    use crate::{audit, download::binary_from, request_error, resolve_options};
+
+    let options = resolve_options(options)?;
+    let url = format!("{}/{}", url.trim_end_matches('/'), service_method.trim_start_matches('/'));
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+    headers.insert(TE, HeaderValue::from_static("trailers"));
+    // grpc-timeout allows at most eight digits, so long timeouts are sent in seconds
+    if let Some(timeout_ms) = options.timeout_ms {
+        let timeout = if timeout_ms < 100_000_000 {
+            format!("{}m", timeout_ms)
+        } else {
+            format!("{}S", timeout_ms / 1000)
+        };
+        headers.insert("grpc-timeout", HeaderValue::from_str(&timeout).expect("digits are a valid header value"));
+    }
+
+    // Binary metadata (keys ending in -bin) is base64-encoded on the wire
+    for (key, value) in &metadata {
+        let name = HeaderName::from_bytes(key.to_lowercase().as_bytes()).map_err(|_| Error::BadArg)?;
+        let value = if key.ends_with("-bin") {
+            HeaderValue::from_str(&BASE64.encode(value.as_slice()))
+        } else {
+            HeaderValue::from_bytes(value.as_slice())
+        }
+        .map_err(|_| Error::BadArg)?;
+        headers.append(name, value);
+    }
+
+    let caller = audit::caller(env);
+    let (status, response_headers, body, trailers) = RUNTIME.block_on(async {
+        let client = client_for(&options).map_err(|e| Error::Term(Box::new(format!("Client error: {}", e))))?;
+
+        let request = client.post(&url).headers(headers).body(frame(message.as_slice()));
+        let response = audit::send_async(caller.as_deref(), request).await.map_err(request_error)?;
+
+        let status = response.status();
+        let response: hyper::Response<reqwest::Body> = response.into();
+        let (parts, body) = response.into_parts();
+        let collected = body
+            .collect()
+            .await
+            .map_err(|e| Error::Term(Box::new(format!("Body error: {}", e))))?;
+        let trailers = collected.trailers().cloned().unwrap_or_default();
+
+        Ok::<_, Error>((status, parts.headers, collected.to_bytes(), trailers))
+    })?;
+
+    if status != reqwest::StatusCode::OK {
+        return Err(Error::Term(Box::new((atoms::unexpected_status(), status.as_u16()))));
+    }
+
+    let (grpc_status, grpc_message) = grpc_status(&response_headers, &trailers)?;
+
+    let body = match unframe(&body)? {
+        Some(message) => Some(binary_from(env, message)?),
+        None => None,
+    };
+
+    Ok(GrpcResponse {
+        status: grpc_status,
+        message: grpc_message,
+        body,
+        headers: crate::header_map(&response_headers).into_iter().collect(),
+        trailers: crate::header_map(&trailers).into_iter().collect(),
+    })
+}
+
+#[cfg(not(feature = "http2"))]
+fn call<'a>(
+    _env: Env<'a>,
+    _url: String,
+    _service_method: String,
+    _message: Binary,
+    _metadata: Vec<(String, Binary)>,
+    _options: HttpOptions,
+) -> NifResult<GrpcResponse<'a>> {
+    Err(grpc_error("this build does not include the http2 feature"))
+}
+
+// Make a unary gRPC call with an already-encoded protobuf `message`
+#[rustler::nif(schedule = "DirtyIo")]
+fn grpc_unary<'a>(
+    env: Env<'a>,
+    url: String,
+    service_method: String,
+    message: Binary,
+    metadata: Vec<(String, Binary)>,
+    options: HttpOptions,
+) -> NifResult<(Atom, GrpcResponse<'a>)> {
+    let _inflight = inflight::acquire()?;
+    Ok((atoms::ok(), call(env, url, service_method, message, metadata, options)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs.iter().map(|(name, value)| (name.parse().unwrap(), HeaderValue::from_static(value))).collect()
+    }
+
+    #[test]
+    fn messages_carry_a_five_byte_prefix() {
+        assert_eq!(frame(b""), [0, 0, 0, 0, 0]);
+        assert_eq!(frame(b"\x08\x96\x01"), [0, 0, 0, 0, 3, 0x08, 0x96, 0x01]);
+        assert_eq!(frame(&[7; 300])[..5], [0, 0, 0, 1, 44]);
+
+        let framed = frame(b"hello");
+        assert_eq!(unframe(&framed).ok(), Some(Some(&b"hello"[..])));
+        assert_eq!(unframe(&frame(b"")).ok(), Some(Some(&b""[..])));
+        assert_eq!(unframe(b"").ok(), Some(None));
+    }
+
+    #[test]
+    fn malformed_frames_are_errors() {
+        // Truncated prefix, compressed flag, short message, a second message
+        assert!(unframe(&[0, 0, 0]).is_err());
+        assert!(unframe(&[1, 0, 0, 0, 1, 9]).is_err());
+        assert!(unframe(&[0, 0, 0, 0, 4, 1, 2]).is_err());
+        assert!(unframe(&[frame(b"a"), frame(b"b")].concat()).is_err());
+    }
+
+    #[test]
+    fn status_comes_from_trailers_or_a_trailers_only_response() {
+        let ok = headers(&[("grpc-status", "0")]);
+        assert_eq!(grpc_status(&HeaderMap::new(), &ok).ok(), Some((0, None)));
+
+        let not_found = headers(&[("grpc-status", "5"), ("grpc-message", "no%20such%20user%3A%20%C3%A9")]);
+        assert_eq!(grpc_status(&not_found, &HeaderMap::new()).ok(), Some((5, Some("no such user: é".to_string()))));
+
+        // Trailers win over headers
+        assert_eq!(grpc_status(&not_found, &ok).ok(), Some((0, Some("no such user: é".to_string()))));
+
+        assert!(grpc_status(&HeaderMap::new(), &HeaderMap::new()).is_err());
+        assert!(grpc_status(&HeaderMap::new(), &headers(&[("grpc-status", "OK")])).is_err());
+        assert!(grpc_status(&HeaderMap::new(), &headers(&[("grpc-status", "-1")])).is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use rustler::NifMap;
+
+// Buckets per power of two, bounding the relative error of a value at 1/32 (about 3%)
+const SUB_BUCKETS: u64 = 32;
+const SUB_BUCKET_BITS: u32 = 5;
+
+// Highest power of two past the linear range; values from 2^36 µs (19 hours) share the
+// last bucket
+const MAGNITUDES: u32 = 30;
+
+// The linear range, then each power of two
+const BUCKETS: usize = (SUB_BUCKETS as usize) * (2 + MAGNITUDES as usize);
+
+// Latencies in microseconds, bucketed as HdrHistogram does: exactly below 32 µs, then
+// in 32 equal steps per power of two, so memory stays constant however many are recorded
+pub struct Histogram {
+    counts: Box<[u64; BUCKETS]>,
+    count: u64,
+    sum_us: u64,
+    min_us: u64,
+    max_us: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            counts: Box::new([0; BUCKETS]),
+            count: 0,
+            sum_us: 0,
+            min_us: u64::MAX,
+            max_us: 0,
+        }
+    }
+}
+
+fn index(value_us: u64) -> usize {
+    if value_us < SUB_BUCKETS {
+        return value_us as usize;
+    }
+
+    let magnitude = (63 - value_us.leading_zeros() - SUB_BUCKET_BITS).min(MAGNITUDES);
+    let sub_bucket = (value_us >> magnitude).min(2 * SUB_BUCKETS - 1) - SUB_BUCKETS;
+    (SUB_BUCKETS * (1 + magnitude as u64) + sub_bucket) as usize
+}
+
+// The highest value bucket `index` holds
+fn upper_bound(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return index;
+    }
+
+    let magnitude = index / SUB_BUCKETS - 1;
+    let sub_bucket = index % SUB_BUCKETS;
+    ((SUB_BUCKETS + sub_bucket + 1) << magnitude) - 1
+}
+
+fn ms(us: u64) -> f64 {
+    us as f64 / 1000.0
+}
+
+#[derive(NifMap)]
+pub struct Percentiles {
+    p50: f64,
+    p90: f64,
+    p99: f64,
+    p999: f64,
+}
+
+#[derive(NifMap)]
+pub struct Summary {
+    count: u64,
+    min_ms: f64,
+    max_ms: f64,
+    mean_ms: f64,
+    percentiles_ms: Percentiles,
+    // `{upper_bound_ms, count}` of every bucket holding a value, in ascending order
+    buckets: Vec<(f64, u64)>,
+}
+
+impl Histogram {
+    pub fn record(&mut self, value_us: u64) {
+        self.counts[index(value_us)] += 1;
+        self.count += 1;
+        self.sum_us = self.sum_us.saturating_add(value_us);
+        self.min_us = self.min_us.min(value_us);
+        self.max_us = self.max_us.max(value_us);
+    }
+
+    // The upper bound of the bucket holding the value at `p` percent, within the
+    // values actually recorded
+    fn percentile(&self, p: f64) -> u64 {
+        let rank = ((p / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return upper_bound(index).clamp(self.min_us, self.max_us);
+            }
+        }
+
+        self.max_us
+    }
+
+    // None before any value is recorded
+    pub fn summary(&self) -> Option<Summary> {
+        if self.count == 0 {
+            return None;
+        }
+
+        Some(Summary {
+            count: self.count,
+            min_ms: ms(self.min_us),
+            max_ms: ms(self.max_us),
+            mean_ms: ms(self.sum_us) / self.count as f64,
+            percentiles_ms: Percentiles {
+                p50: ms(self.percentile(50.0)),
+                p90: ms(self.percentile(90.0)),
+                p99: ms(self.percentile(99.0)),
+                p999: ms(self.percentile(99.9)),
+            },
+            buckets: self
+                .counts
+                .iter()
+                .enumerate()
+                .filter(|(_, &count)| count > 0)
+                .map(|(index, &count)| (ms(upper_bound(index)), count))
+                .collect(),
+        })
+    }
+}
@@ -0,0 +1,51 @@
+use rustler::{Atom, NifUnitEnum};
+
+use crate::{atoms, HttpOptions, HttpResponse, ResponseMeta};
+
+// HTTP versions `http_version` lets a client speak
+#[derive(Clone, Copy, Debug, PartialEq, NifUnitEnum)]
+pub enum HttpVersion {
+    // HTTP/2 where the server offers it over TLS, HTTP/1.1 otherwise
+    Auto,
+    Http1Only,
+    // HTTP/2 from the first byte, without upgrade or ALPN, as gRPC backends expect
+    Http2PriorKnowledge,
+}
+
+// `http_version`, failing for HTTP/2 in builds without the `http2` feature
+pub fn resolve(options: &HttpOptions) -> Result<HttpVersion, String> {
+    let version = options.http_version.unwrap_or(HttpVersion::Auto);
+    if version == HttpVersion::Http2PriorKnowledge && !cfg!(feature = "http2") {
+        return Err("http2_prior_knowledge needs the http2 feature".to_string());
+    }
+    Ok(version)
+}
+
+// ALPN protocols to offer on TLS connections
+pub fn alpn(options: &HttpOptions) -> Vec<Vec<u8>> {
+    match options.http_version.unwrap_or(HttpVersion::Auto) {
+        HttpVersion::Http1Only => vec![b"http/1.1".to_vec()],
+        HttpVersion::Http2PriorKnowledge => vec![b"h2".to_vec()],
+        HttpVersion::Auto if cfg!(feature = "http2") => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+        HttpVersion::Auto => vec![b"http/1.1".to_vec()],
+    }
+}
+
+// The protocol a response came over, as `version_info` reports it
+fn label(version: reqwest::Version) -> Atom {
+    match version {
+        reqwest::Version::HTTP_09 => atoms::http0_9(),
+        reqwest::Version::HTTP_10 => atoms::http1_0(),
+        reqwest::Version::HTTP_2 => atoms::http2(),
+        reqwest::Version::HTTP_3 => atoms::http3(),
+        _ => atoms::http1_1(),
+    }
+}
+
+// Report the protocol as `:version` in the response metadata when `version_info` is set
+pub fn annotate(options: &HttpOptions, version: reqwest::Version, mut response: HttpResponse) -> HttpResponse {
+    if options.version_info == Some(true) {
+        response.meta.get_or_insert_with(ResponseMeta::default).version = Some(label(version));
+    }
+    response
+}
@@ -0,0 +1,47 @@
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::{HttpOptions, HttpResponse, ResponseMeta};
+
+// Methods that get a key; the others are idempotent by definition
+const METHODS: [&str; 2] = ["POST", "PATCH"];
+
+// A random (version 4) UUID
+fn uuid() -> String {
+    let mut bytes = [0u8; 16];
+    SystemRandom::new().fill(&mut bytes).expect("system randomness");
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+// With `idempotency`, give a POST or PATCH an `Idempotency-Key` header, generated once
+// per call so every resend of the request carries the same one. A key the caller set
+// is kept. Returns the headers and the key in use.
+pub fn apply(options: &HttpOptions, method: &str, mut headers: Vec<(String, String)>) -> (Vec<(String, String)>, Option<String>) {
+    if options.idempotency != Some(true) || !METHODS.contains(&method) {
+        return (headers, None);
+    }
+
+    let existing = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("idempotency-key"))
+        .map(|(_, value)| value.clone());
+
+    let key = existing.unwrap_or_else(|| {
+        let key = uuid();
+        headers.push(("idempotency-key".to_string(), key.clone()));
+        key
+    });
+
+    (headers, Some(key))
+}
+
+// Report the key a request was sent with as `idempotency_key` in the response metadata
+pub fn annotate(key: Option<String>, mut response: HttpResponse) -> HttpResponse {
+    if let Some(key) = key {
+        response.meta.get_or_insert_with(ResponseMeta::default).idempotency_key = Some(key);
+    }
+    response
+}
@@ -0,0 +1,249 @@
+use rustler::{Atom, Encoder, Env, Error, NifMap, NifResult, NifUnitEnum, Term};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+use crate::atoms;
+
+// What a request does when `global_max_inflight` requests are already out
+#[derive(Clone, Copy, Debug, PartialEq, NifUnitEnum)]
+pub enum WhenFull {
+    // Fail at once with `:overloaded`
+    Reject,
+    // Wait for one to finish
+    Queue,
+}
+
+// Outbound requests across every caller, client and batch of the node
+struct Limit {
+    // Requests allowed out at once; None for no limit
+    max: Option<usize>,
+    when_full: WhenFull,
+    active: usize,
+    // Of `active`, those running on a blocking client, which `drain` cannot abort
+    blocking: usize,
+    waiting: usize,
+    // Set by `drain` until `resume`: no request is admitted
+    draining: bool,
+}
+
+static LIMIT: Mutex<Limit> = Mutex::new(Limit {
+    max: None,
+    when_full: WhenFull::Reject,
+    active: 0,
+    blocking: 0,
+    waiting: 0,
+    draining: false,
+});
+
+// Signalled whenever a request finishes or the limit changes, for blocking and async
+// waiters respectively
+static FREED: Condvar = Condvar::new();
+static FREED_ASYNC: Notify = Notify::const_new();
+
+// Set once `drain` times out, ending the async requests still running (see `abortable`)
+static ABORTING: AtomicBool = AtomicBool::new(false);
+static ABORT: Notify = Notify::const_new();
+
+// Grace period for aborted requests to let go of their slots
+const ABORT_GRACE: Duration = Duration::from_secs(1);
+
+#[derive(NifMap)]
+pub struct Stats {
+    global_max_inflight: Option<usize>,
+    when_full: WhenFull,
+    active: usize,
+    waiting: usize,
+}
+
+// A slot for one request, given back when dropped
+pub struct Permit {
+    blocking: bool,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut limit = LIMIT.lock().unwrap();
+        limit.active -= 1;
+        if self.blocking {
+            limit.blocking -= 1;
+        }
+        drop(limit);
+
+        FREED.notify_all();
+        FREED_ASYNC.notify_waiters();
+    }
+}
+
+impl Limit {
+    fn full(&self) -> bool {
+        self.max.is_some_and(|max| self.active >= max)
+    }
+
+    fn admit(&mut self, blocking: bool) -> Permit {
+        self.active += 1;
+        if blocking {
+            self.blocking += 1;
+        }
+        Permit { blocking }
+    }
+}
+
+// Wait for a slot on the calling thread, or fail with `:overloaded` when the limit
+// rejects and `:draining` during `drain`
+pub fn acquire() -> NifResult<Permit> {
+    let mut limit = LIMIT.lock().unwrap();
+
+    if limit.full() && !limit.draining {
+        if limit.when_full == WhenFull::Reject {
+            return Err(Error::Term(Box::new(atoms::overloaded())));
+        }
+
+        limit.waiting += 1;
+        limit = FREED.wait_while(limit, |limit| limit.full() && !limit.draining).unwrap();
+        limit.waiting -= 1;
+    }
+
+    if limit.draining {
+        return Err(Error::Term(Box::new(atoms::draining())));
+    }
+
+    Ok(limit.admit(true))
+}
+
+// A queued async request, no longer counted once admitted or cancelled
+struct Waiting(());
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        LIMIT.lock().unwrap().waiting -= 1;
+    }
+}
+
+// `acquire` for async code, failing with the `:overloaded` or `:draining` atom
+pub async fn acquire_async() -> Result<Permit, Atom> {
+    let mut waiting = None;
+
+    loop {
+        let freed = FREED_ASYNC.notified();
+        tokio::pin!(freed);
+        freed.as_mut().enable();
+
+        {
+            let mut limit = LIMIT.lock().unwrap();
+            if limit.draining {
+                return Err(atoms::draining());
+            }
+            if !limit.full() {
+                return Ok(limit.admit(false));
+            }
+            if waiting.is_none() {
+                if limit.when_full == WhenFull::Reject {
+                    return Err(atoms::overloaded());
+                }
+                limit.waiting += 1;
+                waiting = Some(Waiting(()));
+            }
+        }
+
+        freed.await;
+    }
+}
+
+pub fn stats() -> Stats {
+    let limit = LIMIT.lock().unwrap();
+    Stats {
+        global_max_inflight: limit.max,
+        when_full: limit.when_full,
+        active: limit.active,
+        waiting: limit.waiting,
+    }
+}
+
+// Limit how many requests are out at once across the node. Requests in flight are
+// unaffected; waiting ones are admitted if the limit grows or is lifted.
+#[rustler::nif]
+fn inflight_configure(global_max_inflight: Option<usize>, when_full: WhenFull) -> NifResult<Atom> {
+    if global_max_inflight == Some(0) {
+        return Err(Error::Term(Box::new("global_max_inflight must be greater than 0")));
+    }
+
+    let mut limit = LIMIT.lock().unwrap();
+    limit.max = global_max_inflight;
+    limit.when_full = when_full;
+    drop(limit);
+
+    FREED.notify_all();
+    FREED_ASYNC.notify_waiters();
+    Ok(atoms::ok())
+}
+
+// Resolves once `drain` gives up on the requests still running
+async fn aborted() {
+    loop {
+        let abort = ABORT.notified();
+        tokio::pin!(abort);
+        abort.as_mut().enable();
+
+        if ABORTING.load(Ordering::Acquire) {
+            return;
+        }
+        abort.await;
+    }
+}
+
+// Run an async request unless `drain` aborts it first, giving None then
+pub async fn abortable<F: Future>(request: F) -> Option<F::Output> {
+    tokio::select! {
+        output = request => Some(output),
+        _ = aborted() => None,
+    }
+}
+
+#[derive(NifMap)]
+struct Unfinished {
+    aborted: usize,
+    still_running: usize,
+}
+
+// Stop admitting requests, failing new and queued ones with `:draining`, and wait up
+// to `timeout_ms` for those in flight to finish. Async requests still running then
+// are aborted; blocking ones cannot be and are left to finish or time out. Returns
+// `:ok` when none were left, or `{:timeout, %{aborted: n, still_running: n}}`.
+#[rustler::nif(schedule = "DirtyIo")]
+fn drain(env: Env, timeout_ms: u64) -> Term {
+    let mut limit = LIMIT.lock().unwrap();
+    limit.draining = true;
+    drop(limit);
+    FREED.notify_all();
+    FREED_ASYNC.notify_waiters();
+
+    let limit = LIMIT.lock().unwrap();
+    let (limit, _) = FREED
+        .wait_timeout_while(limit, Duration::from_millis(timeout_ms), |limit| limit.active > 0)
+        .unwrap();
+    if limit.active == 0 {
+        return atoms::ok().encode(env);
+    }
+
+    let aborted = limit.active - limit.blocking;
+    ABORTING.store(true, Ordering::Release);
+    ABORT.notify_waiters();
+
+    let (limit, _) = FREED
+        .wait_timeout_while(limit, ABORT_GRACE, |limit| limit.active > limit.blocking)
+        .unwrap();
+    let still_running = limit.active;
+
+    (atoms::timeout(), Unfinished { aborted, still_running }).encode(env)
+}
+
+// Admit requests again after `drain`
+#[rustler::nif]
+fn resume() -> Atom {
+    LIMIT.lock().unwrap().draining = false;
+    ABORTING.store(false, Ordering::Release);
+    atoms::ok()
+}
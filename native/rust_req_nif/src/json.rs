@@ -0,0 +1,62 @@
+use rustler::types::atom;
+use rustler::types::map::MapIterator;
+use rustler::{Encoder, Env, Error, ListIterator, NifResult, Term, TermType};
+use serde_json::{Map, Number, Value};
+
+// Convert an Elixir term to JSON: nil/true/false map to null and booleans, other
+// atoms and binaries to strings, lists to arrays and maps to objects
+pub fn from_term(term: Term) -> NifResult<Value> {
+    match term.get_type() {
+        TermType::Atom if term == atom::nil().to_term(term.get_env()) => Ok(Value::Null),
+        TermType::Atom if term == atom::true_().to_term(term.get_env()) => Ok(Value::Bool(true)),
+        TermType::Atom if term == atom::false_().to_term(term.get_env()) => Ok(Value::Bool(false)),
+        TermType::Atom => Ok(Value::String(term.atom_to_string()?)),
+        TermType::Binary => Ok(Value::String(term.decode()?)),
+        TermType::Integer => match term.decode::<i64>() {
+            Ok(n) => Ok(Value::from(n)),
+            Err(_) => Ok(Value::from(term.decode::<u64>()?)),
+        },
+        TermType::Float => Number::from_f64(term.decode()?).map(Value::Number).ok_or(Error::BadArg),
+        TermType::List => term
+            .decode::<ListIterator>()?
+            .map(from_term)
+            .collect::<NifResult<Vec<_>>>()
+            .map(Value::Array),
+        TermType::Map => {
+            let mut object = Map::new();
+            for (key, value) in MapIterator::new(term).ok_or(Error::BadArg)? {
+                object.insert(key_string(key)?, from_term(value)?);
+            }
+            Ok(Value::Object(object))
+        }
+        _ => Err(Error::BadArg),
+    }
+}
+
+fn key_string(key: Term) -> NifResult<String> {
+    match from_term(key)? {
+        Value::String(s) => Ok(s),
+        Value::Number(n) => Ok(n.to_string()),
+        _ => Err(Error::BadArg),
+    }
+}
+
+// Convert JSON to an Elixir term; objects become maps with string keys
+pub fn to_term<'a>(env: Env<'a>, value: &Value) -> Term<'a> {
+    match value {
+        Value::Null => atom::nil().encode(env),
+        Value::Bool(b) => b.encode(env),
+        Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => i.encode(env),
+            (None, Some(u)) => u.encode(env),
+            _ => n.as_f64().encode(env),
+        },
+        Value::String(s) => s.encode(env),
+        Value::Array(items) => items.iter().map(|item| to_term(env, item)).collect::<Vec<_>>().encode(env),
+        Value::Object(object) => {
+            let keys: Vec<Term> = object.keys().map(|k| k.encode(env)).collect();
+            let values: Vec<Term> = object.values().map(|v| to_term(env, v)).collect();
+            Term::map_from_term_arrays(env, &keys, &values).expect("object keys are unique")
+        }
+    }
+}
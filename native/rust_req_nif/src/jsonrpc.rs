@@ -0,0 +1,245 @@
+use reqwest::blocking::Client;
+use rustler::{Encoder, Env, Error, NifResult, Term};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{atoms, audit, blocking, client_for, inflight, json, pipeline, resolve_options, HttpOptions};
+
+// Request ids are unique across all calls made by this VM
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+fn client_error(e: Box<dyn std::error::Error>) -> Error {
+    Error::Term(Box::new(format!("Client error: {}", e)))
+}
+
+fn invalid_response(reason: &str) -> Error {
+    Error::Term(Box::new(format!("Invalid JSON-RPC response: {}", reason)))
+}
+
+fn envelope(method: String, params: Term, id: u64) -> NifResult<Value> {
+    let mut request = Map::new();
+    request.insert("jsonrpc".to_string(), json!("2.0"));
+    request.insert("method".to_string(), Value::String(method));
+
+    // Params are by-position (list) or by-name (map), and may be omitted
+    match json::from_term(params)? {
+        Value::Null => {}
+        params @ (Value::Array(_) | Value::Object(_)) => {
+            request.insert("params".to_string(), params);
+        }
+        _ => return Err(Error::BadArg),
+    }
+
+    request.insert("id".to_string(), json!(id));
+    Ok(Value::Object(request))
+}
+
+fn post(
+    caller: Option<&str>,
+    options: &HttpOptions,
+    client: &Client,
+    url: &str,
+    headers: &[(String, String)],
+    body: &Value,
+) -> NifResult<Value> {
+    let mut request = client.post(url).json(body);
+
+    for (key, value) in headers {
+        request = request.header(key, value);
+    }
+
+    let response = pipeline::send(caller, options, request).map_err(|e| e.into_error())?;
+    let status = response.status();
+    let text = response
+        .text()
+        .map_err(|e| Error::Term(Box::new(format!("Body error: {}", e))))?;
+
+    decode(status, &text)
+}
+
+// Servers may report JSON-RPC errors with a non-2xx status; prefer the error object
+fn decode(status: reqwest::StatusCode, text: &str) -> NifResult<Value> {
+    match serde_json::from_str::<Value>(text) {
+        Ok(value) if status.is_success() || is_response(&value) => Ok(value),
+        _ if !status.is_success() => Err(Error::Term(Box::new((atoms::unexpected_status(), status.as_u16())))),
+        Err(e) => Err(invalid_response(&e.to_string())),
+        Ok(_) => Err(invalid_response("not a response object")),
+    }
+}
+
+fn is_response(value: &Value) -> bool {
+    match value {
+        Value::Object(object) => object.contains_key("result") || object.contains_key("error"),
+        Value::Array(items) => !items.is_empty() && items.iter().all(is_response),
+        _ => false,
+    }
+}
+
+fn error_reason(code: i64) -> rustler::Atom {
+    match code {
+        -32700 => atoms::parse_error(),
+        -32600 => atoms::invalid_request(),
+        -32601 => atoms::method_not_found(),
+        -32602 => atoms::invalid_params(),
+        -32603 => atoms::internal_error(),
+        -32099..=-32000 => atoms::server_error(),
+        _ => atoms::application_error(),
+    }
+}
+
+// `{:ok, result}` or `{:error, {:jsonrpc_error, reason, %{code, message, data}}}`
+fn outcome<'a>(env: Env<'a>, response: &Value) -> Term<'a> {
+    if let Some(error) = response.get("error") {
+        let code = error.get("code").and_then(Value::as_i64).unwrap_or(0);
+        let message = error.get("message").and_then(Value::as_str).unwrap_or("");
+        let data = json::to_term(env, error.get("data").unwrap_or(&Value::Null));
+
+        let details = Term::map_from_arrays(
+            env,
+            &[atoms::code(), atoms::message(), atoms::data()],
+            &[code.encode(env), message.encode(env), data],
+        )
+        .expect("keys are unique");
+
+        return (atoms::error(), (atoms::jsonrpc_error(), error_reason(code), details)).encode(env);
+    }
+
+    match response.get("result") {
+        Some(result) => (atoms::ok(), json::to_term(env, result)).encode(env),
+        None => (atoms::error(), "Invalid JSON-RPC response: no result or error").encode(env),
+    }
+}
+
+// Call `method` with `params` (a list, a map, or nil for none)
+#[rustler::nif(schedule = "DirtyIo")]
+fn jsonrpc_call<'a>(
+    env: Env<'a>,
+    url: String,
+    method: String,
+    params: Term<'a>,
+    headers: Vec<(String, String)>,
+    options: HttpOptions,
+) -> NifResult<Term<'a>> {
+    let _permit = blocking::acquire()?;
+    let _inflight = inflight::acquire()?;
+    let options = resolve_options(options)?;
+    let client = client_for(&options).map_err(client_error)?;
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let response = post(audit::caller(env).as_deref(), &options, &client, &url, &headers, &envelope(method, params, id)?)?;
+
+    if !answers(&response, id) {
+        return Err(invalid_response("id does not match the request"));
+    }
+
+    Ok(outcome(env, &response))
+}
+
+// Whether `response` answers the request with `id`. A request the server couldn't parse
+// is answered with an error and a null id.
+fn answers(response: &Value, id: u64) -> bool {
+    match response.get("id") {
+        Some(Value::Number(n)) => n.as_u64() == Some(id),
+        Some(Value::Null) | None => response.get("error").is_some(),
+        _ => false,
+    }
+}
+
+// The response to each of `ids`, in their order whatever order `responses` came in
+fn correlate(ids: &[u64], responses: Vec<Value>) -> Vec<Option<Value>> {
+    let mut by_id: HashMap<u64, Value> = responses
+        .into_iter()
+        .filter_map(|response| Some((response.get("id")?.as_u64()?, response)))
+        .collect();
+
+    ids.iter().map(|id| by_id.remove(id)).collect()
+}
+
+// Send `calls` as one batch; results are returned in the order of `calls`
+#[rustler::nif(schedule = "DirtyIo")]
+fn jsonrpc_batch<'a>(
+    env: Env<'a>,
+    url: String,
+    calls: Vec<(String, Term<'a>)>,
+    headers: Vec<(String, String)>,
+    options: HttpOptions,
+) -> NifResult<Term<'a>> {
+    let _permit = blocking::acquire()?;
+    let _inflight = inflight::acquire()?;
+    let options = resolve_options(options)?;
+    let client = client_for(&options).map_err(client_error)?;
+
+    let first_id = NEXT_ID.fetch_add(calls.len() as u64, Ordering::Relaxed);
+    let ids: Vec<u64> = (first_id..first_id + calls.len() as u64).collect();
+
+    let batch = calls
+        .into_iter()
+        .zip(&ids)
+        .map(|((method, params), id)| envelope(method, params, *id))
+        .collect::<NifResult<Vec<_>>>()?;
+
+    let responses = match post(audit::caller(env).as_deref(), &options, &client, &url, &headers, &Value::Array(batch))? {
+        Value::Array(responses) => responses,
+        // The batch as a whole was rejected, e.g. with a parse error
+        response @ Value::Object(_) if response.get("error").is_some() => return Ok(outcome(env, &response)),
+        _ => return Err(invalid_response("not a response array")),
+    };
+
+    let results: Vec<Term> = correlate(&ids, responses)
+        .into_iter()
+        .map(|response| match response {
+            Some(response) => outcome(env, &response),
+            None => (atoms::error(), atoms::no_response()).encode(env),
+        })
+        .collect();
+
+    Ok((atoms::ok(), results).encode(env))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn batch_responses_are_matched_by_id() {
+        let responses = vec![
+            json!({"jsonrpc": "2.0", "id": 12, "error": {"code": -32601, "message": "Method not found"}}),
+            json!({"jsonrpc": "2.0", "id": 99, "result": "stray"}),
+            json!({"jsonrpc": "2.0", "id": 10, "result": 1}),
+            json!({"jsonrpc": "2.0", "id": null, "error": {"code": -32600, "message": "Invalid Request"}}),
+        ];
+
+        let matched = correlate(&[10, 11, 12], responses);
+        assert_eq!(matched[0], Some(json!({"jsonrpc": "2.0", "id": 10, "result": 1})));
+        assert_eq!(matched[1], None);
+        assert_eq!(matched[2].as_ref().and_then(|r| r.pointer("/error/code")), Some(&json!(-32601)));
+    }
+
+    #[test]
+    fn a_call_is_answered_by_its_id_or_a_null_id_error() {
+        assert!(answers(&json!({"id": 7, "result": true}), 7));
+        assert!(!answers(&json!({"id": 8, "result": true}), 7));
+        assert!(!answers(&json!({"id": "7", "result": true}), 7));
+        assert!(answers(&json!({"id": null, "error": {"code": -32700, "message": "Parse error"}}), 7));
+        assert!(answers(&json!({"error": {"code": -32700, "message": "Parse error"}}), 7));
+        assert!(!answers(&json!({"id": null, "result": true}), 7));
+    }
+
+    #[test]
+    fn error_objects_count_as_responses_whatever_the_status() {
+        let error = r#"{"jsonrpc": "2.0", "id": 1, "error": {"code": -32000, "message": "busy", "data": {"retry": 5}}}"#;
+        assert_eq!(
+            decode(StatusCode::INTERNAL_SERVER_ERROR, error).ok().and_then(|r| r.pointer("/error/data/retry").cloned()),
+            Some(json!(5))
+        );
+        assert!(decode(StatusCode::OK, r#"[{"id": 1, "result": 1}, {"id": 2, "error": {}}]"#).is_ok());
+
+        assert!(decode(StatusCode::OK, "not json").is_err());
+        assert!(is_response(&json!({"result": null})));
+        assert!(!is_response(&json!({"id": 1})));
+        assert!(!is_response(&json!([])));
+        assert!(!is_response(&json!([{"result": 1}, {"id": 2}])));
+    }
+}
@@ -1,5 +1,66 @@
-use rustler::{Env, Term, NifResult, Error, Encoder, NifStruct};
-use std::collections::HashMap;
+use rustler::types::binary::NewBinary;
+use rustler::{Env, Term, NifResult, Error, Encoder, Decoder, NifStruct, NifMap, Atom, LocalPid, Binary, ResourceArc};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use background::RUNTIME;
+
+mod accept;
+mod audit;
+mod background;
+mod blocking;
+mod body;
+mod breaker;
+mod buffers;
+mod charset;
+mod clients;
+mod clock;
+mod cookies;
+mod dns;
+mod download;
+mod events;
+mod grpc;
+mod histogram;
+mod http_version;
+mod idempotency;
+mod inflight;
+mod json;
+mod jsonrpc;
+mod link;
+mod messages;
+mod multipart;
+mod ndjson;
+mod middleware;
+mod pac;
+mod pipeline;
+mod politeness;
+mod problem;
+mod progress;
+mod proxy_pool;
+mod rate_limit;
+mod retry;
+mod robots;
+mod rotation;
+mod s3;
+mod signatures;
+mod sigv4;
+mod soap;
+mod spnego;
+mod stats;
+mod stream;
+mod system_proxy;
+mod throttle;
+mod trailers;
+mod trust;
+mod tunnel;
+mod urls;
+mod tus;
+mod user_agents;
+mod webdav;
+mod wire;
+mod xml;
 
 mod atoms {
     rustler::atoms! {
@@ -7,179 +68,2005 @@ mod atoms {
         error,
         timeout,
         network_error,
+        dns_error,
+        tls_error,
+        connection_refused,
+        connection_reset,
+        proxy_error,
+        body_interrupted,
+        status,
+        headers,
+        partial_body,
+        bytes_received,
         invalid_url,
+        global,
+        unknown_profile,
+        profile_cycle,
+        eof,
+        upload_progress,
+        segment_failed,
+        resource_changed,
+        offset_mismatch,
+        checksum_mismatch,
+        upload_not_found,
+        unexpected_status,
+        upload_failed,
+        too_many_redirects,
+        early_hints,
+        informational,
+        rust_req_event,
+        enqueue,
+        connect,
+        redirect,
+        retry,
+        warning,
+        complete,
+        debug,
+        dns,
+        rust_req_middleware,
+        on_request,
+        on_response,
+        middleware_halt,
+        pass,
+        halt,
+        jsonrpc_error,
+        parse_error,
+        invalid_request,
+        method_not_found,
+        invalid_params,
+        internal_error,
+        server_error,
+        application_error,
+        no_response,
+        code,
+        message,
+        data,
+        soap_fault,
+        reason,
+        detail,
+        http_status,
+        body,
+        raw_body,
+        meta,
+        first,
+        last,
+        problem,
+        type_ = "type",
+        title,
+        instance,
+        extensions,
+        not_attempted,
+        attempts,
+        idempotency_key,
+        rate_limit,
+        variant,
+        overloaded,
+        draining,
+        slices,
+        rust_req_response,
+        __struct__,
+        __exception__,
+        error_module = "Elixir.RustReq.Error",
+        method,
+        url,
+        attempt,
+        elapsed_ms,
+        rust_req_batch,
+        cancelled,
+        batch_progress,
+        invalid_key,
+        decryption_failed,
+        not_modified,
+        allow,
+        disallow,
+        no_proxy_available,
+        available,
+        quarantined,
+        rust_req_ndjson,
+        line,
+        invalid_line,
+        done,
+        key_id,
+        algorithm,
+        key,
+        components,
+        label,
+        expires_in,
+        nonce,
+        tag,
+        required_components,
+        signature,
+        clock_skew,
+        invalid_signature,
+        form,
+        pin_mismatch,
+        version,
+        http0_9,
+        http1_0,
+        http1_1,
+        http2,
+        http3,
+        system,
+        circuit_open,
+        closed,
+        open,
+        half_open,
+    }
+}
+
+// Arbitrary Elixir term carried in options, stored in external term format
+#[derive(Debug, Clone, PartialEq)]
+struct EncodedTerm(Vec<u8>);
+
+impl<'a> Decoder<'a> for EncodedTerm {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        Ok(EncodedTerm(term.to_binary().as_slice().to_vec()))
+    }
+}
+
+impl Encoder for EncodedTerm {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        env.binary_to_term(&self.0)
+            .map(|(term, _)| term)
+            .unwrap_or_else(|| rustler::types::atom::nil().encode(env))
+    }
+}
+
+// Statuses `error_on_status` turns into errors, as inclusive ranges. Keeps the term it
+// was given, so options read back unchanged.
+#[derive(Debug, Clone, PartialEq)]
+struct StatusFilter {
+    ranges: Vec<(u16, u16)>,
+    term: EncodedTerm,
+}
+
+impl StatusFilter {
+    fn matches(&self, status: u16) -> bool {
+        self.ranges.iter().any(|&(first, last)| (first..=last).contains(&status))
+    }
+}
+
+// `true` for every 4xx and 5xx, `false` for none, or a status, a `first..last` range or
+// a list of those
+fn status_ranges(term: Term) -> NifResult<Vec<(u16, u16)>> {
+    if let Ok(enabled) = term.decode::<bool>() {
+        return Ok(if enabled { vec![(400, 599)] } else { Vec::new() });
+    }
+
+    if let Ok(status) = term.decode::<u16>() {
+        return Ok(vec![(status, status)]);
+    }
+
+    if let Ok(items) = term.decode::<Vec<Term>>() {
+        let ranges = items.into_iter().map(status_ranges).collect::<NifResult<Vec<_>>>()?;
+        return Ok(ranges.concat());
+    }
+
+    let first: u16 = term.map_get(atoms::first())?.decode()?;
+    let last: u16 = term.map_get(atoms::last())?.decode()?;
+    Ok(vec![(first.min(last), first.max(last))])
+}
+
+impl<'a> Decoder<'a> for StatusFilter {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        Ok(StatusFilter {
+            ranges: status_ranges(term)?,
+            term: term.decode()?,
+        })
+    }
+}
+
+impl Encoder for StatusFilter {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        self.term.encode(env)
+    }
+}
+
+#[derive(Clone, PartialEq, NifStruct)]
+#[module = "RustReq.Options"]
+struct HttpOptions {
+    timeout_ms: Option<u64>,
+    proxy: Option<system_proxy::ProxySetting>,
+    proxy_headers: Option<Vec<(String, String)>>,
+    follow_redirects: Option<bool>,
+    max_redirects: Option<usize>,
+    user_agent: Option<String>,
+    ca_bundle: Option<String>,
+    ca_cert_pem: Option<String>,
+    insecure_skip_verify: Option<bool>,
+    verify_hostname: Option<bool>,
+    tls_min_version: Option<trust::TlsVersion>,
+    tls_max_version: Option<trust::TlsVersion>,
+    pinned_spki_sha256: Option<Vec<String>>,
+    http_version: Option<http_version::HttpVersion>,
+    version_info: Option<bool>,
+    proxy_username: Option<String>,
+    proxy_password: Option<String>,
+    no_proxy: Option<Vec<String>>,
+    connect_timeout_ms: Option<u64>,
+    read_timeout_ms: Option<u64>,
+    pool_idle_timeout_ms: Option<u64>,
+    retries: Option<u32>,
+    retry_backoff_ms: Option<u64>,
+    retry_jitter: Option<bool>,
+    circuit_breaker_threshold: Option<u32>,
+    circuit_breaker_cooldown_ms: Option<u64>,
+    request_rate: Option<(u32, u64)>,
+    request_rate_per_host: Option<bool>,
+    tls_roots: Option<trust::TlsRoots>,
+    profile: Option<String>,
+    tee_bytes: Option<usize>,
+    early_hints: Option<bool>,
+    informational_pid: Option<LocalPid>,
+    debug: Option<bool>,
+    dns_info: Option<bool>,
+    middleware: Option<LocalPid>,
+    middleware_timeout_ms: Option<u64>,
+    progress_pid: Option<LocalPid>,
+    progress_tag: Option<EncodedTerm>,
+    progress_interval_ms: Option<u64>,
+    segments: Option<usize>,
+    segment_retries: Option<u32>,
+    error_on_status: Option<StatusFilter>,
+    problem_details: Option<bool>,
+    request_timeout_ms: Option<u64>,
+    batch_deadline_ms: Option<u64>,
+    batch_retries: Option<u32>,
+    batch_retry_backoff_ms: Option<u64>,
+    batch_progress_interval_ms: Option<u64>,
+    batch_progress_every: Option<usize>,
+    cookie_jar: Option<cookies::JarRef>,
+    user_agent_pool: Option<Vec<String>>,
+    user_agent_strategy: Option<rotation::Strategy>,
+    proxies: Option<Vec<String>>,
+    proxy_strategy: Option<rotation::Strategy>,
+    proxy_quarantine_ms: Option<u64>,
+    proxy_max_failures: Option<u32>,
+    per_host_delay_ms: Option<u64>,
+    negotiate_auth: Option<bool>,
+    proxy_negotiate_auth: Option<bool>,
+    proxy_pac: Option<String>,
+    idempotency: Option<bool>,
+    body_resource: Option<bool>,
+    multipart: Option<bool>,
+    rate_limit: Option<bool>,
+    accept: Option<Vec<(String, accept::QValue)>>,
+    http_signature: Option<signatures::Signer>,
+    verify_http_signatures: Option<Vec<signatures::VerifyKey>>,
+    request_trailers: Option<Vec<(String, String)>>,
+    expect_fallback: Option<bool>,
+    raw_query: Option<bool>,
+    url_fragment: Option<urls::Fragment>,
+    url_userinfo: Option<urls::Userinfo>,
+    clock_skew: Option<bool>,
+    max_download_bytes_per_sec: Option<u64>,
+    max_upload_bytes_per_sec: Option<u64>,
+    body_format: Option<body::BodyFormat>,
+    decode_json: Option<bool>,
+    query: Option<Vec<(String, String)>>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        HttpOptions {
+            timeout_ms: Some(30000),
+            proxy: None,
+            proxy_headers: None,
+            follow_redirects: Some(true),
+            max_redirects: Some(10),
+            user_agent: None,
+            ca_bundle: None,
+            ca_cert_pem: None,
+            insecure_skip_verify: Some(false),
+            verify_hostname: Some(true),
+            tls_min_version: None,
+            tls_max_version: None,
+            pinned_spki_sha256: None,
+            http_version: Some(http_version::HttpVersion::Auto),
+            version_info: Some(false),
+            proxy_username: None,
+            proxy_password: None,
+            no_proxy: None,
+            connect_timeout_ms: None,
+            read_timeout_ms: None,
+            pool_idle_timeout_ms: Some(90000),
+            retries: Some(0),
+            retry_backoff_ms: Some(100),
+            retry_jitter: Some(false),
+            circuit_breaker_threshold: None,
+            circuit_breaker_cooldown_ms: Some(30000),
+            request_rate: None,
+            request_rate_per_host: Some(false),
+            tls_roots: Some(trust::TlsRoots::Webpki),
+            profile: None,
+            tee_bytes: Some(0),
+            early_hints: Some(false),
+            informational_pid: None,
+            debug: Some(false),
+            dns_info: Some(false),
+            middleware: None,
+            middleware_timeout_ms: Some(1000),
+            progress_pid: None,
+            progress_tag: None,
+            progress_interval_ms: Some(500),
+            segments: Some(4),
+            segment_retries: Some(3),
+            error_on_status: None,
+            problem_details: Some(false),
+            request_timeout_ms: None,
+            batch_deadline_ms: None,
+            batch_retries: Some(0),
+            batch_retry_backoff_ms: Some(100),
+            batch_progress_interval_ms: None,
+            batch_progress_every: None,
+            cookie_jar: None,
+            user_agent_pool: None,
+            user_agent_strategy: Some(rotation::Strategy::RoundRobin),
+            proxies: None,
+            proxy_strategy: Some(rotation::Strategy::RoundRobin),
+            proxy_quarantine_ms: Some(30000),
+            proxy_max_failures: Some(3),
+            per_host_delay_ms: None,
+            negotiate_auth: Some(false),
+            proxy_negotiate_auth: Some(false),
+            proxy_pac: None,
+            idempotency: Some(false),
+            body_resource: Some(false),
+            multipart: Some(false),
+            rate_limit: Some(false),
+            accept: None,
+            http_signature: None,
+            verify_http_signatures: None,
+            request_trailers: None,
+            expect_fallback: Some(true),
+            raw_query: Some(false),
+            url_fragment: Some(urls::Fragment::Strip),
+            url_userinfo: Some(urls::Userinfo::BasicAuth),
+            clock_skew: Some(false),
+            max_download_bytes_per_sec: None,
+            max_upload_bytes_per_sec: None,
+            body_format: Some(body::BodyFormat::Binary),
+            decode_json: Some(false),
+            query: None,
+        }
+    }
+}
+
+// What is wrong with a proxy URL, if anything
+fn proxy_url_error(proxy_url: &str) -> Option<String> {
+    match reqwest::Url::parse(proxy_url) {
+        Ok(url) if !matches!(url.scheme(), "http" | "https" | "socks5" | "socks5h") => {
+            Some(format!("unsupported proxy scheme: {}", url.scheme()))
+        }
+        Ok(url) if url.host_str().is_none() => Some("proxy URL has no host".to_string()),
+        Ok(_) => reqwest::Proxy::all(proxy_url).err().map(|e| e.to_string()),
+        Err(e) => Some(format!("invalid proxy URL: {}", e)),
+    }
+}
+
+impl HttpOptions {
+    // Fill every unset field from `base`, so per-call options win over defaults
+    fn merge(self, base: &HttpOptions) -> HttpOptions {
+        HttpOptions {
+            timeout_ms: self.timeout_ms.or(base.timeout_ms),
+            proxy: self.proxy.or_else(|| base.proxy.clone()),
+            proxy_headers: self.proxy_headers.or_else(|| base.proxy_headers.clone()),
+            follow_redirects: self.follow_redirects.or(base.follow_redirects),
+            max_redirects: self.max_redirects.or(base.max_redirects),
+            user_agent: self.user_agent.or_else(|| base.user_agent.clone()),
+            ca_bundle: self.ca_bundle.or_else(|| base.ca_bundle.clone()),
+            ca_cert_pem: self.ca_cert_pem.or_else(|| base.ca_cert_pem.clone()),
+            insecure_skip_verify: self.insecure_skip_verify.or(base.insecure_skip_verify),
+            verify_hostname: self.verify_hostname.or(base.verify_hostname),
+            tls_min_version: self.tls_min_version.or(base.tls_min_version),
+            tls_max_version: self.tls_max_version.or(base.tls_max_version),
+            pinned_spki_sha256: self.pinned_spki_sha256.or_else(|| base.pinned_spki_sha256.clone()),
+            http_version: self.http_version.or(base.http_version),
+            version_info: self.version_info.or(base.version_info),
+            proxy_username: self.proxy_username.or_else(|| base.proxy_username.clone()),
+            proxy_password: self.proxy_password.or_else(|| base.proxy_password.clone()),
+            no_proxy: self.no_proxy.or_else(|| base.no_proxy.clone()),
+            connect_timeout_ms: self.connect_timeout_ms.or(base.connect_timeout_ms),
+            read_timeout_ms: self.read_timeout_ms.or(base.read_timeout_ms),
+            pool_idle_timeout_ms: self.pool_idle_timeout_ms.or(base.pool_idle_timeout_ms),
+            retries: self.retries.or(base.retries),
+            retry_backoff_ms: self.retry_backoff_ms.or(base.retry_backoff_ms),
+            retry_jitter: self.retry_jitter.or(base.retry_jitter),
+            circuit_breaker_threshold: self.circuit_breaker_threshold.or(base.circuit_breaker_threshold),
+            circuit_breaker_cooldown_ms: self.circuit_breaker_cooldown_ms.or(base.circuit_breaker_cooldown_ms),
+            request_rate: self.request_rate.or(base.request_rate),
+            request_rate_per_host: self.request_rate_per_host.or(base.request_rate_per_host),
+            tls_roots: self.tls_roots.or(base.tls_roots),
+            profile: self.profile.or_else(|| base.profile.clone()),
+            tee_bytes: self.tee_bytes.or(base.tee_bytes),
+            early_hints: self.early_hints.or(base.early_hints),
+            informational_pid: self.informational_pid.or(base.informational_pid),
+            debug: self.debug.or(base.debug),
+            dns_info: self.dns_info.or(base.dns_info),
+            middleware: self.middleware.or(base.middleware),
+            middleware_timeout_ms: self.middleware_timeout_ms.or(base.middleware_timeout_ms),
+            progress_pid: self.progress_pid.or(base.progress_pid),
+            progress_tag: self.progress_tag.or_else(|| base.progress_tag.clone()),
+            progress_interval_ms: self.progress_interval_ms.or(base.progress_interval_ms),
+            segments: self.segments.or(base.segments),
+            segment_retries: self.segment_retries.or(base.segment_retries),
+            error_on_status: self.error_on_status.or_else(|| base.error_on_status.clone()),
+            problem_details: self.problem_details.or(base.problem_details),
+            request_timeout_ms: self.request_timeout_ms.or(base.request_timeout_ms),
+            batch_deadline_ms: self.batch_deadline_ms.or(base.batch_deadline_ms),
+            batch_retries: self.batch_retries.or(base.batch_retries),
+            batch_retry_backoff_ms: self.batch_retry_backoff_ms.or(base.batch_retry_backoff_ms),
+            batch_progress_interval_ms: self.batch_progress_interval_ms.or(base.batch_progress_interval_ms),
+            batch_progress_every: self.batch_progress_every.or(base.batch_progress_every),
+            cookie_jar: self.cookie_jar.or_else(|| base.cookie_jar.clone()),
+            user_agent_pool: self.user_agent_pool.or_else(|| base.user_agent_pool.clone()),
+            user_agent_strategy: self.user_agent_strategy.or(base.user_agent_strategy),
+            proxies: self.proxies.or_else(|| base.proxies.clone()),
+            proxy_strategy: self.proxy_strategy.or(base.proxy_strategy),
+            proxy_quarantine_ms: self.proxy_quarantine_ms.or(base.proxy_quarantine_ms),
+            proxy_max_failures: self.proxy_max_failures.or(base.proxy_max_failures),
+            per_host_delay_ms: self.per_host_delay_ms.or(base.per_host_delay_ms),
+            negotiate_auth: self.negotiate_auth.or(base.negotiate_auth),
+            proxy_negotiate_auth: self.proxy_negotiate_auth.or(base.proxy_negotiate_auth),
+            proxy_pac: self.proxy_pac.or_else(|| base.proxy_pac.clone()),
+            idempotency: self.idempotency.or(base.idempotency),
+            body_resource: self.body_resource.or(base.body_resource),
+            multipart: self.multipart.or(base.multipart),
+            rate_limit: self.rate_limit.or(base.rate_limit),
+            accept: self.accept.or_else(|| base.accept.clone()),
+            http_signature: self.http_signature.or_else(|| base.http_signature.clone()),
+            verify_http_signatures: self.verify_http_signatures.or_else(|| base.verify_http_signatures.clone()),
+            request_trailers: self.request_trailers.or_else(|| base.request_trailers.clone()),
+            expect_fallback: self.expect_fallback.or(base.expect_fallback),
+            raw_query: self.raw_query.or(base.raw_query),
+            url_fragment: self.url_fragment.or(base.url_fragment),
+            url_userinfo: self.url_userinfo.or(base.url_userinfo),
+            clock_skew: self.clock_skew.or(base.clock_skew),
+            max_download_bytes_per_sec: self.max_download_bytes_per_sec.or(base.max_download_bytes_per_sec),
+            max_upload_bytes_per_sec: self.max_upload_bytes_per_sec.or(base.max_upload_bytes_per_sec),
+            body_format: self.body_format.or(base.body_format),
+            decode_json: self.decode_json.or(base.decode_json),
+            query: self.query.or_else(|| base.query.clone()),
+        }
+    }
+
+    // Check every set field, collecting a `(field, reason)` pair per problem
+    fn validate(&self) -> Vec<(&'static str, String)> {
+        let mut errors = Vec::new();
+
+        if self.timeout_ms == Some(0) {
+            errors.push(("timeout_ms", "must be greater than 0".to_string()));
+        }
+
+        if self.connect_timeout_ms == Some(0) {
+            errors.push(("connect_timeout_ms", "must be greater than 0".to_string()));
+        }
+
+        if self.read_timeout_ms == Some(0) {
+            errors.push(("read_timeout_ms", "must be greater than 0".to_string()));
+        }
+
+        if self.pool_idle_timeout_ms == Some(0) {
+            errors.push(("pool_idle_timeout_ms", "must be greater than 0".to_string()));
+        }
+
+        if self.circuit_breaker_threshold == Some(0) {
+            errors.push(("circuit_breaker_threshold", "must be greater than 0".to_string()));
+        }
+
+        if self.circuit_breaker_cooldown_ms == Some(0) {
+            errors.push(("circuit_breaker_cooldown_ms", "must be greater than 0".to_string()));
+        }
+
+        if self.request_rate.is_some_and(|(requests, per_ms)| requests == 0 || per_ms == 0) {
+            errors.push(("request_rate", "requests and per_ms must be greater than 0".to_string()));
+        }
+
+        if self.request_timeout_ms == Some(0) {
+            errors.push(("request_timeout_ms", "must be greater than 0".to_string()));
+        }
+
+        if self.batch_progress_interval_ms == Some(0) {
+            errors.push(("batch_progress_interval_ms", "must be greater than 0".to_string()));
+        }
+
+        if self.batch_progress_every == Some(0) {
+            errors.push(("batch_progress_every", "must be greater than 0".to_string()));
+        }
+
+        if let Some(error) = self.proxy.as_ref().and_then(|proxy| proxy.url()).and_then(proxy_url_error) {
+            errors.push(("proxy", error));
+        }
+
+        if let Some(proxies) = &self.proxies {
+            if proxies.is_empty() {
+                errors.push(("proxies", "must not be empty".to_string()));
+            }
+            for error in proxies.iter().filter_map(|proxy_url| proxy_url_error(proxy_url)) {
+                errors.push(("proxies", error));
+            }
+        }
+
+        if self.max_download_bytes_per_sec == Some(0) {
+            errors.push(("max_download_bytes_per_sec", "must be greater than 0".to_string()));
+        }
+
+        if self.max_upload_bytes_per_sec == Some(0) {
+            errors.push(("max_upload_bytes_per_sec", "must be greater than 0".to_string()));
+        }
+
+        if self.proxy_max_failures == Some(0) {
+            errors.push(("proxy_max_failures", "must be greater than 0".to_string()));
+        }
+
+        if self.proxy_password.is_some() && self.proxy_username.is_none() {
+            errors.push(("proxy_password", "requires proxy_username".to_string()));
+        }
+
+        if self.proxy_username.as_deref().is_some_and(|username| username.contains(':')) {
+            errors.push(("proxy_username", "must not contain ':'".to_string()));
+        }
+
+        if let Some(hosts) = &self.no_proxy {
+            for host in hosts.iter().filter(|host| host.is_empty() || host.contains(|c: char| c == ',' || c.is_whitespace())) {
+                errors.push(("no_proxy", format!("invalid entry: {:?}", host)));
+            }
+        }
+
+        if let Some(headers) = &self.proxy_headers {
+            if let Err(e) = proxy_header_map(headers) {
+                errors.push(("proxy_headers", e.to_string()));
+            }
+        }
+
+        if let Some(user_agent) = &self.user_agent {
+            if reqwest::header::HeaderValue::from_str(user_agent).is_err() {
+                errors.push(("user_agent", "not a valid header value".to_string()));
+            }
+        }
+
+        if let Some(pool) = &self.user_agent_pool {
+            if pool.is_empty() {
+                errors.push(("user_agent_pool", "must not be empty".to_string()));
+            }
+            if pool.iter().any(|user_agent| reqwest::header::HeaderValue::from_str(user_agent).is_err()) {
+                errors.push(("user_agent_pool", "not a list of valid header values".to_string()));
+            }
+        }
+
+        if let Some(preferences) = &self.accept {
+            for error in accept::errors(preferences) {
+                errors.push(("accept", error));
+            }
+        }
+
+        errors.extend(signatures::errors(self));
+
+        for error in trailers::errors(self) {
+            errors.push(("request_trailers", error));
+        }
+
+        if let Some(path) = &self.ca_bundle {
+            match std::fs::read(path) {
+                Ok(pem) => match reqwest::Certificate::from_pem_bundle(&pem) {
+                    Ok(certs) if certs.is_empty() => {
+                        errors.push(("ca_bundle", "no certificates found".to_string()));
+                    }
+                    Ok(_) => {}
+                    Err(e) => errors.push(("ca_bundle", format!("invalid PEM: {}", e))),
+                },
+                Err(e) => errors.push(("ca_bundle", format!("cannot read {}: {}", path, e))),
+            }
+        }
+
+        if let Err(e) = trust::insecure(self) {
+            errors.push(("insecure_skip_verify", e));
+        }
+
+        if trust::versions(self).is_empty() {
+            errors.push(("tls_min_version", "must not be above tls_max_version".to_string()));
+        }
+
+        match http_version::resolve(self) {
+            Err(e) => errors.push(("http_version", e)),
+            Ok(http_version::HttpVersion::Http2PriorKnowledge) if self.needs_wire() => errors.push((
+                "http_version",
+                "early_hints, informational_pid, debug, dns_info and raw_query speak HTTP/1.1 only".to_string(),
+            )),
+            Ok(_) => {}
+        }
+
+        if let Err(e) = trust::pins(self) {
+            errors.push(("pinned_spki_sha256", e));
+        }
+
+        if let Some(pem) = &self.ca_cert_pem {
+            match reqwest::Certificate::from_pem_bundle(pem.as_bytes()) {
+                Ok(certs) if certs.is_empty() => errors.push(("ca_cert_pem", "no certificates found".to_string())),
+                Ok(_) => {}
+                Err(e) => errors.push(("ca_cert_pem", format!("invalid PEM: {}", e))),
+            }
+        }
+
+        if trust::sources(self).0 {
+            if let Err(e) = trust::system_roots() {
+                errors.push(("tls_roots", e));
+            }
+        }
+
+        if let Some(name) = &self.profile {
+            if !PROFILES.read().unwrap().contains_key(name) {
+                errors.push(("profile", format!("unknown profile: {}", name)));
+            }
+        }
+
+        errors
+    }
+
+    // Whether the request needs a connection of our own (see `wire`)
+    fn needs_wire(&self) -> bool {
+        self.early_hints == Some(true)
+            || self.informational_pid.is_some()
+            || self.debug == Some(true)
+            || self.dns_info == Some(true)
+            || self.raw_query == Some(true)
+    }
+
+    // Lowest-precedence configuration layer, read from `RUST_REQ_*` environment variables,
+    // with the fields whose variables hold values that don't parse
+    fn from_env() -> (HttpOptions, Vec<(&'static str, String)>) {
+        fn var(name: &str) -> Option<String> {
+            std::env::var(name).ok().filter(|value| !value.is_empty())
+        }
+
+        fn parsed<T: std::str::FromStr>(name: &str, field: &'static str, errors: &mut Vec<(&'static str, String)>) -> Option<T> {
+            let value = var(name)?;
+            let parsed = value.trim().parse().ok();
+            if parsed.is_none() {
+                errors.push((field, format!("{} has invalid value {:?}", name, value)));
+            }
+            parsed
+        }
+
+        let mut errors = Vec::new();
+        let options = HttpOptions {
+            timeout_ms: parsed("RUST_REQ_TIMEOUT_MS", "timeout_ms", &mut errors),
+            proxy: var("RUST_REQ_PROXY").map(system_proxy::ProxySetting::Url),
+            proxy_headers: None,
+            follow_redirects: parsed("RUST_REQ_FOLLOW_REDIRECTS", "follow_redirects", &mut errors),
+            max_redirects: parsed("RUST_REQ_MAX_REDIRECTS", "max_redirects", &mut errors),
+            user_agent: var("RUST_REQ_USER_AGENT"),
+            ca_bundle: var("RUST_REQ_CA_BUNDLE"),
+            ca_cert_pem: None,
+            insecure_skip_verify: None,
+            verify_hostname: None,
+            tls_min_version: None,
+            tls_max_version: None,
+            pinned_spki_sha256: None,
+            http_version: None,
+            version_info: None,
+            proxy_username: var("RUST_REQ_PROXY_USERNAME"),
+            proxy_password: var("RUST_REQ_PROXY_PASSWORD"),
+            no_proxy: None,
+            connect_timeout_ms: parsed("RUST_REQ_CONNECT_TIMEOUT_MS", "connect_timeout_ms", &mut errors),
+            read_timeout_ms: parsed("RUST_REQ_READ_TIMEOUT_MS", "read_timeout_ms", &mut errors),
+            pool_idle_timeout_ms: parsed("RUST_REQ_POOL_IDLE_TIMEOUT_MS", "pool_idle_timeout_ms", &mut errors),
+            retries: None,
+            retry_backoff_ms: None,
+            retry_jitter: None,
+            circuit_breaker_threshold: None,
+            circuit_breaker_cooldown_ms: None,
+            request_rate: None,
+            request_rate_per_host: None,
+            tls_roots: None,
+            profile: None,
+            tee_bytes: None,
+            early_hints: None,
+            informational_pid: None,
+            debug: None,
+            dns_info: None,
+            middleware: None,
+            middleware_timeout_ms: None,
+            progress_pid: None,
+            progress_tag: None,
+            progress_interval_ms: None,
+            segments: None,
+            segment_retries: None,
+            error_on_status: None,
+            problem_details: None,
+            request_timeout_ms: None,
+            batch_deadline_ms: None,
+            batch_retries: None,
+            batch_retry_backoff_ms: None,
+            batch_progress_interval_ms: None,
+            batch_progress_every: None,
+            cookie_jar: None,
+            user_agent_pool: None,
+            user_agent_strategy: None,
+            proxies: None,
+            proxy_strategy: None,
+            proxy_quarantine_ms: None,
+            proxy_max_failures: None,
+            per_host_delay_ms: None,
+            negotiate_auth: None,
+            proxy_negotiate_auth: None,
+            proxy_pac: None,
+            idempotency: None,
+            body_resource: None,
+            multipart: None,
+            rate_limit: None,
+            accept: None,
+            http_signature: None,
+            verify_http_signatures: None,
+            request_trailers: None,
+            expect_fallback: None,
+            raw_query: None,
+            url_fragment: None,
+            url_userinfo: None,
+            clock_skew: None,
+            max_download_bytes_per_sec: None,
+            max_upload_bytes_per_sec: None,
+            body_format: None,
+            decode_json: None,
+            query: None,
+        };
+
+        // Values that parse may still be invalid, e.g. a zero timeout or a bad proxy URL
+        errors.extend(
+            options
+                .validate()
+                .into_iter()
+                .map(|(field, reason)| (field, format!("{} (from the environment)", reason))),
+        );
+        (options, errors)
+    }
+}
+
+// The environment layer and its problems, read once at NIF load
+static ENVIRONMENT: LazyLock<(HttpOptions, Vec<(&'static str, String)>)> = LazyLock::new(HttpOptions::from_env);
+
+// Built-in defaults overlaid with the environment
+static BASE_DEFAULTS: LazyLock<HttpOptions> = LazyLock::new(|| ENVIRONMENT.0.clone().merge(&HttpOptions::default()));
+
+// Application-wide defaults, layered between per-call options and the base defaults
+static DEFAULTS: LazyLock<RwLock<HttpOptions>> = LazyLock::new(|| RwLock::new(BASE_DEFAULTS.clone()));
+
+// Named option presets; a profile may itself name a parent profile to inherit from
+static PROFILES: LazyLock<RwLock<HashMap<String, HttpOptions>>> = LazyLock::new(|| RwLock::new(HashMap::new()));
+
+// Longest profile inheritance chain followed before assuming a cycle
+const MAX_PROFILE_DEPTH: usize = 16;
+
+// Layer per-call options over their profile chain, then over the application-wide defaults
+fn resolve_options(options: HttpOptions) -> NifResult<HttpOptions> {
+    let defaults = DEFAULTS.read().unwrap().clone();
+    let profiles = PROFILES.read().unwrap();
+
+    let mut resolved = options;
+    let mut next = resolved.profile.clone().or_else(|| defaults.profile.clone());
+    let mut depth = 0;
+
+    while let Some(name) = next {
+        if depth == MAX_PROFILE_DEPTH {
+            return Err(Error::Term(Box::new((atoms::profile_cycle(), name))));
+        }
+
+        let profile = profiles
+            .get(&name)
+            .ok_or_else(|| Error::Term(Box::new((atoms::unknown_profile(), name.clone()))))?;
+
+        resolved = resolved.merge(profile);
+        next = profile.profile.clone();
+        depth += 1;
+    }
+
+    Ok(resolved.merge(&defaults))
+}
+
+#[derive(Debug)]
+struct HttpResponse {
+    status: u16,
+    headers: HashMap<String, String>,
+    body: body::ResponseBody,
+    meta: Option<ResponseMeta>,
+}
+
+impl HttpResponse {
+    // `{status, headers, body}`, or `{status, headers, body, meta}` with metadata
+    fn encode_tuple<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let headers_map: Vec<(String, String)> = self.headers.clone().into_iter().collect();
+
+        match &self.meta {
+            Some(meta) => (self.status, headers_map, &self.body, meta).encode(env),
+            None => (self.status, headers_map, &self.body).encode(env),
+        }
+    }
+}
+
+impl Encoder for HttpResponse {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        (atoms::ok(), self.encode_tuple(env)).encode(env)
+    }
+}
+
+// A response failed by `problem_details` or `error_on_status`
+enum StatusError {
+    // `{:problem, details}`
+    Problem(problem::Problem),
+    // `{:http_status, status, response}`
+    Status(Box<HttpResponse>),
+    // `{:invalid_signature, message}`
+    Signature(String),
+}
+
+// The decoded body of a JSON (`application/json` or `+json`) response, if it parses
+fn json_body(response: &HttpResponse) -> Option<serde_json::Value> {
+    let media_type = response.headers.get("content-type")?.split(';').next()?.trim().to_ascii_lowercase();
+    if media_type != "application/json" && !media_type.ends_with("+json") {
+        return None;
+    }
+    serde_json::from_str(response.body.text()).ok()
+}
+
+impl Encoder for StatusError {
+    // A JSON response is given as `%{status, headers, body, raw_body, meta}` with the body
+    // decoded, any other as the response tuple
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            StatusError::Problem(problem) => problem.encode(env),
+            StatusError::Signature(message) => (atoms::invalid_signature(), message).encode(env),
+            StatusError::Status(response) => {
+                let details = match json_body(response) {
+                    Some(body) => Term::map_from_arrays(
+                        env,
+                        &[atoms::status(), atoms::headers(), atoms::body(), atoms::raw_body(), atoms::meta()],
+                        &[
+                            response.status.encode(env),
+                            response.headers.clone().into_iter().collect::<Vec<_>>().encode(env),
+                            json::to_term(env, &body),
+                            response.body.encode(env),
+                            response.meta.as_ref().map(|meta| meta.encode(env)).encode(env),
+                        ],
+                    )
+                    .expect("keys are unique"),
+                    None => response.encode_tuple(env),
+                };
+                (atoms::http_status(), response.status, details).encode(env)
+            }
+        }
+    }
+}
+
+impl From<StatusError> for Error {
+    fn from(error: StatusError) -> Self {
+        Error::Term(Box::new(error))
+    }
+}
+
+// Fail a response without a valid signature when `verify_http_signatures` is set, an
+// error response carrying problem details when `problem_details` is set, and any
+// response whose status `error_on_status` selects. Rate limits and the variant served
+// are reported first, so failed responses carry them too.
+fn check_status(options: &HttpOptions, response: HttpResponse) -> Result<HttpResponse, StatusError> {
+    let response = accept::annotate(options, rate_limit::annotate(options, response));
+    let response = clock::annotate(options, response);
+    let response = signatures::verify(options, response).map_err(StatusError::Signature)?;
+
+    if options.problem_details == Some(true) {
+        if let Some(problem) = problem::parse(response.status, &response.headers, response.body.text()) {
+            return Err(StatusError::Problem(problem));
+        }
+    }
+
+    match &options.error_on_status {
+        Some(filter) if filter.matches(response.status) => Err(StatusError::Status(Box::new(response))),
+        _ => Ok(response),
+    }
+}
+
+// Extra response details for options that ask for them, returned as a fourth tuple element
+#[derive(Debug, Default)]
+struct ResponseMeta {
+    early_hints: Option<Vec<Vec<(String, String)>>>,
+    debug: Option<wire::Capture>,
+    dns: Option<wire::Resolution>,
+    attempts: Option<u32>,
+    idempotency_key: Option<String>,
+    rate_limit: Option<rate_limit::RateLimit>,
+    variant: Option<accept::Variant>,
+    signature: Option<signatures::Verified>,
+    clock_skew: Option<clock::ClockSkew>,
+    version: Option<Atom>,
+}
+
+impl ResponseMeta {
+    fn is_empty(&self) -> bool {
+        self.early_hints.is_none()
+            && self.debug.is_none()
+            && self.dns.is_none()
+            && self.attempts.is_none()
+            && self.idempotency_key.is_none()
+            && self.rate_limit.is_none()
+            && self.variant.is_none()
+            && self.signature.is_none()
+            && self.clock_skew.is_none()
+            && self.version.is_none()
+    }
+}
+
+impl Encoder for ResponseMeta {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let mut map = rustler::types::map::map_new(env);
+
+        if let Some(hints) = &self.early_hints {
+            map = map.map_put(atoms::early_hints(), hints).expect("map");
+        }
+
+        if let Some(capture) = &self.debug {
+            map = map.map_put(atoms::debug(), capture).expect("map");
+        }
+
+        if let Some(resolution) = &self.dns {
+            map = map.map_put(atoms::dns(), resolution).expect("map");
+        }
+
+        if let Some(attempts) = self.attempts {
+            map = map.map_put(atoms::attempts(), attempts).expect("map");
+        }
+
+        if let Some(key) = &self.idempotency_key {
+            map = map.map_put(atoms::idempotency_key(), key).expect("map");
+        }
+
+        if let Some(rate_limit) = &self.rate_limit {
+            map = map.map_put(atoms::rate_limit(), rate_limit).expect("map");
+        }
+
+        if let Some(variant) = &self.variant {
+            map = map.map_put(atoms::variant(), variant).expect("map");
+        }
+
+        if let Some(signature) = &self.signature {
+            map = map.map_put(atoms::signature(), signature).expect("map");
+        }
+
+        if let Some(skew) = &self.clock_skew {
+            map = map.map_put(atoms::clock_skew(), skew).expect("map");
+        }
+
+        if let Some(version) = self.version {
+            map = map.map_put(atoms::version(), version).expect("map");
+        }
+
+        map
+    }
+}
+
+// Headers in wire order, keeping repeated fields such as `Link` apart
+fn header_list(headers: &reqwest::header::HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
+        .collect()
+}
+
+// Send a request over a connection of our own, for options reqwest can't serve
+fn wire_request(
+    id: u64,
+    method: reqwest::Method,
+    url: &str,
+    headers: &[(String, String)],
+    body: Vec<u8>,
+    options: &HttpOptions,
+) -> NifResult<HttpResponse> {
+    let bytes_out = body.len();
+    let started = Instant::now();
+    let exchange = wire::send_request(method, url, headers, body, options)
+        .inspect_err(|_| stats::record_error(url, id, started, bytes_out))?;
+    stats::record(url, id, started, exchange.status, bytes_out, exchange.body.len(), exchange.connection);
+
+    let mut meta = ResponseMeta::default();
+
+    if options.early_hints == Some(true) {
+        meta.early_hints = Some(
+            exchange
+                .informational
+                .iter()
+                .filter(|(status, _)| *status == 103)
+                .map(|(_, headers)| header_list(headers))
+                .collect(),
+        );
+    }
+
+    meta.debug = exchange.capture;
+    meta.dns = (options.dns_info == Some(true)).then_some(exchange.resolution);
+    meta.version = (options.version_info == Some(true)).then(atoms::http1_1);
+
+    Ok(HttpResponse {
+        status: exchange.status,
+        headers: header_map(&exchange.headers),
+        body: body::ResponseBody::new(
+            options,
+            exchange.headers.get(reqwest::header::CONTENT_TYPE).and_then(|v| v.to_str().ok()),
+            exchange.body,
+        ),
+        meta: (!meta.is_empty()).then_some(meta),
+    })
+}
+
+fn header_map(headers: &reqwest::header::HeaderMap) -> HashMap<String, String> {
+    headers
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
+        .collect()
+}
+
+// `trust::client_config` with the ALPN protocols reqwest offers
+fn reqwest_tls_config(options: &HttpOptions) -> Result<tokio_rustls::rustls::ClientConfig, String> {
+    let mut config = trust::client_config(options)?;
+    config.alpn_protocols = http_version::alpn(options);
+    Ok(config)
+}
+
+// Failure class of an I/O error, when it is one callers commonly branch on
+fn io_error_class(e: &std::io::Error) -> Option<Atom> {
+    use std::io::ErrorKind;
+
+    // TLS failures arrive as I/O errors wrapping the rustls error, sometimes twice over
+    if let Some(inner) = e.get_ref() {
+        if let Some(tls) = inner.downcast_ref::<tokio_rustls::rustls::Error>() {
+            return Some(tls_error_class(tls));
+        }
+        if let Some(class) = inner.downcast_ref::<std::io::Error>().and_then(io_error_class) {
+            return Some(class);
+        }
+    }
+
+    match e.kind() {
+        ErrorKind::ConnectionRefused => Some(atoms::connection_refused()),
+        ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe => {
+            Some(atoms::connection_reset())
+        }
+        ErrorKind::TimedOut => Some(atoms::timeout()),
+        _ => None,
+    }
+}
+
+// `:pin_mismatch` for a handshake `pinned_spki_sha256` failed, `:tls_error` otherwise
+fn tls_error_class(e: &tokio_rustls::rustls::Error) -> Atom {
+    match e {
+        tokio_rustls::rustls::Error::InvalidCertificate(tokio_rustls::rustls::CertificateError::Other(other))
+            if other.0.is::<trust::PinMismatch>() =>
+        {
+            atoms::pin_mismatch()
+        }
+        _ => atoms::tls_error(),
+    }
+}
+
+// Failure class of a request error, found by walking its chain of causes; transport
+// failures that fit no finer class are `:network_error`
+fn error_class(e: &reqwest::Error) -> Option<Atom> {
+    if e.is_timeout() {
+        return Some(atoms::timeout());
+    }
+    if e.is_redirect() {
+        return Some(atoms::too_many_redirects());
+    }
+
+    let mut source = std::error::Error::source(e);
+    while let Some(cause) = source {
+        // Tunnel, SOCKS and lookup failures are only told apart by their messages,
+        // as hyper-util and reqwest keep those error types private
+        let message = cause.to_string();
+        if message.starts_with("tunnel error") || message.contains("socks proxy") {
+            return Some(atoms::proxy_error());
+        }
+        if message == "dns error" {
+            return Some(atoms::dns_error());
+        }
+        if let Some(tls) = cause.downcast_ref::<tokio_rustls::rustls::Error>() {
+            return Some(tls_error_class(tls));
+        }
+        if let Some(class) = cause.downcast_ref::<std::io::Error>().and_then(io_error_class) {
+            return Some(class);
+        }
+        source = cause.source();
+    }
+
+    e.is_connect().then(atoms::network_error)
+}
+
+// Reason for `{:error, reason}`: the failure class, or the message under `context`
+fn reason(e: &reqwest::Error, context: &str) -> Box<dyn Encoder + Send> {
+    match error_class(e) {
+        Some(class) => Box::new(class),
+        None => Box::new(format!("{}: {}", context, e)),
+    }
+}
+
+fn request_error(e: reqwest::Error) -> Error {
+    Error::Term(reason(&e, "Request error"))
+}
+
+// Whether a request failed because its connection was reset or closed before any of
+// the response arrived, as when a pooled connection the server already dropped is
+// reused. Failures to connect don't count.
+fn reset_before_response(e: &reqwest::Error) -> bool {
+    if e.is_connect() || e.is_timeout() {
+        return false;
+    }
+
+    let mut source = std::error::Error::source(e);
+    while let Some(cause) = source {
+        if cause.downcast_ref::<hyper::Error>().is_some_and(hyper::Error::is_incomplete_message) {
+            return true;
+        }
+        if cause.downcast_ref::<std::io::Error>().and_then(io_error_class) == Some(atoms::connection_reset()) {
+            return true;
+        }
+        source = cause.source();
+    }
+
+    false
+}
+
+// The request to send again after `reset_before_response`: only idempotent methods
+// are retried, and only when the body can be replayed
+fn replayable<R>(method: &reqwest::Method, try_clone: impl FnOnce() -> Option<R>) -> Option<R> {
+    method.is_idempotent().then(try_clone).flatten()
+}
+
+// The request to send again after a 417 Expectation Failed: the same one without its
+// `Expect` header, as curl does, unless `expect_fallback` is false or the body cannot
+// be replayed
+fn expect_fallback<R>(
+    options: &HttpOptions,
+    headers: &reqwest::header::HeaderMap,
+    try_clone: impl FnOnce() -> Option<R>,
+) -> Option<R> {
+    (options.expect_fallback != Some(false) && headers.contains_key(reqwest::header::EXPECT))
+        .then(try_clone)
+        .flatten()
+}
+
+// Send a request, up to `retries` more times on transient failures (see `retry`) when
+// its body can be replayed, waiting longer before each
+fn send_retrying(
+    request: reqwest::blocking::RequestBuilder,
+    options: &HttpOptions,
+) -> reqwest::Result<reqwest::blocking::Response> {
+    let (client, request) = request.build_split();
+    let mut request = request?;
+    let retries = options.retries.unwrap_or(0);
+    let mut attempt = 1;
+
+    loop {
+        let again = (attempt <= retries).then(|| request.try_clone()).flatten();
+        let method = request.method().clone();
+        let result = send_recovering(&client, request, options);
+
+        let next = match (again, &result) {
+            (Some(next), Err(e)) if retry::error(&method, e) => next,
+            (Some(next), Ok(response)) if retry::status(&method, response.status()) => next,
+            _ => return result,
+        };
+
+        std::thread::sleep(retry::delay(options, attempt));
+        request = next;
+        attempt += 1;
+    }
+}
+
+// Send a request, sending it again once on a fresh connection when a stale pooled one
+// is reset before the response arrives, as browsers and curl do, and once without
+// `Expect` when the server refuses the expectation (see `expect_fallback`). The reset
+// retry goes through a client of its own, whose pool holds no connection the server
+// may have dropped.
+fn send_recovering(
+    client: &reqwest::blocking::Client,
+    request: reqwest::blocking::Request,
+    options: &HttpOptions,
+) -> reqwest::Result<reqwest::blocking::Response> {
+    let retry = replayable(request.method(), || request.try_clone());
+    let fallback = expect_fallback(options, request.headers(), || request.try_clone());
+
+    match client.execute(request) {
+        Err(e) if reset_before_response(&e) => match (retry, build_client(options)) {
+            (Some(request), Ok(client)) => client.execute(request),
+            _ => Err(e),
+        },
+        Ok(response) if response.status() == reqwest::StatusCode::EXPECTATION_FAILED => match fallback {
+            Some(mut request) => {
+                request.headers_mut().remove(reqwest::header::EXPECT);
+                client.execute(request)
+            }
+            None => Ok(response),
+        },
+        result => result,
+    }
+}
+
+// `send_retrying` for the async client
+async fn send_retrying_async(request: reqwest::RequestBuilder, options: &HttpOptions) -> reqwest::Result<reqwest::Response> {
+    let (client, request) = request.build_split();
+    let mut request = request?;
+    let retries = options.retries.unwrap_or(0);
+    let mut attempt = 1;
+
+    loop {
+        let again = (attempt <= retries).then(|| request.try_clone()).flatten();
+        let method = request.method().clone();
+        let result = send_recovering_async(&client, request, options).await;
+
+        let next = match (again, &result) {
+            (Some(next), Err(e)) if retry::error(&method, e) => next,
+            (Some(next), Ok(response)) if retry::status(&method, response.status()) => next,
+            _ => return result,
+        };
+
+        tokio::time::sleep(retry::delay(options, attempt)).await;
+        request = next;
+        attempt += 1;
+    }
+}
+
+// `send_recovering` for the async client
+async fn send_recovering_async(
+    client: &reqwest::Client,
+    request: reqwest::Request,
+    options: &HttpOptions,
+) -> reqwest::Result<reqwest::Response> {
+    let retry = replayable(request.method(), || request.try_clone());
+    let fallback = expect_fallback(options, request.headers(), || request.try_clone());
+
+    match client.execute(request).await {
+        Err(e) if reset_before_response(&e) => match (retry, build_async_client(options).ok()) {
+            (Some(request), Some(client)) => client.execute(request).await,
+            _ => Err(e),
+        },
+        Ok(response) if response.status() == reqwest::StatusCode::EXPECTATION_FAILED => match fallback {
+            Some(mut request) => {
+                request.headers_mut().remove(reqwest::header::EXPECT);
+                client.execute(request).await
+            }
+            None => Ok(response),
+        },
+        result => result,
+    }
+}
+
+// Response whose body stream failed part-way, with the bytes read until then
+struct BodyInterrupted {
+    status: u16,
+    headers: Vec<(String, String)>,
+    partial_body: Vec<u8>,
+}
+
+impl Encoder for BodyInterrupted {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let mut partial_body = NewBinary::new(env, self.partial_body.len());
+        partial_body.as_mut_slice().copy_from_slice(&self.partial_body);
+
+        Term::map_from_arrays(
+            env,
+            &[atoms::status(), atoms::headers(), atoms::partial_body(), atoms::bytes_received()],
+            &[
+                self.status.encode(env),
+                self.headers.encode(env),
+                Binary::from(partial_body).encode(env),
+                self.partial_body.len().encode(env),
+            ],
+        )
+        .expect("keys are unique")
+    }
+}
+
+// `{:body_interrupted, details}` for a body that failed after `partial` was read
+fn body_interrupted(status: u16, headers: &HashMap<String, String>, partial: Vec<u8>) -> Error {
+    Error::Term(Box::new((
+        atoms::body_interrupted(),
+        BodyInterrupted {
+            status,
+            headers: headers.clone().into_iter().collect(),
+            partial_body: partial,
+        },
+    )))
+}
+
+fn content_type(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    headers
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+// Read the whole body, or return what arrived before the stream failed
+fn read_body(response: &mut reqwest::blocking::Response, options: &HttpOptions) -> Result<body::ResponseBody, Vec<u8>> {
+    use std::io::Read;
+
+    let content_type = content_type(response.headers());
+    let mut body = buffers::take();
+    match throttle::Reader::new(response, throttle::download(options)).read_to_end(&mut body) {
+        Ok(_) => Ok(body::ResponseBody::new(options, content_type.as_deref(), body)),
+        Err(_) => Err(body),
+    }
+}
+
+async fn read_body_async(response: &mut reqwest::Response, options: &HttpOptions) -> Result<body::ResponseBody, Vec<u8>> {
+    let content_type = content_type(response.headers());
+    let mut body = buffers::take();
+    let limiter = throttle::download(options);
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                body.extend_from_slice(&chunk);
+                throttle::pace(limiter.as_deref(), chunk.len()).await;
+            }
+            Ok(None) => return Ok(body::ResponseBody::new(options, content_type.as_deref(), body)),
+            Err(_) => return Err(body),
+        }
+    }
+}
+
+fn parse_method(method: &str) -> NifResult<reqwest::Method> {
+    reqwest::Method::from_bytes(method.to_uppercase().as_bytes()).map_err(|_| Error::BadArg)
+}
+
+fn proxy_header_map(headers: &[(String, String)]) -> Result<reqwest::header::HeaderMap, Box<dyn std::error::Error>> {
+    let mut map = reqwest::header::HeaderMap::new();
+
+    for (key, value) in headers {
+        map.append(
+            reqwest::header::HeaderName::from_bytes(key.as_bytes())?,
+            reqwest::header::HeaderValue::from_str(value)?,
+        );
+    }
+
+    Ok(map)
+}
+
+// Proxies for all requests, one per scheme with `proxy: :system`; `proxy_headers` are
+// sent to the proxy itself, including on the CONNECT that opens an HTTPS tunnel.
+// `proxy_username` takes precedence over credentials in the URL, and a
+// `Proxy-Authorization` proxy header over both. Hosts matching `no_proxy`, or with
+// `:system` those the environment's `NO_PROXY` names, are connected to directly.
+fn build_proxies(options: &HttpOptions) -> Result<Vec<reqwest::Proxy>, Box<dyn std::error::Error>> {
+    let (proxies, no_proxy) = match &options.proxy {
+        None => return Ok(Vec::new()),
+        Some(system_proxy::ProxySetting::Url(url)) => (vec![(reqwest::Proxy::all(url)?, url.clone())], None),
+        Some(system_proxy::ProxySetting::System) => {
+            let environment = system_proxy::environment();
+            let mut proxies = Vec::new();
+            if let Some(url) = environment.http {
+                proxies.push((reqwest::Proxy::http(&url)?, url));
+            }
+            if let Some(url) = environment.https {
+                proxies.push((reqwest::Proxy::https(&url)?, url));
+            }
+            (proxies, environment.no_proxy)
+        }
+    };
+    let no_proxy = options.no_proxy.as_ref().map(|hosts| hosts.join(",")).or(no_proxy);
+
+    proxies
+        .into_iter()
+        .map(|(proxy, url)| configure_proxy(options, proxy, &url, no_proxy.as_deref()))
+        .collect()
+}
+
+fn configure_proxy(
+    options: &HttpOptions,
+    mut proxy: reqwest::Proxy,
+    proxy_url: &str,
+    no_proxy: Option<&str>,
+) -> Result<reqwest::Proxy, Box<dyn std::error::Error>> {
+    if let Some((username, password)) = proxy_credentials(options) {
+        proxy = proxy.basic_auth(username, password);
+    }
+
+    if let Some(hosts) = no_proxy {
+        proxy = proxy.no_proxy(reqwest::NoProxy::from_string(hosts));
+    }
+
+    if let Some(headers) = &options.proxy_headers {
+        let mut headers = proxy_header_map(headers)?;
+
+        if let Some(auth) = headers.remove(reqwest::header::PROXY_AUTHORIZATION) {
+            proxy = proxy.custom_http_auth(auth);
+        }
+        proxy = proxy.headers(headers);
+    }
+
+    // Tokens are single-use, so a client carrying one is built for one request and
+    // never shared, see `client_for`
+    if options.proxy_negotiate_auth == Some(true) {
+        let host = reqwest::Url::parse(proxy_url)?.host_str().unwrap_or_default().to_string();
+        let authorization = spnego::authorization(&host).map_err(|e| format!("Negotiate error: {}", e))?;
+        proxy = proxy.custom_http_auth(reqwest::header::HeaderValue::from_str(&authorization)?);
+    }
+
+    Ok(proxy)
+}
+
+// Bound on resolving and connecting to a host, within the request's `timeout_ms`
+fn connect_timeout(options: &HttpOptions) -> Option<std::time::Duration> {
+    options.connect_timeout_ms.map(std::time::Duration::from_millis)
+}
+
+// Longest wait for the next piece of a response, reset by each one that arrives
+fn read_timeout(options: &HttpOptions) -> Option<std::time::Duration> {
+    options.read_timeout_ms.map(std::time::Duration::from_millis)
+}
+
+// How long a pooled connection may sit unused before it is closed
+fn pool_idle_timeout(options: &HttpOptions) -> std::time::Duration {
+    std::time::Duration::from_millis(options.pool_idle_timeout_ms.unwrap_or(90000))
+}
+
+// Remove the headers carrying credentials, before a redirect to another origin
+fn drop_credentials(headers: &mut Vec<(String, String)>) {
+    headers.retain(|(key, _)| !["authorization", "cookie", "proxy-authorization"].contains(&key.to_lowercase().as_str()));
+}
+
+// `proxy_username` and `proxy_password`, the latter empty when unset
+fn proxy_credentials(options: &HttpOptions) -> Option<(&str, &str)> {
+    let username = options.proxy_username.as_deref()?;
+    Some((username, options.proxy_password.as_deref().unwrap_or("")))
+}
+
+// `Policy::limited`, reporting each redirect it follows to the event sink
+fn redirect_policy(max: usize) -> reqwest::redirect::Policy {
+    let limited = reqwest::redirect::Policy::limited(max);
+
+    reqwest::redirect::Policy::custom(move |attempt| {
+        if let Some(from) = attempt.previous().last().filter(|_| attempt.previous().len() <= max) {
+            events::redirect(from.as_str(), attempt.url().as_str(), attempt.status().as_u16());
+        }
+        limited.redirect(attempt)
+    })
+}
+
+fn build_client(options: &HttpOptions) -> Result<reqwest::blocking::Client, Box<dyn std::error::Error>> {
+    // The blocking builder has no read timeout of its own, so it wraps an async one set
+    // up with it
+    let mut inner = reqwest::Client::builder();
+    if let Some(timeout) = read_timeout(options) {
+        inner = inner.read_timeout(timeout);
+    }
+
+    let mut builder = reqwest::blocking::ClientBuilder::from(inner)
+        .timeout(std::time::Duration::from_millis(options.timeout_ms.unwrap_or(30000)))
+        .pool_idle_timeout(pool_idle_timeout(options))
+        .dns_resolver(Arc::new(dns::Resolver));
+
+    if let Some(timeout) = connect_timeout(options) {
+        builder = builder.connect_timeout(timeout);
+    }
+
+    // Without `proxy` requests go direct, whatever the environment says
+    builder = builder.no_proxy();
+    for proxy in build_proxies(options)? {
+        builder = builder.proxy(proxy);
+    }
+
+    if let Some(user_agent) = &options.user_agent {
+        builder = builder.user_agent(user_agent);
+    }
+
+    let (system, built_in) = trust::sources(options);
+    if system {
+        builder = builder.tls_built_in_root_certs(built_in);
+        for cert in trust::system_roots()? {
+            builder = builder.add_root_certificate(reqwest::Certificate::from_der(cert)?);
+        }
+    }
+
+    for pem in trust::extra_pems(options)? {
+        for cert in reqwest::Certificate::from_pem_bundle(&pem)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    let (skip_certs, skip_hostname) = trust::insecure(options)?;
+    builder = builder.danger_accept_invalid_certs(skip_certs).danger_accept_invalid_hostnames(skip_hostname);
+
+    if let Some(version) = options.tls_min_version {
+        builder = builder.min_tls_version(version.reqwest());
+    }
+    if let Some(version) = options.tls_max_version {
+        builder = builder.max_tls_version(version.reqwest());
+    }
+
+    if trust::needs_client_config(options) {
+        builder = builder.use_preconfigured_tls(reqwest_tls_config(options)?);
+    }
+
+    match http_version::resolve(options)? {
+        http_version::HttpVersion::Auto => {}
+        http_version::HttpVersion::Http1Only => builder = builder.http1_only(),
+        #[cfg(feature = "http2")]
+        http_version::HttpVersion::Http2PriorKnowledge => builder = builder.http2_prior_knowledge(),
+        #[cfg(not(feature = "http2"))]
+        http_version::HttpVersion::Http2PriorKnowledge => unreachable!("refused by resolve"),
+    }
+
+    if options.follow_redirects == Some(false) || cookies::follows_redirects(options) {
+        builder = builder.redirect(reqwest::redirect::Policy::none());
+    } else if let Some(max) = options.max_redirects.filter(|_| options.follow_redirects.is_some()) {
+        builder = builder.redirect(redirect_policy(max));
+    }
+
+    Ok(builder.build()?)
+}
+
+fn build_async_client(options: &HttpOptions) -> Result<reqwest::Client, Box<dyn std::error::Error>> {
+    Ok(async_client_builder(options)?.build()?)
+}
+
+fn async_client_builder(options: &HttpOptions) -> Result<reqwest::ClientBuilder, Box<dyn std::error::Error>> {
+    Ok(async_client_base(options)?.timeout(std::time::Duration::from_millis(options.timeout_ms.unwrap_or(30000))))
+}
+
+// `async_client_builder` without a limit on the whole request, for responses that may
+// stream indefinitely
+fn async_client_base(options: &HttpOptions) -> Result<reqwest::ClientBuilder, Box<dyn std::error::Error>> {
+    let mut builder = reqwest::Client::builder()
+        .pool_idle_timeout(pool_idle_timeout(options))
+        .dns_resolver(Arc::new(dns::Resolver));
+
+    if let Some(timeout) = connect_timeout(options) {
+        builder = builder.connect_timeout(timeout);
+    }
+
+    if let Some(timeout) = read_timeout(options) {
+        builder = builder.read_timeout(timeout);
     }
+
+    // Without `proxy` requests go direct, whatever the environment says
+    builder = builder.no_proxy();
+    for proxy in build_proxies(options)? {
+        builder = builder.proxy(proxy);
+    }
+
+    if let Some(user_agent) = &options.user_agent {
+        builder = builder.user_agent(user_agent);
+    }
+
+    let (system, built_in) = trust::sources(options);
+    if system {
+        builder = builder.tls_built_in_root_certs(built_in);
+        for cert in trust::system_roots()? {
+            builder = builder.add_root_certificate(reqwest::Certificate::from_der(cert)?);
+        }
+    }
+
+    for pem in trust::extra_pems(options)? {
+        for cert in reqwest::Certificate::from_pem_bundle(&pem)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    let (skip_certs, skip_hostname) = trust::insecure(options)?;
+    builder = builder.danger_accept_invalid_certs(skip_certs).danger_accept_invalid_hostnames(skip_hostname);
+
+    if let Some(version) = options.tls_min_version {
+        builder = builder.min_tls_version(version.reqwest());
+    }
+    if let Some(version) = options.tls_max_version {
+        builder = builder.max_tls_version(version.reqwest());
+    }
+
+    if trust::needs_client_config(options) {
+        builder = builder.use_preconfigured_tls(reqwest_tls_config(options)?);
+    }
+
+    match http_version::resolve(options)? {
+        http_version::HttpVersion::Auto => {}
+        http_version::HttpVersion::Http1Only => builder = builder.http1_only(),
+        #[cfg(feature = "http2")]
+        http_version::HttpVersion::Http2PriorKnowledge => builder = builder.http2_prior_knowledge(),
+        #[cfg(not(feature = "http2"))]
+        http_version::HttpVersion::Http2PriorKnowledge => unreachable!("refused by resolve"),
+    }
+
+    if options.follow_redirects == Some(false) || cookies::follows_redirects(options) {
+        builder = builder.redirect(reqwest::redirect::Policy::none());
+    } else if let Some(max) = options.max_redirects.filter(|_| options.follow_redirects.is_some()) {
+        builder = builder.redirect(redirect_policy(max));
+    }
+
+    Ok(builder)
 }
 
-#[derive(Debug, NifStruct)]
-#[module = "RustReq.Options"]
-struct HttpOptions {
-    timeout_ms: Option<u64>,
-    proxy: Option<String>,
-    follow_redirects: Option<bool>,
-    max_redirects: Option<usize>,
+// Shared blocking client for calls whose resolved options equal the defaults it was built from
+struct DefaultClient {
+    options: HttpOptions,
+    client: Arc<reqwest::blocking::Client>,
 }
 
-impl Default for HttpOptions {
-    fn default() -> Self {
-        HttpOptions {
-            timeout_ms: Some(30000),
-            proxy: None,
-            follow_redirects: Some(true),
-            max_redirects: Some(10),
+static DEFAULT_CLIENT: RwLock<Option<DefaultClient>> = RwLock::new(None);
+static DEFAULT_CLIENT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+// Build a client for the new defaults in the background and swap it in atomically.
+// Requests holding the previous client keep it alive until they complete, at which
+// point its pool is dropped; requests started meanwhile get a dedicated client.
+fn swap_default_client(options: HttpOptions) {
+    let generation = DEFAULT_CLIENT_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+
+    std::thread::spawn(move || {
+        let Ok(client) = build_client(&options) else {
+            return;
+        };
+
+        let mut slot = DEFAULT_CLIENT.write().unwrap();
+        // A newer swap superseded this one while the client was being built
+        if DEFAULT_CLIENT_GENERATION.load(Ordering::SeqCst) != generation {
+            return;
+        }
+
+        let previous = slot.replace(DefaultClient {
+            options,
+            client: Arc::new(client),
+        });
+        drop(slot);
+        drop(previous);
+    });
+}
+
+fn client_for(options: &HttpOptions) -> Result<Arc<reqwest::blocking::Client>, Box<dyn std::error::Error>> {
+    // The proxy's Negotiate token is minted as the client is built
+    if options.proxy_negotiate_auth == Some(true) {
+        return Ok(Arc::new(build_client(options)?));
+    }
+
+    if let Some(default) = DEFAULT_CLIENT.read().unwrap().as_ref() {
+        if default.options == *options {
+            return Ok(default.client.clone());
         }
     }
+
+    Ok(Arc::new(build_client(options)?))
 }
 
-#[derive(Debug)]
-struct HttpResponse {
-    status: u16,
-    headers: HashMap<String, String>,
-    body: String,
+// Synchronous HTTP GET
+#[rustler::nif(schedule = "DirtyIo")]
+fn http_get(env: Env, url: String, headers: Vec<(String, String)>, options: HttpOptions) -> NifResult<HttpResponse> {
+    get(url, headers, options, audit::caller(env))
 }
 
-impl Encoder for HttpResponse {
-    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
-        let headers_map: Vec<(String, String)> = self.headers.clone().into_iter().collect();
+// Validators of a conditional GET's response, to send with the next poll
+#[derive(NifMap)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<u64>,
+}
 
-        (
-            atoms::ok(),
-            (
-                self.status,
-                headers_map,
-                self.body.clone(),
-            )
-        ).encode(env)
+enum Conditional {
+    // `:not_modified`
+    NotModified,
+    // `{:ok, response, validators}`
+    Modified(Box<HttpResponse>, Validators),
+}
+
+impl Encoder for Conditional {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            Conditional::NotModified => atoms::not_modified().encode(env),
+            Conditional::Modified(response, validators) => {
+                (atoms::ok(), response.encode_tuple(env), validators).encode(env)
+            }
+        }
     }
 }
 
-fn build_client(options: &HttpOptions) -> Result<reqwest::blocking::Client, Box<dyn std::error::Error>> {
-    let mut builder = reqwest::blocking::Client::builder()
-        .timeout(std::time::Duration::from_millis(options.timeout_ms.unwrap_or(30000)));
+// GET that only returns the body when it changed since a previous response, given its
+// entity tag and/or a Unix timestamp sent as `If-Modified-Since`
+#[rustler::nif(schedule = "DirtyIo")]
+fn http_get_if_modified(
+    env: Env,
+    url: String,
+    etag: Option<String>,
+    since: Option<u64>,
+    headers: Vec<(String, String)>,
+    options: HttpOptions,
+) -> NifResult<Conditional> {
+    let mut headers = headers;
 
-    if let Some(proxy_url) = &options.proxy {
-        builder = builder.proxy(reqwest::Proxy::all(proxy_url)?);
+    if let Some(etag) = etag {
+        // Entity tags are quoted on the wire; accept them bare as well
+        let etag = if etag.starts_with('"') || etag.starts_with("W/") { etag } else { format!("\"{}\"", etag) };
+        headers.push(("If-None-Match".to_string(), etag));
     }
 
-    if let Some(follow) = options.follow_redirects {
-        if !follow {
-            builder = builder.redirect(reqwest::redirect::Policy::none());
-        } else if let Some(max) = options.max_redirects {
-            builder = builder.redirect(reqwest::redirect::Policy::limited(max));
-        }
+    if let Some(since) = since {
+        headers.push(("If-Modified-Since".to_string(), cookies::format_http_date(since)));
     }
 
-    Ok(builder.build()?)
+    let response = get(url, headers, options, audit::caller(env))?;
+
+    if response.status == 304 {
+        return Ok(Conditional::NotModified);
+    }
+
+    let validators = Validators {
+        etag: response.headers.get("etag").cloned(),
+        last_modified: response.headers.get("last-modified").and_then(|date| cookies::parse_http_date(date)),
+    };
+
+    Ok(Conditional::Modified(Box::new(response), validators))
 }
 
-fn build_async_client(options: &HttpOptions) -> Result<reqwest::Client, Box<dyn std::error::Error>> {
-    let mut builder = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_millis(options.timeout_ms.unwrap_or(30000)));
+fn get(url: String, headers: Vec<(String, String)>, options: HttpOptions, caller: Option<String>) -> NifResult<HttpResponse> {
+    request(reqwest::Method::GET, url, headers, None, options, caller, None)
+}
 
-    if let Some(proxy_url) = &options.proxy {
-        builder = builder.proxy(reqwest::Proxy::all(proxy_url)?);
-    }
+// Synchronous HTTP POST
+#[rustler::nif(schedule = "DirtyIo")]
+fn http_post(env: Env, url: String, headers: Vec<(String, String)>, body: Payload, options: HttpOptions) -> NifResult<HttpResponse> {
+    let (headers, body) = body.prepare(headers);
+    request(reqwest::Method::POST, url, headers, Some(RequestBody::Bytes(body)), options, audit::caller(env), None)
+}
 
-    if let Some(follow) = options.follow_redirects {
-        if !follow {
-            builder = builder.redirect(reqwest::redirect::Policy::none());
-        } else if let Some(max) = options.max_redirects {
-            builder = builder.redirect(reqwest::redirect::Policy::limited(max));
-        }
+// Synchronous request with any method
+#[rustler::nif(schedule = "DirtyIo")]
+fn http_request(
+    env: Env,
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Payload,
+    options: HttpOptions,
+) -> NifResult<HttpResponse> {
+    let method = parse_method(&method)?;
+    let (headers, body) = body.prepare(headers);
+    let body = request_body(&method, body, &options);
+    request(method, url, headers, body, options, audit::caller(env), None)
+}
+
+// Synchronous request whose body is streamed from the file at `path` as it is sent
+#[rustler::nif(schedule = "DirtyIo")]
+fn http_upload(
+    env: Env,
+    method: String,
+    url: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    options: HttpOptions,
+) -> NifResult<HttpResponse> {
+    let method = parse_method(&method)?;
+    let file_error = |e: std::io::Error| Error::Term(Box::new(format!("File error: {}", e)));
+    let file = std::fs::File::open(&path).map_err(file_error)?;
+    let size = file.metadata().map_err(file_error)?.len();
+
+    request(method, url, headers, Some(RequestBody::File(file, size)), options, audit::caller(env), None)
+}
+
+// Synchronous POST of a multipart/form-data body built from `parts`, with files
+// streamed from disk as it is sent
+#[rustler::nif(schedule = "DirtyIo")]
+fn http_post_multipart(
+    env: Env,
+    url: String,
+    parts: Vec<multipart::FormPart>,
+    headers: Vec<(String, String)>,
+    options: HttpOptions,
+) -> NifResult<HttpResponse> {
+    let form = multipart::Form::new(parts).map_err(|e| Error::Term(Box::new(format!("File error: {}", e))))?;
+    let mut headers: Vec<(String, String)> =
+        headers.into_iter().filter(|(name, _)| !name.eq_ignore_ascii_case("content-type")).collect();
+    headers.push(("content-type".to_string(), form.content_type()));
+
+    request(reqwest::Method::POST, url, headers, Some(RequestBody::Form(form)), options, audit::caller(env), None)
+}
+
+// Synchronous POST of `data` serialized to JSON, with `Content-Type: application/json`
+// unless `headers` set one
+#[rustler::nif(schedule = "DirtyIo")]
+fn http_post_json(
+    env: Env,
+    url: String,
+    data: Term,
+    headers: Vec<(String, String)>,
+    options: HttpOptions,
+) -> NifResult<HttpResponse> {
+    let mut headers = headers;
+    let body = serde_json::to_vec(&json::from_term(data)?).map_err(|e| Error::Term(Box::new(format!("JSON error: {}", e))))?;
+    if !headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("content-type")) {
+        headers.push(("content-type".to_string(), "application/json".to_string()));
     }
 
-    Ok(builder.build()?)
+    request(reqwest::Method::POST, url, headers, Some(RequestBody::Bytes(body)), options, audit::caller(env), None)
 }
 
-// Synchronous HTTP GET
-#[rustler::nif]
-fn http_get(url: String, headers: Vec<(String, String)>, options: HttpOptions) -> NifResult<HttpResponse> {
-    let client = build_client(&options)
-        .map_err(|e| Error::Term(Box::new(format!("Client error: {}", e))))?;
+// The body to send with `method`. An empty one is left out, except on POST, PUT and
+// PATCH, which are expected to carry one, and when trailers follow it.
+fn request_body(method: &reqwest::Method, body: Vec<u8>, options: &HttpOptions) -> Option<RequestBody> {
+    let expected = [reqwest::Method::POST, reqwest::Method::PUT, reqwest::Method::PATCH].contains(method);
+    (expected || !body.is_empty() || options.request_trailers.is_some()).then_some(RequestBody::Bytes(body))
+}
+
+// A request body as Elixir passes it: a string sent as is, or `{:form, pairs}` sent
+// as application/x-www-form-urlencoded
+enum Payload {
+    Raw(String),
+    Form(Vec<(String, String)>),
+}
 
-    let mut request = client.get(&url);
+impl<'a> Decoder<'a> for Payload {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        if term.is_binary() {
+            return Ok(Payload::Raw(term.decode()?));
+        }
 
-    for (key, value) in headers {
-        request = request.header(key, value);
+        let (tag, pairs): (Atom, Vec<(String, String)>) = term.decode()?;
+        if tag != atoms::form() {
+            return Err(Error::BadArg);
+        }
+        Ok(Payload::Form(pairs))
     }
+}
 
-    let response = request
-        .send()
-        .map_err(|e| {
-            if e.is_timeout() {
-                Error::Atom("timeout")
-            } else if e.is_connect() {
-                Error::Atom("network_error")
-            } else {
-                Error::Term(Box::new(format!("Request error: {}", e)))
+impl Payload {
+    // The bytes to send, with the Content-Type of a form added unless `headers` set one
+    fn prepare(self, mut headers: Vec<(String, String)>) -> (Vec<(String, String)>, Vec<u8>) {
+        match self {
+            Payload::Raw(body) => (headers, body.into_bytes()),
+            Payload::Form(pairs) => {
+                if !headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("content-type")) {
+                    headers.push(("content-type".to_string(), "application/x-www-form-urlencoded".to_string()));
+                }
+                (headers, urls::form_encode(&pairs).into_bytes())
             }
-        })?;
+        }
+    }
+}
 
-    let status = response.status().as_u16();
-    let headers_map: HashMap<String, String> = response
-        .headers()
-        .iter()
-        .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
-        .collect();
+// The body of a synchronous request
+enum RequestBody {
+    Bytes(Vec<u8>),
+    // An open file and its size, read as the request is sent
+    File(std::fs::File, u64),
+    Form(multipart::Form),
+}
 
-    let body = response.text()
-        .map_err(|e| Error::Term(Box::new(format!("Body error: {}", e))))?;
+// A synchronous request, sent on `client` when given and otherwise on the client its
+// options call for. Without a body none is sent, not even an empty one.
+fn request(
+    method: reqwest::Method,
+    url: String,
+    headers: Vec<(String, String)>,
+    mut body: Option<RequestBody>,
+    options: HttpOptions,
+    caller: Option<String>,
+    client: Option<Arc<reqwest::blocking::Client>>,
+) -> NifResult<HttpResponse> {
+    let _permit = blocking::acquire()?;
+    let _inflight = inflight::acquire()?;
+    let mut options = resolve_options(options)?;
+    let (url, headers) = urls::apply(&options, url, headers).map_err(urls::error)?;
+    let proxy = proxy_pool::select(&mut options, &url).map_err(|reason| Error::Term(Box::new(reason)))?;
+    pac::select(&mut options, &url).map_err(pac::error)?;
+    let headers = user_agents::apply(&options, &url, headers);
+    let headers = accept::apply(&options, headers);
+    let headers = spnego::apply(&options, &url, headers).map_err(spnego::error)?;
+    let (headers, idempotency_key) = idempotency::apply(&options, method.as_str(), headers);
+    let headers = if body.is_some() { trailers::apply(&options, headers) } else { headers };
+    let headers = middleware::before_send(&options, method.as_str(), &url, headers)?;
+    let headers = match &mut body {
+        Some(RequestBody::File(file, _)) => signatures::apply_streamed(&options, method.as_str(), &url, headers, file),
+        Some(RequestBody::Form(form)) => signatures::apply_streamed(&options, method.as_str(), &url, headers, form),
+        Some(RequestBody::Bytes(bytes)) => signatures::apply(&options, method.as_str(), &url, headers, bytes),
+        None => signatures::apply(&options, method.as_str(), &url, headers, b""),
+    }
+    .map_err(signatures::error)?;
+    let id = events::enqueue(method.as_str(), &url, caller);
+    politeness::wait(&options, &url);
 
-    Ok(HttpResponse {
-        status,
-        headers: headers_map,
-        body,
-    })
-}
+    // Blocking bodies cannot carry trailers, so those go over a connection of our own
+    let trailed = body.is_some() && trailers::trailers(&options).is_some();
+    if options.needs_wire() || trailed {
+        let body = match body {
+            Some(RequestBody::Bytes(bytes)) => bytes,
+            Some(RequestBody::File(..) | RequestBody::Form(_)) => {
+                return Err(Error::Term(Box::new(
+                    "early_hints, informational_pid, debug, dns_info, raw_query and request_trailers cannot be used with file uploads",
+                )))
+            }
+            None => Vec::new(),
+        };
+        return wire_request(id, method.clone(), &url, &headers, body, &options)
+            .inspect(|response| middleware::after_receive(&options, method.as_str(), &url, response.status, &response.headers))
+            .and_then(|response| Ok(check_status(&options, idempotency::annotate(idempotency_key, response))?));
+    }
 
-// Synchronous HTTP POST
-#[rustler::nif]
-fn http_post(url: String, headers: Vec<(String, String)>, body: String, options: HttpOptions) -> NifResult<HttpResponse> {
-    let client = build_client(&options)
-        .map_err(|e| Error::Term(Box::new(format!("Client error: {}", e))))?;
+    let client = match client {
+        Some(client) => client,
+        None => client_for(&options).map_err(|e| Error::Term(Box::new(format!("Client error: {}", e))))?,
+    };
 
-    let mut request = client.post(&url).body(body);
+    let mut request = client.request(method.clone(), &url);
+
+    let bytes_out = match &body {
+        Some(RequestBody::Bytes(bytes)) => bytes.len(),
+        Some(RequestBody::File(_, size)) => *size as usize,
+        Some(RequestBody::Form(form)) => form.len() as usize,
+        None => 0,
+    };
+    match body {
+        Some(RequestBody::Bytes(bytes)) => request = request.body(progress::upload_body(bytes, &options)),
+        Some(RequestBody::File(file, size)) => request = request.body(progress::upload_reader(file, size, &options)),
+        Some(RequestBody::Form(form)) => {
+            let size = form.len();
+            request = request.body(progress::upload_reader(form, size, &options));
+        }
+        None => {}
+    }
 
     for (key, value) in headers {
         request = request.header(key, value);
     }
 
-    let response = request
-        .send()
+    if let Some(cookie) = cookies::request_header(&options, &url) {
+        request = request.header(reqwest::header::COOKIE, cookie);
+    }
+
+    let started = Instant::now();
+    let mut response = cookies::send(&options, &url, request)
         .map_err(|e| {
-            if e.is_timeout() {
-                Error::Atom("timeout")
-            } else if e.is_connect() {
-                Error::Atom("network_error")
-            } else {
-                Error::Term(Box::new(format!("Request error: {}", e)))
-            }
+            stats::record_error(&url, id, started, bytes_out);
+            proxy_pool::report_failure(&options, proxy.as_deref());
+            e.into_error()
         })?;
 
     let status = response.status().as_u16();
+    let version = response.version();
+    let connection = stats::connection(response.extensions());
     let headers_map: HashMap<String, String> = response
         .headers()
         .iter()
         .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
         .collect();
+    proxy_pool::report_response(&options, proxy.as_deref(), status, &headers_map);
 
-    let body = response.text()
-        .map_err(|e| Error::Term(Box::new(format!("Body error: {}", e))))?;
+    let body = read_body(&mut response, &options)
+        .map_err(|partial| {
+            stats::record_error(&url, id, started, bytes_out);
+            body_interrupted(status, &headers_map, partial)
+        })?;
 
-    Ok(HttpResponse {
+    stats::record(&url, id, started, status, bytes_out, body.size(), connection);
+
+    middleware::after_receive(&options, method.as_str(), &url, status, &headers_map);
+
+    let response = HttpResponse {
         status,
         headers: headers_map,
         body,
-    })
+        meta: None,
+    };
+    let response = http_version::annotate(&options, version, response);
+    Ok(check_status(&options, idempotency::annotate(idempotency_key, response))?)
 }
 
 // Async HTTP GET (for concurrent requests)
-#[rustler::nif]
-fn http_get_async(url: String, headers: Vec<(String, String)>, options: HttpOptions) -> NifResult<HttpResponse> {
-    let rt = tokio::runtime::Runtime::new()
-        .map_err(|e| Error::Term(Box::new(format!("Runtime error: {}", e))))?;
+#[rustler::nif(schedule = "DirtyIo")]
+fn http_get_async(env: Env, url: String, headers: Vec<(String, String)>, options: HttpOptions) -> NifResult<HttpResponse> {
+    let mut options = resolve_options(options)?;
+    let (url, headers) = urls::apply(&options, url, headers).map_err(urls::error)?;
+    let proxy = proxy_pool::select(&mut options, &url).map_err(|reason| Error::Term(Box::new(reason)))?;
+    pac::select(&mut options, &url).map_err(pac::error)?;
+    let headers = user_agents::apply(&options, &url, headers);
+    let headers = accept::apply(&options, headers);
+    let headers = spnego::apply(&options, &url, headers).map_err(spnego::error)?;
+    let headers = middleware::before_send(&options, "GET", &url, headers)?;
+    let headers = signatures::apply(&options, "GET", &url, headers, b"").map_err(signatures::error)?;
+    let _inflight = RUNTIME.block_on(inflight::acquire_async()).map_err(|reason| Error::Term(Box::new(reason)))?;
+    let id = events::enqueue("GET", &url, audit::caller(env));
+
+    RUNTIME.block_on(inflight::abortable(async {
+        politeness::wait_async(&options, &url).await;
 
-    rt.block_on(async {
         let client = build_async_client(&options)
             .map_err(|e| Error::Term(Box::new(format!("Client error: {}", e))))?;
 
@@ -189,151 +2076,583 @@ fn http_get_async(url: String, headers: Vec<(String, String)>, options: HttpOpti
             request = request.header(key, value);
         }
 
-        let response = request
-            .send()
+        if let Some(cookie) = cookies::request_header(&options, &url) {
+            request = request.header(reqwest::header::COOKIE, cookie);
+        }
+
+        let started = Instant::now();
+        let mut response = cookies::send_async(&options, &url, request)
             .await
             .map_err(|e| {
-                if e.is_timeout() {
-                    Error::Atom("timeout")
-                } else if e.is_connect() {
-                    Error::Atom("network_error")
-                } else {
-                    Error::Term(Box::new(format!("Request error: {}", e)))
-                }
+                stats::record_error(&url, id, started, 0);
+                proxy_pool::report_failure(&options, proxy.as_deref());
+                e.into_error()
             })?;
 
         let status = response.status().as_u16();
+        let version = response.version();
+        let connection = stats::connection(response.extensions());
         let headers_map: HashMap<String, String> = response
             .headers()
             .iter()
             .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
             .collect();
+        proxy_pool::report_response(&options, proxy.as_deref(), status, &headers_map);
 
-        let body = response.text()
+        let body = read_body_async(&mut response, &options)
             .await
-            .map_err(|e| Error::Term(Box::new(format!("Body error: {}", e))))?;
+            .map_err(|partial| {
+                stats::record_error(&url, id, started, 0);
+                body_interrupted(status, &headers_map, partial)
+            })?;
+
+        stats::record(&url, id, started, status, 0, body.size(), connection);
 
-        Ok(HttpResponse {
+        middleware::after_receive(&options, "GET", &url, status, &headers_map);
+
+        let response = HttpResponse {
             status,
             headers: headers_map,
             body,
-        })
-    })
+            meta: None,
+        };
+        Ok(check_status(&options, http_version::annotate(&options, version, response))?)
+    }))
+    .unwrap_or_else(|| Err(Error::Term(Box::new(atoms::cancelled()))))
 }
 
 // Async HTTP POST
-#[rustler::nif]
-fn http_post_async(url: String, headers: Vec<(String, String)>, body: String, options: HttpOptions) -> NifResult<HttpResponse> {
-    let rt = tokio::runtime::Runtime::new()
-        .map_err(|e| Error::Term(Box::new(format!("Runtime error: {}", e))))?;
+#[rustler::nif(schedule = "DirtyIo")]
+fn http_post_async(env: Env, url: String, headers: Vec<(String, String)>, body: String, options: HttpOptions) -> NifResult<HttpResponse> {
+    let mut options = resolve_options(options)?;
+    let (url, headers) = urls::apply(&options, url, headers).map_err(urls::error)?;
+    let proxy = proxy_pool::select(&mut options, &url).map_err(|reason| Error::Term(Box::new(reason)))?;
+    pac::select(&mut options, &url).map_err(pac::error)?;
+    let headers = user_agents::apply(&options, &url, headers);
+    let headers = accept::apply(&options, headers);
+    let headers = spnego::apply(&options, &url, headers).map_err(spnego::error)?;
+    let (headers, idempotency_key) = idempotency::apply(&options, "POST", headers);
+    let headers = trailers::apply(&options, headers);
+    let headers = middleware::before_send(&options, "POST", &url, headers)?;
+    let headers = signatures::apply(&options, "POST", &url, headers, body.as_bytes()).map_err(signatures::error)?;
+    let _inflight = RUNTIME.block_on(inflight::acquire_async()).map_err(|reason| Error::Term(Box::new(reason)))?;
+    let id = events::enqueue("POST", &url, audit::caller(env));
+
+    RUNTIME.block_on(inflight::abortable(async {
+        politeness::wait_async(&options, &url).await;
 
-    rt.block_on(async {
         let client = build_async_client(&options)
             .map_err(|e| Error::Term(Box::new(format!("Client error: {}", e))))?;
 
-        let mut request = client.post(&url).body(body);
+        let bytes_out = body.len();
+        let mut request = client.post(&url).body(throttle::upload_body(&options, trailers::body(&options, body.into_bytes())));
 
         for (key, value) in headers {
             request = request.header(key, value);
         }
 
-        let response = request
-            .send()
+        if let Some(cookie) = cookies::request_header(&options, &url) {
+            request = request.header(reqwest::header::COOKIE, cookie);
+        }
+
+        let started = Instant::now();
+        let mut response = cookies::send_async(&options, &url, request)
             .await
             .map_err(|e| {
-                if e.is_timeout() {
-                    Error::Atom("timeout")
-                } else if e.is_connect() {
-                    Error::Atom("network_error")
-                } else {
-                    Error::Term(Box::new(format!("Request error: {}", e)))
-                }
+                stats::record_error(&url, id, started, bytes_out);
+                proxy_pool::report_failure(&options, proxy.as_deref());
+                e.into_error()
             })?;
 
         let status = response.status().as_u16();
+        let version = response.version();
+        let connection = stats::connection(response.extensions());
         let headers_map: HashMap<String, String> = response
             .headers()
             .iter()
             .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
             .collect();
+        proxy_pool::report_response(&options, proxy.as_deref(), status, &headers_map);
 
-        let body = response.text()
+        let body = read_body_async(&mut response, &options)
             .await
-            .map_err(|e| Error::Term(Box::new(format!("Body error: {}", e))))?;
+            .map_err(|partial| {
+                stats::record_error(&url, id, started, bytes_out);
+                body_interrupted(status, &headers_map, partial)
+            })?;
+
+        stats::record(&url, id, started, status, bytes_out, body.size(), connection);
 
-        Ok(HttpResponse {
+        middleware::after_receive(&options, "POST", &url, status, &headers_map);
+
+        let response = HttpResponse {
             status,
             headers: headers_map,
             body,
-        })
-    })
+            meta: None,
+        };
+        let response = http_version::annotate(&options, version, response);
+        Ok(check_status(&options, idempotency::annotate(idempotency_key, response))?)
+    }))
+    .unwrap_or_else(|| Err(Error::Term(Box::new(atoms::cancelled()))))
 }
 
 // Batch async requests for maximum throughput
-#[rustler::nif]
+#[rustler::nif(schedule = "DirtyIo")]
 fn http_get_batch<'a>(env: Env<'a>, urls: Vec<String>, headers: Vec<(String, String)>, options: HttpOptions) -> NifResult<Term<'a>> {
-    let rt = tokio::runtime::Runtime::new()
-        .map_err(|e| Error::Term(Box::new(format!("Runtime error: {}", e))))?;
+    let options = resolve_options(options)?;
+    let caller = audit::caller(env);
 
-    rt.block_on(async {
+    RUNTIME.block_on(async {
         let client = build_async_client(&options)
             .map_err(|e| Error::Term(Box::new(format!("Client error: {}", e))))?;
 
-        let tasks: Vec<_> = urls.into_iter().map(|url| {
-            let client = client.clone();
-            let headers = headers.clone();
-
-            tokio::spawn(async move {
-                let mut request = client.get(&url);
-
-                for (key, value) in headers {
-                    request = request.header(key, value);
-                }
+        let deadline = options
+            .batch_deadline_ms
+            .map(|ms| tokio::time::Instant::now() + Duration::from_millis(ms));
 
-                match request.send().await {
-                    Ok(response) => {
-                        let status = response.status().as_u16();
-                        let headers_map: HashMap<String, String> = response
-                            .headers()
-                            .iter()
-                            .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
-                            .collect();
-
-                        match response.text().await {
-                            Ok(body) => Ok(HttpResponse {
-                                status,
-                                headers: headers_map,
-                                body,
-                            }),
-                            Err(e) => Err(format!("Body error: {}", e)),
-                        }
-                    }
-                    Err(e) => Err(format!("Request error: {}", e)),
-                }
+        let tasks: Vec<_> = urls
+            .into_iter()
+            .map(|url| {
+                let entry = batch_entry(client.clone(), url, headers.clone(), options.clone(), deadline, caller.clone());
+                tokio::spawn(entry)
             })
-        }).collect();
+            .collect();
 
-        let mut results = Vec::new();
+        let mut results = VecDeque::new();
         for task in tasks {
-            match task.await {
-                Ok(Ok(response)) => {
-                    // Encode as {:ok, {status, headers, body}}
-                    let headers_list: Vec<(String, String)> = response.headers.clone().into_iter().collect();
-                    results.push((atoms::ok(), (response.status, headers_list, response.body)).encode(env));
-                }
-                Ok(Err(error_msg)) => {
-                    // Encode as {:error, reason}
-                    results.push((atoms::error(), error_msg).encode(env));
-                }
-                Err(e) => {
-                    // Encode as {:error, reason}
-                    results.push((atoms::error(), format!("Task error: {}", e)).encode(env));
+            results.push_back(match task.await {
+                Ok((Ok(response), attempts)) => BatchResult::Ok(Box::new(with_attempts(&options, response, attempts))),
+                Ok((Err(reason), attempts)) => BatchResult::Err(reason, attempts),
+                Err(e) => BatchResult::Err(Box::new(format!("Task error: {}", e)), 1),
+            });
+        }
+
+        // Large batches are encoded a slice per call, so no single call holds the
+        // scheduler for long
+        if results.len() <= BATCH_SLICE {
+            Ok(Vec::from(results).encode(env))
+        } else {
+            let handle = ResourceArc::new(BatchResults { results: Mutex::new(results) });
+            Ok((atoms::slices(), handle).encode(env))
+        }
+    })
+}
+
+// Entries encoded per call of `http_get_batch` and `batch_results_next`
+const BATCH_SLICE: usize = 1000;
+
+// One entry of an `http_get_batch` result
+enum BatchResult {
+    // `{:ok, {status, headers, body}}`, with `%{attempts: n}` as metadata when retries
+    // are enabled
+    Ok(Box<HttpResponse>),
+    // `{:error, reason, attempts}`
+    Err(Box<dyn Encoder + Send>, u32),
+}
+
+impl Encoder for BatchResult {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            BatchResult::Ok(response) => response.encode(env),
+            BatchResult::Err(reason, attempts) => (atoms::error(), reason.encode(env), *attempts).encode(env),
+        }
+    }
+}
+
+// The entries of a large batch not yet handed to Elixir
+struct BatchResults {
+    results: Mutex<VecDeque<BatchResult>>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for BatchResults {}
+
+// The next slice of a large batch's results, empty once all were taken
+#[rustler::nif]
+fn batch_results_next<'a>(env: Env<'a>, handle: ResourceArc<BatchResults>) -> Term<'a> {
+    let mut results = handle.results.lock().unwrap();
+    let count = BATCH_SLICE.min(results.len());
+    let slice: Vec<BatchResult> = results.drain(..count).collect();
+    slice.encode(env)
+}
+
+// Report a batch entry's attempt count as response metadata when retries are enabled
+fn with_attempts(options: &HttpOptions, mut response: HttpResponse, attempts: u32) -> HttpResponse {
+    if options.batch_retries.is_some_and(|retries| retries > 0) {
+        response.meta.get_or_insert_with(ResponseMeta::default).attempts = Some(attempts);
+    }
+    response
+}
+
+// Statuses a batch entry is retried on while it has retries left
+const RETRY_STATUSES: [u16; 4] = [429, 502, 503, 504];
+
+// Fetch one `http_get_batch` entry, retrying transient failures up to `batch_retries`
+// times, and report how many attempts were made. An entry that can't be sent before
+// the batch deadline is `:not_attempted`, and one `drain` aborts is `:cancelled`.
+async fn batch_entry(
+    client: reqwest::Client,
+    url: String,
+    headers: Vec<(String, String)>,
+    options: HttpOptions,
+    deadline: Option<tokio::time::Instant>,
+    caller: Option<String>,
+) -> (Result<HttpResponse, Box<dyn Encoder + Send>>, u32) {
+    inflight::abortable(batch_entry_attempts(client, url, headers, options, deadline, caller))
+        .await
+        .unwrap_or_else(|| (Err(Box::new(atoms::cancelled())), 1))
+}
+
+// `batch_entry` until aborted; no retry is started that would only begin after the
+// deadline
+async fn batch_entry_attempts(
+    client: reqwest::Client,
+    url: String,
+    headers: Vec<(String, String)>,
+    options: HttpOptions,
+    deadline: Option<tokio::time::Instant>,
+    caller: Option<String>,
+) -> (Result<HttpResponse, Box<dyn Encoder + Send>>, u32) {
+    if deadline.is_some_and(|deadline| tokio::time::Instant::now() >= deadline) {
+        return (Err(Box::new(atoms::not_attempted())), 1);
+    }
+
+    let (url, headers) = match urls::apply(&options, url, headers) {
+        Ok(applied) => applied,
+        Err(message) => return (Err(Box::new((atoms::invalid_url(), message))), 1),
+    };
+    let headers = user_agents::apply(&options, &url, headers);
+    let headers = accept::apply(&options, headers);
+    let headers = match spnego::apply(&options, &url, headers) {
+        Ok(headers) => headers,
+        Err(message) => return (Err(Box::new(message)), 1),
+    };
+    let headers = match signatures::apply(&options, "GET", &url, headers, b"") {
+        Ok(headers) => headers,
+        Err(message) => return (Err(Box::new(message)), 1),
+    };
+    let retries = options.batch_retries.unwrap_or(0);
+    let backoff_ms = options.batch_retry_backoff_ms.unwrap_or(100);
+    let mut attempt = 1;
+
+    loop {
+        // With `proxies` or `proxy_pac`, each attempt may go through another proxy, on a
+        // client of its own; so does each with `proxy_negotiate_auth`, for a fresh token
+        let mut attempt_options = options.clone();
+        let proxy = match proxy_pool::select(&mut attempt_options, &url) {
+            Ok(proxy) => proxy,
+            Err(reason) => return (Err(Box::new(reason)), attempt),
+        };
+        if let Err(message) = pac::select(&mut attempt_options, &url) {
+            return (Err(Box::new(format!("PAC error: {}", message))), attempt);
+        }
+        let client = if attempt_options.proxy != options.proxy || options.proxy_negotiate_auth == Some(true) {
+            match build_async_client(&attempt_options) {
+                Ok(client) => client,
+                Err(e) => return (Err(Box::new(format!("Client error: {}", e))), attempt),
+            }
+        } else {
+            client.clone()
+        };
+
+        let mut request = client.get(&url);
+
+        if let Some(deadline) = deadline {
+            if tokio::time::timeout_at(deadline, politeness::wait_async(&options, &url)).await.is_err() {
+                return (Err(Box::new(atoms::not_attempted())), attempt);
+            }
+        } else {
+            politeness::wait_async(&options, &url).await;
+        }
+
+        if let Some(ms) = options.request_timeout_ms {
+            request = request.timeout(Duration::from_millis(ms));
+        }
+
+        for (key, value) in &headers {
+            request = request.header(key, value);
+        }
+
+        let outcome = send_async(request, "GET", &url, 0, &options, deadline, caller.clone()).await;
+        match &outcome {
+            Ok(response) => proxy_pool::report_response(&options, proxy.as_deref(), response.status, &response.headers),
+            Err(_) => proxy_pool::report_failure(&options, proxy.as_deref()),
+        }
+
+        let retryable = match &outcome {
+            Ok(response) => RETRY_STATUSES.contains(&response.status),
+            Err((_, retryable)) => *retryable,
+        };
+
+        // Exponential backoff: `batch_retry_backoff_ms`, then twice that, and so on
+        let delay = Duration::from_millis(backoff_ms.saturating_mul(1 << (attempt - 1).min(16)));
+        let out_of_time = deadline.is_some_and(|deadline| tokio::time::Instant::now() + delay >= deadline);
+
+        if !retryable || attempt > retries || out_of_time {
+            let result = match outcome {
+                Ok(response) => check_status(&options, response).map_err(|e| Box::new(e) as Box<dyn Encoder + Send>),
+                Err((reason, _)) => Err(reason),
+            };
+            return (result, attempt);
+        }
+
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+// Send a prepared async request and read its response, cutting it off with `:timeout`
+// at `deadline`. A failure comes with whether it is worth retrying.
+async fn send_async(
+    request: reqwest::RequestBuilder,
+    method: &str,
+    url: &str,
+    bytes_out: usize,
+    options: &HttpOptions,
+    deadline: Option<tokio::time::Instant>,
+    caller: Option<String>,
+) -> Result<HttpResponse, (Box<dyn Encoder + Send>, bool)> {
+    // Waiting for a slot counts against the batch deadline
+    let inflight = inflight::acquire_async();
+    let admitted = match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, inflight).await.unwrap_or(Err(atoms::not_attempted())),
+        None => inflight.await,
+    };
+    let _inflight = admitted.map_err(|reason| (Box::new(reason) as Box<dyn Encoder + Send>, false))?;
+
+    let id = events::enqueue(method, url, caller);
+    let started = Instant::now();
+
+    let exchange = async {
+        match send_retrying_async(request, options).await {
+            Ok(mut response) => {
+                let status = response.status().as_u16();
+                let version = response.version();
+                let connection = stats::connection(response.extensions());
+                let headers_map: HashMap<String, String> = response
+                    .headers()
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
+                    .collect();
+
+                match read_body_async(&mut response, options).await {
+                    Ok(body) => {
+                        stats::record(url, id, started, status, bytes_out, body.size(), connection);
+                        let response = HttpResponse {
+                            status,
+                            headers: headers_map,
+                            body,
+                            meta: None,
+                        };
+                        Ok(http_version::annotate(options, version, response))
+                    }
+                    Err(partial) => {
+                        stats::record_error(url, id, started, bytes_out);
+                        let reason = Box::new((atoms::body_interrupted(), BodyInterrupted {
+                            status,
+                            headers: headers_map.into_iter().collect(),
+                            partial_body: partial,
+                        }));
+                        Err((reason as Box<dyn Encoder + Send>, true))
+                    }
                 }
             }
+            Err(e) => {
+                stats::record_error(url, id, started, bytes_out);
+                let retryable = error_class(&e).is_some_and(|class| {
+                    class == atoms::timeout()
+                        || class == atoms::connection_refused()
+                        || class == atoms::connection_reset()
+                        || class == atoms::network_error()
+                });
+                Err((reason(&e, "Request error"), retryable))
+            }
         }
+    };
 
-        Ok(results.encode(env))
-    })
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, exchange).await.unwrap_or_else(|_| {
+            stats::record_error(url, id, started, bytes_out);
+            Err((Box::new(atoms::timeout()), false))
+        }),
+        None => exchange.await,
+    }
+}
+
+// Replace the application-wide defaults used to fill unset per-call options
+#[rustler::nif]
+fn configure_defaults(options: HttpOptions) -> Atom {
+    let mut defaults = DEFAULTS.write().unwrap();
+    *defaults = options.merge(&BASE_DEFAULTS);
+    swap_default_client(defaults.clone());
+
+    atoms::ok()
+}
+
+// Apply a delta to the defaults of a target, `:global` or a client; fields left unset
+// in the delta are kept
+#[rustler::nif(schedule = "DirtyIo")]
+fn reconfigure(target: Term, delta: HttpOptions) -> NifResult<Atom> {
+    if let Ok(client) = target.decode::<ResourceArc<clients::Client>>() {
+        clients::reconfigure(&client, delta)?;
+        return Ok(atoms::ok());
+    }
+    if !target.decode::<Atom>().is_ok_and(|atom| atom == atoms::global()) {
+        return Err(Error::BadArg);
+    }
+
+    let mut defaults = DEFAULTS.write().unwrap();
+    *defaults = delta.merge(&defaults);
+    swap_default_client(defaults.clone());
+
+    Ok(atoms::ok())
+}
+
+// Validate options without making a request, returning `[{field, reason}]`. Problems
+// with the `RUST_REQ_*` environment variables are reported too, as nothing else
+// reports them.
+#[rustler::nif(schedule = "DirtyIo")]
+fn validate_options(env: Env, options: HttpOptions) -> NifResult<Vec<(Atom, String)>> {
+    options
+        .validate()
+        .into_iter()
+        .chain(ENVIRONMENT.1.iter().cloned())
+        .map(|(field, reason)| Ok((Atom::from_str(env, field)?, reason)))
+        .collect()
+}
+
+// Define or replace a named profile usable via the `profile` option
+#[rustler::nif]
+fn profile_define(name: String, options: HttpOptions) -> NifResult<Atom> {
+    let mut profiles = PROFILES.write().unwrap();
+
+    // Walk the parent chain to reject definitions that would inherit from themselves
+    let mut parent = options.profile.clone();
+    while let Some(current) = parent {
+        if current == name {
+            return Err(Error::Term(Box::new((atoms::profile_cycle(), name))));
+        }
+        parent = profiles.get(&current).and_then(|p| p.profile.clone());
+    }
+
+    profiles.insert(name, options);
+    Ok(atoms::ok())
+}
+
+// Current application-wide defaults
+#[rustler::nif]
+fn get_defaults() -> HttpOptions {
+    DEFAULTS.read().unwrap().clone()
+}
+
+#[derive(NifMap)]
+struct Features {
+    http2: bool,
+    http3: bool,
+    brotli: bool,
+    zstd: bool,
+    socks: bool,
+    spnego: bool,
+    insecure_tls: bool,
+}
+
+#[derive(NifMap)]
+struct RuntimeStats {
+    uptime_ms: u64,
+    profiles: usize,
+    buffers: buffers::Stats,
+    blocking: blocking::Stats,
+    inflight: inflight::Stats,
+}
+
+#[derive(NifMap)]
+struct BuildInfo {
+    version: String,
+    reqwest_version: String,
+    tls_backend: String,
+    nif_version: String,
+    features: Features,
+    runtime: RuntimeStats,
+}
+
+static LOADED_AT: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+// Build and capability information for the loaded library
+#[rustler::nif]
+fn info() -> BuildInfo {
+    let nif_version = if cfg!(feature = "nif_version_2_17") {
+        "2.17"
+    } else if cfg!(feature = "nif_version_2_16") {
+        "2.16"
+    } else {
+        "2.15"
+    };
+
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        reqwest_version: env!("REQWEST_VERSION").to_string(),
+        tls_backend: "rustls".to_string(),
+        nif_version: nif_version.to_string(),
+        features: Features {
+            http2: cfg!(feature = "http2"),
+            http3: false,
+            brotli: cfg!(feature = "brotli"),
+            zstd: cfg!(feature = "zstd"),
+            socks: cfg!(feature = "socks"),
+            spnego: cfg!(feature = "spnego"),
+            insecure_tls: cfg!(feature = "insecure_tls"),
+        },
+        runtime: RuntimeStats {
+            uptime_ms: LOADED_AT.elapsed().as_millis() as u64,
+            profiles: PROFILES.read().unwrap().len(),
+            buffers: buffers::stats(),
+            blocking: blocking::stats(),
+            inflight: inflight::stats(),
+        },
+    }
+}
+
+fn load(_env: Env, _info: Term) -> bool {
+    LazyLock::force(&LOADED_AT);
+    swap_default_client(DEFAULTS.read().unwrap().clone());
+    true
 }
 
-rustler::init!("Elixir.RustReq.Native");
+rustler::init!("Elixir.RustReq.Native", load = load);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_environment_values_are_reported() {
+        let variables = [
+            ("RUST_REQ_TIMEOUT_MS", "5s"),
+            ("RUST_REQ_FOLLOW_REDIRECTS", "yes"),
+            ("RUST_REQ_MAX_REDIRECTS", " 3 "),
+            ("RUST_REQ_CONNECT_TIMEOUT_MS", "0"),
+            ("RUST_REQ_READ_TIMEOUT_MS", ""),
+        ];
+        for (name, value) in variables {
+            std::env::set_var(name, value);
+        }
+        let (options, errors) = HttpOptions::from_env();
+        for (name, _) in variables {
+            std::env::remove_var(name);
+        }
+
+        assert_eq!(options.timeout_ms, None);
+        assert_eq!(options.follow_redirects, None);
+        assert_eq!(options.max_redirects, Some(3));
+        assert_eq!(options.read_timeout_ms, None);
+        assert_eq!(
+            errors,
+            [
+                ("timeout_ms", "RUST_REQ_TIMEOUT_MS has invalid value \"5s\"".to_string()),
+                ("follow_redirects", "RUST_REQ_FOLLOW_REDIRECTS has invalid value \"yes\"".to_string()),
+                ("connect_timeout_ms", "must be greater than 0 (from the environment)".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use percent_encoding::percent_decode_str;
+use rustler::NifMap;
+use std::collections::HashMap;
+
+// One link of a `Link` header (RFC 8288)
+#[derive(Debug, NifMap)]
+pub struct Link {
+    // The target as written, relative references left unresolved
+    pub url: String,
+    // Relation types, lowercased and space-separated, e.g. `"next"` or `"prev first"`
+    pub rel: Option<String>,
+    // The other parameters, names lowercased; a parameter without a value maps to ""
+    pub params: HashMap<String, String>,
+}
+
+const WHITESPACE: [char; 2] = [' ', '\t'];
+
+// A quoted string, unescaped, and the input after its closing quote; an unterminated one
+// runs to the end
+fn quoted(input: &str) -> (String, &str) {
+    let mut value = String::new();
+    let mut chars = input.char_indices().skip(1);
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return (value, &input[i + 1..]),
+            '\\' => value.extend(chars.next().map(|(_, escaped)| escaped)),
+            _ => value.push(c),
+        }
+    }
+
+    (value, "")
+}
+
+// An RFC 8187 extended value such as `UTF-8'en'%E2%82%AC%20rates`; other charsets are
+// kept as written
+fn ext_value(value: &str) -> String {
+    let mut parts = value.splitn(3, '\'');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(charset), Some(_language), Some(encoded)) if charset.eq_ignore_ascii_case("utf-8") => {
+            match percent_decode_str(encoded).decode_utf8() {
+                Ok(decoded) => decoded.into_owned(),
+                Err(_) => value.to_string(),
+            }
+        }
+        _ => value.to_string(),
+    }
+}
+
+// The `; name=value` parameters after a link's target, and the input after them
+fn parameters(mut rest: &str) -> (Vec<(String, String)>, &str) {
+    let mut params = Vec::new();
+
+    while let Some(after) = rest.trim_start_matches(WHITESPACE).strip_prefix(';') {
+        let after = after.trim_start_matches(WHITESPACE);
+        let name_end = after
+            .find(|c: char| matches!(c, '=' | ';' | ',') || c.is_whitespace())
+            .unwrap_or(after.len());
+        let name = after[..name_end].to_ascii_lowercase();
+        rest = after[name_end..].trim_start_matches(WHITESPACE);
+
+        let value = match rest.strip_prefix('=').map(|after| after.trim_start_matches(WHITESPACE)) {
+            Some(after) if after.starts_with('"') => {
+                let (value, after) = quoted(after);
+                rest = after;
+                value
+            }
+            Some(after) => {
+                let end = after.find([';', ',']).unwrap_or(after.len());
+                rest = &after[end..];
+                after[..end].trim_end().to_string()
+            }
+            None => String::new(),
+        };
+
+        if !name.is_empty() {
+            params.push((name, value));
+        }
+    }
+
+    (params, rest)
+}
+
+// Parse a `Link` header value, or several joined with commas. Only the first occurrence
+// of a parameter counts, and an extended `title*` replaces `title`. Malformed members
+// are skipped.
+pub fn parse(value: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+    let mut rest = value;
+
+    loop {
+        rest = rest.trim_start_matches([' ', '\t', ',']);
+        if rest.is_empty() {
+            return links;
+        }
+
+        if let Some(target) = rest.strip_prefix('<') {
+            let Some(end) = target.find('>') else {
+                return links;
+            };
+            let (params, after) = parameters(&target[end + 1..]);
+            rest = after;
+
+            let mut named = HashMap::new();
+            let mut extended = HashMap::new();
+            for (name, value) in params {
+                match name.strip_suffix('*') {
+                    Some(base) => extended.entry(base.to_string()).or_insert_with(|| ext_value(&value)),
+                    None => named.entry(name).or_insert(value),
+                };
+            }
+            named.extend(extended);
+
+            let rel = named.remove("rel").map(|rel| {
+                rel.split_whitespace()
+                    .map(str::to_lowercase)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            });
+
+            links.push(Link {
+                url: target[..end].trim().to_string(),
+                rel,
+                params: named,
+            });
+        }
+
+        // Move past anything left of this member
+        match rest.find(',') {
+            Some(comma) => rest = &rest[comma + 1..],
+            None => return links,
+        }
+    }
+}
+
+#[rustler::nif]
+fn parse_link_header(value: String) -> Vec<Link> {
+    parse(&value)
+}
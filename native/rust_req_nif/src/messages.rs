@@ -0,0 +1,32 @@
+use rustler::env::OwnedEnv;
+use rustler::{Env, LocalPid, Term};
+use std::sync::mpsc;
+use std::sync::LazyLock;
+
+type Message = Box<dyn for<'a> FnOnce(Env<'a>) -> Term<'a> + Send>;
+
+// Messages are built and sent from a dedicated thread, because the VM forbids
+// `OwnedEnv::send_and_clear` on scheduler threads, where most NIF code runs.
+static OUTBOX: LazyLock<mpsc::Sender<(LocalPid, Message)>> = LazyLock::new(|| {
+    let (sender, receiver) = mpsc::channel::<(LocalPid, Message)>();
+
+    std::thread::Builder::new()
+        .name("rust_req_messenger".to_string())
+        .spawn(move || {
+            let mut env = OwnedEnv::new();
+            for (pid, message) in receiver {
+                let _ = env.send_and_clear(&pid, message);
+            }
+        })
+        .expect("failed to spawn messenger thread");
+
+    sender
+});
+
+// Queue a message for `pid`; the closure builds the term on the messenger thread
+pub fn send<F>(pid: LocalPid, message: F)
+where
+    F: for<'a> FnOnce(Env<'a>) -> Term<'a> + Send + 'static,
+{
+    let _ = OUTBOX.send((pid, Box::new(message)));
+}
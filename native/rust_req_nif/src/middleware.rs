@@ -0,0 +1,106 @@
+use rustler::{Atom, Encoder, Error, NifMap, NifResult, Term};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, LazyLock, Mutex};
+use std::time::Duration;
+
+use crate::{atoms, EncodedTerm, HttpOptions};
+
+// What the middleware process answered to `on_request`
+enum Decision {
+    Pass,
+    Headers(Vec<(String, String)>),
+    Halt(EncodedTerm),
+}
+
+// Requests waiting for their middleware decision, by call id
+static PENDING: LazyLock<Mutex<HashMap<u64, mpsc::SyncSender<Decision>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(NifMap)]
+struct RequestInfo {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+#[derive(NifMap)]
+struct ResponseInfo {
+    method: String,
+    url: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+}
+
+// Consult the `middleware` process before a request is sent. It may replace the
+// headers or halt the request; without an answer in time the request goes ahead as is.
+pub fn before_send(options: &HttpOptions, method: &str, url: &str, headers: Vec<(String, String)>) -> NifResult<Vec<(String, String)>> {
+    let Some(pid) = options.middleware else {
+        return Ok(headers);
+    };
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let (sender, receiver) = mpsc::sync_channel(1);
+    PENDING.lock().unwrap().insert(id, sender);
+
+    let request = RequestInfo {
+        method: method.to_string(),
+        url: url.to_string(),
+        headers: headers.clone(),
+    };
+    crate::messages::send(pid, move |env| {
+        (atoms::rust_req_middleware(), atoms::on_request(), id, request).encode(env)
+    });
+
+    let timeout = Duration::from_millis(options.middleware_timeout_ms.unwrap_or(1000));
+    let decision = receiver.recv_timeout(timeout);
+    PENDING.lock().unwrap().remove(&id);
+
+    match decision {
+        Ok(Decision::Headers(replaced)) => Ok(replaced),
+        Ok(Decision::Halt(reason)) => Err(Error::Term(Box::new((atoms::middleware_halt(), reason)))),
+        Ok(Decision::Pass) | Err(_) => Ok(headers),
+    }
+}
+
+// Let the `middleware` process observe a response; nothing waits for it
+pub fn after_receive(options: &HttpOptions, method: &str, url: &str, status: u16, headers: &HashMap<String, String>) {
+    let Some(pid) = options.middleware else {
+        return;
+    };
+
+    let response = ResponseInfo {
+        method: method.to_string(),
+        url: url.to_string(),
+        status,
+        headers: headers.clone().into_iter().collect(),
+    };
+    crate::messages::send(pid, move |env| {
+        (atoms::rust_req_middleware(), atoms::on_response(), response).encode(env)
+    });
+}
+
+// Answer an `on_request` message: `:pass`, `{:ok, headers}` or `{:halt, reason}`.
+// Answers arriving after the request stopped waiting are dropped.
+#[rustler::nif]
+fn middleware_reply(id: u64, reply: Term) -> NifResult<Atom> {
+    let decision = if reply.decode::<Atom>().is_ok_and(|atom| atom == atoms::pass()) {
+        Decision::Pass
+    } else {
+        let (tag, value): (Atom, Term) = reply.decode()?;
+        if tag == atoms::ok() {
+            Decision::Headers(value.decode()?)
+        } else if tag == atoms::halt() {
+            Decision::Halt(value.decode()?)
+        } else {
+            return Err(Error::BadArg);
+        }
+    };
+
+    if let Some(sender) = PENDING.lock().unwrap().remove(&id) {
+        let _ = sender.try_send(decision);
+    }
+
+    Ok(atoms::ok())
+}
@@ -0,0 +1,292 @@
+use ring::rand::{SecureRandom, SystemRandom};
+use rustler::types::binary::NewBinary;
+use rustler::{Binary, Encoder, Env, NifMap, Term};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::accept;
+
+// Multipart types whose parts `multipart` splits out: ranges of a `Range` response
+// (RFC 9110) and the generic mixed type (RFC 2046)
+const TYPES: [&str; 2] = ["multipart/byteranges", "multipart/mixed"];
+
+// One body part, with its header names lowercased
+#[derive(Debug)]
+pub struct Part {
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Encoder for Part {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let mut body = NewBinary::new(env, self.body.len());
+        body.as_mut_slice().copy_from_slice(&self.body);
+        (&self.headers, Term::from(body)).encode(env)
+    }
+}
+
+// The boundary of a supported multipart type, or None for any other
+fn boundary(content_type: &str) -> Option<String> {
+    let (essence, params) = accept::media_type(content_type);
+    if !TYPES.contains(&essence.as_str()) {
+        return None;
+    }
+
+    params
+        .into_iter()
+        .find(|(name, _)| name == "boundary")
+        .map(|(_, value)| value)
+        .filter(|value| !value.is_empty())
+}
+
+// Position of the next delimiter from `from` that starts a line
+fn find_delimiter(body: &[u8], delimiter: &[u8], from: usize) -> Option<usize> {
+    (from..=body.len().checked_sub(delimiter.len())?)
+        .find(|&at| (at == 0 || body[at - 1] == b'\n') && body[at..].starts_with(delimiter))
+}
+
+// Headers of a part as written before the blank line, with folded lines joined
+fn headers(block: &[u8]) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+
+    for line in String::from_utf8_lossy(block).lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    headers
+}
+
+// Split a part into its headers and body; a part starting with a blank line has no headers
+fn part(content: &[u8]) -> Part {
+    if let Some(body) = content.strip_prefix(b"\r\n").or_else(|| content.strip_prefix(b"\n")) {
+        return Part {
+            headers: Vec::new(),
+            body: body.to_vec(),
+        };
+    }
+
+    let split = [&b"\r\n\r\n"[..], b"\n\n"]
+        .iter()
+        .filter_map(|separator| {
+            let at = content.windows(separator.len()).position(|window| window == *separator)?;
+            Some((at, at + separator.len()))
+        })
+        .min();
+
+    let (block, body) = match split {
+        Some((end, start)) => (&content[..end], &content[start..]),
+        None => (content, &[][..]),
+    };
+
+    Part {
+        headers: headers(block),
+        body: body.to_vec(),
+    }
+}
+
+// The parts of a multipart/byteranges or multipart/mixed body, in order. None when
+// `content_type` is another type, names no boundary, or the body is not closed by a
+// final delimiter. The preamble and epilogue are dropped, and bare LF line breaks are
+// accepted as well as CRLF.
+pub fn parse(content_type: &str, body: &[u8]) -> Option<Vec<Part>> {
+    let delimiter = format!("--{}", boundary(content_type)?).into_bytes();
+    let mut at = find_delimiter(body, &delimiter, 0)?;
+    let mut parts = Vec::new();
+
+    loop {
+        let after = at + delimiter.len();
+        if body[after..].starts_with(b"--") {
+            return Some(parts);
+        }
+
+        // Whatever follows the delimiter on its line is transport padding
+        let start = after + body[after..].iter().position(|&byte| byte == b'\n')? + 1;
+        let next = find_delimiter(body, &delimiter, start)?;
+
+        // The line break before a delimiter belongs to it
+        let mut content = &body[start..next];
+        if next > start {
+            content = content.strip_suffix(b"\n").unwrap_or(content);
+            content = content.strip_suffix(b"\r").unwrap_or(content);
+        }
+
+        parts.push(part(content));
+        at = next;
+    }
+}
+
+#[rustler::nif]
+fn parse_multipart(content_type: String, body: rustler::Binary) -> Option<Vec<Part>> {
+    parse(&content_type, body.as_slice())
+}
+
+// A multipart/form-data part as `post_multipart/4` passes it: a text field, in-memory
+// data or a file streamed from `path`
+#[derive(NifMap)]
+pub struct FormPart<'a> {
+    name: String,
+    data: Option<Binary<'a>>,
+    path: Option<String>,
+    filename: Option<String>,
+    content_type: Option<String>,
+}
+
+// A stretch of the encoded form: bytes held in memory or a whole file
+enum Segment {
+    Bytes(Vec<u8>),
+    File(File, u64),
+}
+
+impl Segment {
+    fn len(&self) -> u64 {
+        match self {
+            Segment::Bytes(bytes) => bytes.len() as u64,
+            Segment::File(_, size) => *size,
+        }
+    }
+}
+
+// A multipart/form-data body (RFC 7578), read as it is sent so files never sit in
+// memory. Its files are opened up front, so a missing one fails before the request.
+pub struct Form {
+    boundary: String,
+    segments: Vec<Segment>,
+    // Segment being read, and the offset into it
+    at: usize,
+    offset: u64,
+}
+
+// Quotes in names and filenames are percent-encoded, as browsers do
+fn quoted(value: &str) -> String {
+    value.replace('"', "%22").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+impl Form {
+    pub fn new(parts: Vec<FormPart>) -> std::io::Result<Form> {
+        let mut random = [0u8; 16];
+        SystemRandom::new().fill(&mut random).expect("system randomness");
+        let boundary: String = random.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let boundary = format!("rust-req-{}", boundary);
+
+        let mut segments = Vec::new();
+        let mut pending = Vec::new();
+
+        for part in parts {
+            let filename = part.filename.or_else(|| {
+                let path = part.path.as_deref()?;
+                Path::new(path).file_name().map(|name| name.to_string_lossy().into_owned())
+            });
+
+            pending.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+            pending.extend_from_slice(format!("Content-Disposition: form-data; name=\"{}\"", quoted(&part.name)).as_bytes());
+            if let Some(filename) = &filename {
+                pending.extend_from_slice(format!("; filename=\"{}\"", quoted(filename)).as_bytes());
+            }
+            pending.extend_from_slice(b"\r\n");
+
+            let content_type = part
+                .content_type
+                .or_else(|| filename.is_some().then(|| "application/octet-stream".to_string()));
+            if let Some(content_type) = content_type {
+                pending.extend_from_slice(format!("Content-Type: {}\r\n", content_type).as_bytes());
+            }
+            pending.extend_from_slice(b"\r\n");
+
+            match (part.path, part.data) {
+                (Some(path), _) => {
+                    let file = File::open(&path)?;
+                    let size = file.metadata()?.len();
+                    segments.push(Segment::Bytes(std::mem::take(&mut pending)));
+                    segments.push(Segment::File(file, size));
+                }
+                (None, Some(data)) => pending.extend_from_slice(data.as_slice()),
+                (None, None) => {}
+            }
+            pending.extend_from_slice(b"\r\n");
+        }
+
+        pending.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+        segments.push(Segment::Bytes(pending));
+
+        Ok(Form {
+            boundary,
+            segments,
+            at: 0,
+            offset: 0,
+        })
+    }
+
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.segments.iter().map(Segment::len).sum()
+    }
+}
+
+impl Read for Form {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        while let Some(segment) = self.segments.get_mut(self.at) {
+            let read = match segment {
+                Segment::Bytes(bytes) => {
+                    let rest = &bytes[self.offset as usize..];
+                    let read = rest.len().min(buf.len());
+                    buf[..read].copy_from_slice(&rest[..read]);
+                    read
+                }
+                // Read no further than the size sent as Content-Length, even if the
+                // file grew since
+                Segment::File(file, size) => {
+                    let len = (*size - self.offset).min(buf.len() as u64) as usize;
+                    let read = file.read(&mut buf[..len])?;
+                    if read == 0 && len > 0 {
+                        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "file shrank while being sent"));
+                    }
+                    read
+                }
+            };
+
+            if read > 0 {
+                self.offset += read as u64;
+                return Ok(read);
+            }
+            self.at += 1;
+            self.offset = 0;
+        }
+        Ok(0)
+    }
+}
+
+// Seeking goes back to the start only, to read the form again once its digest is taken
+impl Seek for Form {
+    fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> {
+        if position != SeekFrom::Start(0) {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "a form can only be rewound"));
+        }
+
+        for segment in &mut self.segments {
+            if let Segment::File(file, _) = segment {
+                file.rewind()?;
+            }
+        }
+        self.at = 0;
+        self.offset = 0;
+        Ok(0)
+    }
+}
@@ -0,0 +1,233 @@
+use rustler::{Atom, Encoder, Env, Error, LocalPid, NifResult, ResourceArc, Term};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::task::AbortHandle;
+
+use crate::background::{RequestError, RUNTIME};
+use crate::{
+    accept, async_client_base, atoms, audit, connect_timeout, events, header_map, inflight, json, pac, parse_method, politeness, proxy_pool,
+    read_timeout, reason, resolve_options, signatures, spnego, stats, throttle, urls, user_agents, EncodedTerm, HttpOptions,
+};
+
+// Longest line buffered while waiting for its newline; past it the stream fails
+const MAX_LINE_BYTES: usize = 16 * 1024 * 1024;
+
+// A JSON-lines stream running in the background, kept to cancel it
+pub struct JsonLinesHandle {
+    task: Mutex<Option<AbortHandle>>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for JsonLinesHandle {}
+
+// What a stream delivers, as `{:rust_req_ndjson, tag, event}`
+enum Event {
+    // `{:status, status, headers}`, before any line
+    Status(u16, Vec<(String, String)>),
+    // `{:line, term}`, objects as maps with string keys
+    Line(serde_json::Value),
+    // `{:invalid_line, text}` for a line that isn't JSON
+    InvalidLine(String),
+    // `:done` once the body ends
+    Done,
+    // `{:error, %RustReq.Error{}}`, ending the stream
+    Failed(RequestError),
+}
+
+impl Encoder for Event {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            Event::Status(status, headers) => (atoms::status(), status, headers).encode(env),
+            Event::Line(value) => (atoms::line(), json::to_term(env, value)).encode(env),
+            Event::InvalidLine(text) => (atoms::invalid_line(), text).encode(env),
+            Event::Done => atoms::done().encode(env),
+            Event::Failed(error) => (atoms::error(), error).encode(env),
+        }
+    }
+}
+
+fn emit(pid: LocalPid, tag: &EncodedTerm, event: Event) {
+    let tag = tag.clone();
+    crate::messages::send(pid, move |env| (atoms::rust_req_ndjson(), tag, event).encode(env));
+}
+
+// Deliver one line; blank lines, which some servers send as keep-alives, are skipped
+fn deliver(pid: LocalPid, tag: &EncodedTerm, line: &[u8]) {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return;
+    }
+
+    let event = match serde_json::from_slice(line) {
+        Ok(value) => Event::Line(value),
+        Err(_) => Event::InvalidLine(String::from_utf8_lossy(line).into_owned()),
+    };
+    emit(pid, tag, event);
+}
+
+// Deliver every complete line of `buffer`, keeping the partial last one
+fn deliver_lines(pid: LocalPid, tag: &EncodedTerm, buffer: &mut Vec<u8>) {
+    let mut start = 0;
+    while let Some(end) = buffer[start..].iter().position(|&byte| byte == b'\n') {
+        deliver(pid, tag, &buffer[start..start + end]);
+        start += end + 1;
+    }
+    buffer.drain(..start);
+}
+
+// Send a request in the background and deliver its newline-delimited JSON body to the
+// caller line by line as `{:rust_req_ndjson, tag, event}`. `timeout_ms` bounds the
+// connection and each wait for data rather than the whole stream. `redacted_url` is
+// the URL as errors report it.
+#[allow(clippy::too_many_arguments)]
+#[rustler::nif]
+fn ndjson_stream(
+    env: Env,
+    method: String,
+    url: String,
+    redacted_url: String,
+    tag: EncodedTerm,
+    headers: Vec<(String, String)>,
+    body: String,
+    options: HttpOptions,
+) -> NifResult<(Atom, ResourceArc<JsonLinesHandle>)> {
+    let method = parse_method(&method)?;
+    let mut options = resolve_options(options)?;
+    let (url, headers) = urls::apply(&options, url, headers).map_err(urls::error)?;
+    let proxy = proxy_pool::select(&mut options, &url).map_err(|reason| Error::Term(Box::new(reason)))?;
+    let headers = user_agents::apply(&options, &url, headers);
+    let headers = accept::apply(&options, headers);
+    let pid = env.pid();
+    let caller = audit::caller(env);
+
+    let task = RUNTIME.spawn(async move {
+        let started = Instant::now();
+        let failed = |reason: Box<dyn Encoder + Send>| {
+            Event::Failed(RequestError {
+                reason,
+                method: method.to_string(),
+                url: redacted_url.clone(),
+                attempt: 1,
+                elapsed_ms: started.elapsed().as_millis() as u64,
+            })
+        };
+
+        // Choosing a `proxy_pac` proxy, minting a Negotiate token and building the client
+        // may all block, so they run here rather than on the caller's scheduler
+        let method_name = method.to_string();
+        let prepared = tokio::task::spawn_blocking(move || {
+            pac::select(&mut options, &url).map_err(|e| format!("PAC error: {}", e))?;
+            let headers = spnego::apply(&options, &url, headers)?;
+            let headers = signatures::apply(&options, &method_name, &url, headers, body.as_bytes())?;
+
+            let idle = Duration::from_millis(options.timeout_ms.unwrap_or(30000));
+            let connect = connect_timeout(&options).unwrap_or(idle);
+            let read = read_timeout(&options).unwrap_or(idle);
+            let client = async_client_base(&options)
+                .and_then(|builder| Ok(builder.connect_timeout(connect).read_timeout(read).build()?))
+                .map_err(|e| format!("Client error: {}", e))?;
+            Ok((options, url, headers, body, client))
+        })
+        .await
+        .unwrap_or_else(|e| Err(format!("Request error: {}", e)));
+
+        let (options, url, headers, body, client) = match prepared {
+            Ok(prepared) => prepared,
+            Err(reason) => {
+                emit(pid, &tag, failed(Box::new(reason)));
+                return;
+            }
+        };
+        politeness::wait_async(&options, &url).await;
+
+        // Ends early if `drain` aborts it
+        let streamed = inflight::abortable(async {
+            // The slot is held for as long as the stream runs
+            let _inflight = match inflight::acquire_async().await {
+                Ok(permit) => permit,
+                Err(reason) => {
+                    emit(pid, &tag, failed(Box::new(reason)));
+                    return;
+                }
+            };
+
+            let mut request = client.request(method.clone(), &url);
+            for (key, value) in headers {
+                request = request.header(key, value);
+            }
+            let bytes_out = body.len();
+            if !body.is_empty() {
+                request = request.body(throttle::upload_body(&options, body.into()));
+            }
+
+            let id = events::enqueue(method.as_str(), &url, caller);
+            let mut response = match request.send().await {
+                Ok(response) => response,
+                Err(e) => {
+                    stats::record_error(&url, id, started, bytes_out);
+                    proxy_pool::report_failure(&options, proxy.as_deref());
+                    emit(pid, &tag, failed(reason(&e, "Request error")));
+                    return;
+                }
+            };
+
+            let status = response.status().as_u16();
+            let connection = stats::connection(response.extensions());
+            let headers = header_map(response.headers());
+            proxy_pool::report_response(&options, proxy.as_deref(), status, &headers);
+            emit(pid, &tag, Event::Status(status, headers.into_iter().collect()));
+
+            let mut buffer = Vec::new();
+            let mut bytes_in = 0;
+            let limiter = throttle::download(&options);
+            loop {
+                match response.chunk().await {
+                    Ok(Some(chunk)) => {
+                        bytes_in += chunk.len();
+                        buffer.extend_from_slice(&chunk);
+                        deliver_lines(pid, &tag, &mut buffer);
+                        throttle::pace(limiter.as_deref(), chunk.len()).await;
+
+                        if buffer.len() > MAX_LINE_BYTES {
+                            stats::record_error(&url, id, started, bytes_out);
+                            let reason = format!("line longer than {} bytes", MAX_LINE_BYTES);
+                            emit(pid, &tag, failed(Box::new(reason)));
+                            return;
+                        }
+                    }
+                    Ok(None) => {
+                        // The last line needs no newline
+                        deliver(pid, &tag, &buffer);
+                        stats::record(&url, id, started, status, bytes_out, bytes_in, connection);
+                        emit(pid, &tag, Event::Done);
+                        return;
+                    }
+                    Err(e) => {
+                        stats::record_error(&url, id, started, bytes_out);
+                        emit(pid, &tag, failed(reason(&e, "Body error")));
+                        return;
+                    }
+                }
+            }
+        })
+        .await;
+
+        if streamed.is_none() {
+            emit(pid, &tag, failed(Box::new(atoms::cancelled())));
+        }
+    });
+
+    let handle = ResourceArc::new(JsonLinesHandle {
+        task: Mutex::new(Some(task.abort_handle())),
+    });
+    Ok((atoms::ok(), handle))
+}
+
+// Stop a stream; no further messages are sent for it
+#[rustler::nif]
+fn ndjson_cancel(handle: ResourceArc<JsonLinesHandle>) -> Atom {
+    if let Some(task) = handle.task.lock().unwrap().take() {
+        task.abort();
+    }
+    atoms::ok()
+}
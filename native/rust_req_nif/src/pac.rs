@@ -0,0 +1,586 @@
+// Proxy auto-config: fetch a PAC script and run its `FindProxyForURL(url, host)` to pick
+// the proxy for each destination. Scripts run on an embedded JavaScript engine (Boa)
+// with the standard PAC helpers defined here; a script that fails sends requests
+// DIRECT, as browsers do.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use boa_engine::{Context, JsResult, JsString, JsValue, NativeFunction, Source};
+use chrono::{Datelike, Local, NaiveDateTime, Timelike, Utc};
+use rustler::Error;
+
+use crate::system_proxy::ProxySetting;
+use crate::{audit, events, HttpOptions};
+
+// How long fetched scripts and the decisions they made are reused
+const CACHE_TTL: Duration = Duration::from_secs(300);
+
+// Decisions kept before the cache is cleared
+const MAX_DECISIONS: usize = 4096;
+
+// Nested function calls allowed, so recursive scripts fail instead of overflowing the stack
+const MAX_CALL_DEPTH: usize = 64;
+
+// Iterations a single loop may run, so a script that never returns fails instead
+const MAX_LOOP_ITERATIONS: u64 = 1_000_000;
+
+// The functions PAC scripts may call, besides the language's own
+const HELPERS: [&str; 14] = [
+    "isPlainHostName",
+    "dnsDomainIs",
+    "localHostOrDomainIs",
+    "isResolvable",
+    "isInNet",
+    "dnsResolve",
+    "myIpAddress",
+    "dnsDomainLevels",
+    "shExpMatch",
+    "convert_addr",
+    "weekdayRange",
+    "dateRange",
+    "timeRange",
+    "alert",
+];
+
+const WEEKDAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+const MONTHS: [&str; 12] = ["JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC"];
+
+// PAC's `shExpMatch`: `*` matches any run of characters and `?` any single one
+fn shell_match(text: &[char], pattern: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len()).any(|skip| shell_match(&text[skip..], rest)),
+        Some(('?', rest)) => !text.is_empty() && shell_match(&text[1..], rest),
+        Some((c, rest)) => text.first() == Some(c) && shell_match(&text[1..], rest),
+    }
+}
+
+fn resolve(host: &str) -> Option<Ipv4Addr> {
+    if let Ok(ip) = host.parse::<Ipv4Addr>() {
+        return Some(ip);
+    }
+    (host, 0).to_socket_addrs().ok()?.find_map(|addr| match addr.ip() {
+        IpAddr::V4(ip) => Some(ip),
+        IpAddr::V6(_) => None,
+    })
+}
+
+// The address of the interface that would route to the internet; connecting a UDP
+// socket sends no packets
+fn my_ip_address() -> Ipv4Addr {
+    UdpSocket::bind("0.0.0.0:0")
+        .and_then(|socket| socket.connect("198.51.100.1:53").map(|_| socket))
+        .and_then(|socket| socket.local_addr())
+        .ok()
+        .and_then(|addr| match addr.ip() {
+            IpAddr::V4(ip) if !ip.is_unspecified() => Some(ip),
+            _ => None,
+        })
+        .unwrap_or(Ipv4Addr::LOCALHOST)
+}
+
+// Whether `value` lies in the inclusive range from `first` to `last`, which wraps
+// around when `first` comes after `last`, as `weekdayRange("FRI", "MON")` does
+fn within<T: PartialOrd>(first: T, value: T, last: T) -> bool {
+    if first <= last {
+        first <= value && value <= last
+    } else {
+        value >= first || value <= last
+    }
+}
+
+// The time the date and time helpers compare against: local, or UTC when their last
+// argument is "GMT", which is then left out
+fn now(args: &[String]) -> (&[String], NaiveDateTime) {
+    match args.split_last() {
+        Some((last, rest)) if last == "GMT" => (rest, Utc::now().naive_utc()),
+        _ => (args, Local::now().naive_local()),
+    }
+}
+
+// `weekdayRange(wd1)` or `weekdayRange(wd1, wd2)`, with days named SUN to SAT
+fn weekday_range(args: &[String], now: NaiveDateTime) -> bool {
+    let day = |name: &String| WEEKDAYS.iter().position(|day| day.eq_ignore_ascii_case(name));
+    let today = now.weekday().num_days_from_sunday() as usize;
+
+    match args {
+        [only] => day(only) == Some(today),
+        [first, last] => match (day(first), day(last)) {
+            (Some(first), Some(last)) => within(first, today, last),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// One argument of `dateRange`: a day of the month, a month named JAN to DEC or a
+// four-digit year
+enum DatePart {
+    Day(u32),
+    Month(u32),
+    Year(i32),
+}
+
+fn date_part(arg: &str) -> Option<DatePart> {
+    if let Some(month) = MONTHS.iter().position(|month| month.eq_ignore_ascii_case(arg)) {
+        return Some(DatePart::Month(month as u32 + 1));
+    }
+    match arg.parse::<i32>().ok()? {
+        day @ 1..=31 => Some(DatePart::Day(day as u32)),
+        year if year >= 1000 => Some(DatePart::Year(year)),
+        _ => None,
+    }
+}
+
+// Which of year, month and day `parts` name, and their values as a comparable
+// (year, month, day), zero for those not named; None when one is named twice
+fn date_key(parts: &[DatePart]) -> Option<([bool; 3], (i32, u32, u32))> {
+    let mut named = [false; 3];
+    let mut key = (0, 0, 0);
+
+    for part in parts {
+        match *part {
+            DatePart::Year(year) if !named[0] => (named[0], key.0) = (true, year),
+            DatePart::Month(month) if !named[1] => (named[1], key.1) = (true, month),
+            DatePart::Day(day) if !named[2] => (named[2], key.2) = (true, day),
+            _ => return None,
+        }
+    }
+    Some((named, key))
+}
+
+// `dateRange` with one bound, such as `dateRange("JAN")`, or two naming the same
+// fields, such as `dateRange(1, "JUN", 15, "AUG")`
+fn date_range(args: &[String], now: NaiveDateTime) -> bool {
+    let Some(parts) = args.iter().map(|arg| date_part(arg)).collect::<Option<Vec<_>>>() else {
+        return false;
+    };
+    let today = |named: [bool; 3]| {
+        (
+            if named[0] { now.year() } else { 0 },
+            if named[1] { now.month() } else { 0 },
+            if named[2] { now.day() } else { 0 },
+        )
+    };
+
+    match parts.len() {
+        1 => date_key(&parts).is_some_and(|(named, key)| key == today(named)),
+        2 | 4 | 6 => {
+            let (first, last) = parts.split_at(parts.len() / 2);
+            match (date_key(first), date_key(last)) {
+                (Some((named, first)), Some((also_named, last))) if named == also_named => within(first, today(named), last),
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+// `timeRange(hour)`, `timeRange(h1, h2)`, `timeRange(h1, m1, h2, m2)` or
+// `timeRange(h1, m1, s1, h2, m2, s2)`; `timeRange(9, 17)` runs to 17:59:59
+fn time_range(args: &[String], now: NaiveDateTime) -> bool {
+    let Some(numbers) = args.iter().map(|arg| arg.parse::<u32>().ok()).collect::<Option<Vec<_>>>() else {
+        return false;
+    };
+    let (hour, minute, second) = (now.hour(), now.minute(), now.second());
+
+    match numbers[..] {
+        [only] => hour == only,
+        [first, last] => within(first, hour, last),
+        [h1, m1, h2, m2] => within((h1, m1), (hour, minute), (h2, m2)),
+        [h1, m1, s1, h2, m2, s2] => within((h1, m1, s1), (hour, minute, second), (h2, m2, s2)),
+        _ => false,
+    }
+}
+
+// The standard PAC helper functions, given their arguments as strings
+fn helper(name: &str, args: &[String]) -> JsValue {
+    let arg = |index: usize| args.get(index).map(String::as_str).unwrap_or_default();
+
+    match name {
+        "isPlainHostName" => (!arg(0).contains('.')).into(),
+        "dnsDomainIs" => arg(0).to_lowercase().ends_with(&arg(1).to_lowercase()).into(),
+        "localHostOrDomainIs" => {
+            let (host, domain) = (arg(0).to_lowercase(), arg(1).to_lowercase());
+            (host == domain || (!host.contains('.') && domain.starts_with(&format!("{}.", host)))).into()
+        }
+        "isResolvable" => resolve(arg(0)).is_some().into(),
+        "isInNet" => {
+            let (Some(ip), Ok(pattern), Ok(mask)) = (resolve(arg(0)), arg(1).parse::<Ipv4Addr>(), arg(2).parse::<Ipv4Addr>())
+            else {
+                return false.into();
+            };
+            let mask = u32::from(mask);
+            (u32::from(ip) & mask == u32::from(pattern) & mask).into()
+        }
+        "dnsResolve" => resolve(arg(0)).map_or(JsValue::null(), |ip| JsString::from(ip.to_string().as_str()).into()),
+        "myIpAddress" => JsString::from(my_ip_address().to_string().as_str()).into(),
+        "dnsDomainLevels" => (arg(0).matches('.').count() as f64).into(),
+        "shExpMatch" => {
+            let text: Vec<char> = arg(0).chars().collect();
+            let pattern: Vec<char> = arg(1).chars().collect();
+            shell_match(&text, &pattern).into()
+        }
+        "convert_addr" => arg(0).parse::<Ipv4Addr>().map_or(0.0, |ip| u32::from(ip) as f64).into(),
+        "weekdayRange" => {
+            let (args, now) = now(args);
+            weekday_range(args, now).into()
+        }
+        "dateRange" => {
+            let (args, now) = now(args);
+            date_range(args, now).into()
+        }
+        "timeRange" => {
+            let (args, now) = now(args);
+            time_range(args, now).into()
+        }
+        _ => JsValue::undefined(),
+    }
+}
+
+// A JavaScript context with the PAC helpers defined and limits on loops and recursion
+fn context() -> JsResult<Context> {
+    let mut context = Context::default();
+    context.runtime_limits_mut().set_loop_iteration_limit(MAX_LOOP_ITERATIONS);
+    context.runtime_limits_mut().set_recursion_limit(MAX_CALL_DEPTH);
+
+    for name in HELPERS {
+        let function = NativeFunction::from_copy_closure(move |_, args, context| {
+            let args = args
+                .iter()
+                .map(|arg| Ok(arg.to_string(context)?.to_std_string_escaped()))
+                .collect::<JsResult<Vec<_>>>()?;
+            Ok(helper(name, &args))
+        });
+        context.register_global_callable(JsString::from(name), 0, function)?;
+    }
+    Ok(context)
+}
+
+// Run the script's `FindProxyForURL(url, host)` and return its result string
+fn find_proxy(source: &str, url: &str, host: &str) -> Result<String, String> {
+    let run = || -> JsResult<Option<String>> {
+        let mut context = context()?;
+        context.eval(Source::from_bytes(source))?;
+
+        let function = context.global_object().get(JsString::from("FindProxyForURL"), &mut context)?;
+        let Some(function) = function.as_callable() else {
+            return Ok(None);
+        };
+        let args = [JsString::from(url).into(), JsString::from(host).into()];
+        let result = function.call(&JsValue::undefined(), &args, &mut context)?;
+        Ok(Some(result.to_string(&mut context)?.to_std_string_escaped()))
+    };
+
+    run()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "the script defines no FindProxyForURL function".to_string())
+}
+
+// The proxy URL for the first usable entry of a `FindProxyForURL` result such as
+// `PROXY proxy.corp:8080; DIRECT`, None for `DIRECT`
+fn proxy_for(result: &str) -> Result<Option<String>, String> {
+    for entry in result.split(';').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let mut parts = entry.split_whitespace();
+        let kind = parts.next().unwrap_or("").to_ascii_uppercase();
+        let address = parts.next();
+
+        match (kind.as_str(), address) {
+            ("DIRECT", _) => return Ok(None),
+            ("PROXY", Some(address)) => return Ok(Some(format!("http://{}", address))),
+            ("HTTPS", Some(address)) => return Ok(Some(format!("https://{}", address))),
+            ("SOCKS" | "SOCKS5", Some(address)) => return Ok(Some(format!("socks5://{}", address))),
+            // SOCKS4 and unknown kinds are skipped in favour of the next entry
+            _ => {}
+        }
+    }
+    Err(format!("no usable proxy in {:?}", result))
+}
+
+// Scripts by location, and the proxy they picked by location and origin, each with the
+// time it was cached
+type Scripts = HashMap<String, (Instant, Arc<String>)>;
+type Decisions = HashMap<(String, String), (Instant, Option<String>)>;
+
+static SCRIPTS: LazyLock<Mutex<Scripts>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+static DECISIONS: LazyLock<Mutex<Decisions>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn fetch(source: &str) -> Result<String, String> {
+    if !source.starts_with("http://") && !source.starts_with("https://") {
+        return std::fs::read_to_string(source).map_err(|e| format!("cannot read {}: {}", source, e));
+    }
+
+    // On a thread of its own, as the blocking client can't run inside an async runtime
+    let url = source.to_string();
+    std::thread::spawn(move || {
+        let client = reqwest::blocking::Client::builder()
+            .no_proxy()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|e| e.to_string())?;
+        let response = audit::send(None, client.get(&url)).map_err(|e| format!("cannot fetch {}: {}", url, e))?;
+        if !response.status().is_success() {
+            return Err(format!("cannot fetch {}: status {}", url, response.status().as_u16()));
+        }
+        response.text().map_err(|e| format!("cannot fetch {}: {}", url, e))
+    })
+    .join()
+    .map_err(|_| "PAC fetch panicked".to_string())?
+}
+
+fn script(source: &str) -> Result<Arc<String>, String> {
+    if let Some((fetched, script)) = SCRIPTS.lock().unwrap().get(source) {
+        if fetched.elapsed() < CACHE_TTL {
+            return Ok(script.clone());
+        }
+    }
+
+    let script = Arc::new(fetch(source)?);
+    SCRIPTS
+        .lock()
+        .unwrap()
+        .insert(source.to_string(), (Instant::now(), script.clone()));
+    Ok(script)
+}
+
+// Route a request to `url` as the `proxy_pac` script decides, setting the request's
+// `proxy` (cleared for DIRECT). A `proxies` pool takes precedence. The script sees the
+// URL without its path and query, so decisions are cached per scheme, host and port.
+// A script that fails or names no usable proxy goes DIRECT with a warning event; only
+// failing to fetch it is an error.
+pub fn select(options: &mut HttpOptions, url: &str) -> Result<(), String> {
+    let Some(source) = options.proxy_pac.clone() else {
+        return Ok(());
+    };
+    if options.proxies.as_ref().is_some_and(|proxies| !proxies.is_empty()) {
+        return Ok(());
+    }
+
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("invalid URL {}: {}", url, e))?;
+    let host = parsed.host_str().unwrap_or_default().to_string();
+    let origin = format!("{}/", parsed.origin().ascii_serialization());
+    let key = (source.clone(), origin.clone());
+
+    let cached = DECISIONS
+        .lock()
+        .unwrap()
+        .get(&key)
+        .filter(|(decided, _)| decided.elapsed() < CACHE_TTL)
+        .map(|(_, proxy)| proxy.clone());
+
+    let proxy = match cached {
+        Some(proxy) => proxy,
+        None => {
+            let script = script(&source)?;
+            let proxy = find_proxy(&script, &origin, &host)
+                .and_then(|result| proxy_for(&result))
+                .unwrap_or_else(|e| {
+                    events::warning(url, &format!("PAC script failed, going DIRECT: {}", e));
+                    None
+                });
+            let mut decisions = DECISIONS.lock().unwrap();
+            if decisions.len() >= MAX_DECISIONS {
+                decisions.clear();
+            }
+            decisions.insert(key, (Instant::now(), proxy.clone()));
+            proxy
+        }
+    };
+
+    options.proxy = proxy.map(ProxySetting::Url);
+    Ok(())
+}
+
+// Error of `select` as returned by the request NIFs
+pub fn error(message: String) -> Error {
+    Error::Term(Box::new(format!("PAC error: {}", message)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn scripts_pick_proxies_by_host() {
+        let source = r#"
+            var corp = "corp.example.com";
+
+            function FindProxyForURL(url, host) {
+                if (isPlainHostName(host) || dnsDomainIs(host, "." + corp)) return "DIRECT";
+                if (shExpMatch(url, "https://*.cdn.example.net*")) {
+                    return "PROXY cdn-proxy:3128; DIRECT";
+                } else if (/\.test$/i.test(host)) {
+                    return isInNet(host, "10.0.0.0", "255.0.0.0") ? "DIRECT" : "SOCKS socks:1080";
+                }
+                var ports = [8000, 80];
+                return "PROXY proxy." + corp + ":" + ports.reduce(function (a, b) { return a + b; });
+            }
+        "#;
+
+        assert_eq!(find_proxy(source, "http://intranet/", "intranet").unwrap(), "DIRECT");
+        assert_eq!(find_proxy(source, "http://wiki.corp.example.com/", "wiki.corp.example.com").unwrap(), "DIRECT");
+        assert_eq!(
+            find_proxy(source, "https://img.cdn.example.net/", "img.cdn.example.net").unwrap(),
+            "PROXY cdn-proxy:3128; DIRECT"
+        );
+        assert_eq!(find_proxy(source, "http://APP.TEST/", "APP.TEST").unwrap(), "SOCKS socks:1080");
+        assert_eq!(
+            find_proxy(source, "http://example.org/", "example.org").unwrap(),
+            "PROXY proxy.corp.example.com:8080"
+        );
+    }
+
+    #[test]
+    fn helpers() {
+        let call = |name: &str, args: &[&str]| helper(name, &strings(args));
+
+        assert_eq!(call("isPlainHostName", &["www"]), JsValue::from(true));
+        assert_eq!(call("isPlainHostName", &["www.example.com"]), JsValue::from(false));
+        assert_eq!(call("dnsDomainIs", &["www.Example.com", ".example.com"]), JsValue::from(true));
+        assert_eq!(call("localHostOrDomainIs", &["www", "www.example.com"]), JsValue::from(true));
+        assert_eq!(call("localHostOrDomainIs", &["www.other.com", "www.example.com"]), JsValue::from(false));
+        assert_eq!(call("isInNet", &["192.168.1.20", "192.168.0.0", "255.255.0.0"]), JsValue::from(true));
+        assert_eq!(call("isInNet", &["10.1.1.1", "192.168.0.0", "255.255.0.0"]), JsValue::from(false));
+        assert_eq!(call("dnsResolve", &["127.0.0.1"]), JsValue::from(JsString::from("127.0.0.1")));
+        assert_eq!(call("dnsDomainLevels", &["www.example.com"]), JsValue::from(2.0));
+        assert_eq!(call("convert_addr", &["1.0.0.1"]), JsValue::from(16_777_217.0));
+    }
+
+    #[test]
+    fn shell_match_patterns() {
+        let cases = [
+            ("www.example.com", "*.example.com", true),
+            ("example.com", "*.example.com", false),
+            ("a.b.example.com", "*.example.*", true),
+            ("host1", "host?", true),
+            ("host12", "host?", false),
+            ("", "*", true),
+            ("anything", "", false),
+            ("exact", "exact", true),
+        ];
+
+        for (text, pattern, expected) in cases {
+            let text: Vec<char> = text.chars().collect();
+            let pattern: Vec<char> = pattern.chars().collect();
+            assert_eq!(shell_match(&text, &pattern), expected, "{:?} against {:?}", text, pattern);
+        }
+    }
+
+    #[test]
+    fn weekday_ranges_wrap_around_the_week() {
+        // A Wednesday
+        let now = at("2026-10-14 12:00:00");
+
+        assert!(weekday_range(&strings(&["WED"]), now));
+        assert!(!weekday_range(&strings(&["THU"]), now));
+        assert!(weekday_range(&strings(&["mon", "fri"]), now));
+        assert!(!weekday_range(&strings(&["FRI", "MON"]), now));
+        assert!(weekday_range(&strings(&["TUE", "SUN"]), now));
+        assert!(!weekday_range(&strings(&["FUNDAY"]), now));
+    }
+
+    #[test]
+    fn date_ranges_compare_the_fields_they_name() {
+        let now = at("2026-10-14 12:00:00");
+
+        assert!(date_range(&strings(&["14"]), now));
+        assert!(date_range(&strings(&["OCT"]), now));
+        assert!(date_range(&strings(&["2026"]), now));
+        assert!(!date_range(&strings(&["SEP"]), now));
+        assert!(date_range(&strings(&["1", "15"]), now));
+        assert!(date_range(&strings(&["NOV", "FEB"]), at("2027-01-20 00:00:00")));
+        assert!(!date_range(&strings(&["NOV", "FEB"]), now));
+        assert!(date_range(&strings(&["1", "OCT", "31", "DEC"]), now));
+        assert!(!date_range(&strings(&["15", "OCT", "31", "DEC"]), now));
+        assert!(date_range(&strings(&["SEP", "2026", "JAN", "2027"]), now));
+        assert!(date_range(&strings(&["1", "JAN", "2026", "31", "DEC", "2026"]), now));
+        assert!(!date_range(&strings(&["1", "JAN", "2027", "31", "DEC", "2027"]), now));
+        // Bounds naming different fields, or a field twice, match nothing
+        assert!(!date_range(&strings(&["1", "DEC"]), now));
+        assert!(!date_range(&strings(&["JAN", "FEB", "MAR"]), now));
+    }
+
+    #[test]
+    fn time_ranges_include_their_last_unit() {
+        let now = at("2026-10-14 17:30:15");
+
+        assert!(time_range(&strings(&["17"]), now));
+        assert!(time_range(&strings(&["9", "17"]), now));
+        assert!(!time_range(&strings(&["9", "16"]), now));
+        assert!(time_range(&strings(&["22", "18"]), now));
+        assert!(time_range(&strings(&["17", "0", "17", "30"]), now));
+        assert!(!time_range(&strings(&["17", "31", "18", "0"]), now));
+        assert!(time_range(&strings(&["17", "30", "0", "17", "30", "15"]), now));
+        assert!(!time_range(&strings(&["17", "30", "16", "17", "30", "59"]), now));
+        assert!(!time_range(&strings(&["noon"]), now));
+    }
+
+    #[test]
+    fn date_helpers_take_a_trailing_gmt() {
+        let args = strings(&["MON", "FRI", "GMT"]);
+        assert_eq!(now(&args).0, strings(&["MON", "FRI"]));
+
+        let source = r#"function FindProxyForURL(url, host) {
+            return weekdayRange("SUN", "SAT", "GMT") && dateRange(1, 31) && timeRange(0, 23) ? "DIRECT" : "PROXY p:1";
+        }"#;
+        assert_eq!(find_proxy(source, "http://example.com/", "example.com").unwrap(), "DIRECT");
+    }
+
+    #[test]
+    fn proxy_for_takes_the_first_usable_entry() {
+        assert_eq!(proxy_for("DIRECT").unwrap(), None);
+        assert_eq!(proxy_for("PROXY p:8080; DIRECT").unwrap(), Some("http://p:8080".to_string()));
+        assert_eq!(proxy_for(" https p:443 ").unwrap(), Some("https://p:443".to_string()));
+        assert_eq!(proxy_for("SOCKS4 s:1080; SOCKS5 s:1080").unwrap(), Some("socks5://s:1080".to_string()));
+        assert_eq!(proxy_for("PROXY; DIRECT").unwrap(), None);
+        assert!(proxy_for("").is_err());
+        assert!(proxy_for("SOCKS4 s:1080").is_err());
+    }
+
+    #[test]
+    fn runaway_and_broken_scripts_fail() {
+        let wrap = |body: &str| format!("function FindProxyForURL(url, host) {{ {} }}", body);
+
+        for source in [
+            wrap("while (true) {} return \"DIRECT\";"),
+            wrap("return FindProxyForURL(url, host);"),
+            wrap("throw new Error(\"no\");"),
+            wrap("return undefinedHelper(host);"),
+            "function FindProxyForURL(url, host) {".to_string(),
+            "function Other() {}".to_string(),
+        ] {
+            assert!(find_proxy(&source, "http://example.com/", "example.com").is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn failing_scripts_go_direct() {
+        let path = std::env::temp_dir().join(format!("rust_req_pac_{}.js", std::process::id()));
+        std::fs::write(&path, "function FindProxyForURL(url, host) { throw 'broken'; }").unwrap();
+
+        let mut options = HttpOptions {
+            proxy_pac: Some(path.to_string_lossy().into_owned()),
+            proxy: Some(ProxySetting::Url("http://stale:8080".to_string())),
+            ..HttpOptions::default()
+        };
+        assert!(select(&mut options, "http://example.com/").is_ok());
+        assert_eq!(options.proxy, None);
+
+        // Only a script that can't be read is an error
+        options.proxy_pac = Some(path.with_extension("missing").to_string_lossy().into_owned());
+        assert!(select(&mut options, "http://example.com/").is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
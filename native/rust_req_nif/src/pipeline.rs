@@ -0,0 +1,70 @@
+use reqwest::blocking::{Client, Request, RequestBuilder, Response};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, COOKIE};
+use rustler::Error;
+use std::time::SystemTime;
+
+use crate::cookies::{self, SendError};
+use crate::{audit, header_map, middleware, HttpOptions};
+
+// The part of the `request/5` pipeline the protocol helpers (downloads, tus, S3 and the
+// rest) go through for each request they make: the `middleware` sees and may halt it,
+// the `cookie_jar` applies, `retries` are made and the audit log records it. Statuses
+// are left to the helpers, which read them as their protocols say.
+
+// Let the `middleware` process replace the headers of `request` or halt it
+pub fn before_send(options: &HttpOptions, request: &mut Request) -> Result<(), SendError> {
+    if options.middleware.is_none() {
+        return Ok(());
+    }
+
+    let headers = request
+        .headers()
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
+        .collect();
+    let headers = middleware::before_send(options, request.method().as_str(), request.url().as_str(), headers)
+        .map_err(SendError::Halted)?;
+
+    let mut replaced = HeaderMap::new();
+    for (key, value) in headers {
+        let name = HeaderName::from_bytes(key.as_bytes());
+        let value = HeaderValue::from_str(&value);
+        let (Ok(name), Ok(value)) = (name, value) else {
+            return Err(SendError::Halted(Error::Term(Box::new(format!("Invalid header from middleware: {}", key)))));
+        };
+        replaced.append(name, value);
+    }
+    *request.headers_mut() = replaced;
+    Ok(())
+}
+
+// Send `request` with the cookies of the `cookie_jar`, through `cookies::send`, and
+// record it in the audit log once the response head arrives
+pub fn execute(caller: Option<&str>, options: &HttpOptions, client: &Client, mut request: Request) -> Result<Response, SendError> {
+    let started = SystemTime::now();
+    let method = request.method().to_string();
+    let url = request.url().to_string();
+    let bytes_out = request.body().and_then(|body| body.as_bytes()).map_or(0, <[u8]>::len);
+
+    if let Some(cookie) = cookies::request_header(options, &url).and_then(|cookie| HeaderValue::from_str(&cookie).ok()) {
+        request.headers_mut().append(COOKIE, cookie);
+    }
+
+    let result = cookies::send(options, &url, RequestBuilder::from_parts(client.clone(), request));
+    let response = result.as_ref().ok();
+    let bytes_in = response.and_then(Response::content_length).unwrap_or(0) as usize;
+    audit::record(caller, &method, &url, started, response.map(|r| r.status().as_u16()), bytes_out, bytes_in);
+
+    if let Some(response) = response {
+        middleware::after_receive(options, &method, &url, response.status().as_u16(), &header_map(response.headers()));
+    }
+    result
+}
+
+// `before_send`, then `execute`
+pub fn send(caller: Option<&str>, options: &HttpOptions, request: RequestBuilder) -> Result<Response, SendError> {
+    let (client, request) = request.build_split();
+    let mut request = request.map_err(SendError::Request)?;
+    before_send(options, &mut request)?;
+    execute(caller, options, &client, request)
+}
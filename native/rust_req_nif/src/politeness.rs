@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::HttpOptions;
+
+// Hosts kept before finished windows are pruned
+const PRUNE_THRESHOLD: usize = 1024;
+
+// Earliest time the next request to each host may be sent, shared by every caller
+static NEXT_SLOT: LazyLock<Mutex<HashMap<String, Instant>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Token buckets of `request_rate`, by host (empty unless `request_rate_per_host`) and
+// rate, so requests sharing both share a bucket
+type Buckets = HashMap<(String, u32, u64), Bucket>;
+
+static BUCKETS: LazyLock<Mutex<Buckets>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Holds up to `requests` tokens, refilled at `requests` per `per_ms`. Tokens taken past
+// what it holds are owed, and the taker waits until they are paid back.
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+// Capacity and refill rate per second of a `request_rate`
+fn bucket_rate(requests: u32, per_ms: u64) -> (f64, f64) {
+    (requests as f64, requests as f64 * 1000.0 / per_ms as f64)
+}
+
+impl Bucket {
+    // Tokens held at `now`
+    fn tokens(&self, now: Instant, (capacity, rate): (f64, f64)) -> f64 {
+        (self.tokens + now.duration_since(self.refilled).as_secs_f64() * rate).min(capacity)
+    }
+}
+
+// Reserve the next free slot for the host of `url` under `per_host_delay_ms` and
+// `request_rate`, returning when the request may be sent
+fn reserve(options: &HttpOptions, url: &str) -> Option<Instant> {
+    let host = reqwest::Url::parse(url).ok()?.host_str()?.to_ascii_lowercase();
+    space(options, &host).max(limit(options, &host))
+}
+
+// Take a token from the `request_rate` bucket of `host`
+fn limit(options: &HttpOptions, host: &str) -> Option<Instant> {
+    let (requests, per_ms) = options.request_rate.filter(|&(requests, per_ms)| requests > 0 && per_ms > 0)?;
+    let host = if options.request_rate_per_host == Some(true) { host.to_string() } else { String::new() };
+    let (capacity, rate) = bucket_rate(requests, per_ms);
+
+    let now = Instant::now();
+    let mut buckets = BUCKETS.lock().unwrap();
+
+    if buckets.len() > PRUNE_THRESHOLD {
+        buckets.retain(|&(_, requests, per_ms), bucket| bucket.tokens(now, bucket_rate(requests, per_ms)) < requests as f64);
+    }
+
+    let bucket = buckets.entry((host, requests, per_ms)).or_insert(Bucket { tokens: capacity, refilled: now });
+    bucket.tokens = bucket.tokens(now, (capacity, rate)) - 1.0;
+    bucket.refilled = now;
+
+    Some(now + Duration::from_secs_f64((-bucket.tokens).max(0.0) / rate))
+}
+
+// Space requests to `host` `per_host_delay_ms` apart
+fn space(options: &HttpOptions, host: &str) -> Option<Instant> {
+    let delay = Duration::from_millis(options.per_host_delay_ms.filter(|ms| *ms > 0)?);
+    let host = host.to_string();
+
+    let now = Instant::now();
+    let mut slots = NEXT_SLOT.lock().unwrap();
+
+    if slots.len() > PRUNE_THRESHOLD {
+        slots.retain(|_, next| *next > now);
+    }
+
+    let slot = slots.get(&host).map_or(now, |next| (*next).max(now));
+    slots.insert(host, slot + delay);
+    Some(slot)
+}
+
+// Block until a request to `url` may be sent
+pub fn wait(options: &HttpOptions, url: &str) {
+    if let Some(slot) = reserve(options, url) {
+        std::thread::sleep(slot.saturating_duration_since(Instant::now()));
+    }
+}
+
+pub async fn wait_async(options: &HttpOptions, url: &str) {
+    if let Some(slot) = reserve(options, url) {
+        tokio::time::sleep_until(slot.into()).await;
+    }
+}
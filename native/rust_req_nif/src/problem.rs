@@ -0,0 +1,152 @@
+use rustler::{Encoder, Env, Term};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+use crate::{atoms, json};
+
+// Members defined by RFC 7807; anything else in the object is an extension
+const MEMBERS: [&str; 5] = ["type", "title", "status", "detail", "instance"];
+
+// An RFC 7807 problem details object from an error response
+#[derive(Debug)]
+pub struct Problem {
+    status: u16,
+    object: Map<String, Value>,
+}
+
+// The problem details of a 4xx/5xx `application/problem+json` response, if it has valid ones
+pub fn parse(status: u16, headers: &HashMap<String, String>, body: &str) -> Option<Problem> {
+    if status < 400 {
+        return None;
+    }
+
+    let media_type = headers.get("content-type")?.split(';').next()?.trim();
+    if !media_type.eq_ignore_ascii_case("application/problem+json") {
+        return None;
+    }
+
+    match serde_json::from_str(body) {
+        Ok(Value::Object(object)) => Some(Problem { status, object }),
+        _ => None,
+    }
+}
+
+impl Problem {
+    // The `type`, "about:blank" when missing or not a string, as the RFC says
+    fn kind(&self) -> &str {
+        match self.object.get("type") {
+            Some(Value::String(kind)) => kind,
+            _ => "about:blank",
+        }
+    }
+
+    // The `status`, the response status when missing or not a number
+    fn status(&self) -> Value {
+        match self.object.get("status") {
+            Some(status @ Value::Number(_)) => status.clone(),
+            _ => Value::from(self.status),
+        }
+    }
+
+    fn extensions(&self) -> Map<String, Value> {
+        self.object
+            .iter()
+            .filter(|(name, _)| !MEMBERS.contains(&name.as_str()))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+}
+
+impl Encoder for Problem {
+    // `%{type, title, status, detail, instance, extensions}`, with the RFC defaults for
+    // a missing `type` ("about:blank") and `status` (the response status)
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let member = |name: &str| json::to_term(env, self.object.get(name).unwrap_or(&Value::Null));
+
+        let details = Term::map_from_arrays(
+            env,
+            &[
+                atoms::type_(),
+                atoms::title(),
+                atoms::status(),
+                atoms::detail(),
+                atoms::instance(),
+                atoms::extensions(),
+            ],
+            &[
+                self.kind().encode(env),
+                member("title"),
+                json::to_term(env, &self.status()),
+                member("detail"),
+                member("instance"),
+                json::to_term(env, &Value::Object(self.extensions())),
+            ],
+        )
+        .expect("keys are unique");
+
+        (atoms::problem(), details).encode(env)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn problem_json(status: u16, content_type: &str, body: &str) -> Option<Problem> {
+        let headers = HashMap::from([("content-type".to_string(), content_type.to_string())]);
+        parse(status, &headers, body)
+    }
+
+    #[test]
+    fn only_valid_objects_of_error_responses_are_problems() {
+        let body = r#"{"type": "https://example.com/probs/out-of-credit", "title": "Out of credit"}"#;
+
+        assert!(problem_json(403, "application/problem+json", body).is_some());
+        assert!(problem_json(500, "Application/Problem+JSON; charset=utf-8", body).is_some());
+        assert!(problem_json(404, "application/problem+json", "{}").is_some());
+
+        let cases = [
+            (200, "application/problem+json", body),
+            (399, "application/problem+json", body),
+            (403, "application/json", body),
+            (403, "application/problem+xml", body),
+            (403, "application/problem+json", ""),
+            (403, "application/problem+json", "not json"),
+            (403, "application/problem+json", r#"{"title": "Out of credit""#),
+            (403, "application/problem+json", r#"["type", "title"]"#),
+            (403, "application/problem+json", r#""Out of credit""#),
+            (403, "application/problem+json", "null"),
+        ];
+        for (status, content_type, body) in cases {
+            assert!(problem_json(status, content_type, body).is_none(), "{} {} {:?}", status, content_type, body);
+        }
+
+        assert!(parse(403, &HashMap::new(), body).is_none());
+    }
+
+    #[test]
+    fn members_of_the_wrong_type_get_their_defaults() {
+        let problem = problem_json(422, "application/problem+json", r#"{"type": 7, "status": "400"}"#).unwrap();
+        assert_eq!(problem.kind(), "about:blank");
+        assert_eq!(problem.status(), json!(422));
+
+        let problem = problem_json(422, "application/problem+json", r#"{"type": null, "status": 409}"#).unwrap();
+        assert_eq!(problem.kind(), "about:blank");
+        assert_eq!(problem.status(), json!(409));
+    }
+
+    #[test]
+    fn unknown_members_are_extensions() {
+        let body = r#"{"type": "https://example.com/probs/out-of-credit", "title": "Out of credit",
+            "detail": "Your balance is 30", "instance": "/account/12345", "balance": 30,
+            "accounts": ["/account/12345", "/account/67890"]}"#;
+        let problem = problem_json(403, "application/problem+json", body).unwrap();
+
+        assert_eq!(problem.kind(), "https://example.com/probs/out-of-credit");
+        assert_eq!(
+            Value::Object(problem.extensions()),
+            json!({"balance": 30, "accounts": ["/account/12345", "/account/67890"]})
+        );
+    }
+}
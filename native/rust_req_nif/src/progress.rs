@@ -0,0 +1,69 @@
+use rustler::{Encoder, LocalPid};
+use std::io::{Cursor, Read};
+use std::time::{Duration, Instant};
+
+use crate::{atoms, messages, throttle, EncodedTerm, HttpOptions};
+
+// Reader that reports `{:upload_progress, tag, sent_bytes, total_bytes}` to a pid as it is consumed
+struct ProgressReader<R> {
+    inner: R,
+    pid: LocalPid,
+    tag: Option<EncodedTerm>,
+    sent: u64,
+    total: u64,
+    interval: Duration,
+    last_report: Option<Instant>,
+}
+
+impl<R> ProgressReader<R> {
+    fn report(&mut self) {
+        let (tag, sent, total) = (self.tag.clone(), self.sent, self.total);
+        messages::send(self.pid, move |env| (atoms::upload_progress(), tag, sent, total).encode(env));
+        self.last_report = Some(Instant::now());
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.sent += read as u64;
+
+        let due = self.last_report.is_none_or(|last| last.elapsed() >= self.interval);
+        if read > 0 && (due || self.sent == self.total) {
+            self.report();
+        }
+
+        Ok(read)
+    }
+}
+
+// Request body that reports upload progress when `progress_pid` is set
+pub fn upload_body(body: Vec<u8>, options: &HttpOptions) -> reqwest::blocking::Body {
+    // Kept as bytes when nothing watches it, so the request can be sent again
+    if options.progress_pid.is_none() && options.max_upload_bytes_per_sec.is_none() {
+        return reqwest::blocking::Body::from(body);
+    }
+
+    let total = body.len() as u64;
+    upload_reader(Cursor::new(body), total, options)
+}
+
+// `upload_body` for `total` bytes read from `reader` as they are sent
+pub fn upload_reader<R: Read + Send + 'static>(reader: R, total: u64, options: &HttpOptions) -> reqwest::blocking::Body {
+    let reader = throttle::Reader::new(reader, throttle::upload(options));
+    let Some(pid) = options.progress_pid else {
+        return reqwest::blocking::Body::sized(reader, total);
+    };
+
+    let reader = ProgressReader {
+        inner: reader,
+        pid,
+        tag: options.progress_tag.clone(),
+        sent: 0,
+        total,
+        interval: Duration::from_millis(options.progress_interval_ms.unwrap_or(500)),
+        last_report: None,
+    };
+
+    reqwest::blocking::Body::sized(reader, total)
+}
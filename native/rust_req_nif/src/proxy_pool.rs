@@ -0,0 +1,143 @@
+use rustler::{Atom, NifMap};
+use std::collections::HashMap;
+use std::sync::atomic::AtomicUsize;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::rotation::{self, Strategy};
+use crate::system_proxy::ProxySetting;
+use crate::{atoms, HttpOptions};
+
+// Statuses meaning the proxy was refused or rate-limited, quarantining it at once
+const QUARANTINE_STATUSES: [u16; 2] = [407, 429];
+
+#[derive(Default)]
+struct Health {
+    successes: u64,
+    failures: u64,
+    // Failures since the last success
+    consecutive_failures: u32,
+    quarantined_until: Option<Instant>,
+}
+
+impl Health {
+    fn quarantined(&self, now: Instant) -> bool {
+        self.quarantined_until.is_some_and(|until| until > now)
+    }
+}
+
+// Health of every proxy used through `proxies`, by URL, shared by all callers
+static HEALTH: LazyLock<Mutex<HashMap<String, Health>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+// Pick a proxy from `proxies` for a request to `url`, skipping quarantined ones, and make
+// it the request's `proxy`. Fails with `:no_proxy_available` when all are quarantined.
+pub fn select(options: &mut HttpOptions, url: &str) -> Result<Option<String>, Atom> {
+    let Some(proxies) = options.proxies.as_ref().filter(|proxies| !proxies.is_empty()) else {
+        return Ok(None);
+    };
+
+    let now = Instant::now();
+    let available: Vec<&String> = {
+        let health = HEALTH.lock().unwrap();
+        proxies
+            .iter()
+            .filter(|proxy| !health.get(*proxy).is_some_and(|health| health.quarantined(now)))
+            .collect()
+    };
+
+    if available.is_empty() {
+        return Err(atoms::no_proxy_available());
+    }
+
+    let strategy = options.proxy_strategy.unwrap_or(Strategy::RoundRobin);
+    let proxy = available[rotation::index(strategy, &NEXT, url, available.len())].clone();
+
+    options.proxy = Some(ProxySetting::Url(proxy.clone()));
+    Ok(Some(proxy))
+}
+
+fn quarantine(health: &mut Health, duration: Duration) {
+    health.quarantined_until = Some(Instant::now() + duration);
+    health.consecutive_failures = 0;
+}
+
+// Record a response received through `proxy`; a 407 or 429 quarantines it, for as long as
+// a `Retry-After` in seconds asks if that is longer than `proxy_quarantine_ms`
+pub fn report_response(options: &HttpOptions, proxy: Option<&str>, status: u16, headers: &HashMap<String, String>) {
+    let Some(proxy) = proxy else {
+        return;
+    };
+
+    let mut table = HEALTH.lock().unwrap();
+    let health = table.entry(proxy.to_string()).or_default();
+
+    if QUARANTINE_STATUSES.contains(&status) {
+        health.failures += 1;
+        let retry_after = headers.get("retry-after").and_then(|value| value.trim().parse().ok());
+        let duration = Duration::from_millis(options.proxy_quarantine_ms.unwrap_or(30000))
+            .max(Duration::from_secs(retry_after.unwrap_or(0)));
+        quarantine(health, duration);
+    } else {
+        health.successes += 1;
+        health.consecutive_failures = 0;
+    }
+}
+
+// Record a request through `proxy` that failed without a response; `proxy_max_failures`
+// of those in a row quarantine it for `proxy_quarantine_ms`
+pub fn report_failure(options: &HttpOptions, proxy: Option<&str>) {
+    let Some(proxy) = proxy else {
+        return;
+    };
+
+    let mut table = HEALTH.lock().unwrap();
+    let health = table.entry(proxy.to_string()).or_default();
+
+    health.failures += 1;
+    health.consecutive_failures += 1;
+
+    if health.consecutive_failures >= options.proxy_max_failures.unwrap_or(3) {
+        quarantine(health, Duration::from_millis(options.proxy_quarantine_ms.unwrap_or(30000)));
+    }
+}
+
+#[derive(NifMap)]
+struct ProxyStatus {
+    proxy: String,
+    // `:available` or `:quarantined`
+    state: Atom,
+    // Time left in quarantine, 0 when available
+    quarantined_ms: u64,
+    successes: u64,
+    failures: u64,
+    consecutive_failures: u32,
+}
+
+// Health of each of `proxies`, in order; proxies never used report as available
+#[rustler::nif]
+fn proxy_pool_status(proxies: Vec<String>) -> Vec<ProxyStatus> {
+    let now = Instant::now();
+    let table = HEALTH.lock().unwrap();
+    let unused = Health::default();
+
+    proxies
+        .into_iter()
+        .map(|proxy| {
+            let health = table.get(&proxy).unwrap_or(&unused);
+            let left = health
+                .quarantined_until
+                .map_or(Duration::ZERO, |until| until.saturating_duration_since(now));
+
+            ProxyStatus {
+                proxy,
+                state: if health.quarantined(now) { atoms::quarantined() } else { atoms::available() },
+                quarantined_ms: left.as_millis() as u64,
+                successes: health.successes,
+                failures: health.failures,
+                consecutive_failures: health.consecutive_failures,
+            }
+        })
+        .collect()
+}
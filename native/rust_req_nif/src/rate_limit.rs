@@ -0,0 +1,99 @@
+use rustler::NifMap;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{cookies, HttpOptions, HttpResponse, ResponseMeta};
+
+// `X-RateLimit-Reset` values past this are Unix timestamps rather than seconds to wait
+const EPOCH_THRESHOLD: u64 = 1_000_000_000;
+
+// Rate limit state announced by a response; every field is nil when no header gives it
+#[derive(Debug, Default, PartialEq, NifMap)]
+pub struct RateLimit {
+    // Requests allowed in the current window
+    limit: Option<u64>,
+    remaining: Option<u64>,
+    // Seconds until the window resets
+    reset: Option<u64>,
+    // Seconds the server asks to wait before the next request
+    retry_after: Option<u64>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+// A non-negative number, rounded up, so fractional resets never report 0 too early
+fn number(value: &str) -> Option<u64> {
+    let value: f64 = value.trim().trim_matches('"').parse().ok()?;
+    (value.is_finite() && value >= 0.0).then(|| value.ceil() as u64)
+}
+
+// The first member of a structured field list, e.g. `100` of `100, 100;w=60`
+fn first_number(value: &str) -> Option<u64> {
+    number(value.split([',', ';']).next()?)
+}
+
+// A parameter of the combined `RateLimit` and `RateLimit-Policy` fields, in the
+// `limit=100, remaining=5, reset=30` form of the early drafts and the
+// `"default";r=5;t=30` form of the later ones
+fn parameter(value: &str, names: &[&str]) -> Option<u64> {
+    value
+        .split([',', ';'])
+        .filter_map(|item| item.split_once('='))
+        .find(|(name, _)| names.iter().any(|wanted| name.trim().eq_ignore_ascii_case(wanted)))
+        .and_then(|(_, value)| number(value))
+}
+
+// `X-RateLimit-Reset` is seconds to wait for some APIs and a Unix timestamp for others
+fn legacy_reset(value: &str) -> Option<u64> {
+    let reset = first_number(value)?;
+    Some(if reset > EPOCH_THRESHOLD { reset.saturating_sub(now()) } else { reset })
+}
+
+// `Retry-After` as seconds or as an HTTP date
+fn retry_after(value: &str) -> Option<u64> {
+    number(value).or_else(|| cookies::parse_http_date(value).map(|at| at.saturating_sub(now())))
+}
+
+// Normalize the `RateLimit-*` fields of the IETF draft, its combined `RateLimit` field,
+// the `X-RateLimit-*` fields in common use and `Retry-After`, preferring the
+// standard fields when several are present. None without any of them.
+pub fn parse(headers: &HashMap<String, String>) -> Option<RateLimit> {
+    let header = |name: &str| headers.get(name).map(String::as_str);
+    let combined = header("ratelimit");
+    let policy = header("ratelimit-policy");
+
+    let rate_limit = RateLimit {
+        limit: header("ratelimit-limit")
+            .and_then(first_number)
+            .or_else(|| combined.and_then(|value| parameter(value, &["limit"])))
+            .or_else(|| policy.and_then(|value| parameter(value, &["q"])))
+            .or_else(|| policy.and_then(first_number))
+            .or_else(|| header("x-ratelimit-limit").and_then(first_number)),
+        remaining: header("ratelimit-remaining")
+            .and_then(first_number)
+            .or_else(|| combined.and_then(|value| parameter(value, &["remaining", "r"])))
+            .or_else(|| header("x-ratelimit-remaining").and_then(first_number)),
+        reset: header("ratelimit-reset")
+            .and_then(first_number)
+            .or_else(|| combined.and_then(|value| parameter(value, &["reset", "t"])))
+            .or_else(|| header("x-ratelimit-reset").and_then(legacy_reset)),
+        retry_after: header("retry-after").and_then(retry_after),
+    };
+
+    (rate_limit != RateLimit::default()).then_some(rate_limit)
+}
+
+// With `rate_limit`, report the response's rate limit headers as `rate_limit` in its metadata
+pub fn annotate(options: &HttpOptions, mut response: HttpResponse) -> HttpResponse {
+    if options.rate_limit == Some(true) {
+        if let Some(rate_limit) = parse(&response.headers) {
+            response.meta.get_or_insert_with(ResponseMeta::default).rate_limit = Some(rate_limit);
+        }
+    }
+    response
+}
@@ -0,0 +1,31 @@
+use ring::rand::{SecureRandom, SystemRandom};
+use std::time::Duration;
+
+use crate::HttpOptions;
+
+// Statuses an idempotent request is sent again on while it has `retries` left
+const RETRY_STATUSES: [u16; 3] = [502, 503, 504];
+
+// Whether a failed attempt is worth another: a failure to connect always is, as
+// nothing reached the server, a timeout only for an idempotent method
+pub fn error(method: &reqwest::Method, e: &reqwest::Error) -> bool {
+    e.is_connect() || (e.is_timeout() && method.is_idempotent())
+}
+
+pub fn status(method: &reqwest::Method, status: reqwest::StatusCode) -> bool {
+    method.is_idempotent() && RETRY_STATUSES.contains(&status.as_u16())
+}
+
+// Wait before attempt `attempt + 1`: `retry_backoff_ms`, then twice that, and so on.
+// With `retry_jitter`, a random part of it, so callers failing together don't all
+// come back at once.
+pub fn delay(options: &HttpOptions, attempt: u32) -> Duration {
+    let backoff = options.retry_backoff_ms.unwrap_or(100).saturating_mul(1 << (attempt - 1).min(16));
+    if options.retry_jitter != Some(true) || backoff == 0 {
+        return Duration::from_millis(backoff);
+    }
+
+    let mut random = [0u8; 8];
+    SystemRandom::new().fill(&mut random).expect("system randomness");
+    Duration::from_millis(u64::from_le_bytes(random) % backoff.saturating_add(1))
+}
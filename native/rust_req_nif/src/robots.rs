@@ -0,0 +1,188 @@
+use reqwest::Url;
+use rustler::{Atom, Env, Error, NifMap, NifResult};
+use std::io::Read;
+
+use crate::{atoms, audit, client_for, request_error, resolve_options, HttpOptions};
+
+// Bytes of a robots.txt file that are parsed, the minimum RFC 9309 requires
+const MAX_BYTES: u64 = 500 * 1024;
+
+// The robots.txt rules that apply to one user agent
+#[derive(NifMap)]
+struct RobotsRules {
+    // `{:allow | :disallow, pattern}`, in file order
+    rules: Vec<(Atom, String)>,
+    crawl_delay: Option<f64>,
+    sitemaps: Vec<String>,
+}
+
+#[derive(Default)]
+struct Group {
+    agents: Vec<String>,
+    rules: Vec<(Atom, String)>,
+    crawl_delay: Option<f64>,
+}
+
+// The product token a robots.txt group is matched against, e.g. `mybot` for
+// `MyBot/1.2 (+https://example.com/bot)`
+fn product_token(user_agent: &str) -> String {
+    user_agent
+        .split(['/', ' '])
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase()
+}
+
+// Parse a robots.txt file and keep the groups for `user_agent`, or failing any, for `*`
+fn parse(contents: &str, user_agent: &str) -> RobotsRules {
+    let mut groups: Vec<Group> = Vec::new();
+    let mut sitemaps = Vec::new();
+    // Consecutive user-agent lines open a single group
+    let mut in_agents = false;
+
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+
+        match key.trim().to_ascii_lowercase().as_str() {
+            "user-agent" => {
+                if !in_agents {
+                    groups.push(Group::default());
+                    in_agents = true;
+                }
+                if let Some(group) = groups.last_mut() {
+                    group.agents.push(value.to_ascii_lowercase());
+                }
+            }
+            "allow" | "disallow" if !groups.is_empty() => {
+                in_agents = false;
+                let kind = if key.trim().eq_ignore_ascii_case("allow") { atoms::allow() } else { atoms::disallow() };
+                // An empty pattern matches nothing
+                if let (Some(group), false) = (groups.last_mut(), value.is_empty()) {
+                    group.rules.push((kind, value.to_string()));
+                }
+            }
+            "crawl-delay" if !groups.is_empty() => {
+                in_agents = false;
+                if let Some(group) = groups.last_mut() {
+                    group.crawl_delay = value.parse().ok().or(group.crawl_delay);
+                }
+            }
+            "sitemap" => sitemaps.push(value.to_string()),
+            _ => {}
+        }
+    }
+
+    let token = product_token(user_agent);
+    let named = groups.iter().any(|group| group.agents.contains(&token));
+    let wanted = if named { token } else { "*".to_string() };
+
+    // Groups naming the same agent are merged
+    let mut matched = Group::default();
+    for group in groups.into_iter().filter(|group| group.agents.contains(&wanted)) {
+        matched.rules.extend(group.rules);
+        matched.crawl_delay = matched.crawl_delay.or(group.crawl_delay);
+    }
+
+    RobotsRules {
+        rules: matched.rules,
+        crawl_delay: matched.crawl_delay,
+        sitemaps,
+    }
+}
+
+// Whether `pattern` matches the start of `path`, with `*` matching any run of
+// characters and a trailing `$` anchoring the end
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    for (index, part) in parts.iter().enumerate() {
+        let last = index == parts.len() - 1;
+        if last && anchored {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(start) => rest = &rest[start + part.len()..],
+            None => return false,
+        }
+    }
+
+    !anchored || rest.is_empty()
+}
+
+// The most specific matching rule decides, `allow` winning a tie; with none, the path
+// is allowed
+fn allowed(rules: &RobotsRules, path: &str) -> bool {
+    if path == "/robots.txt" {
+        return true;
+    }
+
+    rules
+        .rules
+        .iter()
+        .filter(|(_, pattern)| matches(pattern, path))
+        .map(|(kind, pattern)| (pattern.len(), *kind == atoms::allow()))
+        .max()
+        .is_none_or(|(_, allow)| allow)
+}
+
+// Fetch and parse `url` (a site's /robots.txt) for `user_agent`. As RFC 9309 specifies,
+// a 4xx status means no restrictions and a 5xx status means the whole site is disallowed.
+#[rustler::nif(schedule = "DirtyIo")]
+fn robots_fetch(env: Env, url: String, user_agent: String, options: HttpOptions) -> NifResult<RobotsRules> {
+    let options = resolve_options(options)?;
+    let client = client_for(&options)
+        .map_err(|e| Error::Term(Box::new(format!("Client error: {}", e))))?;
+
+    let mut request = client.get(&url);
+    if options.user_agent.is_none() {
+        request = request.header(reqwest::header::USER_AGENT, &user_agent);
+    }
+
+    let response = audit::send(audit::caller(env).as_deref(), request).map_err(request_error)?;
+    let status = response.status();
+
+    if status.is_server_error() {
+        return Ok(RobotsRules {
+            rules: vec![(atoms::disallow(), "/".to_string())],
+            crawl_delay: None,
+            sitemaps: Vec::new(),
+        });
+    }
+
+    let mut body = Vec::new();
+    if status.is_success() {
+        response
+            .take(MAX_BYTES)
+            .read_to_end(&mut body)
+            .map_err(|e| Error::Term(Box::new(format!("Body error: {}", e))))?;
+    }
+
+    Ok(parse(&String::from_utf8_lossy(&body), &user_agent))
+}
+
+// Whether rules returned by `robots_fetch` let the crawler fetch `url`
+#[rustler::nif]
+fn robots_allowed(rules: RobotsRules, url: String) -> NifResult<bool> {
+    let url = Url::parse(&url).map_err(|_| Error::Term(Box::new(atoms::invalid_url())))?;
+
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+
+    Ok(allowed(&rules, &path))
+}
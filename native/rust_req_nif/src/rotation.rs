@@ -0,0 +1,45 @@
+use ring::rand::{SecureRandom, SystemRandom};
+use rustler::NifUnitEnum;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// How the entries of a pool option such as `user_agent_pool` or `proxies` are handed out
+#[derive(Clone, Copy, Debug, PartialEq, NifUnitEnum)]
+pub enum Strategy {
+    // Each request takes the next entry
+    RoundRobin,
+    // Each request takes an entry at random
+    Random,
+    // Requests to the same host always take the same entry
+    StickyPerHost,
+}
+
+fn random_index(next: &AtomicUsize, len: usize) -> usize {
+    let mut bytes = [0u8; 8];
+    match SystemRandom::new().fill(&mut bytes) {
+        Ok(()) => (u64::from_le_bytes(bytes) % len as u64) as usize,
+        Err(_) => next.fetch_add(1, Ordering::Relaxed) % len,
+    }
+}
+
+fn host_index(url: &str, len: usize) -> usize {
+    let host = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+        .unwrap_or_default();
+
+    let mut hasher = DefaultHasher::new();
+    host.hash(&mut hasher);
+    (hasher.finish() % len as u64) as usize
+}
+
+// Index of the entry of a `len`-entry pool that a request to `url` takes; `next` is the
+// pool's round-robin counter
+pub fn index(strategy: Strategy, next: &AtomicUsize, url: &str, len: usize) -> usize {
+    match strategy {
+        Strategy::RoundRobin => next.fetch_add(1, Ordering::Relaxed) % len,
+        Strategy::Random => random_index(next, len),
+        Strategy::StickyPerHost => host_index(url, len),
+    }
+}
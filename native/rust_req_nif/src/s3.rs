@@ -0,0 +1,296 @@
+use rustler::{Atom, Encoder, Env, Error, NifMap, NifResult, Term};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::sigv4::{self, AwsCredentials};
+use crate::cookies::SendError;
+use crate::{atoms, audit, blocking, client_for, inflight, pipeline, resolve_options, HttpOptions};
+
+// S3 limits: parts are at least 5 MiB (except the last) and at most 10,000 per upload
+const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+const MAX_PARTS: u64 = 10_000;
+
+// Attempts per part before the whole upload is reported as failed, when the failures
+// are ones a retry may fix
+const MAX_PART_ATTEMPTS: u32 = 3;
+
+#[derive(NifMap)]
+struct MultipartUpload {
+    upload_id: String,
+    etag: String,
+    parts: usize,
+    resumed_parts: usize,
+}
+
+// Text of the first `<tag>...</tag>` element in an S3 XML response
+fn xml_text<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&close)? + start;
+    Some(&xml[start..end])
+}
+
+fn object_url(url: &str, query: &[(&str, &str)]) -> Result<reqwest::Url, String> {
+    let mut url = reqwest::Url::parse(url).map_err(|e| format!("Invalid URL: {}", e))?;
+    if !query.is_empty() {
+        url.query_pairs_mut().extend_pairs(query);
+    }
+    Ok(url)
+}
+
+// A failed S3 call, and whether sending it again may succeed: after a network error,
+// a 5xx or a 429, but not after S3 rejected the request itself
+struct CallError {
+    reason: String,
+    transient: bool,
+}
+
+impl From<CallError> for String {
+    fn from(error: CallError) -> String {
+        error.reason
+    }
+}
+
+// Send a SigV4-signed S3 request, returning its status, ETag header and body. The
+// `middleware` sees it before it is signed, so the headers it sets are signed too.
+fn s3_call(
+    caller: Option<&str>,
+    options: &HttpOptions,
+    client: &reqwest::blocking::Client,
+    credentials: &AwsCredentials,
+    method: reqwest::Method,
+    url: reqwest::Url,
+    body: Vec<u8>,
+) -> Result<(u16, Option<String>, String), CallError> {
+    let failed = |reason: String, transient: bool| CallError { reason, transient };
+
+    let payload_hash = sigv4::sha256_hex(&body);
+    let mut request = client
+        .request(method, url)
+        .body(body)
+        .build()
+        .map_err(|e| failed(format!("Request error: {}", e), false))?;
+    let request_error = |e: SendError| {
+        let transient = matches!(e, SendError::Request(_));
+        failed(format!("Request error: {}", e), transient)
+    };
+    pipeline::before_send(options, &mut request).map_err(request_error)?;
+    sigv4::sign(&mut request, credentials, "s3", &payload_hash);
+
+    let response = pipeline::execute(caller, options, client, request).map_err(request_error)?;
+    let status = response.status().as_u16();
+    let etag = response
+        .headers()
+        .get("etag")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let text = response.text().map_err(|e| failed(format!("Body error: {}", e), true))?;
+
+    if !(200..300).contains(&status) {
+        let code = xml_text(&text, "Code").unwrap_or("unknown");
+        return Err(failed(format!("S3 returned {} ({})", status, code), status >= 500 || status == 429));
+    }
+
+    Ok((status, etag, text))
+}
+
+// Parts already stored for an upload, as `(part_number, etag, size)`
+fn list_parts(
+    caller: Option<&str>,
+    options: &HttpOptions,
+    client: &reqwest::blocking::Client,
+    credentials: &AwsCredentials,
+    url: &str,
+    upload_id: &str,
+) -> Result<Vec<(u64, String, u64)>, String> {
+    let mut parts = Vec::new();
+    let mut marker = String::from("0");
+
+    loop {
+        let list_url = object_url(url, &[("uploadId", upload_id), ("part-number-marker", &marker)])?;
+        let (_, _, xml) = s3_call(caller, options, client, credentials, reqwest::Method::GET, list_url, Vec::new())?;
+
+        for part in xml.split("<Part>").skip(1) {
+            let number = xml_text(part, "PartNumber").and_then(|n| n.parse().ok());
+            let etag = xml_text(part, "ETag").map(|e| e.replace("&quot;", "\""));
+            let size = xml_text(part, "Size").and_then(|s| s.parse().ok());
+            if let (Some(number), Some(etag), Some(size)) = (number, etag, size) {
+                parts.push((number, etag, size));
+            }
+        }
+
+        match xml_text(&xml, "NextPartNumberMarker") {
+            Some(next) if xml_text(&xml, "IsTruncated") == Some("true") => marker = next.to_string(),
+            _ => return Ok(parts),
+        }
+    }
+}
+
+fn read_part(path: &str, offset: u64, length: u64) -> Result<Vec<u8>, String> {
+    let mut file = File::open(path).map_err(|e| format!("File error: {}", e))?;
+    let mut part = Vec::with_capacity(length as usize);
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.take(length).read_to_end(&mut part))
+        .map_err(|e| format!("File error: {}", e))?;
+    Ok(part)
+}
+
+// Upload a file to S3 with a multipart upload, sending `concurrency` parts in parallel.
+// Passing the `upload_id` of an interrupted upload resumes it, skipping stored parts.
+#[allow(clippy::too_many_arguments)]
+#[rustler::nif(schedule = "DirtyIo")]
+fn s3_multipart_upload<'a>(
+    env: Env<'a>,
+    url: String,
+    path: String,
+    credentials: AwsCredentials,
+    part_size: u64,
+    concurrency: usize,
+    upload_id: Option<String>,
+    options: HttpOptions,
+) -> NifResult<Term<'a>> {
+    let _permit = blocking::acquire()?;
+    let _inflight = inflight::acquire()?;
+    let options = resolve_options(options)?;
+    let client = client_for(&options)
+        .map_err(|e| Error::Term(Box::new(format!("Client error: {}", e))))?;
+    let fail = |reason: String| Error::Term(Box::new(reason));
+    let caller = audit::caller(env);
+    let caller = caller.as_deref();
+
+    let size = std::fs::metadata(&path)
+        .map_err(|e| fail(format!("File error: {}", e)))?
+        .len();
+    let part_size = part_size.max(MIN_PART_SIZE).max(size.div_ceil(MAX_PARTS));
+    let part_count = size.div_ceil(part_size).max(1);
+
+    let (upload_id, stored) = match upload_id {
+        Some(upload_id) => {
+            let stored = list_parts(caller, &options, &client, &credentials, &url, &upload_id).map_err(fail)?;
+            (upload_id, stored)
+        }
+        None => {
+            let initiate_url = object_url(&url, &[("uploads", "")]).map_err(fail)?;
+            let (_, _, xml) = s3_call(caller, &options, &client, &credentials, reqwest::Method::POST, initiate_url, Vec::new())
+                .map_err(|e| fail(e.into()))?;
+            let upload_id = xml_text(&xml, "UploadId")
+                .ok_or_else(|| fail("Missing UploadId in response".to_string()))?
+                .to_string();
+            (upload_id, Vec::new())
+        }
+    };
+
+    // Parts already stored with the expected size are kept as-is
+    let mut etags: Vec<Option<String>> = (1..=part_count)
+        .map(|number| {
+            let expected = part_size.min(size - (number - 1) * part_size);
+            stored
+                .iter()
+                .find(|(stored_number, _, stored_size)| *stored_number == number && *stored_size == expected)
+                .map(|(_, etag, _)| etag.clone())
+        })
+        .collect();
+    let resumed_parts = etags.iter().filter(|etag| etag.is_some()).count();
+
+    let pending: Vec<u64> = (1..=part_count)
+        .filter(|number| etags[(*number - 1) as usize].is_none())
+        .collect();
+    let next = AtomicUsize::new(0);
+    let uploaded = Mutex::new(Vec::new());
+    let failure = Mutex::new(None);
+
+    std::thread::scope(|scope| {
+        for _ in 0..concurrency.max(1).min(pending.len().max(1)) {
+            scope.spawn(|| loop {
+                if failure.lock().unwrap().is_some() {
+                    return;
+                }
+
+                let Some(&number) = pending.get(next.fetch_add(1, Ordering::SeqCst)) else {
+                    return;
+                };
+
+                let offset = (number - 1) * part_size;
+                let result = read_part(&path, offset, part_size.min(size - offset)).and_then(|body| {
+                    let number = number.to_string();
+                    let part_url = object_url(&url, &[("partNumber", &number), ("uploadId", &upload_id)])?;
+
+                    let mut attempt = 1;
+                    loop {
+                        match s3_call(caller, &options, &client, &credentials, reqwest::Method::PUT, part_url.clone(), body.clone()) {
+                            Ok((_, Some(etag), _)) => return Ok(etag),
+                            Ok((_, None, _)) => return Err("Missing ETag for uploaded part".to_string()),
+                            Err(e) if e.transient && attempt < MAX_PART_ATTEMPTS => attempt += 1,
+                            Err(e) => return Err(e.into()),
+                        }
+                    }
+                });
+
+                match result {
+                    Ok(etag) => uploaded.lock().unwrap().push((number, etag)),
+                    Err(reason) => {
+                        failure.lock().unwrap().get_or_insert((number, reason));
+                    }
+                }
+            });
+        }
+    });
+
+    if let Some((number, reason)) = failure.into_inner().unwrap() {
+        return Err(Error::Term(Box::new((atoms::upload_failed(), upload_id, number, reason))));
+    }
+
+    for (number, etag) in uploaded.into_inner().unwrap() {
+        etags[(number - 1) as usize] = Some(etag);
+    }
+
+    let manifest: String = etags
+        .iter()
+        .enumerate()
+        .map(|(index, etag)| {
+            format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                index + 1,
+                etag.as_deref().unwrap_or_default()
+            )
+        })
+        .collect();
+    let complete_url = object_url(&url, &[("uploadId", &upload_id)]).map_err(fail)?;
+    let body = format!("<CompleteMultipartUpload>{}</CompleteMultipartUpload>", manifest);
+
+    // CompleteMultipartUpload can report errors inside a 200 response
+    let (_, _, xml) = s3_call(caller, &options, &client, &credentials, reqwest::Method::POST, complete_url, body.into_bytes())
+        .map_err(|e| Error::Term(Box::new((atoms::upload_failed(), upload_id.clone(), 0, e.reason))))?;
+    if xml.contains("<Error>") {
+        let code = xml_text(&xml, "Code").unwrap_or("unknown").to_string();
+        return Err(Error::Term(Box::new((atoms::upload_failed(), upload_id, 0, code))));
+    }
+
+    let upload = MultipartUpload {
+        etag: xml_text(&xml, "ETag").unwrap_or_default().replace("&quot;", "\""),
+        upload_id,
+        parts: part_count as usize,
+        resumed_parts,
+    };
+
+    Ok((atoms::ok(), upload).encode(env))
+}
+
+// Abort a multipart upload, discarding its stored parts
+#[rustler::nif(schedule = "DirtyIo")]
+fn s3_multipart_abort(env: Env, url: String, upload_id: String, credentials: AwsCredentials, options: HttpOptions) -> NifResult<Atom> {
+    let _permit = blocking::acquire()?;
+    let _inflight = inflight::acquire()?;
+    let options = resolve_options(options)?;
+    let client = client_for(&options)
+        .map_err(|e| Error::Term(Box::new(format!("Client error: {}", e))))?;
+
+    let abort_url = object_url(&url, &[("uploadId", &upload_id)]).map_err(|e| Error::Term(Box::new(e)))?;
+    s3_call(audit::caller(env).as_deref(), &options, &client, &credentials, reqwest::Method::DELETE, abort_url, Vec::new())
+        .map_err(|e| Error::Term(Box::new(e.reason)))?;
+
+    Ok(atoms::ok())
+}
@@ -0,0 +1,826 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{digest, hmac, signature};
+use rustler::types::binary::NewBinary;
+use rustler::{Atom, Binary, Decoder, Encoder, Env, Error, NifMap, NifResult, NifUnitEnum, Term};
+use std::io::{Read, Seek};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{atoms, HttpOptions, HttpResponse, ResponseMeta};
+
+// HTTP Message Signatures (RFC 9421): requests signed with `http_signature`, responses
+// checked against `verify_http_signatures`
+
+#[derive(Clone, Copy, Debug, PartialEq, NifUnitEnum)]
+pub enum Algorithm {
+    Ed25519,
+    RsaPssSha512,
+    HmacSha256,
+}
+
+impl Algorithm {
+    // The name registered for the `alg` parameter
+    fn name(self) -> &'static str {
+        match self {
+            Algorithm::Ed25519 => "ed25519",
+            Algorithm::RsaPssSha512 => "rsa-pss-sha512",
+            Algorithm::HmacSha256 => "hmac-sha256",
+        }
+    }
+}
+
+// Key material as given: PEM, DER, a raw Ed25519 key or an HMAC secret, which need not
+// be UTF-8
+#[derive(Clone, PartialEq)]
+pub struct KeyBytes(Vec<u8>);
+
+impl<'a> Decoder<'a> for KeyBytes {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        Ok(KeyBytes(term.decode::<Binary>()?.as_slice().to_vec()))
+    }
+}
+
+impl Encoder for KeyBytes {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let mut binary = NewBinary::new(env, self.0.len());
+        binary.as_mut_slice().copy_from_slice(&self.0);
+        Term::from(binary)
+    }
+}
+
+// Components signed when `components` is not given
+const DEFAULT_COMPONENTS: [&str; 3] = ["@method", "@target-uri", "content-digest"];
+
+// The `http_signature` option: how to sign each request
+#[derive(Clone, PartialEq)]
+pub struct Signer {
+    key_id: String,
+    algorithm: Algorithm,
+    key: KeyBytes,
+    components: Option<Vec<String>>,
+    label: Option<String>,
+    expires_in: Option<u64>,
+    nonce: Option<bool>,
+    tag: Option<String>,
+}
+
+fn optional<'a, T: Decoder<'a>>(term: Term<'a>, key: Atom) -> NifResult<Option<T>> {
+    match term.map_get(key) {
+        Ok(value) => value.decode(),
+        Err(_) => Ok(None),
+    }
+}
+
+// A map where only `key_id`, `algorithm` and `key` are required
+impl<'a> Decoder<'a> for Signer {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        Ok(Signer {
+            key_id: term.map_get(atoms::key_id())?.decode()?,
+            algorithm: term.map_get(atoms::algorithm())?.decode()?,
+            key: term.map_get(atoms::key())?.decode()?,
+            components: optional(term, atoms::components())?,
+            label: optional(term, atoms::label())?,
+            expires_in: optional(term, atoms::expires_in())?,
+            nonce: optional(term, atoms::nonce())?,
+            tag: optional(term, atoms::tag())?,
+        })
+    }
+}
+
+impl Encoder for Signer {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        Term::map_from_arrays(
+            env,
+            &[
+                atoms::key_id(),
+                atoms::algorithm(),
+                atoms::key(),
+                atoms::components(),
+                atoms::label(),
+                atoms::expires_in(),
+                atoms::nonce(),
+                atoms::tag(),
+            ],
+            &[
+                self.key_id.encode(env),
+                self.algorithm.encode(env),
+                self.key.encode(env),
+                self.components.encode(env),
+                self.label.encode(env),
+                self.expires_in.encode(env),
+                self.nonce.encode(env),
+                self.tag.encode(env),
+            ],
+        )
+        .expect("keys are unique")
+    }
+}
+
+impl Signer {
+    fn components(&self) -> Vec<String> {
+        match &self.components {
+            Some(components) => components.iter().map(|name| name.to_ascii_lowercase()).collect(),
+            None => DEFAULT_COMPONENTS.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    fn label(&self) -> &str {
+        self.label.as_deref().unwrap_or("sig1")
+    }
+
+    // The signature parameters, serialized as in `Signature-Input`
+    fn params(&self, components: &[String], created: i64) -> Result<String, String> {
+        let mut params = vec![
+            ("created".to_string(), Bare::Integer(created)),
+            ("keyid".to_string(), Bare::String(self.key_id.clone())),
+            ("alg".to_string(), Bare::String(self.algorithm.name().to_string())),
+        ];
+
+        if let Some(seconds) = self.expires_in {
+            params.push(("expires".to_string(), Bare::Integer(created.saturating_add(seconds as i64))));
+        }
+
+        if self.nonce == Some(true) {
+            let mut bytes = [0u8; 16];
+            SystemRandom::new()
+                .fill(&mut bytes)
+                .map_err(|_| "Signature error: no randomness for a nonce".to_string())?;
+            params.push(("nonce".to_string(), Bare::String(BASE64.encode(bytes))));
+        }
+
+        if let Some(tag) = &self.tag {
+            params.push(("tag".to_string(), Bare::String(tag.clone())));
+        }
+
+        let items: Vec<(Bare, Params)> = components
+            .iter()
+            .map(|name| (Bare::String(name.clone()), Vec::new()))
+            .collect();
+        Ok(inner_list(&items, &params))
+    }
+}
+
+// A key of `verify_http_signatures`, matched to signatures by their `keyid`
+#[derive(Clone, PartialEq)]
+pub struct VerifyKey {
+    key_id: String,
+    algorithm: Algorithm,
+    key: KeyBytes,
+    required_components: Option<Vec<String>>,
+}
+
+// A map where only `key_id`, `algorithm` and `key` are required
+impl<'a> Decoder<'a> for VerifyKey {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        Ok(VerifyKey {
+            key_id: term.map_get(atoms::key_id())?.decode()?,
+            algorithm: term.map_get(atoms::algorithm())?.decode()?,
+            key: term.map_get(atoms::key())?.decode()?,
+            required_components: optional(term, atoms::required_components())?,
+        })
+    }
+}
+
+impl Encoder for VerifyKey {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        Term::map_from_arrays(
+            env,
+            &[atoms::key_id(), atoms::algorithm(), atoms::key(), atoms::required_components()],
+            &[
+                self.key_id.encode(env),
+                self.algorithm.encode(env),
+                self.key.encode(env),
+                self.required_components.encode(env),
+            ],
+        )
+        .expect("keys are unique")
+    }
+}
+
+impl VerifyKey {
+    // Components a signature by this key must cover to be accepted: by default the
+    // status, and the `Content-Digest` when the response has a body
+    fn required_components(&self, response: &HttpResponse) -> Vec<String> {
+        match &self.required_components {
+            Some(components) => components.iter().map(|name| name.to_ascii_lowercase()).collect(),
+            None if response.body.bytes().is_none_or(|body| !body.is_empty()) => {
+                vec!["@status".to_string(), "content-digest".to_string()]
+            }
+            None => vec!["@status".to_string()],
+        }
+    }
+}
+
+// A signature accepted by `verify_http_signatures`, reported as `:signature` in the
+// response metadata
+#[derive(Debug, NifMap)]
+pub struct Verified {
+    label: String,
+    key_id: String,
+    created: Option<i64>,
+    components: Vec<String>,
+}
+
+// Structured field values (RFC 8941), as far as signature and digest headers use them
+#[derive(Clone, Debug, PartialEq)]
+enum Bare {
+    Integer(i64),
+    String(String),
+    Token(String),
+    Bytes(Vec<u8>),
+    Boolean(bool),
+}
+
+type Params = Vec<(String, Bare)>;
+
+// Dictionary members; the parameters of items are parsed but not kept, as no header
+// here uses them
+enum Member {
+    Item(Bare),
+    InnerList(Vec<(Bare, Params)>, Params),
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let found = self.peek() == Some(byte);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn skip(&mut self, skipped: impl Fn(u8) -> bool) {
+        while self.peek().is_some_and(&skipped) {
+            self.pos += 1;
+        }
+    }
+
+    fn take(&mut self, taken: impl Fn(u8) -> bool) -> &str {
+        let start = self.pos;
+        self.skip(taken);
+        std::str::from_utf8(&self.input[start..self.pos]).unwrap_or("")
+    }
+
+    fn key(&mut self) -> Option<String> {
+        if !self.peek().is_some_and(|byte| byte.is_ascii_lowercase() || byte == b'*') {
+            return None;
+        }
+        let key = self.take(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || b"_-.*".contains(&byte));
+        Some(key.to_string())
+    }
+
+    fn bare(&mut self) -> Option<Bare> {
+        match self.peek()? {
+            b'"' => {
+                self.pos += 1;
+                let mut value = String::new();
+                loop {
+                    let byte = self.peek()?;
+                    self.pos += 1;
+                    match byte {
+                        b'"' => return Some(Bare::String(value)),
+                        b'\\' => {
+                            let escaped = self.peek().filter(|byte| *byte == b'"' || *byte == b'\\')?;
+                            self.pos += 1;
+                            value.push(escaped as char);
+                        }
+                        0x20..=0x7e => value.push(byte as char),
+                        _ => return None,
+                    }
+                }
+            }
+            b':' => {
+                self.pos += 1;
+                let encoded = self.take(|byte| byte != b':').to_string();
+                self.eat(b':').then_some(())?;
+                BASE64.decode(encoded).ok().map(Bare::Bytes)
+            }
+            b'?' => {
+                self.pos += 1;
+                match (self.eat(b'1'), self.eat(b'0')) {
+                    (true, _) => Some(Bare::Boolean(true)),
+                    (_, true) => Some(Bare::Boolean(false)),
+                    _ => None,
+                }
+            }
+            b'-' | b'0'..=b'9' => {
+                let start = self.pos;
+                self.eat(b'-');
+                self.skip(|byte| byte.is_ascii_digit());
+                // Decimals are not used by these headers
+                if self.peek() == Some(b'.') {
+                    return None;
+                }
+                std::str::from_utf8(&self.input[start..self.pos]).ok()?.parse().ok().map(Bare::Integer)
+            }
+            byte if byte.is_ascii_alphabetic() || byte == b'*' => {
+                let token = self.take(|byte| byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~:/".contains(&byte));
+                Some(Bare::Token(token.to_string()))
+            }
+            _ => None,
+        }
+    }
+
+    fn params(&mut self) -> Option<Params> {
+        let mut params = Vec::new();
+        while self.eat(b';') {
+            self.skip(|byte| byte == b' ');
+            let key = self.key()?;
+            let value = if self.eat(b'=') { self.bare()? } else { Bare::Boolean(true) };
+            params.push((key, value));
+        }
+        Some(params)
+    }
+
+    fn inner_list(&mut self) -> Option<Member> {
+        self.eat(b'(').then_some(())?;
+        let mut items = Vec::new();
+        loop {
+            self.skip(|byte| byte == b' ');
+            if self.eat(b')') {
+                return Some(Member::InnerList(items, self.params()?));
+            }
+            items.push((self.bare()?, self.params()?));
+            if !matches!(self.peek(), Some(b' ' | b')')) {
+                return None;
+            }
+        }
+    }
+
+    // A dictionary's members in order, or None when the value is malformed
+    fn dictionary(input: &str) -> Option<Vec<(String, Member)>> {
+        let mut parser = Parser {
+            input: input.as_bytes(),
+            pos: 0,
+        };
+        let mut members = Vec::new();
+
+        loop {
+            parser.skip(|byte| byte == b' ' || byte == b'\t');
+            let key = parser.key()?;
+            let member = match (parser.eat(b'='), parser.peek()) {
+                (true, Some(b'(')) => parser.inner_list()?,
+                (true, _) => {
+                    let item = parser.bare()?;
+                    parser.params()?;
+                    Member::Item(item)
+                }
+                (false, _) => {
+                    parser.params()?;
+                    Member::Item(Bare::Boolean(true))
+                }
+            };
+            members.push((key, member));
+
+            parser.skip(|byte| byte == b' ' || byte == b'\t');
+            if parser.peek().is_none() {
+                return Some(members);
+            }
+            parser.eat(b',').then_some(())?;
+        }
+    }
+}
+
+fn sf_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn bare(value: &Bare) -> String {
+    match value {
+        Bare::Integer(value) => value.to_string(),
+        Bare::String(value) => sf_string(value),
+        Bare::Token(value) => value.clone(),
+        Bare::Bytes(value) => format!(":{}:", BASE64.encode(value)),
+        Bare::Boolean(value) => if *value { "?1" } else { "?0" }.to_string(),
+    }
+}
+
+fn params(params: &Params) -> String {
+    params
+        .iter()
+        .map(|(key, value)| match value {
+            Bare::Boolean(true) => format!(";{}", key),
+            value => format!(";{}={}", key, bare(value)),
+        })
+        .collect()
+}
+
+fn inner_list(items: &[(Bare, Params)], list_params: &Params) -> String {
+    let items: Vec<String> = items.iter().map(|(item, item_params)| bare(item) + &params(item_params)).collect();
+    format!("({}){}", items.join(" "), params(list_params))
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+// The label and DER of a PEM block, or the bytes as given when they aren't PEM
+fn der(key: &[u8]) -> Result<(Option<String>, Vec<u8>), String> {
+    let Some(text) = std::str::from_utf8(key).ok().filter(|text| text.contains("-----BEGIN ")) else {
+        return Ok((None, key.to_vec()));
+    };
+
+    let begin = &text[text.find("-----BEGIN ").unwrap_or(0) + "-----BEGIN ".len()..];
+    let (label, rest) = begin.split_once("-----").ok_or("malformed PEM")?;
+    let body = &rest[..rest.find("-----END ").ok_or("malformed PEM")?];
+    let encoded: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+    let der = BASE64.decode(encoded).map_err(|e| format!("malformed PEM: {}", e))?;
+    Ok((Some(label.to_string()), der))
+}
+
+// A DER element: its tag, contents and whatever follows it
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (length, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let length = rest[..count].iter().fold(0usize, |length, &byte| length << 8 | byte as usize);
+        (length, &rest[count..])
+    };
+    (rest.len() >= length).then(|| (tag, &rest[..length], &rest[length..]))
+}
+
+// The key inside a SubjectPublicKeyInfo: an RSAPublicKey or the raw Ed25519 key
+fn spki_key(der: &[u8]) -> Option<&[u8]> {
+    let (0x30, info, _) = der_element(der)? else { return None };
+    let (0x30, _, rest) = der_element(info)? else { return None };
+    let (0x03, bits, _) = der_element(rest)? else { return None };
+    bits.strip_prefix(&[0])
+}
+
+enum PrivateKey {
+    Ed25519(signature::Ed25519KeyPair),
+    Rsa(signature::RsaKeyPair),
+    Hmac(hmac::Key),
+}
+
+// Ed25519 keys as PKCS#8 or a raw 32-byte seed, RSA keys as PKCS#8 or PKCS#1
+fn private_key(algorithm: Algorithm, key: &[u8]) -> Result<PrivateKey, String> {
+    match algorithm {
+        Algorithm::Ed25519 => {
+            let (_, der) = der(key)?;
+            let pair = if der.len() == 32 {
+                signature::Ed25519KeyPair::from_seed_unchecked(&der)
+            } else {
+                signature::Ed25519KeyPair::from_pkcs8_maybe_unchecked(&der)
+            };
+            pair.map(PrivateKey::Ed25519).map_err(|e| format!("invalid Ed25519 private key: {}", e))
+        }
+        Algorithm::RsaPssSha512 => {
+            let (_, der) = der(key)?;
+            signature::RsaKeyPair::from_pkcs8(&der)
+                .or_else(|_| signature::RsaKeyPair::from_der(&der))
+                .map(PrivateKey::Rsa)
+                .map_err(|e| format!("invalid RSA private key: {}", e))
+        }
+        Algorithm::HmacSha256 => Ok(PrivateKey::Hmac(hmac::Key::new(hmac::HMAC_SHA256, key))),
+    }
+}
+
+impl PrivateKey {
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, String> {
+        match self {
+            PrivateKey::Ed25519(pair) => Ok(pair.sign(message).as_ref().to_vec()),
+            PrivateKey::Rsa(pair) => {
+                let mut signature = vec![0; pair.public().modulus_len()];
+                pair.sign(&signature::RSA_PSS_SHA512, &SystemRandom::new(), message, &mut signature)
+                    .map_err(|_| "RSA signing failed".to_string())?;
+                Ok(signature)
+            }
+            PrivateKey::Hmac(key) => Ok(hmac::sign(key, message).as_ref().to_vec()),
+        }
+    }
+}
+
+// Ed25519 keys as SubjectPublicKeyInfo or the raw 32 bytes, RSA keys as
+// SubjectPublicKeyInfo or PKCS#1
+fn public_key(algorithm: Algorithm, key: &[u8]) -> Result<Vec<u8>, String> {
+    match algorithm {
+        Algorithm::Ed25519 => {
+            let (_, der) = der(key)?;
+            match der.len() {
+                32 => Ok(der),
+                _ => spki_key(&der)
+                    .filter(|key| key.len() == 32)
+                    .map(<[u8]>::to_vec)
+                    .ok_or_else(|| "invalid Ed25519 public key".to_string()),
+            }
+        }
+        Algorithm::RsaPssSha512 => {
+            let (_, der) = der(key)?;
+            match spki_key(&der) {
+                Some(key) => Ok(key.to_vec()),
+                None if der_element(&der).is_some_and(|(tag, _, _)| tag == 0x30) => Ok(der),
+                None => Err("invalid RSA public key".to_string()),
+            }
+        }
+        Algorithm::HmacSha256 => Ok(key.to_vec()),
+    }
+}
+
+fn verify_signature(algorithm: Algorithm, key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), String> {
+    let public = public_key(algorithm, key)?;
+    let verified = match algorithm {
+        Algorithm::Ed25519 => signature::UnparsedPublicKey::new(&signature::ED25519, &public).verify(message, signature),
+        Algorithm::RsaPssSha512 => {
+            signature::UnparsedPublicKey::new(&signature::RSA_PSS_2048_8192_SHA512, &public).verify(message, signature)
+        }
+        Algorithm::HmacSha256 => hmac::verify(&hmac::Key::new(hmac::HMAC_SHA256, &public), message, signature),
+    };
+    verified.map_err(|_| "signature does not verify".to_string())
+}
+
+// Problems with the keys of `http_signature` and `verify_http_signatures`, for `validate`
+pub fn errors(options: &HttpOptions) -> Vec<(&'static str, String)> {
+    let mut errors = Vec::new();
+
+    if let Some(signer) = &options.http_signature {
+        if let Err(e) = private_key(signer.algorithm, &signer.key.0) {
+            errors.push(("http_signature", e));
+        }
+        if signer.components.as_ref().is_some_and(Vec::is_empty) {
+            errors.push(("http_signature", "components must not be empty".to_string()));
+        }
+        if Parser::dictionary(&format!("{}=?1", signer.label())).is_none() {
+            errors.push(("http_signature", "label must be a lowercase key".to_string()));
+        }
+    }
+
+    for key in options.verify_http_signatures.iter().flatten() {
+        if let Err(e) = public_key(key.algorithm, &key.key.0) {
+            errors.push(("verify_http_signatures", format!("{}: {}", key.key_id, e)));
+        }
+    }
+
+    errors
+}
+
+// `sha-256=:digest:`, a `Content-Digest` (RFC 9530) of `body`
+fn content_digest(body: &[u8]) -> String {
+    format!("sha-256=:{}:", BASE64.encode(digest::digest(&digest::SHA256, body)))
+}
+
+fn header_value<'a>(mut values: impl Iterator<Item = &'a str>, name: &str) -> Result<String, String> {
+    let first = values.next().ok_or_else(|| format!("no {} header to cover", name))?;
+    Ok(values.fold(first.trim().to_string(), |joined, value| joined + ", " + value.trim()))
+}
+
+// The value a component of a request signature covers
+fn request_component(name: &str, method: &str, url: &reqwest::Url, headers: &[(String, String)]) -> Result<String, String> {
+    let query = url.query().map(|query| format!("?{}", query));
+    Ok(match name {
+        "@method" => method.to_ascii_uppercase(),
+        "@target-uri" => url.as_str().to_string(),
+        "@authority" => {
+            let host = url.host_str().unwrap_or("").to_ascii_lowercase();
+            match url.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host,
+            }
+        }
+        "@scheme" => url.scheme().to_string(),
+        "@request-target" => format!("{}{}", url.path(), query.as_deref().unwrap_or("")),
+        "@path" => url.path().to_string(),
+        "@query" => query.unwrap_or_else(|| "?".to_string()),
+        _ if name.starts_with('@') => return Err(format!("unsupported component {}", name)),
+        _ => header_value(
+            headers
+                .iter()
+                .filter(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str()),
+            name,
+        )?,
+    })
+}
+
+// Sign a request with `http_signature`, adding its `Signature-Input` and `Signature`
+// headers, and a `Content-Digest` of `body` when that is covered but not set
+pub fn apply(
+    options: &HttpOptions,
+    method: &str,
+    url: &str,
+    mut headers: Vec<(String, String)>,
+    body: &[u8],
+) -> Result<Vec<(String, String)>, String> {
+    let Some(signer) = &options.http_signature else {
+        return Ok(headers);
+    };
+
+    let sign = |headers: &mut Vec<(String, String)>| -> Result<(), String> {
+        let url = reqwest::Url::parse(url).map_err(|e| e.to_string())?;
+        let components = signer.components();
+
+        if components.iter().any(|name| name == "content-digest")
+            && !headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("content-digest"))
+        {
+            headers.push(("content-digest".to_string(), content_digest(body)));
+        }
+
+        let mut base = String::new();
+        for name in &components {
+            let value = request_component(name, method, &url, headers)?;
+            base.push_str(&format!("{}: {}\n", sf_string(name), value));
+        }
+        let params = signer.params(&components, now())?;
+        base.push_str(&format!("\"@signature-params\": {}", params));
+
+        let signature = private_key(signer.algorithm, &signer.key.0)?.sign(base.as_bytes())?;
+        headers.push(("signature-input".to_string(), format!("{}={}", signer.label(), params)));
+        headers.push(("signature".to_string(), format!("{}=:{}:", signer.label(), BASE64.encode(signature))));
+        Ok(())
+    };
+
+    sign(&mut headers).map_err(|e| format!("Signature error: {}", e))?;
+    Ok(headers)
+}
+
+// `apply` for a body streamed from `body`, such as a file or form, which is read for
+// its digest only when that is covered, then rewound
+pub fn apply_streamed<R: Read + Seek>(
+    options: &HttpOptions,
+    method: &str,
+    url: &str,
+    mut headers: Vec<(String, String)>,
+    body: &mut R,
+) -> Result<Vec<(String, String)>, String> {
+    let covered = options
+        .http_signature
+        .as_ref()
+        .is_some_and(|signer| signer.components().iter().any(|name| name == "content-digest"));
+
+    if covered && !headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("content-digest")) {
+        let file_error = |e: std::io::Error| format!("File error: {}", e);
+        let mut context = digest::Context::new(&digest::SHA256);
+        let mut buffer = [0; 64 * 1024];
+        loop {
+            let read = body.read(&mut buffer).map_err(file_error)?;
+            if read == 0 {
+                break;
+            }
+            context.update(&buffer[..read]);
+        }
+        body.rewind().map_err(file_error)?;
+
+        let value = format!("sha-256=:{}:", BASE64.encode(context.finish()));
+        headers.push(("content-digest".to_string(), value));
+    }
+
+    apply(options, method, url, headers, b"")
+}
+
+pub fn error(message: String) -> Error {
+    Error::Term(Box::new(message))
+}
+
+// Check `Content-Digest` against the body: every SHA-256 and SHA-512 digest must match,
+// and there must be at least one
+fn check_digest(value: &str, body: &[u8]) -> Result<(), String> {
+    let members = Parser::dictionary(value).ok_or("malformed Content-Digest")?;
+    let mut checked = false;
+
+    for (name, member) in members {
+        let algorithm = match name.as_str() {
+            "sha-256" => &digest::SHA256,
+            "sha-512" => &digest::SHA512,
+            _ => continue,
+        };
+        let Member::Item(Bare::Bytes(expected)) = member else {
+            return Err("malformed Content-Digest".to_string());
+        };
+        if digest::digest(algorithm, body).as_ref() != expected.as_slice() {
+            return Err("Content-Digest does not match the body".to_string());
+        }
+        checked = true;
+    }
+
+    checked.then_some(()).ok_or_else(|| "no SHA-256 or SHA-512 Content-Digest".to_string())
+}
+
+// Check one signature of a response, returning what it covers
+fn verify_one(
+    response: &HttpResponse,
+    label: &str,
+    items: &[(Bare, Params)],
+    signature_params: &Params,
+    key: &VerifyKey,
+    signature: &[u8],
+) -> Result<Verified, String> {
+    let param = |name: &str| signature_params.iter().find(|(key, _)| key == name).map(|(_, value)| value);
+
+    if let Some(alg) = param("alg") {
+        if *alg != Bare::String(key.algorithm.name().to_string()) {
+            return Err(format!("signature {} uses another algorithm than key {}", label, key.key_id));
+        }
+    }
+
+    if let Some(Bare::Integer(expires)) = param("expires") {
+        if *expires < now() {
+            return Err(format!("signature {} expired", label));
+        }
+    }
+
+    let mut base = String::new();
+    let mut components = Vec::new();
+    for (item, item_params) in items {
+        let Bare::String(name) = item else {
+            return Err(format!("signature {} has a malformed component", label));
+        };
+        if !item_params.is_empty() {
+            return Err(format!("unsupported component {}{}", sf_string(name), params(item_params)));
+        }
+
+        let value = match name.as_str() {
+            "@status" => response.status.to_string(),
+            _ if name.starts_with('@') => return Err(format!("unsupported component {}", name)),
+            _ => header_value(response.headers.get(name).map(String::as_str).into_iter(), name)?,
+        };
+
+        if name == "content-digest" {
+            let body = response.body.bytes().ok_or("cannot check Content-Digest of a multipart body")?;
+            check_digest(&value, body)?;
+        }
+
+        base.push_str(&format!("{}: {}\n", sf_string(name), value));
+        components.push(name.clone());
+    }
+    base.push_str(&format!("\"@signature-params\": {}", inner_list(items, signature_params)));
+
+    if let Some(missing) = key.required_components(response).into_iter().find(|name| !components.contains(name)) {
+        return Err(format!("signature {} does not cover {}", label, missing));
+    }
+
+    verify_signature(key.algorithm, &key.key.0, base.as_bytes(), signature)?;
+
+    Ok(Verified {
+        label: label.to_string(),
+        key_id: key.key_id.clone(),
+        created: match param("created") {
+            Some(Bare::Integer(created)) => Some(*created),
+            _ => None,
+        },
+        components,
+    })
+}
+
+// With `verify_http_signatures`, require a response signature by one of its keys that
+// verifies, and report it as `:signature` in the response metadata
+pub fn verify(options: &HttpOptions, mut response: HttpResponse) -> Result<HttpResponse, String> {
+    let Some(keys) = &options.verify_http_signatures else {
+        return Ok(response);
+    };
+
+    let inputs = response.headers.get("signature-input").ok_or("no Signature-Input header")?;
+    let inputs = Parser::dictionary(inputs).ok_or("malformed Signature-Input header")?;
+    let signatures = response.headers.get("signature").ok_or("no Signature header")?;
+    let signatures = Parser::dictionary(signatures).ok_or("malformed Signature header")?;
+
+    let mut error = "no signature by a known key".to_string();
+    for (label, input) in &inputs {
+        let Member::InnerList(items, signature_params) = input else {
+            continue;
+        };
+        let key_id = signature_params.iter().find_map(|(name, value)| match (name.as_str(), value) {
+            ("keyid", Bare::String(key_id)) => Some(key_id),
+            _ => None,
+        });
+        let Some(key) = keys.iter().find(|key| Some(&key.key_id) == key_id) else {
+            continue;
+        };
+        let signature = signatures.iter().find_map(|(name, member)| match member {
+            Member::Item(Bare::Bytes(signature)) if name == label => Some(signature),
+            _ => None,
+        });
+        let Some(signature) = signature else {
+            error = format!("no Signature for {}", label);
+            continue;
+        };
+
+        match verify_one(&response, label, items, signature_params, key, signature) {
+            Ok(verified) => {
+                response.meta.get_or_insert_with(ResponseMeta::default).signature = Some(verified);
+                return Ok(response);
+            }
+            Err(e) => error = e,
+        }
+    }
+
+    Err(error)
+}
@@ -0,0 +1,199 @@
+use reqwest::header::CONTENT_TYPE;
+use rustler::{Encoder, Env, Error, NifResult, Term};
+use serde_json::{Map, Value};
+
+use crate::{atoms, audit, blocking, client_for, inflight, json, pipeline, resolve_options, xml, HttpOptions};
+
+const SOAP_11_NS: &str = "http://schemas.xmlsoap.org/soap/envelope/";
+const SOAP_12_NS: &str = "http://www.w3.org/2003/05/soap-envelope";
+
+fn client_error(e: Box<dyn std::error::Error>) -> Error {
+    Error::Term(Box::new(format!("Client error: {}", e)))
+}
+
+fn envelope(namespace: &str, header: Option<&str>, body: &str) -> String {
+    let header = header
+        .map(|header| format!("<soap:Header>{}</soap:Header>", header))
+        .unwrap_or_default();
+
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?><soap:Envelope xmlns:soap="{}">{}<soap:Body>{}</soap:Body></soap:Envelope>"#,
+        namespace, header, body
+    )
+}
+
+// Text content of a converted element, whether or not it carried attributes
+fn text_of(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Object(fields)) => text_of(fields.get("#text")),
+        Some(Value::Array(items)) => text_of(items.first()),
+        _ => String::new(),
+    }
+}
+
+// Code, reason and detail of a SOAP 1.1 or 1.2 Fault element
+fn fault_parts(fault: &Value) -> (String, String, Option<&Value>) {
+    if fault.get("faultcode").is_some() {
+        (
+            text_of(fault.get("faultcode")),
+            text_of(fault.get("faultstring")),
+            fault.get("detail"),
+        )
+    } else {
+        (
+            text_of(fault.get("Code").and_then(|code| code.get("Value"))),
+            text_of(fault.get("Reason").and_then(|reason| reason.get("Text"))),
+            fault.get("Detail"),
+        )
+    }
+}
+
+// `{:soap_fault, %{code, reason, detail}}` from a SOAP 1.1 or 1.2 Fault element
+fn fault<'a>(env: Env<'a>, fault: &Value) -> Term<'a> {
+    let (code, reason, detail) = fault_parts(fault);
+
+    let details = Term::map_from_arrays(
+        env,
+        &[atoms::code(), atoms::reason(), atoms::detail()],
+        &[code.encode(env), reason.encode(env), json::to_term(env, detail.unwrap_or(&Value::Null))],
+    )
+    .expect("keys are unique");
+
+    (atoms::soap_fault(), details).encode(env)
+}
+
+// The converted `Body` of a response envelope
+fn response_body(text: &str) -> Option<Value> {
+    match xml::to_json(text) {
+        Ok((root, envelope)) if root == "Envelope" => envelope.get("Body").cloned(),
+        _ => None,
+    }
+}
+
+// POST `body` wrapped in a SOAP envelope and return the converted response Body
+#[allow(clippy::too_many_arguments)]
+#[rustler::nif(schedule = "DirtyIo")]
+fn soap_call<'a>(
+    env: Env<'a>,
+    url: String,
+    action: String,
+    body: String,
+    header: Option<String>,
+    version: String,
+    headers: Vec<(String, String)>,
+    options: HttpOptions,
+) -> NifResult<Term<'a>> {
+    let _permit = blocking::acquire()?;
+    let _inflight = inflight::acquire()?;
+    let options = resolve_options(options)?;
+    let client = client_for(&options).map_err(client_error)?;
+
+    let mut request = match version.as_str() {
+        "1.1" => client
+            .post(&url)
+            .header(CONTENT_TYPE, "text/xml; charset=utf-8")
+            .header("SOAPAction", format!("\"{}\"", action))
+            .body(envelope(SOAP_11_NS, header.as_deref(), &body)),
+        "1.2" => client
+            .post(&url)
+            .header(CONTENT_TYPE, format!("application/soap+xml; charset=utf-8; action=\"{}\"", action))
+            .body(envelope(SOAP_12_NS, header.as_deref(), &body)),
+        _ => return Err(Error::BadArg),
+    };
+
+    for (key, value) in headers {
+        request = request.header(key, value);
+    }
+
+    let response = pipeline::send(audit::caller(env).as_deref(), &options, request).map_err(|e| e.into_error())?;
+    let status = response.status();
+    let text = response
+        .text()
+        .map_err(|e| Error::Term(Box::new(format!("Body error: {}", e))))?;
+
+    // Faults usually arrive with a 500 status, so parse the body before judging the status
+    let Some(body) = response_body(&text) else {
+        if !status.is_success() {
+            return Err(Error::Term(Box::new((atoms::unexpected_status(), status.as_u16()))));
+        }
+        return Err(Error::Term(Box::new("Invalid SOAP response: no Envelope/Body".to_string())));
+    };
+
+    if let Some(fault_element) = body.get("Fault") {
+        return Ok((atoms::error(), fault(env, fault_element)).encode(env));
+    }
+
+    let body = match body {
+        Value::Object(_) => body,
+        _ => Value::Object(Map::new()),
+    };
+
+    Ok((atoms::ok(), json::to_term(env, &body)).encode(env))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fault_of(xml: &str) -> (String, String, Option<Value>) {
+        let body = response_body(xml).expect("an envelope with a body");
+        let (code, reason, detail) = fault_parts(body.get("Fault").expect("a fault"));
+        (code, reason, detail.cloned())
+    }
+
+    #[test]
+    fn soap_11_faults() {
+        let xml = r#"<?xml version="1.0"?>
+            <soap:Envelope xmlns:soap="http://schemas.xmlsoap.org/soap/envelope/">
+              <soap:Body>
+                <soap:Fault>
+                  <faultcode>soap:Client</faultcode>
+                  <faultstring xml:lang="en">Unknown item</faultstring>
+                  <detail><e:Error xmlns:e="urn:example"><e:Item>Pears</e:Item></e:Error></detail>
+                </soap:Fault>
+              </soap:Body>
+            </soap:Envelope>"#;
+
+        assert_eq!(
+            fault_of(xml),
+            ("soap:Client".to_string(), "Unknown item".to_string(), Some(json!({"Error": {"Item": "Pears"}})))
+        );
+    }
+
+    #[test]
+    fn soap_12_faults() {
+        let xml = r#"<env:Envelope xmlns:env="http://www.w3.org/2003/05/soap-envelope">
+              <env:Body>
+                <env:Fault>
+                  <env:Code><env:Value>env:Sender</env:Value><env:Subcode><env:Value>m:Quota</env:Value></env:Subcode></env:Code>
+                  <env:Reason><env:Text xml:lang="en">Over quota</env:Text><env:Text xml:lang="fr">Quota dépassé</env:Text></env:Reason>
+                </env:Fault>
+              </env:Body>
+            </env:Envelope>"#;
+
+        assert_eq!(fault_of(xml), ("env:Sender".to_string(), "Over quota".to_string(), None));
+    }
+
+    #[test]
+    fn bodies_come_from_envelopes_only() {
+        let xml = r#"<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Header/><s:Body><m:GetPriceResponse xmlns:m="urn:example"><m:Price currency="EUR">1.90</m:Price></m:GetPriceResponse></s:Body></s:Envelope>"#;
+        assert_eq!(
+            response_body(xml),
+            Some(json!({"GetPriceResponse": {"Price": {"@currency": "EUR", "#text": "1.90"}}}))
+        );
+
+        assert_eq!(response_body("<html><body>Bad Gateway</body></html>"), None);
+        assert_eq!(response_body("<s:Envelope><s:Header/></s:Envelope>"), None);
+        assert_eq!(response_body("Service Unavailable"), None);
+    }
+
+    #[test]
+    fn envelopes_wrap_the_header_and_body() {
+        let envelope = envelope(SOAP_12_NS, Some("<t:Token>abc</t:Token>"), "<m:Ping/>");
+        assert!(envelope.starts_with(r#"<?xml version="1.0" encoding="utf-8"?><soap:Envelope xmlns:soap="http://www.w3.org/2003/05/soap-envelope">"#));
+        assert!(envelope.ends_with("<soap:Header><t:Token>abc</t:Token></soap:Header><soap:Body><m:Ping/></soap:Body></soap:Envelope>"));
+        assert!(!super::envelope(SOAP_11_NS, None, "").contains("Header"));
+    }
+}
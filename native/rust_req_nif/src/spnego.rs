@@ -0,0 +1,166 @@
+use rustler::Error;
+
+use crate::HttpOptions;
+
+// GSSAPI as provided by MIT/Heimdal Kerberos, declared here to avoid a binding crate
+#[cfg(all(feature = "spnego", unix))]
+mod gssapi {
+    use std::ffi::c_void;
+
+    pub type OmUint32 = u32;
+
+    #[repr(C)]
+    pub struct Buffer {
+        pub length: usize,
+        pub value: *mut c_void,
+    }
+
+    #[repr(C)]
+    pub struct Oid {
+        pub length: OmUint32,
+        pub elements: *mut c_void,
+    }
+
+    pub const C_MUTUAL_FLAG: OmUint32 = 2;
+    pub const C_REPLAY_FLAG: OmUint32 = 4;
+    pub const S_CONTINUE_NEEDED: OmUint32 = 1;
+
+    // 1.2.840.113554.1.2.1.4, GSS_C_NT_HOSTBASED_SERVICE
+    pub const NT_HOSTBASED_SERVICE: [u8; 10] = [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x12, 0x01, 0x02, 0x01, 0x04];
+    // 1.3.6.1.5.5.2, SPNEGO
+    pub const SPNEGO_MECHANISM: [u8; 6] = [0x2b, 0x06, 0x01, 0x05, 0x05, 0x02];
+
+    #[cfg_attr(target_os = "macos", link(name = "GSS", kind = "framework"))]
+    #[cfg_attr(not(target_os = "macos"), link(name = "gssapi_krb5"))]
+    extern "C" {
+        pub fn gss_import_name(
+            minor: *mut OmUint32,
+            input_name: *mut Buffer,
+            name_type: *mut Oid,
+            output_name: *mut *mut c_void,
+        ) -> OmUint32;
+
+        #[allow(clippy::too_many_arguments)]
+        pub fn gss_init_sec_context(
+            minor: *mut OmUint32,
+            credential: *mut c_void,
+            context: *mut *mut c_void,
+            target_name: *mut c_void,
+            mechanism: *mut Oid,
+            flags: OmUint32,
+            time_requested: OmUint32,
+            channel_bindings: *mut c_void,
+            input_token: *mut Buffer,
+            actual_mechanism: *mut *mut Oid,
+            output_token: *mut Buffer,
+            returned_flags: *mut OmUint32,
+            time_received: *mut OmUint32,
+        ) -> OmUint32;
+
+        pub fn gss_release_buffer(minor: *mut OmUint32, buffer: *mut Buffer) -> OmUint32;
+        pub fn gss_release_name(minor: *mut OmUint32, name: *mut *mut c_void) -> OmUint32;
+        pub fn gss_delete_sec_context(minor: *mut OmUint32, context: *mut *mut c_void, output_token: *mut Buffer) -> OmUint32;
+    }
+}
+
+// `Negotiate <token>`: the first SPNEGO token for the `HTTP@host` service, obtained with
+// the Kerberos credentials of the OS user (e.g. from `kinit` or the login session)
+#[cfg(all(feature = "spnego", unix))]
+pub fn authorization(host: &str) -> Result<String, String> {
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+    use gssapi::*;
+    use std::ptr::null_mut;
+
+    let mut service = format!("HTTP@{}", host).into_bytes();
+    let mut name_type_bytes = NT_HOSTBASED_SERVICE;
+    let mut mechanism_bytes = SPNEGO_MECHANISM;
+    let mut name_type = Oid {
+        length: name_type_bytes.len() as OmUint32,
+        elements: name_type_bytes.as_mut_ptr().cast(),
+    };
+    let mut mechanism = Oid {
+        length: mechanism_bytes.len() as OmUint32,
+        elements: mechanism_bytes.as_mut_ptr().cast(),
+    };
+
+    let mut minor = 0;
+    let mut name = null_mut();
+    let mut context = null_mut();
+    let mut token = Buffer {
+        length: 0,
+        value: null_mut(),
+    };
+
+    // SAFETY: every pointer passed is valid for the duration of the call, and the name,
+    // context and output token GSSAPI allocates are released below
+    unsafe {
+        let mut input = Buffer {
+            length: service.len(),
+            value: service.as_mut_ptr().cast(),
+        };
+        if gss_import_name(&mut minor, &mut input, &mut name_type, &mut name) != 0 {
+            return Err(format!("cannot import service name HTTP@{} (minor status {})", host, minor));
+        }
+
+        let major = gss_init_sec_context(
+            &mut minor,
+            null_mut(),
+            &mut context,
+            name,
+            &mut mechanism,
+            C_MUTUAL_FLAG | C_REPLAY_FLAG,
+            0,
+            null_mut(),
+            null_mut(),
+            null_mut(),
+            &mut token,
+            null_mut(),
+            null_mut(),
+        );
+
+        let result = if (major == 0 || major == S_CONTINUE_NEEDED) && token.length > 0 {
+            let bytes = std::slice::from_raw_parts(token.value as *const u8, token.length);
+            Ok(format!("Negotiate {}", BASE64.encode(bytes)))
+        } else {
+            Err(format!(
+                "no Kerberos ticket for HTTP@{} (major status {:#x}, minor status {})",
+                host, major, minor
+            ))
+        };
+
+        gss_release_buffer(&mut minor, &mut token);
+        gss_delete_sec_context(&mut minor, &mut context, null_mut());
+        gss_release_name(&mut minor, &mut name);
+
+        result
+    }
+}
+
+#[cfg(not(all(feature = "spnego", unix)))]
+pub fn authorization(_host: &str) -> Result<String, String> {
+    Err("this build does not include the spnego feature".to_string())
+}
+
+// Add an `Authorization: Negotiate` header to a request to `url` when `negotiate_auth`
+// is set, unless the caller set that header themselves
+pub fn apply(options: &HttpOptions, url: &str, mut headers: Vec<(String, String)>) -> Result<Vec<(String, String)>, String> {
+    if options.negotiate_auth != Some(true)
+        || headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("authorization"))
+    {
+        return Ok(headers);
+    }
+
+    let Some(host) = reqwest::Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_string)) else {
+        return Ok(headers);
+    };
+
+    let authorization = authorization(&host).map_err(|e| format!("Negotiate error: {}", e))?;
+    headers.push(("authorization".to_string(), authorization));
+    Ok(headers)
+}
+
+// Error of `apply` as returned by the request NIFs
+pub fn error(message: String) -> Error {
+    Error::Term(Box::new(message))
+}
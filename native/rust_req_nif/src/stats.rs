@@ -0,0 +1,185 @@
+use hyper_util::client::legacy::connect::HttpInfo;
+use rustler::{Atom, NifMap};
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
+
+use crate::histogram::{Histogram, Summary};
+use crate::{atoms, audit, events};
+
+// Percentiles are taken over the most recent latencies only
+const LATENCY_SAMPLES: usize = 1024;
+
+// Local addresses of recent connections, to tell a pooled connection from a new one
+const KNOWN_CONNECTIONS: usize = 256;
+
+#[derive(Default)]
+struct HostStats {
+    requests: u64,
+    errors: u64,
+    reused: u64,
+    bytes_in: u64,
+    bytes_out: u64,
+    latencies_ms: VecDeque<f64>,
+    // Every latency since the stats were reset, unlike the recent samples above
+    histogram: Histogram,
+    connections: VecDeque<SocketAddr>,
+}
+
+static HOSTS: LazyLock<Mutex<HashMap<String, HostStats>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// `host` or `host:port` when the port is not the scheme default
+fn host_key(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let host = url.host_str()?;
+
+    Some(match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    })
+}
+
+// Local and remote address of the connection a response arrived on, when the connector reports them
+pub fn connection(extensions: &hyper::http::Extensions) -> Option<(SocketAddr, SocketAddr)> {
+    extensions.get::<HttpInfo>().map(|info| (info.local_addr(), info.remote_addr()))
+}
+
+// Record a completed request and report it to the event sink. Without connection
+// addresses the connection counts as new.
+pub fn record(
+    url: &str,
+    id: u64,
+    started: Instant,
+    status: u16,
+    bytes_out: usize,
+    bytes_in: usize,
+    connection: Option<(SocketAddr, SocketAddr)>,
+) {
+    events::complete(id, Some(status), started);
+    audit::finish(id, Some(status), bytes_out, bytes_in);
+
+    let Some(key) = host_key(url) else {
+        return;
+    };
+
+    let mut hosts = HOSTS.lock().unwrap();
+    let host = hosts.entry(key).or_default();
+
+    host.requests += 1;
+    host.bytes_out += bytes_out as u64;
+    host.bytes_in += bytes_in as u64;
+
+    let elapsed = started.elapsed();
+    if host.latencies_ms.len() == LATENCY_SAMPLES {
+        host.latencies_ms.pop_front();
+    }
+    host.latencies_ms.push_back(elapsed.as_secs_f64() * 1000.0);
+    host.histogram.record(elapsed.as_micros() as u64);
+
+    if let Some((local, remote)) = connection {
+        if host.connections.contains(&local) {
+            host.reused += 1;
+        } else {
+            if host.connections.len() == KNOWN_CONNECTIONS {
+                host.connections.pop_front();
+            }
+            host.connections.push_back(local);
+            events::connect(id, local, remote);
+        }
+    }
+}
+
+// Record a request that failed before a complete response was read
+pub fn record_error(url: &str, id: u64, started: Instant, bytes_out: usize) {
+    events::complete(id, None, started);
+    audit::finish(id, None, bytes_out, 0);
+
+    let Some(key) = host_key(url) else {
+        return;
+    };
+
+    let mut hosts = HOSTS.lock().unwrap();
+    let host = hosts.entry(key).or_default();
+
+    host.requests += 1;
+    host.errors += 1;
+    host.bytes_out += bytes_out as u64;
+}
+
+#[derive(NifMap)]
+struct Latency {
+    p50: Option<f64>,
+    p90: Option<f64>,
+    p99: Option<f64>,
+}
+
+#[derive(NifMap)]
+struct HostSummary {
+    requests: u64,
+    errors: u64,
+    error_rate: f64,
+    latency_ms: Latency,
+    bytes_in: u64,
+    bytes_out: u64,
+    pool_reuse_ratio: f64,
+}
+
+// Nearest-rank percentile of sorted samples
+fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+fn summary(host: &HostStats) -> HostSummary {
+    let mut latencies: Vec<f64> = host.latencies_ms.iter().copied().collect();
+    latencies.sort_by(f64::total_cmp);
+
+    let completed = host.requests - host.errors;
+    let ratio = |count: u64, total: u64| if total == 0 { 0.0 } else { count as f64 / total as f64 };
+
+    HostSummary {
+        requests: host.requests,
+        errors: host.errors,
+        error_rate: ratio(host.errors, host.requests),
+        latency_ms: Latency {
+            p50: percentile(&latencies, 50.0),
+            p90: percentile(&latencies, 90.0),
+            p99: percentile(&latencies, 99.0),
+        },
+        bytes_in: host.bytes_in,
+        bytes_out: host.bytes_out,
+        pool_reuse_ratio: ratio(host.reused, completed),
+    }
+}
+
+// Per-host metrics since load or the last `host_stats_reset/0`
+#[rustler::nif]
+fn host_stats() -> HashMap<String, HostSummary> {
+    HOSTS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(key, host)| (key.clone(), summary(host)))
+        .collect()
+}
+
+// Latency histogram of the completed requests to `host` (`host` or `host:port`, as
+// `host_stats` keys it) since load or the last reset; None for a host without any
+#[rustler::nif]
+fn latency_histogram(host: String) -> Option<Summary> {
+    HOSTS
+        .lock()
+        .unwrap()
+        .get(&host.to_ascii_lowercase())
+        .and_then(|host| host.histogram.summary())
+}
+
+#[rustler::nif]
+fn host_stats_reset() -> Atom {
+    HOSTS.lock().unwrap().clear();
+    atoms::ok()
+}
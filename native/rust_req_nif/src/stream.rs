@@ -0,0 +1,116 @@
+use rustler::{Atom, Binary, Encoder, Env, Error, NifResult, OwnedBinary, ResourceArc, Term};
+use std::io::Read;
+use std::sync::Mutex;
+
+use crate::{
+    accept, atoms, audit, blocking, body_interrupted, client_for, header_map, inflight, pac, parse_method, pipeline, politeness, progress,
+    proxy_pool, read_body, resolve_options, signatures, spnego, throttle, urls, user_agents, HttpOptions, HttpResponse, StatusError,
+};
+
+// Response whose body is read on demand from Elixir
+pub struct ResponseStream {
+    // Held with the slot of `inflight` it occupies until the body is exhausted or closed
+    response: Mutex<Option<(throttle::Reader<reqwest::blocking::Response>, inflight::Permit)>>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for ResponseStream {}
+
+// Send a request and return its status and headers with the unread body as a resource.
+// The request goes through the pipeline of `request/5`; a status `error_on_status`
+// selects is returned as an error with its body read in full.
+#[rustler::nif(schedule = "DirtyIo")]
+fn stream_request<'a>(
+    env: Env<'a>,
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Binary,
+    options: HttpOptions,
+) -> NifResult<Term<'a>> {
+    let _permit = blocking::acquire()?;
+    let permit = inflight::acquire()?;
+    let method = parse_method(&method)?;
+    let mut options = resolve_options(options)?;
+    let (url, headers) = urls::apply(&options, url, headers).map_err(urls::error)?;
+    let proxy = proxy_pool::select(&mut options, &url).map_err(|reason| Error::Term(Box::new(reason)))?;
+    pac::select(&mut options, &url).map_err(pac::error)?;
+    let headers = user_agents::apply(&options, &url, headers);
+    let headers = accept::apply(&options, headers);
+    let headers = spnego::apply(&options, &url, headers).map_err(spnego::error)?;
+    let headers = signatures::apply(&options, method.as_str(), &url, headers, body.as_slice()).map_err(signatures::error)?;
+    let client = client_for(&options)
+        .map_err(|e| Error::Term(Box::new(format!("Client error: {}", e))))?;
+    politeness::wait(&options, &url);
+
+    let mut request = client.request(method, &url);
+
+    for (key, value) in headers {
+        request = request.header(key, value);
+    }
+
+    if !body.is_empty() {
+        request = request.body(progress::upload_body(body.as_slice().to_vec(), &options));
+    }
+
+    let mut response = pipeline::send(audit::caller(env).as_deref(), &options, request).map_err(|e| {
+        proxy_pool::report_failure(&options, proxy.as_deref());
+        e.into_error()
+    })?;
+
+    let status = response.status().as_u16();
+    let headers_map = header_map(response.headers());
+    proxy_pool::report_response(&options, proxy.as_deref(), status, &headers_map);
+
+    if options.error_on_status.as_ref().is_some_and(|filter| filter.matches(status)) {
+        let body = read_body(&mut response, &options).map_err(|partial| body_interrupted(status, &headers_map, partial))?;
+        let response = HttpResponse {
+            status,
+            headers: headers_map,
+            body,
+            meta: None,
+        };
+        return Err(StatusError::Status(Box::new(response)).into());
+    }
+
+    let headers_list: Vec<(String, String)> = headers_map.into_iter().collect();
+    let resource = ResourceArc::new(ResponseStream {
+        response: Mutex::new(Some((throttle::Reader::new(response, throttle::download(&options)), permit))),
+    });
+
+    Ok((atoms::ok(), (status, headers_list, resource)).encode(env))
+}
+
+// Read up to `max_bytes` of the body; returns `:eof` once the body is exhausted or closed.
+// `max_bytes` must be positive, as an empty read would look like the end of the body.
+#[rustler::nif(schedule = "DirtyIo")]
+fn stream_read<'a>(env: Env<'a>, stream: ResourceArc<ResponseStream>, max_bytes: usize) -> NifResult<Term<'a>> {
+    if max_bytes == 0 {
+        return Err(Error::BadArg);
+    }
+
+    let mut slot = stream.response.lock().unwrap();
+    let Some((response, _)) = slot.as_mut() else {
+        return Ok(atoms::eof().encode(env));
+    };
+
+    let mut chunk = OwnedBinary::new(max_bytes).ok_or(Error::RaiseAtom("enomem"))?;
+    let read = response
+        .read(chunk.as_mut_slice())
+        .map_err(|e| Error::Term(Box::new(format!("Body error: {}", e))))?;
+
+    if read == 0 {
+        *slot = None;
+        return Ok(atoms::eof().encode(env));
+    }
+
+    chunk.realloc_or_copy(read);
+    Ok((atoms::ok(), Binary::from_owned(chunk, env)).encode(env))
+}
+
+// Drop the response, releasing its connection
+#[rustler::nif]
+fn stream_close(stream: ResourceArc<ResponseStream>) -> Atom {
+    stream.response.lock().unwrap().take();
+    atoms::ok()
+}
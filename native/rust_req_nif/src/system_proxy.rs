@@ -0,0 +1,75 @@
+use rustler::{Decoder, Encoder, Env, NifResult, Term};
+
+use crate::atoms;
+
+// The `proxy` option: a URL, or `:system` for the proxies the environment names
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProxySetting {
+    Url(String),
+    System,
+}
+
+impl<'a> Decoder<'a> for ProxySetting {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        if term.decode::<rustler::Atom>().is_ok_and(|atom| atom == atoms::system()) {
+            return Ok(ProxySetting::System);
+        }
+        term.decode().map(ProxySetting::Url)
+    }
+}
+
+impl Encoder for ProxySetting {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            ProxySetting::Url(url) => url.encode(env),
+            ProxySetting::System => atoms::system().encode(env),
+        }
+    }
+}
+
+impl ProxySetting {
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            ProxySetting::Url(url) => Some(url),
+            ProxySetting::System => None,
+        }
+    }
+}
+
+// Proxies named by the environment, read as curl does
+pub struct Environment {
+    // For `http://` requests: `http_proxy`, `HTTP_PROXY`, else `all_proxy`, `ALL_PROXY`
+    pub http: Option<String>,
+    // For `https://` requests: `https_proxy`, `HTTPS_PROXY`, else the same fallbacks
+    pub https: Option<String>,
+    // `no_proxy` or `NO_PROXY`, in the format of the `no_proxy` option joined by commas
+    pub no_proxy: Option<String>,
+}
+
+fn var(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .map(|value| value.trim().to_string())
+        .find(|value| !value.is_empty())
+}
+
+// A proxy given as a bare `host:port` is an HTTP proxy
+fn proxy_url(value: String) -> String {
+    if value.contains("://") {
+        value
+    } else {
+        format!("http://{}", value)
+    }
+}
+
+// Read when a client is built, so changes reach requests on new clients only
+pub fn environment() -> Environment {
+    let all = || var(&["all_proxy", "ALL_PROXY"]);
+
+    Environment {
+        http: var(&["http_proxy", "HTTP_PROXY"]).or_else(all).map(proxy_url),
+        https: var(&["https_proxy", "HTTPS_PROXY"]).or_else(all).map(proxy_url),
+        no_proxy: var(&["no_proxy", "NO_PROXY"]),
+    }
+}
@@ -0,0 +1,187 @@
+use hyper::body::{Bytes, Frame, SizeHint};
+use std::future::Future;
+use std::io::Read;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::HttpOptions;
+
+// Token bucket holding up to one second's worth of bytes. Bytes taken past what it
+// holds are owed, and the taker waits until they are paid back.
+struct Bucket {
+    rate: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+// A byte rate shared by everything reading or writing one request's body, such as the
+// segments of a download
+pub struct Limiter {
+    bucket: Mutex<Bucket>,
+    // Largest piece passed at once, so slow rates pace smoothly rather than in bursts
+    chunk: usize,
+}
+
+impl Limiter {
+    fn new(bytes_per_sec: u64) -> Limiter {
+        let rate = bytes_per_sec as f64;
+        Limiter {
+            bucket: Mutex::new(Bucket {
+                rate,
+                tokens: rate,
+                refilled: Instant::now(),
+            }),
+            chunk: (bytes_per_sec / 10).clamp(1024, 64 * 1024) as usize,
+        }
+    }
+
+    // Take `bytes`, returning how long to wait before passing more
+    pub fn take(&self, bytes: usize) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.refilled).as_secs_f64() * bucket.rate;
+        bucket.tokens = (bucket.tokens + refill).min(bucket.rate) - bytes as f64;
+        bucket.refilled = now;
+
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / bucket.rate)
+        }
+    }
+}
+
+// The limiter for response bodies, when `max_download_bytes_per_sec` is set
+pub fn download(options: &HttpOptions) -> Option<Arc<Limiter>> {
+    options.max_download_bytes_per_sec.filter(|&rate| rate > 0).map(|rate| Arc::new(Limiter::new(rate)))
+}
+
+// The limiter for request bodies, when `max_upload_bytes_per_sec` is set
+pub fn upload(options: &HttpOptions) -> Option<Arc<Limiter>> {
+    options.max_upload_bytes_per_sec.filter(|&rate| rate > 0).map(|rate| Arc::new(Limiter::new(rate)))
+}
+
+// Wait until `bytes` more may pass, in async code
+pub async fn pace(limiter: Option<&Limiter>, bytes: usize) {
+    if let Some(limiter) = limiter {
+        let wait = limiter.take(bytes);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+// Reader passing its bytes no faster than its limiter allows, sleeping the calling
+// thread; without a limiter it reads straight through
+pub struct Reader<R> {
+    inner: R,
+    limiter: Option<Arc<Limiter>>,
+}
+
+impl<R> Reader<R> {
+    pub fn new(inner: R, limiter: Option<Arc<Limiter>>) -> Reader<R> {
+        Reader { inner, limiter }
+    }
+}
+
+impl<R: Read> Read for Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let Some(limiter) = &self.limiter else {
+            return self.inner.read(buf);
+        };
+
+        let len = buf.len().min(limiter.chunk);
+        let read = self.inner.read(&mut buf[..len])?;
+        let wait = limiter.take(read);
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+        Ok(read)
+    }
+}
+
+// Request body passing its data no faster than its limiter allows; other frames, such
+// as trailers, go through as they come
+pub struct Body<B> {
+    inner: B,
+    limiter: Option<Arc<Limiter>>,
+    // Rest of a data frame larger than the limiter's chunk
+    pending: Option<Bytes>,
+    sleep: Option<Pin<Box<tokio::time::Sleep>>>,
+}
+
+impl<B> Body<B> {
+    pub fn new(inner: B, limiter: Option<Arc<Limiter>>) -> Body<B> {
+        Body {
+            inner,
+            limiter,
+            pending: None,
+            sleep: None,
+        }
+    }
+}
+
+impl<B> hyper::body::Body for Body<B>
+where
+    B: hyper::body::Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, B::Error>>> {
+        let Some(limiter) = self.limiter.clone() else {
+            return Pin::new(&mut self.inner).poll_frame(cx);
+        };
+
+        if let Some(sleep) = self.sleep.as_mut() {
+            ready!(sleep.as_mut().poll(cx));
+            self.sleep = None;
+        }
+
+        let mut data = match self.pending.take() {
+            Some(data) => data,
+            None => match ready!(Pin::new(&mut self.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => data,
+                    Err(frame) => return Poll::Ready(Some(Ok(frame))),
+                },
+                other => return Poll::Ready(other),
+            },
+        };
+
+        if data.len() > limiter.chunk {
+            self.pending = Some(data.split_off(limiter.chunk));
+        }
+
+        let wait = limiter.take(data.len());
+        if !wait.is_zero() {
+            self.sleep = Some(Box::pin(tokio::time::sleep(wait)));
+        }
+        Poll::Ready(Some(Ok(Frame::data(data))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.pending.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let pending = self.pending.as_ref().map_or(0, |data| data.len() as u64);
+        let inner = self.inner.size_hint();
+        let mut hint = SizeHint::new();
+        hint.set_lower(inner.lower() + pending);
+        if let Some(upper) = inner.upper() {
+            hint.set_upper(upper + pending);
+        }
+        hint
+    }
+}
+
+// An async request body, throttled when `max_upload_bytes_per_sec` is set
+pub fn upload_body(options: &HttpOptions, body: reqwest::Body) -> reqwest::Body {
+    match upload(options) {
+        Some(limiter) => reqwest::Body::wrap(Body::new(body, Some(limiter))),
+        None => body,
+    }
+}
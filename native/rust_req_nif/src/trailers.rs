@@ -0,0 +1,122 @@
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::HttpOptions;
+
+// Fields a sender must not put in trailers (RFC 9110 section 6.5.1): framing, routing,
+// authentication and the ones describing the content, which recipients need up front
+const FORBIDDEN: [&str; 12] = [
+    "authorization",
+    "cache-control",
+    "content-encoding",
+    "content-length",
+    "content-range",
+    "content-type",
+    "host",
+    "max-forwards",
+    "set-cookie",
+    "te",
+    "trailer",
+    "transfer-encoding",
+];
+
+// A request body sent whole, followed by trailer fields when it has any. Bodies with
+// trailers report no size, so they go out chunked.
+pub struct TrailedBody {
+    data: Option<Bytes>,
+    trailers: Option<HeaderMap>,
+}
+
+impl TrailedBody {
+    pub fn new(data: Vec<u8>, trailers: Option<HeaderMap>) -> TrailedBody {
+        TrailedBody {
+            data: Some(Bytes::from(data)).filter(|data| !data.is_empty()),
+            trailers,
+        }
+    }
+}
+
+impl Body for TrailedBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        if let Some(data) = self.data.take() {
+            return Poll::Ready(Some(Ok(Frame::data(data))));
+        }
+        Poll::Ready(self.trailers.take().map(|trailers| Ok(Frame::trailers(trailers))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.data.is_none() && self.trailers.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match (&self.trailers, &self.data) {
+            (Some(_), _) => SizeHint::default(),
+            (None, Some(data)) => SizeHint::with_exact(data.len() as u64),
+            (None, None) => SizeHint::with_exact(0),
+        }
+    }
+}
+
+fn trailer_map(trailers: &[(String, String)]) -> Result<HeaderMap, String> {
+    let mut map = HeaderMap::new();
+    for (name, value) in trailers {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("invalid trailer name: {}", name))?;
+        if FORBIDDEN.contains(&name.as_str()) {
+            return Err(format!("{} is not allowed in trailers", name));
+        }
+        let value = HeaderValue::from_str(value).map_err(|_| format!("invalid value for trailer {}", name))?;
+        map.append(name, value);
+    }
+    Ok(map)
+}
+
+// The `request_trailers` to send, if any
+pub fn trailers(options: &HttpOptions) -> Option<HeaderMap> {
+    options
+        .request_trailers
+        .as_deref()
+        .filter(|trailers| !trailers.is_empty())
+        .and_then(|trailers| trailer_map(trailers).ok())
+}
+
+pub fn errors(options: &HttpOptions) -> Vec<String> {
+    options
+        .request_trailers
+        .as_deref()
+        .and_then(|trailers| trailer_map(trailers).err())
+        .into_iter()
+        .collect()
+}
+
+// Declare `request_trailers` in a `Trailer` header and send the body chunked, as
+// trailers can only follow a chunked body
+pub fn apply(options: &HttpOptions, mut headers: Vec<(String, String)>) -> Vec<(String, String)> {
+    let Some(trailers) = trailers(options) else {
+        return headers;
+    };
+
+    let mut names: Vec<&str> = trailers.keys().map(HeaderName::as_str).collect();
+    names.dedup();
+    headers.retain(|(name, _)| {
+        !name.eq_ignore_ascii_case("trailer")
+            && !name.eq_ignore_ascii_case("transfer-encoding")
+            && !name.eq_ignore_ascii_case("content-length")
+    });
+    headers.push(("trailer".to_string(), names.join(", ")));
+    headers.push(("transfer-encoding".to_string(), "chunked".to_string()));
+    headers
+}
+
+// The body for the async client, carrying `request_trailers` when set
+pub fn body(options: &HttpOptions, data: Vec<u8>) -> reqwest::Body {
+    match trailers(options) {
+        Some(trailers) => reqwest::Body::wrap(TrailedBody::new(data, Some(trailers))),
+        None => reqwest::Body::from(data),
+    }
+}
@@ -0,0 +1,617 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ring::digest;
+use rustler::NifUnitEnum;
+use std::fmt;
+use std::sync::{Arc, LazyLock};
+use tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::client::WebPkiServerVerifier;
+use tokio_rustls::rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, SignatureVerificationAlgorithm, UnixTime};
+use tokio_rustls::rustls::{
+    version, CertificateError, ClientConfig, DigitallySignedStruct, OtherError, RootCertStore, SignatureScheme,
+    SupportedProtocolVersion,
+};
+
+use crate::HttpOptions;
+
+#[cfg(all(unix, not(target_os = "macos")))]
+use std::path::Path;
+
+// Where TLS root certificates come from
+#[derive(Clone, Copy, Debug, PartialEq, NifUnitEnum)]
+pub enum TlsRoots {
+    // The operating system's trust store
+    System,
+    // The Mozilla roots compiled into the library
+    Webpki,
+    Both,
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+// CA bundles of the common Linux and BSD distributions, as probed by OpenSSL
+const BUNDLE_FILES: [&str; 7] = [
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/pki/ca-trust/extracted/pem/tls-ca-bundle.pem",
+    "/etc/ssl/ca-bundle.pem",
+    "/etc/pki/tls/cacert.pem",
+    "/etc/ssl/cert.pem",
+    "/usr/local/share/certs/ca-root-nss.crt",
+];
+
+#[cfg(all(unix, not(target_os = "macos")))]
+const BUNDLE_DIRS: [&str; 2] = ["/etc/ssl/certs", "/etc/pki/tls/certs"];
+
+#[cfg(all(unix, not(target_os = "macos")))]
+// Every certificate of PEM files; entries that don't parse are skipped, as stores
+// commonly hold a few unusable ones
+fn read_pem(path: &Path, certs: &mut Vec<Vec<u8>>) {
+    if let Ok(pem) = std::fs::read(path) {
+        certs.extend(CertificateDer::pem_slice_iter(&pem).flatten().map(|cert| cert.to_vec()));
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn read_pem_dir(dir: &Path, certs: &mut Vec<Vec<u8>>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_file() {
+            read_pem(&path, certs);
+        }
+    }
+}
+
+// The trust store as OpenSSL would find it, honouring `SSL_CERT_FILE` and `SSL_CERT_DIR`
+#[cfg(all(unix, not(target_os = "macos")))]
+fn load() -> Result<Vec<Vec<u8>>, String> {
+    let mut certs = Vec::new();
+    let file = std::env::var_os("SSL_CERT_FILE");
+    let dir = std::env::var_os("SSL_CERT_DIR");
+
+    if let Some(file) = &file {
+        read_pem(Path::new(file), &mut certs);
+    }
+    if let Some(dir) = &dir {
+        std::env::split_paths(dir).for_each(|dir| read_pem_dir(&dir, &mut certs));
+    }
+
+    if file.is_none() && dir.is_none() {
+        match BUNDLE_FILES.iter().map(Path::new).find(|path| path.is_file()) {
+            Some(path) => read_pem(path, &mut certs),
+            None => BUNDLE_DIRS.iter().for_each(|dir| read_pem_dir(Path::new(dir), &mut certs)),
+        }
+    }
+
+    Ok(certs)
+}
+
+// The system roots and the System keychain, where administrators install company CAs
+#[cfg(target_os = "macos")]
+fn load() -> Result<Vec<Vec<u8>>, String> {
+    let output = std::process::Command::new("/usr/bin/security")
+        .args([
+            "find-certificate",
+            "-a",
+            "-p",
+            "/System/Library/Keychains/SystemRootCertificates.keychain",
+            "/Library/Keychains/System.keychain",
+        ])
+        .output()
+        .map_err(|e| format!("cannot run security: {}", e))?;
+
+    Ok(CertificateDer::pem_slice_iter(&output.stdout)
+        .flatten()
+        .map(|cert| cert.to_vec())
+        .collect())
+}
+
+#[cfg(not(unix))]
+fn load() -> Result<Vec<Vec<u8>>, String> {
+    Err("the system trust store is not supported on this platform".to_string())
+}
+
+// Read once, as the store rarely changes while the VM runs. Certificates rustls can't use
+// as trust anchors are dropped, so they don't fail every client build.
+static SYSTEM_ROOTS: LazyLock<Result<Vec<Vec<u8>>, String>> = LazyLock::new(|| {
+    let mut certs = load()?;
+    certs.sort();
+    certs.dedup();
+    certs.retain(|cert| RootCertStore::empty().add(CertificateDer::from(cert.as_slice())).is_ok());
+
+    if certs.is_empty() {
+        Err("no certificates found in the system trust store".to_string())
+    } else {
+        Ok(certs)
+    }
+});
+
+// DER certificates of the operating system's trust store
+pub fn system_roots() -> Result<&'static [Vec<u8>], String> {
+    SYSTEM_ROOTS.as_deref().map_err(Clone::clone)
+}
+
+// TLS protocol versions `tls_min_version` and `tls_max_version` may name; rustls
+// speaks no earlier ones
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, NifUnitEnum)]
+pub enum TlsVersion {
+    Tls12,
+    Tls13,
+}
+
+impl TlsVersion {
+    pub fn reqwest(self) -> reqwest::tls::Version {
+        match self {
+            TlsVersion::Tls12 => reqwest::tls::Version::TLS_1_2,
+            TlsVersion::Tls13 => reqwest::tls::Version::TLS_1_3,
+        }
+    }
+}
+
+// The versions allowed between `tls_min_version` and `tls_max_version`, newest first
+pub fn versions(options: &HttpOptions) -> Vec<&'static SupportedProtocolVersion> {
+    let min = options.tls_min_version.unwrap_or(TlsVersion::Tls12);
+    let max = options.tls_max_version.unwrap_or(TlsVersion::Tls13);
+
+    [(TlsVersion::Tls13, &version::TLS13), (TlsVersion::Tls12, &version::TLS12)]
+        .into_iter()
+        .filter(|(version, _)| (min..=max).contains(version))
+        .map(|(_, supported)| supported)
+        .collect()
+}
+
+// Whether `tls_roots` asks for the system store, and for the built-in roots
+pub fn sources(options: &HttpOptions) -> (bool, bool) {
+    match options.tls_roots.unwrap_or(TlsRoots::Webpki) {
+        TlsRoots::System => (true, false),
+        TlsRoots::Webpki => (false, true),
+        TlsRoots::Both => (true, true),
+    }
+}
+
+// PEM certificates trusted on top of the roots: the `ca_bundle` file, then `ca_cert_pem`
+pub fn extra_pems(options: &HttpOptions) -> std::io::Result<Vec<Vec<u8>>> {
+    let mut pems = Vec::new();
+    if let Some(path) = &options.ca_bundle {
+        pems.push(std::fs::read(path)?);
+    }
+    if let Some(pem) = &options.ca_cert_pem {
+        pems.push(pem.clone().into_bytes());
+    }
+    Ok(pems)
+}
+
+// Whether `insecure_skip_verify` and `verify_hostname: false` turn off certificate and
+// host name checks. Only builds with the `insecure_tls` feature honour them; others
+// refuse the request rather than silently verifying.
+pub fn insecure(options: &HttpOptions) -> Result<(bool, bool), String> {
+    let skip_certs = options.insecure_skip_verify == Some(true);
+    let skip_hostname = skip_certs || options.verify_hostname == Some(false);
+
+    if skip_hostname && !cfg!(feature = "insecure_tls") {
+        return Err("insecure_skip_verify and verify_hostname need the insecure_tls feature".to_string());
+    }
+    Ok((skip_certs, skip_hostname))
+}
+
+// Accepts any certificate, still checking the handshake signatures made with it
+#[derive(Debug)]
+struct AcceptAny(Arc<CryptoProvider>);
+
+// Checks the chain but not whether it names the host
+#[derive(Debug)]
+struct AnyName(Arc<WebPkiServerVerifier>);
+
+impl ServerCertVerifier for AcceptAny {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+impl ServerCertVerifier for AnyName {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        match self.0.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now) {
+            Err(tokio_rustls::rustls::Error::InvalidCertificate(
+                CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. },
+            )) => Ok(ServerCertVerified::assertion()),
+            result => result,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        self.0.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        self.0.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_verify_schemes()
+    }
+}
+
+// Checks the chain with `inner`, then fails the handshake with `PinMismatch` unless a
+// certificate on a verified path from the server's certificate to one of `roots` has
+// a key in `pinned_spki_sha256`. Certificates the server sends that are off every such
+// path don't count. Without `roots`, when the chain goes unverified, only the server's
+// own certificate does.
+#[derive(Debug)]
+struct Pinned {
+    inner: Arc<dyn ServerCertVerifier>,
+    roots: Option<Arc<RootCertStore>>,
+    algorithms: &'static [&'static dyn SignatureVerificationAlgorithm],
+    pins: Vec<Vec<u8>>,
+}
+
+// The error a pinned handshake fails with, reported as `:pin_mismatch`
+#[derive(Debug)]
+pub struct PinMismatch;
+
+impl fmt::Display for PinMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "no certificate of the chain matches a pinned key")
+    }
+}
+
+impl std::error::Error for PinMismatch {}
+
+// A DER element read off the start of some input
+struct Element<'a> {
+    whole: &'a [u8],
+    content: &'a [u8],
+    rest: &'a [u8],
+}
+
+fn der_element(input: &[u8]) -> Option<Element<'_>> {
+    let (&first, rest) = input.get(1..)?.split_first()?;
+
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let len = rest[..count].iter().fold(0usize, |len, &byte| (len << 8) | byte as usize);
+        (len, &rest[count..])
+    };
+
+    let header = input.len() - rest.len();
+    if rest.len() < len {
+        return None;
+    }
+    Some(Element {
+        whole: &input[..header + len],
+        content: &rest[..len],
+        rest: &rest[len..],
+    })
+}
+
+// The DER subjectPublicKeyInfo of an X.509 certificate: the seventh field of its
+// TBSCertificate, counting the optional version
+fn spki(cert: &[u8]) -> Option<&[u8]> {
+    let certificate = der_element(cert)?.content;
+    let mut fields = der_element(certificate)?.content;
+
+    if fields.first() == Some(&0xa0) {
+        fields = der_element(fields)?.rest;
+    }
+    // Serial number, signature algorithm, issuer, validity and subject
+    for _ in 0..5 {
+        fields = der_element(fields)?.rest;
+    }
+    Some(der_element(fields)?.whole)
+}
+
+// A DER SEQUENCE around `content`; trust anchors keep their key without one
+fn der_sequence(content: &[u8]) -> Vec<u8> {
+    let mut der = vec![0x30];
+    if content.len() < 0x80 {
+        der.push(content.len() as u8);
+    } else {
+        let len: Vec<u8> = content.len().to_be_bytes().into_iter().skip_while(|&byte| byte == 0).collect();
+        der.push(0x80 | len.len() as u8);
+        der.extend(len);
+    }
+    der.extend_from_slice(content);
+    der
+}
+
+impl Pinned {
+    fn matches_spki(&self, spki: &[u8]) -> bool {
+        let hash = digest::digest(&digest::SHA256, spki);
+        self.pins.iter().any(|pin| pin == hash.as_ref())
+    }
+
+    fn matches(&self, cert: &CertificateDer<'_>) -> bool {
+        spki(cert).is_some_and(|spki| self.matches_spki(spki))
+    }
+
+    fn path_matches(&self, path: &webpki::VerifiedPath<'_>) -> bool {
+        self.matches_spki(path.end_entity().subject_public_key_info().as_ref())
+            || path
+                .intermediate_certificates()
+                .any(|cert| self.matches_spki(cert.subject_public_key_info().as_ref()))
+            || self.matches_spki(&der_sequence(&path.anchor().subject_public_key_info))
+    }
+
+    // Whether some path webpki can build to `roots` carries a pinned key; paths that
+    // don't make it look for another, as the server may send more than one
+    fn verified_path_matches(
+        &self,
+        roots: &RootCertStore,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> bool {
+        let Ok(cert) = webpki::EndEntityCert::try_from(end_entity) else {
+            return false;
+        };
+        let pinned = |path: &webpki::VerifiedPath<'_>| match self.path_matches(path) {
+            true => Ok(()),
+            false => Err(webpki::Error::UnknownIssuer),
+        };
+
+        cert.verify_for_usage(
+            self.algorithms,
+            &roots.roots,
+            intermediates,
+            now,
+            webpki::KeyUsage::server_auth(),
+            None,
+            Some(&pinned),
+        )
+        .is_ok()
+    }
+}
+
+impl ServerCertVerifier for Pinned {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        let verified = self.inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+
+        let pinned = match &self.roots {
+            Some(roots) => self.verified_path_matches(roots, end_entity, intermediates, now),
+            None => self.matches(end_entity),
+        };
+
+        if pinned {
+            Ok(verified)
+        } else {
+            Err(tokio_rustls::rustls::Error::InvalidCertificate(CertificateError::Other(OtherError(Arc::new(
+                PinMismatch,
+            )))))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+// `pinned_spki_sha256` decoded, accepting HPKP's `sha256/` prefix
+pub fn pins(options: &HttpOptions) -> Result<Option<Vec<Vec<u8>>>, String> {
+    let Some(pins) = &options.pinned_spki_sha256 else {
+        return Ok(None);
+    };
+    if pins.is_empty() {
+        return Err("must list at least one pin".to_string());
+    }
+
+    pins.iter()
+        .map(|pin| {
+            let encoded = pin.strip_prefix("sha256/").unwrap_or(pin);
+            match BASE64.decode(encoded) {
+                Ok(hash) if hash.len() == 32 => Ok(hash),
+                _ => Err(format!("not a base64 SHA-256 hash: {}", pin)),
+            }
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+// Whether the rustls configuration must be built by `client_config` rather than left
+// to reqwest, which cannot pin keys
+pub fn needs_client_config(options: &HttpOptions) -> bool {
+    options.pinned_spki_sha256.is_some()
+}
+
+// The rustls configuration for `options`: the trusted roots, TLS versions and
+// verification they ask for. ALPN protocols are left for the caller to set.
+pub fn client_config(options: &HttpOptions) -> Result<ClientConfig, String> {
+    let (system, built_in) = sources(options);
+    let mut roots = RootCertStore::empty();
+
+    if built_in {
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    }
+    if system {
+        for cert in system_roots()? {
+            roots.add(CertificateDer::from(cert.as_slice())).map_err(|e| e.to_string())?;
+        }
+    }
+
+    for pem in extra_pems(options).map_err(|e| e.to_string())? {
+        for cert in CertificateDer::pem_slice_iter(&pem) {
+            roots.add(cert.map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        }
+    }
+
+    let versions = versions(options);
+    if versions.is_empty() {
+        return Err("tls_min_version is above tls_max_version".to_string());
+    }
+    let mut config = ClientConfig::builder_with_protocol_versions(&versions)
+        .with_root_certificates(roots.clone())
+        .with_no_client_auth();
+
+    let provider = config.crypto_provider().clone();
+    let verifier: Option<Arc<dyn ServerCertVerifier>> = match insecure(options)? {
+        (true, _) => Some(Arc::new(AcceptAny(provider.clone()))),
+        (false, true) => Some(Arc::new(AnyName(webpki_verifier(&roots, &provider)?))),
+        (false, false) => None,
+    };
+
+    if let Some(pins) = pins(options)? {
+        // Chains that skip verification have no path for the pins to be found on
+        let verified = !matches!(insecure(options)?, (true, _));
+        let inner = match verifier {
+            Some(verifier) => verifier,
+            None => webpki_verifier(&roots, &provider)?,
+        };
+        config.dangerous().set_certificate_verifier(Arc::new(Pinned {
+            inner,
+            roots: verified.then(|| Arc::new(roots)),
+            algorithms: provider.signature_verification_algorithms.all,
+            pins,
+        }));
+    } else if let Some(verifier) = verifier {
+        config.dangerous().set_certificate_verifier(verifier);
+    }
+
+    Ok(config)
+}
+
+fn webpki_verifier(roots: &RootCertStore, provider: &Arc<CryptoProvider>) -> Result<Arc<WebPkiServerVerifier>, String> {
+    WebPkiServerVerifier::builder_with_provider(Arc::new(roots.clone()), provider.clone())
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair};
+
+    fn ca(name: &str, issuer: Option<(&rcgen::Certificate, &KeyPair)>) -> (rcgen::Certificate, KeyPair) {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.distinguished_name.push(DnType::CommonName, name);
+
+        let cert = match issuer {
+            Some((issuer, issuer_key)) => params.signed_by(&key, issuer, issuer_key),
+            None => params.self_signed(&key),
+        };
+        (cert.unwrap(), key)
+    }
+
+    fn pin(key: &KeyPair) -> Vec<u8> {
+        digest::digest(&digest::SHA256, &key.public_key_der()).as_ref().to_vec()
+    }
+
+    fn pinned(roots: &RootCertStore, pins: Vec<Vec<u8>>) -> Pinned {
+        let provider = Arc::new(tokio_rustls::rustls::crypto::ring::default_provider());
+        Pinned {
+            inner: webpki_verifier(roots, &provider).unwrap(),
+            roots: Some(Arc::new(roots.clone())),
+            algorithms: provider.signature_verification_algorithms.all,
+            pins,
+        }
+    }
+
+    #[test]
+    fn pins_only_match_certificates_on_the_verified_path() {
+        let (root, root_key) = ca("Root", None);
+        let (intermediate, intermediate_key) = ca("Intermediate", Some((&root, &root_key)));
+        // A CA the chain doesn't lead to, whose public certificate the server tacks on
+        let (unrelated, unrelated_key) = ca("Unrelated", None);
+
+        let leaf_key = KeyPair::generate().unwrap();
+        let leaf = CertificateParams::new(vec!["example.test".to_string()])
+            .unwrap()
+            .signed_by(&leaf_key, &intermediate, &intermediate_key)
+            .unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots.add(root.der().clone()).unwrap();
+        let name = ServerName::try_from("example.test").unwrap();
+        let sent = [intermediate.der().clone(), unrelated.der().clone()];
+        let verify = |pins| pinned(&roots, pins).verify_server_cert(leaf.der(), &sent, &name, &[], UnixTime::now());
+
+        for key in [&leaf_key, &intermediate_key, &root_key] {
+            assert!(verify(vec![pin(key)]).is_ok());
+        }
+
+        let mismatch = verify(vec![pin(&unrelated_key)]).unwrap_err();
+        assert!(matches!(
+            mismatch,
+            tokio_rustls::rustls::Error::InvalidCertificate(CertificateError::Other(other)) if other.0.is::<PinMismatch>()
+        ));
+    }
+}
@@ -0,0 +1,259 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use percent_encoding::percent_decode_str;
+use rustler::{Atom, Binary, Encoder, Env, Error, NifResult, OwnedBinary, ResourceArc, Term};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{IpAddr, Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::{atoms, audit, connect_timeout, inflight, proxy_credentials, resolve_options, HttpOptions};
+
+// Upper bound on the proxy's CONNECT response headers
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+
+// TCP connection tunnelled through an HTTP proxy. The halves are locked
+// separately so a blocked read doesn't hold up writes, and `socket`, a handle of its
+// own outside both locks, lets `tunnel_close` shut it down while either is blocked.
+pub struct Tunnel {
+    reader: Mutex<Option<BufReader<TcpStream>>>,
+    writer: Mutex<Option<TcpStream>>,
+    socket: TcpStream,
+    closed: AtomicBool,
+    // The slot of `inflight` the tunnel occupies until it is closed
+    permit: Mutex<Option<inflight::Permit>>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for Tunnel {}
+
+fn io_error(e: std::io::Error) -> Error {
+    Error::Term(Box::new(format!("Connection error: {}", e)))
+}
+
+fn tunnel_error(message: &str) -> Error {
+    Error::Term(Box::new(format!("Tunnel error: {}", message)))
+}
+
+fn connect(host: &str, port: u16, timeout: Duration) -> std::io::Result<TcpStream> {
+    let mut last_error = std::io::Error::new(ErrorKind::NotFound, "no addresses resolved");
+
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(last_error)
+}
+
+// `target_host:port` as the CONNECT request names it, when the host is an IP address,
+// bracketed or not when IPv6, or a host name of letters, digits, `-`, `_` and `.`;
+// anything else could smuggle headers into the request
+fn target_authority(host: &str, port: u16) -> Option<String> {
+    let bare = host.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(host);
+
+    match bare.parse::<IpAddr>() {
+        Ok(IpAddr::V6(ip)) => Some(format!("[{}]:{}", ip, port)),
+        Ok(IpAddr::V4(ip)) => Some(format!("{}:{}", ip, port)),
+        Err(_) if bare != host => None,
+        Err(_) => {
+            let valid = !host.is_empty()
+                && host.len() <= 253
+                && host.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+            valid.then(|| format!("{}:{}", host, port))
+        }
+    }
+}
+
+// Read the proxy's response to CONNECT, returning its status code. Bytes the
+// proxy sends after the headers stay buffered in `reader`.
+fn read_connect_response(reader: &mut BufReader<TcpStream>) -> NifResult<u16> {
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line).map_err(io_error)?;
+
+    let status = status_line
+        .strip_prefix("HTTP/1.")
+        .and_then(|rest| rest.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| tunnel_error("malformed proxy response"))?;
+
+    let mut total = status_line.len();
+    loop {
+        let mut line = String::new();
+        let read = reader.read_line(&mut line).map_err(io_error)?;
+        total += read;
+
+        if read == 0 {
+            return Err(tunnel_error("proxy closed the connection"));
+        }
+        if total > MAX_HEADER_BYTES {
+            return Err(tunnel_error("proxy response headers too large"));
+        }
+        if line == "\r\n" || line == "\n" {
+            return Ok(status);
+        }
+    }
+}
+
+// Open a TCP tunnel to `target_host:port` through the HTTP proxy at `proxy`.
+// `proxy_username` and `proxy_password`, or else credentials in the proxy URL, are sent
+// as Basic `Proxy-Authorization`.
+#[rustler::nif(schedule = "DirtyIo")]
+fn tunnel_open(
+    env: Env,
+    proxy: String,
+    target_host: String,
+    port: u16,
+    headers: Vec<(String, String)>,
+    options: HttpOptions,
+) -> NifResult<(Atom, ResourceArc<Tunnel>)> {
+    let permit = inflight::acquire()?;
+    let options = resolve_options(options)?;
+    let timeout = Duration::from_millis(options.timeout_ms.unwrap_or(30000));
+    let authority = target_authority(&target_host, port).ok_or_else(|| tunnel_error("invalid target host"))?;
+
+    let proxy = reqwest::Url::parse(&proxy).map_err(|_| Error::Term(Box::new(atoms::invalid_url())))?;
+    if proxy.scheme() != "http" {
+        return Err(tunnel_error("only http:// proxies are supported"));
+    }
+    let proxy_host = proxy
+        .host_str()
+        .ok_or_else(|| Error::Term(Box::new(atoms::invalid_url())))?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+
+    let proxy_port = proxy.port_or_known_default().unwrap_or(80);
+    let stream = connect(proxy_host, proxy_port, connect_timeout(&options).unwrap_or(timeout)).map_err(io_error)?;
+    stream.set_read_timeout(Some(timeout)).map_err(io_error)?;
+    stream.set_write_timeout(Some(timeout)).map_err(io_error)?;
+
+    let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", authority, authority);
+
+    let credentials = match proxy_credentials(&options) {
+        Some((user, password)) => Some(format!("{}:{}", user, password)),
+        None if !proxy.username().is_empty() => {
+            let user = percent_decode_str(proxy.username()).decode_utf8_lossy();
+            let password = percent_decode_str(proxy.password().unwrap_or("")).decode_utf8_lossy();
+            Some(format!("{}:{}", user, password))
+        }
+        None => None,
+    };
+    if let Some(credentials) = credentials {
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", BASE64.encode(credentials)));
+    }
+
+    // Connection-level proxy headers from the options come before the per-call ones
+    let proxy_headers = options.proxy_headers.iter().flatten();
+
+    for (key, value) in proxy_headers.chain(&headers) {
+        if key.contains(['\r', '\n']) || value.contains(['\r', '\n']) {
+            return Err(tunnel_error("invalid header"));
+        }
+        request.push_str(&format!("{}: {}\r\n", key, value));
+    }
+    request.push_str("\r\n");
+
+    let started = SystemTime::now();
+    let mut writer = stream.try_clone().map_err(io_error)?;
+    writer.write_all(request.as_bytes()).map_err(io_error)?;
+
+    let mut reader = BufReader::new(stream);
+    let status = read_connect_response(&mut reader);
+    let caller = audit::caller(env);
+    audit::record(caller.as_deref(), "CONNECT", &authority, started, status.as_ref().ok().copied(), request.len(), 0);
+    let status = status?;
+
+    if !(200..300).contains(&status) {
+        return Err(Error::Term(Box::new((atoms::unexpected_status(), status))));
+    }
+
+    // The tunnel may sit idle indefinitely; reads take their own timeout
+    reader.get_ref().set_read_timeout(None).map_err(io_error)?;
+    writer.set_write_timeout(None).map_err(io_error)?;
+
+    let tunnel = ResourceArc::new(Tunnel {
+        socket: writer.try_clone().map_err(io_error)?,
+        reader: Mutex::new(Some(reader)),
+        writer: Mutex::new(Some(writer)),
+        closed: AtomicBool::new(false),
+        permit: Mutex::new(Some(permit)),
+    });
+
+    Ok((atoms::ok(), tunnel))
+}
+
+// Read up to `max_bytes` from the tunnel, waiting at most `timeout_ms` when given.
+// Returns `:eof` once the remote end or `tunnel_close` has closed the tunnel; `max_bytes`
+// must be positive, as an empty read would look like the end.
+#[rustler::nif(schedule = "DirtyIo")]
+fn tunnel_read<'a>(
+    env: Env<'a>,
+    tunnel: ResourceArc<Tunnel>,
+    max_bytes: usize,
+    timeout_ms: Option<u64>,
+) -> NifResult<Term<'a>> {
+    if max_bytes == 0 {
+        return Err(Error::BadArg);
+    }
+
+    let mut slot = tunnel.reader.lock().unwrap();
+    let Some(reader) = slot.as_mut() else {
+        return Ok(atoms::eof().encode(env));
+    };
+
+    reader
+        .get_ref()
+        .set_read_timeout(timeout_ms.map(|ms| Duration::from_millis(ms.max(1))))
+        .map_err(io_error)?;
+
+    let mut chunk = OwnedBinary::new(max_bytes).ok_or(Error::RaiseAtom("enomem"))?;
+    let read = match reader.read(chunk.as_mut_slice()) {
+        Ok(read) => read,
+        Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+            return Err(Error::Term(Box::new(atoms::timeout())));
+        }
+        Err(e) => return Err(io_error(e)),
+    };
+
+    if read == 0 {
+        *slot = None;
+        return Ok(atoms::eof().encode(env));
+    }
+
+    chunk.realloc_or_copy(read);
+    Ok((atoms::ok(), Binary::from_owned(chunk, env)).encode(env))
+}
+
+// Write all of `data` to the tunnel
+#[rustler::nif(schedule = "DirtyIo")]
+fn tunnel_write(tunnel: ResourceArc<Tunnel>, data: Binary) -> NifResult<Atom> {
+    let mut slot = tunnel.writer.lock().unwrap();
+    let writer = slot
+        .as_mut()
+        .filter(|_| !tunnel.closed.load(Ordering::Acquire))
+        .ok_or_else(|| tunnel_error("closed"))?;
+
+    writer.write_all(data.as_slice()).map_err(io_error)?;
+    Ok(atoms::ok())
+}
+
+// Close both directions without waiting on either half: a read blocked on another
+// process returns `:eof` and a blocked write fails, so this never holds up the
+// scheduler it runs on
+#[rustler::nif]
+fn tunnel_close(tunnel: ResourceArc<Tunnel>) -> Atom {
+    tunnel.closed.store(true, Ordering::Release);
+    let _ = tunnel.socket.shutdown(Shutdown::Both);
+
+    if let Ok(mut writer) = tunnel.writer.try_lock() {
+        writer.take();
+    }
+    if let Ok(mut reader) = tunnel.reader.try_lock() {
+        reader.take();
+    }
+    tunnel.permit.lock().unwrap().take();
+    atoms::ok()
+}
@@ -0,0 +1,229 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use reqwest::header::{CONTENT_TYPE, LOCATION};
+use reqwest::StatusCode;
+use rustler::{Atom, Binary, Env, Error, NifResult};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{atoms, audit, blocking, client_for, inflight, pipeline, resolve_options, HttpOptions};
+
+const TUS_VERSION: &str = "1.0.0";
+
+// Consecutive failed PATCH requests tolerated by `tus_upload_file` before giving up
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+
+fn tus_request(
+    client: &reqwest::blocking::Client,
+    method: reqwest::Method,
+    url: &str,
+    headers: &[(String, String)],
+) -> reqwest::blocking::RequestBuilder {
+    let mut request = client.request(method, url).header("Tus-Resumable", TUS_VERSION);
+
+    for (key, value) in headers {
+        request = request.header(key, value);
+    }
+
+    request
+}
+
+fn status_error(status: StatusCode) -> Error {
+    match status.as_u16() {
+        409 => Error::Term(Box::new(atoms::offset_mismatch())),
+        460 => Error::Term(Box::new(atoms::checksum_mismatch())),
+        404 | 410 => Error::Term(Box::new(atoms::upload_not_found())),
+        code => Error::Term(Box::new((atoms::unexpected_status(), code))),
+    }
+}
+
+fn header_u64(response: &reqwest::blocking::Response, name: &str) -> Option<u64> {
+    response.headers().get(name)?.to_str().ok()?.parse().ok()
+}
+
+fn client_error(e: Box<dyn std::error::Error>) -> Error {
+    Error::Term(Box::new(format!("Client error: {}", e)))
+}
+
+fn offset(
+    caller: Option<&str>,
+    options: &HttpOptions,
+    client: &reqwest::blocking::Client,
+    upload_url: &str,
+    headers: &[(String, String)],
+) -> NifResult<(u64, Option<u64>)> {
+    let request = tus_request(client, reqwest::Method::HEAD, upload_url, headers);
+    let response = pipeline::send(caller, options, request).map_err(|e| e.into_error())?;
+
+    if !response.status().is_success() {
+        return Err(status_error(response.status()));
+    }
+
+    let offset = header_u64(&response, "Upload-Offset")
+        .ok_or_else(|| Error::Term(Box::new("Missing Upload-Offset header".to_string())))?;
+
+    Ok((offset, header_u64(&response, "Upload-Length")))
+}
+
+fn patch(
+    caller: Option<&str>,
+    options: &HttpOptions,
+    client: &reqwest::blocking::Client,
+    upload_url: &str,
+    offset: u64,
+    chunk: Vec<u8>,
+    headers: &[(String, String)],
+) -> NifResult<u64> {
+    let digest = ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, &chunk);
+
+    let request = tus_request(client, reqwest::Method::PATCH, upload_url, headers)
+        .header(CONTENT_TYPE, "application/offset+octet-stream")
+        .header("Upload-Offset", offset.to_string())
+        .header("Upload-Checksum", format!("sha1 {}", BASE64.encode(digest.as_ref())))
+        .body(chunk);
+    let response = pipeline::send(caller, options, request).map_err(|e| e.into_error())?;
+
+    if response.status() != StatusCode::NO_CONTENT {
+        return Err(status_error(response.status()));
+    }
+
+    header_u64(&response, "Upload-Offset")
+        .ok_or_else(|| Error::Term(Box::new("Missing Upload-Offset header".to_string())))
+}
+
+// Create an upload of `upload_length` bytes, returning its absolute URL
+#[rustler::nif(schedule = "DirtyIo")]
+fn tus_create(
+    env: Env,
+    url: String,
+    upload_length: u64,
+    metadata: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    options: HttpOptions,
+) -> NifResult<(Atom, String)> {
+    let _permit = blocking::acquire()?;
+    let _inflight = inflight::acquire()?;
+    let options = resolve_options(options)?;
+    let client = client_for(&options).map_err(client_error)?;
+
+    let mut request = tus_request(&client, reqwest::Method::POST, &url, &headers)
+        .header("Upload-Length", upload_length.to_string());
+
+    if !metadata.is_empty() {
+        let encoded: Vec<String> = metadata
+            .iter()
+            .map(|(key, value)| format!("{} {}", key, BASE64.encode(value)))
+            .collect();
+        request = request.header("Upload-Metadata", encoded.join(","));
+    }
+
+    let response = pipeline::send(audit::caller(env).as_deref(), &options, request).map_err(|e| e.into_error())?;
+
+    if response.status() != StatusCode::CREATED {
+        return Err(status_error(response.status()));
+    }
+
+    let location = response
+        .headers()
+        .get(LOCATION)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| Error::Term(Box::new("Missing Location header".to_string())))?;
+
+    let upload_url = response
+        .url()
+        .join(location)
+        .map_err(|e| Error::Term(Box::new(format!("Invalid Location header: {}", e))))?;
+
+    Ok((atoms::ok(), upload_url.to_string()))
+}
+
+// Discover how many bytes of an upload the server has, and its total length if known
+#[rustler::nif(schedule = "DirtyIo")]
+fn tus_offset(env: Env, upload_url: String, headers: Vec<(String, String)>, options: HttpOptions) -> NifResult<(Atom, (u64, Option<u64>))> {
+    let _permit = blocking::acquire()?;
+    let _inflight = inflight::acquire()?;
+    let options = resolve_options(options)?;
+    let client = client_for(&options).map_err(client_error)?;
+
+    Ok((atoms::ok(), offset(audit::caller(env).as_deref(), &options, &client, &upload_url, &headers)?))
+}
+
+// Append `chunk` at `offset`, returning the new offset
+#[rustler::nif(schedule = "DirtyIo")]
+fn tus_patch(
+    env: Env,
+    upload_url: String,
+    offset: u64,
+    chunk: Binary,
+    headers: Vec<(String, String)>,
+    options: HttpOptions,
+) -> NifResult<(Atom, u64)> {
+    let _permit = blocking::acquire()?;
+    let _inflight = inflight::acquire()?;
+    let options = resolve_options(options)?;
+    let client = client_for(&options).map_err(client_error)?;
+
+    Ok((atoms::ok(), patch(audit::caller(env).as_deref(), &options, &client, &upload_url, offset, chunk.as_slice().to_vec(), &headers)?))
+}
+
+// Upload a file to an existing upload, resuming from the offset the server reports.
+// After a failed PATCH, or one the server answers without moving the offset forward,
+// the offset is rediscovered and the upload continues from there.
+#[rustler::nif(schedule = "DirtyIo")]
+fn tus_upload_file(
+    env: Env,
+    upload_url: String,
+    path: String,
+    chunk_size: usize,
+    headers: Vec<(String, String)>,
+    options: HttpOptions,
+) -> NifResult<(Atom, u64)> {
+    if chunk_size == 0 {
+        return Err(Error::BadArg);
+    }
+
+    let _permit = blocking::acquire()?;
+    let _inflight = inflight::acquire()?;
+    let options = resolve_options(options)?;
+    let client = client_for(&options).map_err(client_error)?;
+    let caller = audit::caller(env);
+    let caller = caller.as_deref();
+
+    let file_error = |e: std::io::Error| Error::Term(Box::new(format!("File error: {}", e)));
+    let mut file = File::open(&path).map_err(file_error)?;
+    let size = file.metadata().map_err(file_error)?.len();
+
+    let (mut current, _) = offset(caller, &options, &client, &upload_url, &headers)?;
+    let mut failures = 0;
+
+    while current < size {
+        let mut chunk = Vec::with_capacity(chunk_size.min((size - current) as usize));
+        file.seek(SeekFrom::Start(current)).map_err(file_error)?;
+        (&mut file)
+            .take(chunk_size as u64)
+            .read_to_end(&mut chunk)
+            .map_err(file_error)?;
+
+        let patched = patch(caller, &options, &client, &upload_url, current, chunk, &headers).and_then(|next| {
+            if next > current {
+                Ok(next)
+            } else {
+                Err(Error::Term(Box::new(format!("Upload error: offset stayed at {} after a PATCH", next))))
+            }
+        });
+
+        match patched {
+            Ok(next) => {
+                current = next;
+                failures = 0;
+            }
+            Err(e) if failures + 1 == MAX_CONSECUTIVE_FAILURES => return Err(e),
+            Err(_) => {
+                failures += 1;
+                current = offset(caller, &options, &client, &upload_url, &headers)?.0;
+            }
+        }
+    }
+
+    Ok((atoms::ok(), current))
+}
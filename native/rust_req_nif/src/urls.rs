@@ -0,0 +1,104 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use rustler::{Error, NifUnitEnum};
+
+use crate::{atoms, events, HttpOptions};
+
+// Bytes left as they are in application/x-www-form-urlencoded; space becomes `+`
+const FORM_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC.remove(b'*').remove(b'-').remove(b'.').remove(b'_').remove(b' ');
+
+// `pairs` as an application/x-www-form-urlencoded body, as the WHATWG URL standard
+// serializes them
+pub fn form_encode(pairs: &[(String, String)]) -> String {
+    let encode = |value: &str| utf8_percent_encode(value, FORM_ENCODE_SET).to_string().replace(' ', "+");
+
+    pairs
+        .iter()
+        .map(|(name, value)| format!("{}={}", encode(name), encode(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+// What `url_fragment` does with a `#fragment`, which is never sent
+#[derive(Clone, Copy, Debug, PartialEq, NifUnitEnum)]
+pub enum Fragment {
+    Strip,
+    Reject,
+}
+
+// What `url_userinfo` does with `user:password@` in a URL
+#[derive(Clone, Copy, Debug, PartialEq, NifUnitEnum)]
+pub enum Userinfo {
+    BasicAuth,
+    Reject,
+    Strip,
+}
+
+// `url` without its userinfo, the rest left as written
+fn without_userinfo(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+
+    match rest[..end].rfind('@') {
+        Some(at) => format!("{}://{}", scheme, &rest[at + 1..]),
+        None => url.to_string(),
+    }
+}
+
+// Apply `url_fragment`, `query` and `url_userinfo` to a request URL, returning the URL
+// to send and its headers. URLs that do not parse are left for the request to fail on.
+pub fn apply(options: &HttpOptions, url: String, mut headers: Vec<(String, String)>) -> Result<(String, Vec<(String, String)>), String> {
+    let Ok(parsed) = reqwest::Url::parse(&url) else {
+        return Ok((url, headers));
+    };
+    let mut url = url;
+
+    if parsed.fragment().is_some() {
+        if options.url_fragment == Some(Fragment::Reject) {
+            return Err("URL has a fragment".to_string());
+        }
+        url.truncate(url.find('#').unwrap_or(url.len()));
+    }
+
+    // Appended to any query the URL has, encoded as `RequestBuilder::query` would
+    if let Some(query) = options.query.as_ref().filter(|query| !query.is_empty()) {
+        if parsed.query().is_none() {
+            url.push('?');
+        } else if !url.ends_with(['?', '&']) {
+            url.push('&');
+        }
+        url.push_str(&form_encode(query));
+    }
+
+    if parsed.username().is_empty() && parsed.password().is_none() {
+        return Ok((url, headers));
+    }
+
+    match options.url_userinfo.unwrap_or(Userinfo::BasicAuth) {
+        Userinfo::Reject => return Err("URL has credentials".to_string()),
+        Userinfo::Strip => {
+            url = without_userinfo(&url);
+            events::warning(&url, "credentials removed from the URL");
+        }
+        Userinfo::BasicAuth => {
+            url = without_userinfo(&url);
+            // A caller's own Authorization header wins
+            if !headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("authorization")) {
+                let username = percent_decode_str(parsed.username()).decode_utf8_lossy();
+                let password = percent_decode_str(parsed.password().unwrap_or("")).decode_utf8_lossy();
+                let credentials = BASE64.encode(format!("{}:{}", username, password));
+                headers.push(("authorization".to_string(), format!("Basic {}", credentials)));
+            }
+        }
+    }
+
+    Ok((url, headers))
+}
+
+// `{:invalid_url, message}` for a URL `apply` rejected
+pub fn error(message: String) -> Error {
+    Error::Term(Box::new((atoms::invalid_url(), message)))
+}
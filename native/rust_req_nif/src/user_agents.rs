@@ -0,0 +1,27 @@
+use std::sync::atomic::AtomicUsize;
+
+use crate::rotation::{self, Strategy};
+use crate::HttpOptions;
+
+// Shared by every pool, so consecutive requests spread over the entries
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+// Add a `User-Agent` header from `user_agent_pool` to the headers of a request to `url`,
+// unless the caller set that header themselves
+pub fn apply(options: &HttpOptions, url: &str, mut headers: Vec<(String, String)>) -> Vec<(String, String)> {
+    if let Some(user_agent) = pick(options, url, &headers) {
+        headers.push(("user-agent".to_string(), user_agent));
+    }
+    headers
+}
+
+fn pick(options: &HttpOptions, url: &str, headers: &[(String, String)]) -> Option<String> {
+    let pool = options.user_agent_pool.as_ref().filter(|pool| !pool.is_empty())?;
+
+    if headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("user-agent")) {
+        return None;
+    }
+
+    let strategy = options.user_agent_strategy.unwrap_or(Strategy::RoundRobin);
+    Some(pool[rotation::index(strategy, &NEXT, url, pool.len())].clone())
+}
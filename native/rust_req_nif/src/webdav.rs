@@ -0,0 +1,464 @@
+use quick_xml::escape::{escape, resolve_predefined_entity};
+use quick_xml::events::Event;
+use quick_xml::name::ResolveResult;
+use quick_xml::NsReader;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Method, StatusCode};
+use rustler::{Atom, Env, Error, NifMap, NifResult, NifUntaggedEnum};
+use std::collections::HashMap;
+
+use crate::{atoms, audit, blocking, client_for, inflight, pipeline, resolve_options, HttpOptions};
+
+const DAV_NS: &str = "DAV:";
+
+// A property is either text or, for structured values such as `resourcetype`,
+// the names of its child elements
+#[derive(NifUntaggedEnum)]
+enum PropValue {
+    Text(String),
+    Elements(Vec<String>),
+}
+
+#[derive(NifMap)]
+struct DavResource {
+    href: String,
+    props: HashMap<String, PropValue>,
+    collection: bool,
+}
+
+// An element name: DAV: elements keep their local name, others use Clark notation
+struct Node {
+    dav: bool,
+    key: String,
+}
+
+fn node(ns: &ResolveResult, local: &[u8]) -> Node {
+    let local = String::from_utf8_lossy(local).into_owned();
+
+    match ns {
+        ResolveResult::Bound(ns) if ns.as_ref() == DAV_NS.as_bytes() => Node { dav: true, key: local },
+        ResolveResult::Bound(ns) => Node {
+            dav: false,
+            key: format!("{{{}}}{}", String::from_utf8_lossy(ns.as_ref()), local),
+        },
+        _ => Node { dav: false, key: local },
+    }
+}
+
+fn xml_error(e: impl std::fmt::Display) -> Error {
+    Error::Term(Box::new(format!("XML error: {}", e)))
+}
+
+fn client_error(e: Box<dyn std::error::Error>) -> Error {
+    Error::Term(Box::new(format!("Client error: {}", e)))
+}
+
+// Whether `name` is an XML NCName, a name without a prefix, so it can be written as
+// an element name as it is
+fn is_ncname(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || matches!(c, '-' | '.' | '_' | '\u{b7}'))
+}
+
+fn propfind_body(props: &[(String, String)]) -> NifResult<String> {
+    if let Some((_, name)) = props.iter().find(|(_, name)| !is_ncname(name)) {
+        return Err(xml_error(format!("invalid property name {:?}", name)));
+    }
+
+    let mut body = String::from(r#"<?xml version="1.0" encoding="utf-8"?><D:propfind xmlns:D="DAV:">"#);
+
+    if props.is_empty() {
+        body.push_str("<D:allprop/>");
+    } else {
+        body.push_str("<D:prop>");
+        for (ns, name) in props {
+            if ns == DAV_NS {
+                body.push_str(&format!("<D:{}/>", name));
+            } else {
+                body.push_str(&format!(r#"<x:{} xmlns:x="{}"/>"#, name, escape(ns.as_str())));
+            }
+        }
+        body.push_str("</D:prop>");
+    }
+
+    body.push_str("</D:propfind>");
+    Ok(body)
+}
+
+// Parse a 207 Multi-Status body. Only properties from propstat elements with a
+// 200 status are kept; the rest were not found or not readable.
+fn parse_multistatus(xml: &str) -> NifResult<Vec<DavResource>> {
+    let mut reader = NsReader::from_str(xml);
+    let mut stack: Vec<Node> = Vec::new();
+    let mut resources = Vec::new();
+
+    let mut href = String::new();
+    let mut props = HashMap::new();
+    let mut collection = false;
+
+    let mut pending = Vec::new();
+    let mut pending_collection = false;
+    let mut status = String::new();
+
+    let mut text = String::new();
+    let mut children = Vec::new();
+
+    let at = |stack: &[Node], depth: usize, name: &str| stack.get(depth).is_some_and(|n| n.dav && n.key == name);
+
+    loop {
+        let (ns, event) = reader.read_resolved_event().map_err(xml_error)?;
+
+        let (start, end) = match &event {
+            Event::Start(e) => (Some(node(&ns, e.local_name().as_ref())), false),
+            Event::Empty(e) => (Some(node(&ns, e.local_name().as_ref())), true),
+            Event::End(_) => (None, true),
+            Event::Text(e) => {
+                push_text(&stack, &mut href, &mut status, &mut text, &e.decode().map_err(xml_error)?);
+                continue;
+            }
+            Event::CData(e) => {
+                push_text(&stack, &mut href, &mut status, &mut text, &e.decode().map_err(xml_error)?);
+                continue;
+            }
+            Event::GeneralRef(e) => {
+                let resolved = match e.resolve_char_ref().map_err(xml_error)? {
+                    Some(ch) => ch.to_string(),
+                    None => {
+                        let name = e.decode().map_err(xml_error)?;
+                        resolve_predefined_entity(&name)
+                            .ok_or_else(|| xml_error(format!("unknown entity &{};", name)))?
+                            .to_string()
+                    }
+                };
+                push_text(&stack, &mut href, &mut status, &mut text, &resolved);
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+
+        if let Some(node) = start {
+            stack.push(node);
+            let in_prop = at(&stack, 2, "propstat") && at(&stack, 3, "prop");
+
+            match stack.len() {
+                2 if at(&stack, 1, "response") => {
+                    href.clear();
+                    props = HashMap::new();
+                    collection = false;
+                }
+                3 if at(&stack, 2, "propstat") => {
+                    pending = Vec::new();
+                    pending_collection = false;
+                    status.clear();
+                }
+                5 if in_prop => {
+                    text.clear();
+                    children = Vec::new();
+                }
+                6 if in_prop => {
+                    if at(&stack, 4, "resourcetype") && at(&stack, 5, "collection") {
+                        pending_collection = true;
+                    }
+                    children.push(stack[5].key.clone());
+                }
+                _ => {}
+            }
+        }
+
+        if end {
+            let in_prop = at(&stack, 2, "propstat") && at(&stack, 3, "prop");
+
+            match stack.len() {
+                2 if at(&stack, 1, "response") => resources.push(DavResource {
+                    href: href.trim().to_string(),
+                    props: std::mem::take(&mut props),
+                    collection,
+                }),
+                3 if at(&stack, 2, "propstat") && status.split_whitespace().nth(1) == Some("200") => {
+                    props.extend(pending.drain(..));
+                    collection |= pending_collection;
+                }
+                5 if in_prop => {
+                    let value = if children.is_empty() {
+                        PropValue::Text(text.trim().to_string())
+                    } else {
+                        PropValue::Elements(std::mem::take(&mut children))
+                    };
+                    pending.push((stack[4].key.clone(), value));
+                }
+                _ => {}
+            }
+
+            stack.pop();
+        }
+    }
+
+    Ok(resources)
+}
+
+fn push_text(stack: &[Node], href: &mut String, status: &mut String, text: &mut String, fragment: &str) {
+    let is = |depth: usize, name: &str| stack.get(depth).is_some_and(|n| n.dav && n.key == name);
+
+    match stack.len() {
+        3 if is(1, "response") && is(2, "href") => href.push_str(fragment),
+        4 if is(2, "propstat") && is(3, "status") => status.push_str(fragment),
+        5 if is(2, "propstat") && is(3, "prop") => text.push_str(fragment),
+        _ => {}
+    }
+}
+
+fn dav_request(
+    client: &reqwest::blocking::Client,
+    method: &[u8],
+    url: &str,
+    headers: &[(String, String)],
+) -> reqwest::blocking::RequestBuilder {
+    let method = Method::from_bytes(method).expect("valid WebDAV method");
+    let mut request = client.request(method, url);
+
+    for (key, value) in headers {
+        request = request.header(key, value);
+    }
+
+    request
+}
+
+fn expect_success(response: reqwest::blocking::Response) -> NifResult<(Atom, u16)> {
+    let status = response.status();
+
+    if !status.is_success() {
+        return Err(Error::Term(Box::new((atoms::unexpected_status(), status.as_u16()))));
+    }
+
+    Ok((atoms::ok(), status.as_u16()))
+}
+
+// PROPFIND `url` at `depth` ("0", "1" or "infinity"). An empty `props` list requests allprop;
+// property names must be XML names without a prefix.
+#[rustler::nif(schedule = "DirtyIo")]
+fn webdav_propfind(
+    env: Env,
+    url: String,
+    depth: String,
+    props: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    options: HttpOptions,
+) -> NifResult<(Atom, Vec<DavResource>)> {
+    let _permit = blocking::acquire()?;
+    let _inflight = inflight::acquire()?;
+    let options = resolve_options(options)?;
+    let client = client_for(&options).map_err(client_error)?;
+
+    let request = dav_request(&client, b"PROPFIND", &url, &headers)
+        .header("Depth", depth)
+        .header(CONTENT_TYPE, "application/xml; charset=utf-8")
+        .body(propfind_body(&props)?);
+    let response = pipeline::send(audit::caller(env).as_deref(), &options, request).map_err(|e| e.into_error())?;
+
+    if response.status() != StatusCode::MULTI_STATUS {
+        return Err(Error::Term(Box::new((atoms::unexpected_status(), response.status().as_u16()))));
+    }
+
+    let body = response
+        .text()
+        .map_err(|e| Error::Term(Box::new(format!("Body error: {}", e))))?;
+
+    Ok((atoms::ok(), parse_multistatus(&body)?))
+}
+
+// Create a collection, returning the response status
+#[rustler::nif(schedule = "DirtyIo")]
+fn webdav_mkcol(env: Env, url: String, headers: Vec<(String, String)>, options: HttpOptions) -> NifResult<(Atom, u16)> {
+    let _permit = blocking::acquire()?;
+    let _inflight = inflight::acquire()?;
+    let options = resolve_options(options)?;
+    let client = client_for(&options).map_err(client_error)?;
+
+    let request = dav_request(&client, b"MKCOL", &url, &headers);
+    let response = pipeline::send(audit::caller(env).as_deref(), &options, request).map_err(|e| e.into_error())?;
+
+    expect_success(response)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn transfer(
+    caller: Option<String>,
+    method: &[u8],
+    url: String,
+    destination: String,
+    overwrite: bool,
+    depth: Option<String>,
+    headers: Vec<(String, String)>,
+    options: HttpOptions,
+) -> NifResult<(Atom, u16)> {
+    let _permit = blocking::acquire()?;
+    let _inflight = inflight::acquire()?;
+    let options = resolve_options(options)?;
+    let client = client_for(&options).map_err(client_error)?;
+
+    // The Destination header must be absolute; resolve relative paths against the source
+    let destination = reqwest::Url::parse(&url)
+        .and_then(|base| base.join(&destination))
+        .map_err(|_| Error::Term(Box::new(atoms::invalid_url())))?;
+
+    let mut request = dav_request(&client, method, &url, &headers)
+        .header("Destination", destination.as_str())
+        .header("Overwrite", if overwrite { "T" } else { "F" });
+
+    if let Some(depth) = depth {
+        request = request.header("Depth", depth);
+    }
+
+    expect_success(pipeline::send(caller.as_deref(), &options, request).map_err(|e| e.into_error())?)
+}
+
+// Copy a resource to `destination`; 201 means created, 204 means overwritten
+#[rustler::nif(schedule = "DirtyIo")]
+fn webdav_copy(
+    env: Env,
+    url: String,
+    destination: String,
+    overwrite: bool,
+    depth: Option<String>,
+    headers: Vec<(String, String)>,
+    options: HttpOptions,
+) -> NifResult<(Atom, u16)> {
+    transfer(audit::caller(env), b"COPY", url, destination, overwrite, depth, headers, options)
+}
+
+// Move a resource to `destination`; 201 means created, 204 means overwritten
+#[rustler::nif(schedule = "DirtyIo")]
+fn webdav_move(
+    env: Env,
+    url: String,
+    destination: String,
+    overwrite: bool,
+    headers: Vec<(String, String)>,
+    options: HttpOptions,
+) -> NifResult<(Atom, u16)> {
+    transfer(audit::caller(env), b"MOVE", url, destination, overwrite, None, headers, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text<'a>(resource: &'a DavResource, key: &str) -> Option<&'a str> {
+        match resource.props.get(key) {
+            Some(PropValue::Text(text)) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn elements<'a>(resource: &'a DavResource, key: &str) -> Option<&'a [String]> {
+        match resource.props.get(key) {
+            Some(PropValue::Elements(elements)) => Some(elements),
+            _ => None,
+        }
+    }
+
+    fn parse(xml: &str) -> Vec<DavResource> {
+        parse_multistatus(xml).unwrap_or_else(|_| panic!("{} did not parse", xml))
+    }
+
+    #[test]
+    fn parses_a_multistatus_with_the_d_prefix() {
+        let resources = parse(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+            <D:multistatus xmlns:D="DAV:">
+              <D:response>
+                <D:href>/files/</D:href>
+                <D:propstat>
+                  <D:prop>
+                    <D:resourcetype><D:collection/></D:resourcetype>
+                    <D:displayname>files</D:displayname>
+                  </D:prop>
+                  <D:status>HTTP/1.1 200 OK</D:status>
+                </D:propstat>
+              </D:response>
+              <D:response>
+                <D:href>/files/a%20&amp;%20b.txt</D:href>
+                <D:propstat>
+                  <D:prop>
+                    <D:resourcetype/>
+                    <D:getcontentlength>1024</D:getcontentlength>
+                  </D:prop>
+                  <D:status>HTTP/1.1 200 OK</D:status>
+                </D:propstat>
+                <D:propstat>
+                  <D:prop><D:getetag/></D:prop>
+                  <D:status>HTTP/1.1 404 Not Found</D:status>
+                </D:propstat>
+              </D:response>
+            </D:multistatus>"#,
+        );
+
+        assert_eq!(resources.len(), 2);
+
+        assert_eq!(resources[0].href, "/files/");
+        assert!(resources[0].collection);
+        assert_eq!(elements(&resources[0], "resourcetype"), Some(&["collection".to_string()][..]));
+        assert_eq!(text(&resources[0], "displayname"), Some("files"));
+
+        assert_eq!(resources[1].href, "/files/a%20&%20b.txt");
+        assert!(!resources[1].collection);
+        assert_eq!(text(&resources[1], "getcontentlength"), Some("1024"));
+        assert!(!resources[1].props.contains_key("getetag"));
+    }
+
+    #[test]
+    fn parses_other_prefixes_and_namespaces() {
+        let resources = parse(
+            r#"<multistatus xmlns="DAV:" xmlns:ex="urn:example">
+              <response>
+                <href>/calendars/home/</href>
+                <propstat>
+                  <prop>
+                    <lp1:resourcetype xmlns:lp1="DAV:"><lp1:collection/><C:calendar xmlns:C="urn:ietf:params:xml:ns:caldav"/></lp1:resourcetype>
+                    <ex:color>#ff0000</ex:color>
+                    <owner xmlns="urn:other"><![CDATA[<me>]]></owner>
+                  </prop>
+                  <status>HTTP/1.1 200 OK</status>
+                </propstat>
+              </response>
+            </multistatus>"#,
+        );
+
+        assert_eq!(resources.len(), 1);
+        assert!(resources[0].collection);
+        assert_eq!(
+            elements(&resources[0], "resourcetype"),
+            Some(&["collection".to_string(), "{urn:ietf:params:xml:ns:caldav}calendar".to_string()][..])
+        );
+        assert_eq!(text(&resources[0], "{urn:example}color"), Some("#ff0000"));
+        assert_eq!(text(&resources[0], "{urn:other}owner"), Some("<me>"));
+    }
+
+    #[test]
+    fn rejects_malformed_bodies() {
+        assert!(parse_multistatus("<D:multistatus xmlns:D=\"DAV:\"><D:response></D:multistatus>").is_err());
+        assert!(parse_multistatus("<D:multistatus xmlns:D=\"DAV:\">&bogus;</D:multistatus>").is_err());
+    }
+
+    #[test]
+    fn propfind_body_writes_names_and_escapes_namespaces() {
+        let props = vec![
+            ("DAV:".to_string(), "getetag".to_string()),
+            ("urn:a&b".to_string(), "color".to_string()),
+        ];
+        let body = propfind_body(&props).unwrap_or_default();
+
+        assert!(body.contains("<D:prop><D:getetag/>"));
+        assert!(body.contains(r#"<x:color xmlns:x="urn:a&amp;b"/>"#));
+        assert!(propfind_body(&[]).unwrap_or_default().contains("<D:allprop/>"));
+    }
+
+    #[test]
+    fn propfind_body_rejects_names_that_are_not_ncnames() {
+        for name in ["", "a b", "x/><D:evil", "ns:name", "1abc", "a\"b"] {
+            assert!(propfind_body(&[("DAV:".to_string(), name.to_string())]).is_err(), "{:?}", name);
+        }
+    }
+}
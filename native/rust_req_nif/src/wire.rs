@@ -0,0 +1,393 @@
+// Requests sent over a hyper HTTP/1.1 connection we drive ourselves, for features
+// that need the connection itself: reqwest swallows 1xx informational responses,
+// re-encodes query strings, and its blocking bodies cannot carry trailers.
+use http_body_util::BodyExt;
+use hyper::header::{HeaderMap, HOST, LOCATION, USER_AGENT};
+use hyper::{Method, Request, StatusCode};
+use hyper_util::rt::TokioIo;
+use rustler::{Encoder, Error, NifMap, NifResult};
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::TlsConnector;
+
+use crate::background::RUNTIME;
+use crate::throttle;
+use crate::trailers::TrailedBody;
+use crate::{trust, HttpOptions};
+
+pub struct Exchange {
+    pub status: u16,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+    // Interim responses of the final hop, in the order they arrived
+    pub informational: Vec<(u16, HeaderMap)>,
+    // What went over the connection of the final hop, when `debug` is set
+    pub capture: Option<Capture>,
+    // How the host of the final hop was resolved
+    pub resolution: Resolution,
+    // Local and remote address of the final hop's connection
+    pub connection: Option<(SocketAddr, SocketAddr)>,
+}
+
+// Outcome of resolving a host: every candidate address and the one connected to.
+// The system resolver (getaddrinfo) does not reveal which nameserver answered.
+#[derive(Debug, NifMap)]
+pub struct Resolution {
+    resolver: String,
+    addresses: Vec<String>,
+    address: String,
+    lookup_ms: f64,
+}
+
+// Resolve `host` and connect to the first candidate that accepts
+async fn connect(host: &str, port: u16) -> NifResult<(TcpStream, Resolution)> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    let started = Instant::now();
+    let (resolver, candidates) = match (host.parse::<IpAddr>(), crate::dns::cached(host)) {
+        (Ok(ip), _) => ("literal", vec![SocketAddr::new(ip, port)]),
+        (Err(_), Some(addresses)) => ("prefetch", addresses.into_iter().map(|ip| SocketAddr::new(ip, port)).collect()),
+        (Err(_), None) => {
+            let found = tokio::net::lookup_host((host, port))
+                .await
+                .map_err(|_| Error::Term(Box::new(crate::atoms::dns_error())))?;
+            ("system", found.collect())
+        }
+    };
+    let lookup_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    let mut failure = None;
+    for candidate in &candidates {
+        match TcpStream::connect(candidate).await {
+            Ok(tcp) => {
+                let resolution = Resolution {
+                    resolver: resolver.to_string(),
+                    addresses: candidates.iter().map(|addr| addr.ip().to_string()).collect(),
+                    address: candidate.ip().to_string(),
+                    lookup_ms,
+                };
+                return Ok((tcp, resolution));
+            }
+            Err(e) => failure = Some(e),
+        }
+    }
+
+    Err(connect_error(failure))
+}
+
+// Request and response heads as serialized on the wire, and the bytes each way.
+// Counts are taken below compression and above TLS.
+#[derive(Debug, NifMap)]
+pub struct Capture {
+    request_head: String,
+    response_head: String,
+    bytes_sent: u64,
+    bytes_received: u64,
+}
+
+// Heads are looked for within the first bytes each way only
+const HEAD_LIMIT: usize = 64 * 1024;
+
+#[derive(Default)]
+struct Recording {
+    sent: Vec<u8>,
+    received: Vec<u8>,
+    bytes_sent: u64,
+    bytes_received: u64,
+}
+
+fn head_end(bytes: &[u8]) -> Option<usize> {
+    bytes.windows(4).position(|w| w == b"\r\n\r\n").map(|i| i + 4)
+}
+
+impl Recording {
+    fn capture(&self) -> Capture {
+        let request_head = &self.sent[..head_end(&self.sent).unwrap_or(self.sent.len())];
+
+        // Skip the heads of interim 1xx responses, which precede the final one
+        let mut received = &self.received[..];
+        while let Some(end) = head_end(received) {
+            if !received.starts_with(b"HTTP/1.1 1") && !received.starts_with(b"HTTP/1.0 1") {
+                break;
+            }
+            received = &received[end..];
+        }
+        let response_head = &received[..head_end(received).unwrap_or(received.len())];
+
+        Capture {
+            request_head: String::from_utf8_lossy(request_head).into_owned(),
+            response_head: String::from_utf8_lossy(response_head).into_owned(),
+            bytes_sent: self.bytes_sent,
+            bytes_received: self.bytes_received,
+        }
+    }
+}
+
+// Passes IO through, noting what goes by when a recording is attached
+struct Recorder<T> {
+    inner: T,
+    recording: Option<Arc<Mutex<Recording>>>,
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Recorder<T> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+
+        if let (Poll::Ready(Ok(())), Some(recording)) = (&poll, &self.recording) {
+            let read = &buf.filled()[before..];
+            let mut recording = recording.lock().unwrap();
+            recording.bytes_received += read.len() as u64;
+            let room = HEAD_LIMIT.saturating_sub(recording.received.len());
+            recording.received.extend_from_slice(&read[..read.len().min(room)]);
+        }
+
+        poll
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Recorder<T> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+
+        if let (Poll::Ready(Ok(written)), Some(recording)) = (&poll, &self.recording) {
+            let mut recording = recording.lock().unwrap();
+            recording.bytes_sent += *written as u64;
+            let room = HEAD_LIMIT.saturating_sub(recording.sent.len());
+            recording.sent.extend_from_slice(&buf[..(*written).min(room)]);
+        }
+
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+fn client_error(message: impl std::fmt::Display) -> Error {
+    Error::Term(Box::new(format!("Client error: {}", message)))
+}
+
+fn request_error(message: impl std::fmt::Display) -> Error {
+    Error::Term(Box::new(format!("Request error: {}", message)))
+}
+
+// Class of the last failed connection attempt, `:network_error` when it has none
+fn connect_error(failure: Option<std::io::Error>) -> Error {
+    let class = failure.as_ref().and_then(crate::io_error_class).unwrap_or_else(crate::atoms::network_error);
+    Error::Term(Box::new(class))
+}
+
+fn tls_connector(options: &HttpOptions) -> NifResult<TlsConnector> {
+    let mut config = trust::client_config(options).map_err(client_error)?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+// One request/response exchange on a fresh connection
+async fn exchange(
+    method: &Method,
+    url: &reqwest::Url,
+    raw_query: Option<&str>,
+    headers: &[(String, String)],
+    body: &[u8],
+    trailers: Option<&HeaderMap>,
+    options: &HttpOptions,
+) -> NifResult<Exchange> {
+    let host = url.host_str().ok_or_else(|| Error::Term(Box::new(crate::atoms::invalid_url())))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    let (tcp, resolution) = match crate::connect_timeout(options) {
+        Some(timeout) => tokio::time::timeout(timeout, connect(host, port))
+            .await
+            .map_err(|_| Error::Term(Box::new(crate::atoms::timeout())))??,
+        None => connect(host, port).await?,
+    };
+    let connection = tcp.local_addr().ok().zip(tcp.peer_addr().ok());
+
+    let informational = Arc::new(Mutex::new(Vec::new()));
+    let recording = (options.debug == Some(true)).then(|| Arc::new(Mutex::new(Recording::default())));
+
+    let target = match raw_query.or(url.query()) {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+
+    let mut request = Request::builder().method(method.clone()).uri(target);
+    let authority = match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    };
+    request = request.header(HOST, authority);
+    if let Some(user_agent) = &options.user_agent {
+        request = request.header(USER_AGENT, user_agent);
+    }
+    for (key, value) in headers {
+        request = request.header(key, value);
+    }
+
+    let mut request = request
+        .body(throttle::Body::new(TrailedBody::new(body.to_vec(), trailers.cloned()), throttle::upload(options)))
+        .map_err(request_error)?;
+
+    // Each interim response is also forwarded to `informational_pid` as it arrives
+    let sink = informational.clone();
+    let pid = options.informational_pid;
+    hyper::ext::on_informational(&mut request, move |response| {
+        let status = response.status().as_u16();
+
+        if let Some(pid) = pid {
+            let headers = crate::header_list(response.headers());
+            crate::messages::send(pid, move |env| (crate::atoms::informational(), status, headers).encode(env));
+        }
+
+        sink.lock().unwrap().push((status, response.headers().clone()));
+    });
+
+    let response = match url.scheme() {
+        "https" => {
+            let server_name = ServerName::try_from(host.to_string()).map_err(client_error)?;
+            let tls = tls_connector(options)?
+                .connect(server_name, tcp)
+                .await
+                .map_err(|e| match crate::io_error_class(&e) {
+                    Some(class) => Error::Term(Box::new(class)),
+                    None => Error::Term(Box::new(crate::atoms::tls_error())),
+                })?;
+            send(TokioIo::new(Recorder { inner: tls, recording: recording.clone() }), request).await?
+        }
+        _ => send(TokioIo::new(Recorder { inner: tcp, recording: recording.clone() }), request).await?,
+    };
+
+    let (parts, mut incoming) = response.into_parts();
+    let mut body = Vec::new();
+    let limiter = throttle::download(options);
+    let read_timeout = crate::read_timeout(options);
+    loop {
+        // None when `read_timeout_ms` passed first, failing like a broken body
+        let frame = match read_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, incoming.frame()).await.ok(),
+            None => Some(incoming.frame().await),
+        };
+        match frame {
+            Some(None) => break,
+            Some(Some(Ok(frame))) => {
+                let data = frame.data_ref().map(|data| &data[..]).unwrap_or_default();
+                body.extend_from_slice(data);
+                throttle::pace(limiter.as_deref(), data.len()).await;
+            }
+            None | Some(Some(Err(_))) => {
+                let headers = crate::header_map(&parts.headers);
+                return Err(crate::body_interrupted(parts.status.as_u16(), &headers, body));
+            }
+        }
+    }
+
+    let informational = std::mem::take(&mut *informational.lock().unwrap());
+
+    Ok(Exchange {
+        status: parts.status.as_u16(),
+        headers: parts.headers,
+        body,
+        informational,
+        capture: recording.map(|recording| recording.lock().unwrap().capture()),
+        resolution,
+        connection,
+    })
+}
+
+async fn send<T>(io: T, request: Request<throttle::Body<TrailedBody>>) -> NifResult<hyper::Response<hyper::body::Incoming>>
+where
+    T: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(io).await.map_err(request_error)?;
+    tokio::spawn(connection);
+
+    sender.send_request(request).await.map_err(request_error)
+}
+
+// The query string as the caller wrote it, for `raw_query`
+fn raw_query(url: &str) -> Option<&str> {
+    let url = url.split_once('#').map_or(url, |(url, _)| url);
+    url.split_once('?').map(|(_, query)| query)
+}
+
+// Send a request on a connection of its own, following redirects like the shared
+// client does, with `request_trailers` after the body. With `raw_query`, the query of
+// `url` goes out byte for byte; those of redirect targets are sent as parsed.
+// Proxies are not supported on this path.
+pub fn send_request(method: Method, url: &str, headers: &[(String, String)], body: Vec<u8>, options: &HttpOptions) -> NifResult<Exchange> {
+    if options.proxy.is_some() {
+        return Err(client_error(
+            "early_hints, informational_pid, debug, dns_info, raw_query and request_trailers on post cannot be combined with a proxy",
+        ));
+    }
+
+    let timeout = Duration::from_millis(options.timeout_ms.unwrap_or(30000));
+
+    RUNTIME.block_on(async {
+        tokio::time::timeout(timeout, async {
+            let mut query = raw_query(url).filter(|_| options.raw_query == Some(true));
+            let mut url = reqwest::Url::parse(url).map_err(|_| Error::Term(Box::new(crate::atoms::invalid_url())))?;
+            let mut method = method;
+            let mut body = body;
+            let mut headers = headers.to_vec();
+            let mut trailers = crate::trailers::trailers(options);
+            let follow = options.follow_redirects != Some(false);
+            let max_redirects = options.max_redirects.unwrap_or(10);
+
+            for _ in 0..=max_redirects {
+                let mut response = exchange(&method, &url, query, &headers, &body, trailers.as_ref(), options).await?;
+
+                // A refused expectation is retried once without it (see `expect_fallback`)
+                let expects = headers.iter().any(|(key, _)| key.eq_ignore_ascii_case("expect"));
+                if response.status == 417 && expects && options.expect_fallback != Some(false) {
+                    headers.retain(|(key, _)| !key.eq_ignore_ascii_case("expect"));
+                    response = exchange(&method, &url, query, &headers, &body, trailers.as_ref(), options).await?;
+                }
+                let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::OK);
+                let location = response.headers.get(LOCATION).and_then(|v| v.to_str().ok());
+
+                let next = location.filter(|_| follow && status.is_redirection()).and_then(|l| url.join(l).ok());
+                let Some(next) = next else {
+                    return Ok(response);
+                };
+
+                // Like browsers, 303 and a POST answered with 301/302 continue as a GET
+                if status == StatusCode::SEE_OTHER || (method == Method::POST && matches!(status.as_u16(), 301 | 302)) {
+                    method = Method::GET;
+                    body = Vec::new();
+                    // Trailers go with the body
+                    if trailers.take().is_some() {
+                        headers.retain(|(key, _)| {
+                            !key.eq_ignore_ascii_case("trailer") && !key.eq_ignore_ascii_case("transfer-encoding")
+                        });
+                    }
+                }
+                // Credentials are only sent to the origin they were given for
+                if next.origin() != url.origin() {
+                    crate::drop_credentials(&mut headers);
+                }
+                crate::events::redirect(url.as_str(), next.as_str(), response.status);
+                url = next;
+                query = None;
+            }
+
+            Err(Error::Term(Box::new(crate::atoms::too_many_redirects())))
+        })
+        .await
+        .map_err(|_| Error::Term(Box::new(crate::atoms::timeout())))?
+    })
+}
@@ -0,0 +1,174 @@
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde_json::{Map, Value};
+
+// Element being built: attributes and children collect in `fields`, text in `text`
+struct Frame {
+    name: String,
+    fields: Map<String, Value>,
+    text: String,
+}
+
+fn frame(start: &BytesStart) -> Result<Frame, String> {
+    let mut fields = Map::new();
+
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(|e| e.to_string())?;
+        let key = attribute.key;
+        if key.as_ref() == b"xmlns" || key.prefix().is_some_and(|p| p.as_ref() == b"xmlns") {
+            continue;
+        }
+        let value = attribute.unescape_value().map_err(|e| e.to_string())?;
+        fields.insert(
+            format!("@{}", String::from_utf8_lossy(key.local_name().as_ref())),
+            Value::String(value.into_owned()),
+        );
+    }
+
+    Ok(Frame {
+        name: String::from_utf8_lossy(start.local_name().as_ref()).into_owned(),
+        fields,
+        text: String::new(),
+    })
+}
+
+// An element with neither attributes nor children is its text; otherwise it is an
+// object of `@attribute` and child keys, plus `#text` when it also has text
+fn finish(frame: Frame) -> Value {
+    let text = frame.text.trim();
+
+    if frame.fields.is_empty() {
+        return Value::String(text.to_string());
+    }
+
+    let mut fields = frame.fields;
+    if !text.is_empty() {
+        fields.insert("#text".to_string(), Value::String(text.to_string()));
+    }
+    Value::Object(fields)
+}
+
+// Repeated child elements collect into a list under their shared name
+fn insert_child(fields: &mut Map<String, Value>, name: String, value: Value) {
+    match fields.get_mut(&name) {
+        Some(Value::Array(items)) => items.push(value),
+        Some(existing) => {
+            let first = existing.take();
+            *existing = Value::Array(vec![first, value]);
+        }
+        None => {
+            fields.insert(name, value);
+        }
+    }
+}
+
+// Convert an XML document to its root element name and value. Namespace prefixes
+// are dropped from element and attribute names.
+pub fn to_json(xml: &str) -> Result<(String, Value), String> {
+    let mut reader = Reader::from_str(xml);
+    let mut stack: Vec<Frame> = Vec::new();
+
+    loop {
+        let completed = match reader.read_event().map_err(|e| e.to_string())? {
+            Event::Start(start) => {
+                stack.push(frame(&start)?);
+                continue;
+            }
+            Event::Empty(start) => frame(&start)?,
+            Event::End(_) => stack.pop().ok_or("unbalanced end tag")?,
+            Event::Text(text) => {
+                if let Some(top) = stack.last_mut() {
+                    top.text.push_str(&text.decode().map_err(|e| e.to_string())?);
+                }
+                continue;
+            }
+            Event::CData(data) => {
+                if let Some(top) = stack.last_mut() {
+                    top.text.push_str(&data.decode().map_err(|e| e.to_string())?);
+                }
+                continue;
+            }
+            Event::GeneralRef(reference) => {
+                let resolved = match reference.resolve_char_ref().map_err(|e| e.to_string())? {
+                    Some(ch) => ch.to_string(),
+                    None => {
+                        let name = reference.decode().map_err(|e| e.to_string())?;
+                        resolve_predefined_entity(&name)
+                            .ok_or_else(|| format!("unknown entity &{};", name))?
+                            .to_string()
+                    }
+                };
+                if let Some(top) = stack.last_mut() {
+                    top.text.push_str(&resolved);
+                }
+                continue;
+            }
+            Event::Eof => return Err("no root element".to_string()),
+            _ => continue,
+        };
+
+        let name = completed.name.clone();
+        let value = finish(completed);
+
+        match stack.last_mut() {
+            Some(parent) => insert_child(&mut parent.fields, name, value),
+            None => return Ok((name, value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn elements_convert_to_text_or_objects() {
+        let xml = r#"<?xml version="1.0"?>
+            <!-- an order -->
+            <p:Order xmlns:p="urn:example" p:id="7" status="open">
+              <p:Customer>Ada</p:Customer>
+              <p:Line sku="A1">2</p:Line>
+              <p:Line sku="B2">1</p:Line>
+              <p:Note/>
+              <p:Line sku="C3"><p:Gift>yes</p:Gift></p:Line>
+            </p:Order>"#;
+
+        assert_eq!(
+            to_json(xml).unwrap(),
+            (
+                "Order".to_string(),
+                json!({
+                    "@id": "7",
+                    "@status": "open",
+                    "Customer": "Ada",
+                    "Line": [
+                        {"@sku": "A1", "#text": "2"},
+                        {"@sku": "B2", "#text": "1"},
+                        {"@sku": "C3", "Gift": "yes"}
+                    ],
+                    "Note": ""
+                })
+            )
+        );
+    }
+
+    #[test]
+    fn text_resolves_entities_and_cdata() {
+        let xml = "<a>Fish &amp; chips &lt;3 &#233;&#x20AC; <![CDATA[<raw> & ]]>done</a>";
+        assert_eq!(to_json(xml).unwrap().1, json!("Fish & chips <3 é€ <raw> & done"));
+
+        let (_, value) = to_json(r#"<a title="&quot;x&quot; &amp; y"/>"#).unwrap();
+        assert_eq!(value, json!({"@title": "\"x\" & y"}));
+    }
+
+    #[test]
+    fn malformed_documents_are_errors() {
+        assert!(to_json("").is_err());
+        assert!(to_json("just text").is_err());
+        assert!(to_json("<a><b></a>").is_err());
+        assert!(to_json("<a>&nbsp;</a>").unwrap_err().contains("unknown entity"));
+        assert!(to_json("<a x='1' x='2'/>").is_err());
+    }
+}